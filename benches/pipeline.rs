@@ -0,0 +1,94 @@
+// Per-frame cost of the processing stages, at the 256x192 sensor resolution and
+// at 384x288. Run with `cargo bench`, or `cargo bench --features parallel`.
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use eframe::egui;
+use thermoscope_app::calibration::Calibration;
+use thermoscope_app::display::DisplaySettings;
+use thermoscope_app::frame::ThermalFrame;
+use thermoscope_app::palette::{self, Palette};
+use thermoscope_app::tracking::SpotTracker;
+
+const SIZES: [(usize, usize); 2] = [(256, 192), (384, 288)];
+
+/// Y16 bytes of a smooth scene around 20-40 °C with some texture.
+fn synthetic_y16(width: usize, height: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(width * height * 2);
+    for y in 0..height {
+        for x in 0..width {
+            let t = 20.0 + 20.0 * (x as f32 / width as f32) + ((x ^ y) % 7) as f32 * 0.1;
+            let raw = ((t + 273.15) * 64.0) as u16;
+            data.extend_from_slice(&raw.to_le_bytes());
+        }
+    }
+    data
+}
+
+fn stages(c: &mut Criterion) {
+    let calibration = Calibration::default();
+    let display = DisplaySettings::default();
+    for (w, h) in SIZES {
+        let data = synthetic_y16(w, h);
+        let id = format!("{}x{}", w, h);
+        let mut frame = ThermalFrame::from_y16(&data, w, h, 0).unwrap();
+        let mut temperature = Vec::new();
+        calibration.temperature_field_into(&frame.raw, &mut temperature);
+        let range = ThermalFrame::min_max(&frame.raw);
+        let mut image = egui::ColorImage::default();
+
+        c.bench_with_input(BenchmarkId::new("decode", &id), &data, |b, data| {
+            b.iter(|| frame.decode_y16(black_box(data), w, h, 0))
+        });
+        c.bench_function(&format!("temperature_field/{}", id), |b| {
+            b.iter(|| calibration.temperature_field_into(black_box(&frame.raw), &mut temperature))
+        });
+        c.bench_function(&format!("colorize_lut/{}", id), |b| {
+            b.iter(|| {
+                palette::colorize_into(
+                    black_box(&frame.raw),
+                    w,
+                    h,
+                    Palette::Ironbow,
+                    range,
+                    &mut image,
+                )
+            })
+        });
+        // The per-pixel float path the LUT replaces, for comparison.
+        c.bench_function(&format!("colorize_float/{}", id), |b| {
+            let inv = 1.0 / (range.1 - range.0) as f32;
+            b.iter(|| {
+                for (pixel, &v) in image.pixels.iter_mut().zip(black_box(&frame.raw)) {
+                    *pixel = Palette::Ironbow.color((v - range.0) as f32 * inv);
+                }
+            })
+        });
+        c.bench_function(&format!("render/{}", id), |b| {
+            b.iter(|| display.render_into(&frame, &temperature, &calibration, &mut image))
+        });
+    }
+}
+
+fn full_frame(c: &mut Criterion) {
+    let calibration = Calibration::default();
+    let display = DisplaySettings::default();
+    let mut tracker = SpotTracker::default();
+    for (w, h) in SIZES {
+        let data = synthetic_y16(w, h);
+        let mut frame = ThermalFrame::default();
+        let mut temperature = Vec::new();
+        let mut image = egui::ColorImage::default();
+        c.bench_function(&format!("frame/{}x{}", w, h), |b| {
+            b.iter(|| {
+                frame.decode_y16(black_box(&data), w, h, 0);
+                calibration.temperature_field_into(&frame.raw, &mut temperature);
+                let spots = tracker.update(&temperature, w, h);
+                let range = display.render_into(&frame, &temperature, &calibration, &mut image);
+                black_box((spots, range))
+            })
+        });
+    }
+}
+
+criterion_group!(benches, stages, full_frame);
+criterion_main!(benches);
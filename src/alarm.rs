@@ -0,0 +1,863 @@
+// Temperature alarms.
+//
+// Rules are evaluated in the engine thread for every frame, so they keep firing
+// when the window is minimised or when running headless. Actions that touch
+// files or the network are handed to the engine's alarm worker thread, which
+// runs them in order; commands are started right away and reaped in the
+// background.
+
+use crate::export::csv_field;
+use crate::frame::ProcessedFrame;
+use crate::measure::{Roi, RoiSet, Shape};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Connect and write timeout of webhooks and MQTT.
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the monitored value comes from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AlarmSource {
+    Frame,
+    Center,
+    /// The measurement ROI with this id; rules wait while it does not exist,
+    /// and an active alarm clears when it is deleted.
+    Region(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Statistic {
+    Min,
+    Max,
+    Mean,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    Above(f32),
+    Below(f32),
+    /// Rise of more than `per_minute` °C/min, measured over `window`.
+    RateOfRise {
+        per_minute: f32,
+        window: Duration,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AlarmAction {
+    /// Flash the UI border while the alarm is active.
+    Flash,
+    /// Terminal bell; audible on most consoles and desktop terminals.
+    Beep,
+    /// Save the current display image into a directory.
+    Snapshot(PathBuf),
+    /// Append a line per event to a log file.
+    EventLog(PathBuf),
+    /// Shell command; gets ALARM_NAME, ALARM_STATE and ALARM_VALUE in its env.
+    RunCommand(String),
+    /// HTTP POST of a JSON event to a plain-http URL (e.g. a local webhook).
+    Webhook(String),
+    /// MQTT 3.1.1 QoS 0 publish of the JSON event.
+    Mqtt {
+        host: String,
+        port: u16,
+        topic: String,
+    },
+}
+
+/// Fields left out of a configuration file take the values of `AlarmRule::new`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlarmRule {
+    pub id: u32,
+    pub name: String,
+    pub enabled: bool,
+    pub source: AlarmSource,
+    pub statistic: Statistic,
+    pub condition: Condition,
+    /// The value has to recede this far (°C, or °C/min for rate rules) past the
+    /// threshold before the alarm clears.
+    pub hysteresis: f32,
+    /// The condition must hold this long before the alarm is raised.
+    pub min_duration: Duration,
+    pub actions: Vec<AlarmAction>,
+}
+
+impl Default for AlarmRule {
+    fn default() -> Self {
+        Self::new(0, "Alarm", AlarmSource::Frame, Condition::Above(60.0))
+    }
+}
+
+impl AlarmRule {
+    pub fn new(id: u32, name: &str, source: AlarmSource, condition: Condition) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            enabled: true,
+            source,
+            statistic: Statistic::Max,
+            condition,
+            hysteresis: 1.0,
+            min_duration: Duration::ZERO,
+            actions: vec![AlarmAction::Flash],
+        }
+    }
+
+    /// Monitored value of this rule for a frame, `None` if the region is empty
+    /// or gone.
+    pub fn value(&self, frame: &ProcessedFrame, rois: &RoiSet) -> Option<f32> {
+        let (w, h) = (frame.frame.width, frame.frame.height);
+        match &self.source {
+            AlarmSource::Center => Some(frame.spots.center.temperature),
+            AlarmSource::Frame => {
+                let stats = Roi::new(
+                    0,
+                    Shape::Rect {
+                        x0: 0.0,
+                        y0: 0.0,
+                        x1: w as f32,
+                        y1: h as f32,
+                    },
+                )
+                .stats(&frame.temperature, w, h)?;
+                Some(self.pick(stats.min, stats.max, stats.mean))
+            }
+            AlarmSource::Region(id) => {
+                let stats = rois.get(*id)?.stats(&frame.temperature, w, h)?;
+                Some(self.pick(stats.min, stats.max, stats.mean))
+            }
+        }
+    }
+
+    fn pick(&self, min: f32, max: f32, mean: f32) -> f32 {
+        match self.statistic {
+            Statistic::Min => min,
+            Statistic::Max => max,
+            Statistic::Mean => mean,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmState {
+    Raised,
+    Cleared,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AlarmEvent {
+    pub rule_id: u32,
+    #[serde(rename = "rule")]
+    pub rule_name: String,
+    pub state: AlarmState,
+    /// Temperature (°C) or rate (°C/min) that triggered the transition.
+    pub value: f32,
+    /// Serialised as Unix time in seconds.
+    #[serde(serialize_with = "unix_secs")]
+    pub timestamp: SystemTime,
+}
+
+impl AlarmEvent {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("alarm events always serialise")
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            AlarmState::Raised => "raised",
+            AlarmState::Cleared => "cleared",
+        }
+    }
+}
+
+fn unix_secs<S: serde::Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    s.serialize_f64(secs.as_secs_f64())
+}
+
+#[derive(Default)]
+struct RuleState {
+    active: bool,
+    /// When the condition started to hold (for `min_duration`).
+    pending_since: Option<Instant>,
+    history: VecDeque<(Instant, f32)>,
+    /// Last measured value, reported when the rule's ROI disappears.
+    last: f32,
+}
+
+/// Alarm status attached to every processed frame, for the UI.
+#[derive(Clone, Debug, Default)]
+pub struct AlarmStatus {
+    /// Names of the currently active alarms.
+    pub active: Vec<String>,
+    /// True while an active alarm has the `Flash` action.
+    pub flash: bool,
+    /// Transitions that happened on this frame.
+    pub events: Vec<AlarmEvent>,
+}
+
+const RECENT_EVENTS: usize = 100;
+
+#[derive(Default)]
+pub struct AlarmEngine {
+    pub rules: Vec<AlarmRule>,
+    states: Vec<(u32, RuleState)>,
+    /// Most recent events, newest last.
+    pub recent: VecDeque<AlarmEvent>,
+    next_id: u32,
+    worker: ActionWorker,
+}
+
+impl AlarmEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule and assigns it a fresh id.
+    pub fn add(&mut self, mut rule: AlarmRule) -> u32 {
+        self.next_id = self
+            .next_id
+            .max(self.rules.iter().map(|r| r.id).max().unwrap_or(0))
+            + 1;
+        rule.id = self.next_id;
+        self.rules.push(rule);
+        self.next_id
+    }
+
+    /// `false` if there was no rule `id`.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.id != id);
+        self.states.retain(|(rid, _)| *rid != id);
+        self.rules.len() != before
+    }
+
+    /// Replaces every rule, e.g. with those of a profile. The rules get fresh
+    /// ids; active alarms are dropped without a Cleared event.
+    pub fn set_rules(&mut self, rules: Vec<AlarmRule>) {
+        self.rules.clear();
+        self.states.clear();
+        for rule in rules {
+            self.add(rule);
+        }
+    }
+
+    pub fn is_active(&self, id: u32) -> bool {
+        self.states.iter().any(|(rid, s)| *rid == id && s.active)
+    }
+
+    /// Evaluates all rules against a frame, runs the actions of every transition
+    /// and returns the resulting status. `rois` are the measurements that
+    /// region rules refer to.
+    pub fn evaluate(&mut self, frame: &ProcessedFrame, rois: &RoiSet, now: Instant) -> AlarmStatus {
+        let mut status = AlarmStatus::default();
+        for rule in self.rules.iter().filter(|r| r.enabled) {
+            let transition = match rule.value(frame, rois) {
+                Some(value) => {
+                    let state = state_for(&mut self.states, rule.id);
+                    let Some((measured, triggered, released)) = check(rule, state, value, now)
+                    else {
+                        continue;
+                    };
+                    state.last = measured;
+
+                    let mut transition = None;
+                    if !state.active {
+                        if triggered {
+                            let since = *state.pending_since.get_or_insert(now);
+                            if now.duration_since(since) >= rule.min_duration {
+                                state.active = true;
+                                transition = Some(AlarmState::Raised);
+                            }
+                        } else {
+                            state.pending_since = None;
+                        }
+                    } else if released {
+                        state.active = false;
+                        state.pending_since = None;
+                        transition = Some(AlarmState::Cleared);
+                    }
+
+                    if state.active {
+                        status.active.push(rule.name.clone());
+                        status.flash |= rule.actions.contains(&AlarmAction::Flash);
+                    }
+                    transition.map(|transition| (transition, measured))
+                }
+                // The ROI was deleted: the rule starts over, and an active
+                // alarm clears with the last value measured.
+                None => match rule.source {
+                    AlarmSource::Region(id) if rois.get(id).is_none() => self
+                        .states
+                        .iter()
+                        .position(|(rid, _)| *rid == rule.id)
+                        .map(|i| self.states.remove(i).1)
+                        .filter(|state| state.active)
+                        .map(|state| (AlarmState::Cleared, state.last)),
+                    _ => None,
+                },
+            };
+
+            if let Some((state, measured)) = transition {
+                let event = AlarmEvent {
+                    rule_id: rule.id,
+                    rule_name: rule.name.clone(),
+                    state,
+                    value: measured,
+                    timestamp: SystemTime::now(),
+                };
+                println!(
+                    "🚨 Alarm '{}' {} ({:.1})",
+                    rule.name,
+                    event.state_name(),
+                    measured
+                );
+                run_actions(rule, &event, frame, &mut self.worker);
+                status.events.push(event);
+            }
+        }
+
+        for event in &status.events {
+            self.recent.push_back(event.clone());
+        }
+        while self.recent.len() > RECENT_EVENTS {
+            self.recent.pop_front();
+        }
+        status
+    }
+}
+
+fn state_for(states: &mut Vec<(u32, RuleState)>, id: u32) -> &mut RuleState {
+    match states.iter().position(|(rid, _)| *rid == id) {
+        Some(i) => &mut states[i].1,
+        None => {
+            states.push((id, RuleState::default()));
+            &mut states.last_mut().unwrap().1
+        }
+    }
+}
+
+/// Returns (measured value, condition holds, condition released), or `None` while
+/// a rate rule is still collecting history.
+fn check(
+    rule: &AlarmRule,
+    state: &mut RuleState,
+    value: f32,
+    now: Instant,
+) -> Option<(f32, bool, bool)> {
+    let h = rule.hysteresis.abs();
+    match rule.condition {
+        Condition::Above(limit) => Some((value, value > limit, value < limit - h)),
+        Condition::Below(limit) => Some((value, value < limit, value > limit + h)),
+        Condition::RateOfRise { per_minute, window } => {
+            state.history.push_back((now, value));
+            while state
+                .history
+                .front()
+                .is_some_and(|(t, _)| now.duration_since(*t) > window)
+            {
+                state.history.pop_front();
+            }
+            let &(t0, v0) = state.history.front()?;
+            let dt = now.duration_since(t0).as_secs_f32();
+            if dt < window.as_secs_f32() * 0.5 {
+                return None;
+            }
+            let rate = (value - v0) / dt * 60.0;
+            Some((rate, rate > per_minute, rate < per_minute - h))
+        }
+    }
+}
+
+fn run_actions(
+    rule: &AlarmRule,
+    event: &AlarmEvent,
+    frame: &ProcessedFrame,
+    worker: &mut ActionWorker,
+) {
+    let raised = event.state == AlarmState::Raised;
+    for action in &rule.actions {
+        match action {
+            AlarmAction::Flash => {}
+            AlarmAction::Beep => {
+                if raised {
+                    print!("\x07");
+                    io::stdout().flush().ok();
+                }
+            }
+            AlarmAction::Snapshot(dir) => {
+                if raised {
+                    worker.send(Job::Snapshot {
+                        dir: dir.clone(),
+                        event: event.clone(),
+                        image: frame.image.clone(),
+                    });
+                }
+            }
+            AlarmAction::EventLog(path) => worker.send(Job::Log {
+                path: path.clone(),
+                event: event.clone(),
+            }),
+            AlarmAction::RunCommand(cmd) => run_command(cmd, event),
+            AlarmAction::Webhook(url) => worker.send(Job::Webhook {
+                url: url.clone(),
+                body: event.to_json(),
+            }),
+            AlarmAction::Mqtt { host, port, topic } => worker.send(Job::Mqtt {
+                addr: format!("{}:{}", host, port),
+                topic: topic.clone(),
+                body: event.to_json(),
+            }),
+        }
+    }
+}
+
+/// An action that may block, for the worker thread.
+enum Job {
+    Snapshot {
+        dir: PathBuf,
+        event: AlarmEvent,
+        image: egui::ColorImage,
+    },
+    Log {
+        path: PathBuf,
+        event: AlarmEvent,
+    },
+    Webhook {
+        url: String,
+        body: String,
+    },
+    Mqtt {
+        addr: String,
+        topic: String,
+        body: String,
+    },
+}
+
+impl Job {
+    fn run(self) {
+        let result = match &self {
+            Job::Snapshot { dir, event, image } => save_snapshot(dir, event, image),
+            Job::Log { path, event } => append_log(path, event),
+            Job::Webhook { url, body } => post_webhook(url, body),
+            Job::Mqtt { addr, topic, body } => mqtt_publish(addr, topic, body.as_bytes()),
+        };
+        if let Err(e) = result {
+            let what = match self {
+                Job::Snapshot { .. } => "snapshot",
+                Job::Log { .. } => "log",
+                Job::Webhook { .. } => "webhook",
+                Job::Mqtt { .. } => "MQTT publish",
+            };
+            eprintln!("⚠ Alarm {} failed: {}", what, e);
+        }
+    }
+}
+
+/// Thread that runs the blocking actions, started with the first one. It ends
+/// when the engine is dropped.
+#[derive(Default)]
+struct ActionWorker {
+    tx: Option<Sender<Job>>,
+}
+
+impl ActionWorker {
+    fn send(&mut self, job: Job) {
+        // Without a thread, or if it is gone (a job panicked), start one.
+        let job = match &self.tx {
+            Some(tx) => match tx.send(job) {
+                Ok(()) => return,
+                Err(mpsc::SendError(job)) => job,
+            },
+            None => job,
+        };
+        let (tx, rx) = mpsc::channel::<Job>();
+        let spawned = thread::Builder::new()
+            .name("alarm actions".to_string())
+            .spawn(move || rx.into_iter().for_each(Job::run));
+        match spawned {
+            Ok(_) => {
+                tx.send(job).ok();
+                self.tx = Some(tx);
+            }
+            Err(e) => eprintln!("⚠ Cannot start the alarm action thread: {}", e),
+        }
+    }
+}
+
+fn save_snapshot(
+    dir: &PathBuf,
+    event: &AlarmEvent,
+    image: &egui::ColorImage,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let secs = event
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!("alarm_{}_{}.png", event.rule_id, secs));
+    let [w, h] = image.size;
+    image::save_buffer(
+        &path,
+        image.as_raw(),
+        w as u32,
+        h as u32,
+        image::ColorType::Rgba8,
+    )?;
+    println!("📸 Alarm snapshot saved to {}", path.display());
+    Ok(())
+}
+
+fn append_log(path: &PathBuf, event: &AlarmEvent) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let secs = event
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    writeln!(
+        file,
+        "{:.3},{},{},{:.2}",
+        secs,
+        csv_field(&event.rule_name),
+        event.state_name(),
+        event.value
+    )?;
+    Ok(())
+}
+
+fn run_command(cmd: &str, event: &AlarmEvent) {
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    };
+    command
+        .env("ALARM_NAME", &event.rule_name)
+        .env("ALARM_STATE", event.state_name())
+        .env("ALARM_VALUE", format!("{:.2}", event.value));
+    match command.spawn() {
+        // Reap the child in the background so it does not linger as a zombie.
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("⚠ Alarm command failed to start: {}", e),
+    }
+}
+
+fn post_webhook(url: &str, body: &str) -> anyhow::Result<()> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("only http:// webhooks are supported"))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let mut stream = connect(&addr)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Minimal MQTT 3.1.1 client: CONNECT, PUBLISH (QoS 0), DISCONNECT.
+fn mqtt_publish(addr: &str, topic: &str, payload: &[u8]) -> anyhow::Result<()> {
+    fn remaining_length(mut len: usize, out: &mut Vec<u8>) {
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            out.push(byte);
+            if len == 0 {
+                break;
+            }
+        }
+    }
+    fn string(s: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    let mut stream = connect(addr)?;
+
+    let mut body = Vec::new();
+    string("MQTT", &mut body);
+    body.extend_from_slice(&[4, 0x02, 0, 30]); // level 4, clean session, keep-alive 30 s
+    string("thermoscope", &mut body);
+    let mut packet = vec![0x10];
+    remaining_length(body.len(), &mut packet);
+    packet.extend(body);
+    stream.write_all(&packet)?;
+
+    let mut connack = [0u8; 4];
+    std::io::Read::read_exact(&mut stream, &mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        anyhow::bail!("broker refused connection (code {})", connack[3]);
+    }
+
+    let mut body = Vec::new();
+    string(topic, &mut body);
+    body.extend_from_slice(payload);
+    let mut packet = vec![0x30];
+    remaining_length(body.len(), &mut packet);
+    packet.extend(body);
+    stream.write_all(&packet)?;
+    stream.write_all(&[0xE0, 0x00])?;
+    Ok(())
+}
+
+/// Connects to the first address of `addr` that answers within
+/// `NETWORK_TIMEOUT`; reads and writes time out likewise.
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, format!("{} not found", addr));
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, NETWORK_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+                stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::FrameContext;
+    use crate::tracking::{HotColdSpots, TrackedSpot};
+
+    /// A frame whose centre spot reads `temperature`.
+    fn frame(temperature: f32) -> ProcessedFrame {
+        FrameContext {
+            spots: HotColdSpots {
+                center: TrackedSpot {
+                    temperature,
+                    ..TrackedSpot::default()
+                },
+                ..HotColdSpots::default()
+            },
+            ..FrameContext::default()
+        }
+        .into_processed()
+    }
+
+    /// Feeds one (seconds, °C) sample per frame; the transitions by second.
+    fn run(rule: AlarmRule, samples: &[(f32, f32)]) -> Vec<(f32, AlarmState)> {
+        let mut engine = AlarmEngine::new();
+        engine.add(rule);
+        let start = Instant::now();
+        let mut transitions = Vec::new();
+        for &(t, value) in samples {
+            let now = start + Duration::from_secs_f32(t);
+            let status = engine.evaluate(&frame(value), &RoiSet::default(), now);
+            transitions.extend(status.events.iter().map(|e| (t, e.state)));
+        }
+        transitions
+    }
+
+    #[test]
+    fn hysteresis_delays_clearing() {
+        let mut rule = AlarmRule::new(0, "hot", AlarmSource::Center, Condition::Above(50.0));
+        rule.hysteresis = 2.0;
+        let samples = [45.0, 51.0, 49.0, 48.5, 47.9, 49.0, 50.5];
+        let samples: Vec<_> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (i as f32, v))
+            .collect();
+        assert_eq!(
+            run(rule, &samples),
+            [
+                (1.0, AlarmState::Raised),
+                (4.0, AlarmState::Cleared),
+                (6.0, AlarmState::Raised)
+            ]
+        );
+    }
+
+    #[test]
+    fn below_clears_above_the_band() {
+        let mut rule = AlarmRule::new(0, "cold", AlarmSource::Center, Condition::Below(5.0));
+        rule.hysteresis = 1.0;
+        let samples = [(0.0, 6.0), (1.0, 4.0), (2.0, 5.5), (3.0, 6.5)];
+        assert_eq!(
+            run(rule, &samples),
+            [(1.0, AlarmState::Raised), (3.0, AlarmState::Cleared)]
+        );
+    }
+
+    #[test]
+    fn min_duration_needs_an_unbroken_run() {
+        let mut rule = AlarmRule::new(0, "hot", AlarmSource::Center, Condition::Above(50.0));
+        rule.min_duration = Duration::from_secs(2);
+        // A dip at 2 s restarts the count.
+        let samples = [
+            (0.0, 60.0),
+            (1.0, 60.0),
+            (2.0, 40.0),
+            (3.0, 60.0),
+            (4.0, 60.0),
+            (5.0, 60.0),
+        ];
+        assert_eq!(run(rule, &samples), [(5.0, AlarmState::Raised)]);
+    }
+
+    #[test]
+    fn rate_of_rise_uses_the_window() {
+        let rule = AlarmRule::new(
+            0,
+            "rising",
+            AlarmSource::Center,
+            Condition::RateOfRise {
+                per_minute: 6.0,
+                window: Duration::from_secs(10),
+            },
+        );
+        // 0.5 °C/s (30 °C/min) for 10 s, then flat.
+        let samples: Vec<_> = (0..=30)
+            .map(|t| (t as f32, 20.0 + 0.5 * t.min(10) as f32))
+            .collect();
+        // The first rate needs half a window of history; the rate over the
+        // window then falls by 3 °C/min per second and clears below 5.
+        assert_eq!(
+            run(rule, &samples),
+            [(5.0, AlarmState::Raised), (19.0, AlarmState::Cleared)]
+        );
+    }
+
+    #[test]
+    fn region_rules_follow_their_roi() {
+        let mut engine = AlarmEngine::new();
+        engine.add(AlarmRule::new(
+            0,
+            "roi",
+            AlarmSource::Region(1),
+            Condition::Above(30.0),
+        ));
+        let mut hot = frame(0.0);
+        hot.frame.width = 4;
+        hot.frame.height = 4;
+        hot.temperature = vec![20.0; 16];
+        hot.temperature[5] = 40.0;
+        let mut rois = RoiSet::default();
+        let now = Instant::now();
+        assert!(engine.evaluate(&hot, &rois, now).active.is_empty());
+
+        let id = rois.add(Shape::Rect {
+            x0: 0.0,
+            y0: 0.0,
+            x1: 2.0,
+            y1: 2.0,
+        });
+        assert_eq!(id, 1);
+        assert_eq!(engine.evaluate(&hot, &rois, now).active, ["roi"]);
+        rois.get_mut(id).unwrap().shape = Shape::Spot { x: 3.0, y: 3.0 };
+        assert_eq!(
+            engine.evaluate(&hot, &rois, now).events[0].state,
+            AlarmState::Cleared
+        );
+
+        // Deleting the ROI of an active alarm clears it once.
+        rois.get_mut(id).unwrap().shape = Shape::Spot { x: 1.0, y: 1.0 };
+        assert_eq!(engine.evaluate(&hot, &rois, now).active, ["roi"]);
+        assert!(rois.remove(id));
+        let status = engine.evaluate(&hot, &rois, now);
+        assert!(status.active.is_empty());
+        assert_eq!(status.events.len(), 1);
+        assert_eq!(
+            (status.events[0].state, status.events[0].value),
+            (AlarmState::Cleared, 40.0)
+        );
+        assert!(!engine.is_active(1));
+        assert!(engine.evaluate(&hot, &rois, now).events.is_empty());
+    }
+
+    #[test]
+    fn log_lines_quote_the_rule_name() {
+        let path = std::env::temp_dir().join(format!("alarm-log-{}.csv", std::process::id()));
+        let event = AlarmEvent {
+            rule_id: 1,
+            rule_name: "Fuse \"A\", left\nbank".to_string(),
+            state: AlarmState::Raised,
+            value: 81.25,
+            timestamp: UNIX_EPOCH + Duration::from_secs(10),
+        };
+        append_log(&path, &event).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "10.000,\"Fuse \"\"A\"\", left\nbank\",raised,81.25\n");
+    }
+
+    #[test]
+    fn events_serialise_any_rule_name() {
+        let event = AlarmEvent {
+            rule_id: 1,
+            rule_name: "Fuse \"A\"\n\tleft\\bank".to_string(),
+            state: AlarmState::Cleared,
+            value: 81.25,
+            timestamp: UNIX_EPOCH + Duration::from_millis(10_500),
+        };
+        let json: serde_json::Value = serde_json::from_str(&event.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "rule_id": 1,
+                "rule": "Fuse \"A\"\n\tleft\\bank",
+                "state": "cleared",
+                "value": 81.25,
+                "timestamp": 10.5,
+            })
+        );
+    }
+
+    #[test]
+    fn rules_round_trip_through_toml() {
+        let mut rule = AlarmRule::new(
+            3,
+            "kiln",
+            AlarmSource::Region(2),
+            Condition::RateOfRise {
+                per_minute: 4.0,
+                window: Duration::from_secs(30),
+            },
+        );
+        rule.actions.push(AlarmAction::Mqtt {
+            host: "broker".to_string(),
+            port: 1883,
+            topic: "alarms".to_string(),
+        });
+        let text = toml::to_string(&rule).unwrap();
+        assert_eq!(toml::from_str::<AlarmRule>(&text).unwrap(), rule);
+    }
+}
@@ -0,0 +1,216 @@
+// REST control API, served by `server.rs` under /api.
+//
+//   GET    /api/openapi.json  OpenAPI 3 description, for generating clients
+//   GET    /api/state         palette, unit, range, calibration, NUC,
+//                             recordings, ROIs and alarms
+//   POST   /api/actions       any `control::Action`, e.g. {"action": "ffc"}
+//   PUT    /api/palette       {"palette": "WhiteHot"}
+//   PUT    /api/unit          {"unit": "Fahrenheit"}
+//   PUT    /api/range         {"range": "Auto"} or
+//                             {"range": {"Manual": {"level": 30, "span": 10}}}
+//   PUT    /api/calibration   {"emissivity": 0.95, "reflected_temp": 21.5}
+//   POST   /api/ffc           flat-field correction (software NUC)
+//   GET    /api/recording     active recordings
+//   POST   /api/recording     {"kind": "avi", "fps": 10}; DELETE stops
+//   GET    /api/rois          every ROI with its statistics on the next frame
+//   POST   /api/rois          {"name": "Fuse", "shape": {"type": "rect", ...}};
+//                             DELETE removes all
+//   GET    /api/rois/{id}     one ROI with statistics; DELETE removes it
+//   GET    /api/alarms        alarm rules and whether they are active
+//   POST   /api/alarms        {"name": "Fuse", "source": {"Region": 1},
+//                             "condition": {"Above": 80}}
+//   DELETE /api/alarms/{id}   removes a rule
+//
+// Every endpoint maps onto a `control::Action` run by the same `Controller`
+// the window uses. Bodies are JSON; errors are {"error": "..."} with a 4xx
+// status. Without a controller only the OpenAPI document is served.
+//
+// There are no CORS headers, so browsers only let pages served from the
+// camera itself read the API. Requests that change something must say
+// Content-Type: application/json, which a cross-site form cannot send, even
+// when their body is empty.
+
+use crate::bus::BusFrame;
+use crate::control::{Action, ControlError, Controller, Outcome};
+use crate::server::FRAME_TIMEOUT;
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+const OPENAPI: &str = include_str!("openapi.json");
+
+pub(crate) struct Response {
+    pub status: &'static str,
+    /// JSON, or empty.
+    pub body: String,
+}
+
+/// Answers a request for a path under /api.
+pub(crate) fn handle(
+    method: &str,
+    path: &str,
+    content_type: Option<&str>,
+    body: &[u8],
+    control: Option<&Controller>,
+) -> Response {
+    let method = if method == "HEAD" { "GET" } else { method };
+    if path == "/api/openapi.json" {
+        return match method {
+            "GET" => Response {
+                status: "200 OK",
+                body: OPENAPI.to_string(),
+            },
+            _ => error("405 Method Not Allowed", "GET only"),
+        };
+    }
+    let Some(control) = control else {
+        return error(
+            "403 Forbidden",
+            "Remote control is disabled; start the server with --control",
+        );
+    };
+    if method != "GET" && !is_json(content_type) {
+        return error(
+            "415 Unsupported Media Type",
+            "Send Content-Type: application/json",
+        );
+    }
+    route(method, path, body, control).unwrap_or_else(|e| {
+        let status = match e.downcast_ref::<ControlError>() {
+            Some(ControlError::Invalid(_)) => "400 Bad Request",
+            Some(ControlError::NotFound(_)) => "404 Not Found",
+            Some(ControlError::Conflict(_)) => "409 Conflict",
+            None if e.is::<NoFrames>() => "503 Service Unavailable",
+            None => "500 Internal Server Error",
+        };
+        error(status, &format!("{:#}", e))
+    })
+}
+
+fn route(method: &str, path: &str, body: &[u8], control: &Controller) -> Result<Response> {
+    let segments: Vec<&str> = path
+        .trim_start_matches("/api")
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    match (method, segments.as_slice()) {
+        ("GET", ["state"]) => json("200 OK", &control.state()),
+        ("POST", ["actions"]) => {
+            let action = serde_json::from_slice(body).map_err(invalid)?;
+            execute(control, action)
+        }
+        ("PUT", ["palette"]) => execute(control, tagged("set_palette", body)?),
+        ("PUT", ["unit"]) => execute(control, tagged("set_unit", body)?),
+        ("PUT", ["range"]) => execute(control, tagged("set_range", body)?),
+        ("PUT", ["calibration"]) => execute(control, tagged("set_calibration", body)?),
+        ("POST", ["ffc"]) => execute(control, Action::Ffc),
+        ("GET", ["recording"]) => json("200 OK", &control.state().recording),
+        ("POST", ["recording"]) => execute(control, tagged("start_recording", body)?),
+        ("DELETE", ["recording"]) => execute(control, tagged("stop_recording", body)?),
+        ("GET", ["rois"]) => {
+            let frame = next_frame(control)?;
+            json("200 OK", &control.readings(&frame))
+        }
+        ("POST", ["rois"]) => execute(control, tagged("add_roi", body)?),
+        ("DELETE", ["rois"]) => execute(control, Action::ClearRois),
+        ("GET", ["rois", id]) => {
+            let id = roi_id(id)?;
+            let frame = next_frame(control)?;
+            json("200 OK", &control.reading(id, &frame)?)
+        }
+        ("DELETE", ["rois", id]) => execute(control, Action::RemoveRoi { id: roi_id(id)? }),
+        ("GET", ["alarms"]) => json("200 OK", &control.state().alarms),
+        ("POST", ["alarms"]) => execute(control, tagged("add_alarm", body)?),
+        ("DELETE", ["alarms", id]) => execute(
+            control,
+            Action::RemoveAlarm {
+                id: id
+                    .parse()
+                    .map_err(|_| ControlError::NotFound(format!("No alarm {:?}", id)))?,
+            },
+        ),
+        (
+            _,
+            [
+                "state" | "actions" | "palette" | "unit" | "range" | "calibration" | "ffc"
+                | "recording" | "rois" | "alarms",
+            ]
+            | ["rois" | "alarms", _],
+        ) => Ok(error("405 Method Not Allowed", "Method not allowed here")),
+        _ => Ok(error("404 Not Found", "No such endpoint")),
+    }
+}
+
+fn execute(control: &Controller, action: Action) -> Result<Response> {
+    let outcome = control.execute(action)?;
+    let status = match outcome {
+        Outcome::Started { .. } | Outcome::Added { .. } => "201 Created",
+        Outcome::Done | Outcome::Stopped { .. } => "200 OK",
+    };
+    json(status, &outcome)
+}
+
+/// The body as the action `tag`; an empty body stands for `{}`.
+fn tagged(tag: &str, body: &[u8]) -> Result<Action> {
+    let mut value = if body.iter().all(u8::is_ascii_whitespace) {
+        serde_json::Value::Object(Default::default())
+    } else {
+        serde_json::from_slice(body).map_err(invalid)?
+    };
+    value
+        .as_object_mut()
+        .ok_or_else(|| ControlError::Invalid("Expected a JSON object".to_string()))?
+        .insert("action".to_string(), tag.into());
+    Ok(serde_json::from_value(value).map_err(invalid)?)
+}
+
+fn is_json(content_type: Option<&str>) -> bool {
+    content_type
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn roi_id(text: &str) -> Result<u32> {
+    Ok(text
+        .parse()
+        .map_err(|_| ControlError::NotFound(format!("No ROI {:?}", text)))?)
+}
+
+/// ROI readings are taken from the next frame, so they are never stale.
+fn next_frame(control: &Controller) -> Result<Arc<BusFrame>> {
+    Ok(control
+        .bus()
+        .subscribe()
+        .next(FRAME_TIMEOUT)
+        .ok_or(NoFrames)?)
+}
+
+fn json(status: &'static str, value: &impl Serialize) -> Result<Response> {
+    Ok(Response {
+        status,
+        body: serde_json::to_string(value)?,
+    })
+}
+
+fn error(status: &'static str, message: &str) -> Response {
+    Response {
+        status,
+        body: serde_json::json!({ "error": message }).to_string(),
+    }
+}
+
+fn invalid(e: serde_json::Error) -> ControlError {
+    ControlError::Invalid(format!("Invalid request: {}", e))
+}
+
+#[derive(Debug)]
+struct NoFrames;
+
+impl fmt::Display for NoFrames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("No frames")
+    }
+}
+
+impl std::error::Error for NoFrames {}
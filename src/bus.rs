@@ -0,0 +1,164 @@
+// Fan-out of processed frames to any number of readers.
+//
+// The engine's frame queue has a single consumer, the window. Readers that
+// want the latest frame at their own pace, like the HTTP server, subscribe to
+// the bus instead. The engine copies a frame onto the bus only while someone
+// is subscribed, so an idle bus costs nothing.
+
+use crate::calibration::Calibration;
+use crate::frame::ProcessedFrame;
+use crate::legend::Legend;
+use crate::snapshot;
+use crate::tracking::HotColdSpots;
+use eframe::egui;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// A frame as published on the bus. Shared between all subscribers.
+pub struct BusFrame {
+    pub sequence: u64,
+    /// Unix time of capture.
+    pub timestamp: f64,
+    pub width: usize,
+    pub height: usize,
+    /// Y16 counts after the pipeline (oriented, filtered), row-major.
+    pub raw: Vec<u16>,
+    /// °C for every pixel of `raw`.
+    pub temperature: Vec<f32>,
+    pub calibration: Calibration,
+    pub spots: HotColdSpots,
+    pub legend: Legend,
+    pub alarms: Vec<String>,
+    /// Display image, with the legend burnt in when exports carry it.
+    pub image: egui::ColorImage,
+    jpeg: OnceLock<Vec<u8>>,
+}
+
+impl BusFrame {
+    fn new(frame: &ProcessedFrame) -> Self {
+        Self {
+            sequence: frame.frame.sequence,
+            timestamp: frame.frame.unix_time(),
+            width: frame.frame.width,
+            height: frame.frame.height,
+            raw: frame.frame.raw.clone(),
+            temperature: frame.temperature.clone(),
+            calibration: frame.calibration,
+            spots: frame.spots,
+            legend: frame.legend,
+            alarms: frame.alarms.active.clone(),
+            image: frame.export_image(),
+            jpeg: OnceLock::new(),
+        }
+    }
+
+    /// `image` as JPEG, encoded by the first reader that asks for it.
+    pub fn jpeg(&self) -> &[u8] {
+        self.jpeg
+            .get_or_init(|| match snapshot::encode_jpeg(&self.image) {
+                Ok(jpeg) => jpeg,
+                Err(e) => {
+                    eprintln!("❌ JPEG encoding failed: {}", e);
+                    Vec::new()
+                }
+            })
+            .as_slice()
+    }
+
+    /// Mean temperature of the frame (°C).
+    pub fn mean(&self) -> f32 {
+        self.temperature.iter().sum::<f32>() / self.temperature.len().max(1) as f32
+    }
+}
+
+#[derive(Default)]
+struct Latest {
+    frame: Option<Arc<BusFrame>>,
+    /// Frames published so far; tells subscribers whether `frame` is new.
+    count: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Mutex<Latest>,
+    published: Condvar,
+    subscribers: AtomicUsize,
+}
+
+/// Handle to the bus; clones share it. See `ThermalEngine::bus`.
+#[derive(Clone, Default)]
+pub struct FrameBus {
+    shared: Arc<Shared>,
+}
+
+impl FrameBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts receiving frames; publishing stops again once every
+    /// subscription is dropped.
+    pub fn subscribe(&self) -> Subscription {
+        self.shared.subscribers.fetch_add(1, Ordering::Relaxed);
+        let seen = self.shared.latest.lock().unwrap().count;
+        Subscription {
+            bus: self.clone(),
+            seen,
+        }
+    }
+
+    /// The last frame published, if any; `None` until someone subscribed.
+    pub fn latest(&self) -> Option<Arc<BusFrame>> {
+        self.shared.latest.lock().unwrap().frame.clone()
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.shared.subscribers.load(Ordering::Relaxed)
+    }
+
+    /// Called by the engine for every processed frame.
+    pub fn publish(&self, frame: &ProcessedFrame) {
+        if self.subscribers() == 0 {
+            return;
+        }
+        let frame = Arc::new(BusFrame::new(frame));
+        let mut latest = self.shared.latest.lock().unwrap();
+        latest.frame = Some(frame);
+        latest.count += 1;
+        self.shared.published.notify_all();
+    }
+}
+
+pub struct Subscription {
+    bus: FrameBus,
+    /// `Latest::count` of the last frame returned.
+    seen: u64,
+}
+
+impl Subscription {
+    /// The next frame newer than the last one returned, or `None` if none
+    /// arrives within `timeout`. Frames published in between are skipped.
+    pub fn next(&mut self, timeout: Duration) -> Option<Arc<BusFrame>> {
+        let deadline = Instant::now() + timeout;
+        let mut latest = self.bus.shared.latest.lock().unwrap();
+        while latest.count == self.seen {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            latest = self
+                .bus
+                .shared
+                .published
+                .wait_timeout(latest, left)
+                .unwrap()
+                .0;
+        }
+        self.seen = latest.count;
+        latest.frame.clone()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.bus.shared.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
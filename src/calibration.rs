@@ -0,0 +1,155 @@
+// Conversion from raw Y16 counts to temperature.
+//
+// The HTI/InfiRay cores deliver Y16 as Kelvin * 64 for an ideal black body. On top
+// of that we apply the usual emissivity/reflected temperature correction using a
+// Stefan-Boltzmann (T^4) approximation of the radiance.
+
+use clap::ValueEnum;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 0 °C in Kelvin.
+pub const KELVIN: f32 = 273.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    /// Raw counts per Kelvin.
+    pub counts_per_kelvin: f32,
+    /// Additive correction in Kelvin (e.g. from a reference black body).
+    pub offset: f32,
+    /// Object emissivity, 0.01..=1.0.
+    pub emissivity: f32,
+    /// Reflected apparent temperature in °C.
+    pub reflected_temp: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            counts_per_kelvin: 64.0,
+            offset: 0.0,
+            emissivity: 1.0,
+            reflected_temp: 20.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// Black-body temperature (K) the sensor reports for `raw`, before the
+    /// emissivity correction.
+    pub fn apparent_kelvin(&self, raw: u16) -> f32 {
+        raw as f32 / self.counts_per_kelvin + self.offset
+    }
+
+    /// Inverse of `apparent_kelvin`.
+    pub fn raw_for_apparent(&self, kelvin: f32) -> u16 {
+        ((kelvin - self.offset) * self.counts_per_kelvin)
+            .round()
+            .clamp(0.0, u16::MAX as f32) as u16
+    }
+
+    pub fn to_celsius(&self, raw: u16) -> f32 {
+        let apparent_k = self.apparent_kelvin(raw);
+        let e = self.emissivity.clamp(0.01, 1.0);
+        if e >= 1.0 {
+            return apparent_k - KELVIN;
+        }
+        let refl_k = self.reflected_temp + KELVIN;
+        let object4 = (apparent_k.powi(4) - (1.0 - e) * refl_k.powi(4)) / e;
+        object4.max(0.0).powf(0.25) - KELVIN
+    }
+
+    /// Inverse of `to_celsius`, used to turn temperature thresholds into counts.
+    pub fn to_raw(&self, celsius: f32) -> u16 {
+        let e = self.emissivity.clamp(0.01, 1.0);
+        let object_k = celsius + KELVIN;
+        let apparent_k = if e >= 1.0 {
+            object_k
+        } else {
+            let refl_k = self.reflected_temp + KELVIN;
+            (e * object_k.powi(4) + (1.0 - e) * refl_k.powi(4)).powf(0.25)
+        };
+        self.raw_for_apparent(apparent_k)
+    }
+
+    pub fn temperature_field(&self, field: &[u16]) -> Vec<f32> {
+        let mut out = Vec::new();
+        self.temperature_field_into(field, &mut out);
+        out
+    }
+
+    /// `temperature_field` into `out`, reusing its allocation.
+    pub fn temperature_field_into(&self, field: &[u16], out: &mut Vec<f32>) {
+        out.clear();
+        out.resize(field.len(), 0.0);
+        #[cfg(feature = "parallel")]
+        out.par_iter_mut()
+            .zip(field.par_iter())
+            .for_each(|(t, &v)| *t = self.to_celsius(v));
+        #[cfg(not(feature = "parallel"))]
+        for (t, &v) in out.iter_mut().zip(field) {
+            *t = self.to_celsius(v);
+        }
+    }
+}
+
+/// On the command line: `c`, `f` or `k`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TemperatureUnit {
+    #[default]
+    #[value(name = "c")]
+    Celsius,
+    #[value(name = "f")]
+    Fahrenheit,
+    #[value(name = "k")]
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    pub const ALL: [TemperatureUnit; 3] = [
+        TemperatureUnit::Celsius,
+        TemperatureUnit::Fahrenheit,
+        TemperatureUnit::Kelvin,
+    ];
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+            TemperatureUnit::Kelvin => "K",
+        }
+    }
+
+    /// Converts an absolute temperature from °C.
+    pub fn from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 1.8 + 32.0,
+            TemperatureUnit::Kelvin => celsius + KELVIN,
+        }
+    }
+
+    /// Converts an absolute temperature to °C.
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => value,
+            TemperatureUnit::Fahrenheit => (value - 32.0) / 1.8,
+            TemperatureUnit::Kelvin => value - KELVIN,
+        }
+    }
+
+    /// Converts a temperature difference (span, rate) from °C.
+    pub fn delta_from_celsius(self, delta: f32) -> f32 {
+        match self {
+            TemperatureUnit::Fahrenheit => delta * 1.8,
+            _ => delta,
+        }
+    }
+
+    /// "12.3°C" style label for an absolute temperature given in °C.
+    pub fn format(self, celsius: f32) -> String {
+        format!("{:.1}{}", self.from_celsius(celsius), self.symbol())
+    }
+}
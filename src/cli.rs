@@ -0,0 +1,774 @@
+// Command-line interface.
+//
+// Without a subcommand (or with `stream` but no `--headless`) the binary opens
+// the window. The other subcommands drive the same engine, pipeline and file
+// formats without a display, for scripts, SSH sessions and services. Status
+// goes to stdout as text or CSV; errors end the process with a non-zero code.
+// The global options (and their THERMOSCOPE_* variables) override the
+// configuration file for this run.
+
+use crate::ThermalEngine;
+use crate::calibration::TemperatureUnit;
+use crate::config::{self, Config};
+use crate::control::{Action, RecordingKind};
+use crate::device::ThermalDevice;
+use crate::export::{self, ExportOptions, MatrixFormat};
+use crate::frame::ProcessedFrame;
+use crate::palette::Palette;
+use crate::pipeline::{FrameContext, PipelineConfig, SharedSettings, StageRegistry};
+use crate::queue::{self, QueueConfig, QueueReceiver, QueueSender};
+use crate::sequence::{self, SequenceReader};
+use crate::server::StreamServer;
+use crate::snapshot::{Snapshot, SnapshotFormat};
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a frame before giving up on the camera.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "thermoscope",
+    version,
+    about = "Thermal camera viewer and recorder"
+)]
+pub struct Cli {
+    #[command(flatten)]
+    pub options: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List USB devices.
+    List,
+    /// Describe a raw sequence (.tseq) or snapshot.
+    Info { file: PathBuf },
+    /// Show the configuration file and the effective settings.
+    Config {
+        /// Write the file, creating it with the built-in profiles.
+        #[arg(long)]
+        save: bool,
+    },
+    /// List the profiles.
+    Profiles,
+    /// Run the vendor unlock and hand the camera to the UVC driver.
+    Unlock,
+    /// Stream from the camera; opens the window unless --headless.
+    Stream {
+        /// Print status lines instead of opening a window.
+        #[arg(long)]
+        headless: bool,
+        /// Stop after this many seconds.
+        #[arg(long)]
+        duration: Option<f64>,
+        #[command(flatten)]
+        monitor: MonitorArgs,
+    },
+    /// Serve the stream over HTTP (MJPEG, JSON statistics, raw Y16) until
+    /// Ctrl-C.
+    Serve {
+        /// Address to listen on [default: from the configuration, 0.0.0.0:8080]
+        #[arg(long)]
+        bind: Option<String>,
+        /// Also serve the REST control API under /api; --control=false turns
+        /// it off [default: from the configuration]
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+        control: Option<bool>,
+        #[command(flatten)]
+        monitor: MonitorArgs,
+    },
+    /// Save one snapshot (colour image + 16-bit raw field).
+    Snapshot {
+        #[arg(long, value_enum, default_value_t = SnapshotKind::Png)]
+        format: SnapshotKind,
+        #[arg(long, default_value = "snapshots")]
+        out: PathBuf,
+        /// Frames to let pass first, so that AGC and filters settle.
+        #[arg(long, default_value_t = 10)]
+        skip: usize,
+    },
+    /// Record video or a raw sequence until Ctrl-C or --duration.
+    Record {
+        #[arg(long, value_enum, default_value_t = RecordingKind::Tseq)]
+        format: RecordingKind,
+        /// Stop after this many seconds.
+        #[arg(long)]
+        duration: Option<f64>,
+        /// Video frame rate (not used for .tseq, which keeps every frame).
+        #[arg(long, default_value_t = 25.0)]
+        fps: f32,
+        /// Do not burn the legend and spot readouts into video frames.
+        #[arg(long)]
+        no_overlays: bool,
+        /// Output file; defaults to recordings/<kind>_<time>.<ext>.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Play a .tseq recording through the pipeline without a window.
+    Replay {
+        file: PathBuf,
+        /// Playback rate; 1.0 is real time.
+        #[arg(long, default_value_t = 1.0)]
+        speed: f32,
+        /// Start over at the end until Ctrl-C.
+        #[arg(long = "loop")]
+        looping: bool,
+        #[command(flatten)]
+        monitor: MonitorArgs,
+    },
+    /// Export temperature fields of a .tseq recording or a snapshot, in the
+    /// unit of the profile or --unit.
+    Export {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = MatrixKind::Csv)]
+        format: MatrixKind,
+        /// Decimal places in CSV/TSV.
+        #[arg(long, default_value_t = 2)]
+        precision: usize,
+        /// First frame of a recording (0-based).
+        #[arg(long, default_value_t = 0)]
+        from: usize,
+        /// Last frame of a recording (inclusive); defaults to the end.
+        #[arg(long)]
+        to: Option<usize>,
+        /// Export every n-th frame. All frames still run through the pipeline.
+        #[arg(long, default_value_t = 1)]
+        step: usize,
+        #[arg(long, default_value = "exports")]
+        out: PathBuf,
+    },
+}
+
+/// Overrides of the configuration file, for every subcommand and the window.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ConfigArgs {
+    /// Configuration file [default: config.toml in the platform config
+    /// directory, e.g. ~/.config/thermoscope]
+    #[arg(long, global = true, env = "THERMOSCOPE_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Profile to start with.
+    #[arg(long, global = true, env = "THERMOSCOPE_PROFILE")]
+    pub profile: Option<String>,
+    /// USB vendor and product id, hex (e.g. 0bda:5830).
+    #[arg(long, global = true, env = "THERMOSCOPE_DEVICE", value_parser = parse_usb_id)]
+    pub device: Option<(u16, u16)>,
+    /// Generated frames instead of the camera, for trying things out.
+    #[arg(long, global = true, env = "THERMOSCOPE_SYNTHETIC")]
+    pub synthetic: bool,
+    /// Pipeline file (as saved from the window) instead of the profile's.
+    #[arg(long, global = true, env = "THERMOSCOPE_PIPELINE")]
+    pub pipeline: Option<PathBuf>,
+    #[arg(long, global = true, env = "THERMOSCOPE_PALETTE", value_enum)]
+    pub palette: Option<Palette>,
+    /// Unit of displayed temperatures.
+    #[arg(long, global = true, env = "THERMOSCOPE_UNIT", value_enum)]
+    pub unit: Option<TemperatureUnit>,
+}
+
+impl ConfigArgs {
+    /// The configuration file with these overrides applied.
+    pub fn load(&self) -> Result<Config> {
+        let mut config = Config::load_or_default(self.config.as_deref())?;
+        if let Some(profile) = &self.profile {
+            config.profile = Some(profile.clone());
+        }
+        if let Some(id) = self.device {
+            config.device.id = id;
+        }
+        if self.synthetic {
+            config.device.synthetic = true;
+        }
+        if let Some(path) = &self.pipeline {
+            config.overrides.pipeline = Some(PipelineConfig::load(path)?);
+        }
+        config.overrides.palette = self.palette;
+        config.overrides.unit = self.unit;
+        // Fail early on an unknown profile rather than when it is applied.
+        config.active()?;
+        Ok(config)
+    }
+}
+
+#[derive(Args, Debug, Clone, Copy)]
+pub struct MonitorArgs {
+    /// Seconds between status lines; 0 prints every frame.
+    #[arg(long, default_value_t = 1.0)]
+    pub every: f64,
+    /// Status lines as CSV (temperatures in °C).
+    #[arg(long)]
+    pub csv: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SnapshotKind {
+    Png,
+    Jpeg,
+    /// FLIR-compatible radiometric JPEG.
+    Radiometric,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum MatrixKind {
+    Csv,
+    Tsv,
+    Npy,
+}
+
+impl From<SnapshotKind> for SnapshotFormat {
+    fn from(kind: SnapshotKind) -> Self {
+        match kind {
+            SnapshotKind::Png => SnapshotFormat::Png,
+            SnapshotKind::Jpeg => SnapshotFormat::Jpeg,
+            SnapshotKind::Radiometric => SnapshotFormat::Radiometric,
+        }
+    }
+}
+
+impl From<MatrixKind> for MatrixFormat {
+    fn from(kind: MatrixKind) -> Self {
+        match kind {
+            MatrixKind::Csv => MatrixFormat::Csv,
+            MatrixKind::Tsv => MatrixFormat::Tsv,
+            MatrixKind::Npy => MatrixFormat::Npy,
+        }
+    }
+}
+
+fn parse_usb_id(text: &str) -> Result<(u16, u16), String> {
+    config::parse_usb_id(text).map_err(|e| format!("{:#}", e))
+}
+
+/// Runs a subcommand that does not need the window.
+pub fn run(command: Command, config: &Config) -> Result<()> {
+    match command {
+        Command::List => list(),
+        Command::Info { file } => info(&file),
+        Command::Config { save } => show_config(config, save),
+        Command::Profiles => profiles(config),
+        Command::Unlock => {
+            let (vid, pid) = config.device.id;
+            ThermalDevice::standalone_unlock(vid, pid)?;
+            println!("✅ {} unlocked.", config.device.name());
+            Ok(())
+        }
+        Command::Stream {
+            duration, monitor, ..
+        } => stream(config, duration, monitor),
+        Command::Serve {
+            bind,
+            control,
+            monitor,
+        } => serve(config, bind.as_deref(), control, monitor),
+        Command::Snapshot { format, out, skip } => snapshot(config, format.into(), &out, skip),
+        Command::Record {
+            format,
+            duration,
+            fps,
+            no_overlays,
+            output,
+        } => record(config, format, duration, fps, !no_overlays, output),
+        Command::Replay {
+            file,
+            speed,
+            looping,
+            monitor,
+        } => replay(config, &file, speed, looping, monitor),
+        Command::Export {
+            file,
+            format,
+            precision,
+            from,
+            to,
+            step,
+            out,
+        } => export(
+            config,
+            &file,
+            format.into(),
+            ExportOptions {
+                unit: config.active()?.unit.unwrap_or_default(),
+                precision,
+            },
+            (from, to, step),
+            &out,
+        ),
+    }
+}
+
+fn is_sequence(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == sequence::EXTENSION)
+}
+
+fn list() -> Result<()> {
+    for device in ThermalDevice::list_devices()? {
+        println!("{}", device);
+    }
+    Ok(())
+}
+
+fn show_config(config: &Config, save: bool) -> Result<()> {
+    if save {
+        let path = config.save()?;
+        println!("✅ Configuration saved to {}", path.display());
+        return Ok(());
+    }
+    match &config.path {
+        Some(path) if path.exists() => println!("# {}", path.display()),
+        Some(path) => println!("# {} (not created yet; built-in defaults)", path.display()),
+        None => println!("# No configuration directory; built-in defaults"),
+    }
+    print!("{}", config.to_toml()?);
+    println!("\n# Effective settings");
+    let active = config.active()?;
+    println!(
+        "# profile: {}",
+        config.profile.as_deref().unwrap_or("(none)")
+    );
+    if let Some(palette) = active.palette {
+        println!("# palette: {}", palette.name());
+    }
+    if let Some(unit) = active.unit {
+        println!("# unit: {}", unit.symbol());
+    }
+    if let Some(calibration) = active.calibration {
+        println!(
+            "# emissivity {:.2}, reflected {:.1} °C",
+            calibration.emissivity, calibration.reflected_temp
+        );
+    }
+    for rule in active.alarms.iter().flatten() {
+        println!("# alarm: {}", rule.name);
+    }
+    Ok(())
+}
+
+fn profiles(config: &Config) -> Result<()> {
+    for (name, profile) in &config.profiles {
+        let marker = if config.profile.as_deref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {:<12} {}", marker, name, profile.description);
+    }
+    Ok(())
+}
+
+fn info(path: &Path) -> Result<()> {
+    if is_sequence(path) {
+        let mut reader = SequenceReader::open(path)?;
+        let info = reader.info().clone();
+        println!("File:        {}", path.display());
+        println!("Device:      {}", info.device);
+        println!("Started:     {:.3} (Unix time)", info.started);
+        println!("Frames:      {}", reader.len());
+        let duration = reader.duration().as_secs_f64();
+        println!("Duration:    {:.2} s", duration);
+        if duration > 0.0 {
+            println!(
+                "Frame rate:  {:.2} fps",
+                (reader.len() - 1) as f64 / duration
+            );
+        }
+        if !reader.is_empty() {
+            let frame = reader.frame(0)?;
+            let size = std::fs::metadata(path)?.len();
+            let raw = reader.len() as u64 * frame.raw.len() as u64 * 2;
+            println!("Frame size:  {}x{}", frame.width, frame.height);
+            println!(
+                "File size:   {:.1} MB ({:.1}:1)",
+                size as f64 / 1e6,
+                raw as f64 / size.max(1) as f64
+            );
+            let changes = (1..reader.len())
+                .filter(|&n| reader.calibration(n) != reader.calibration(n - 1))
+                .count();
+            if let Some(calibration) = reader.calibration(0) {
+                println!(
+                    "Calibration: emissivity {:.2}, reflected {:.1} °C ({} changes)",
+                    calibration.emissivity, calibration.reflected_temp, changes
+                );
+            }
+        }
+        println!(
+            "Indexed:     {}",
+            if reader.is_indexed() {
+                "yes"
+            } else {
+                "no (recovered by scanning)"
+            }
+        );
+        return Ok(());
+    }
+
+    let snapshot = Snapshot::load(path)?;
+    print!("{}", snapshot.metadata.to_toml()?);
+    let temperature = snapshot.temperature();
+    let (min, max) = temperature
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &t| (lo.min(t), hi.max(t)));
+    let mean = temperature.iter().sum::<f32>() / temperature.len().max(1) as f32;
+    println!(
+        "# min {:.2} °C, max {:.2} °C, mean {:.2} °C",
+        min, max, mean
+    );
+    Ok(())
+}
+
+/// An engine fed by the camera or a recording, with the consumer end of its
+/// frame queue.
+struct Headless {
+    engine: ThermalEngine,
+    frames: QueueReceiver<ProcessedFrame>,
+    recycler: QueueSender<ProcessedFrame>,
+}
+
+impl Headless {
+    /// An engine set up from `config`, not yet started.
+    fn new(config: &Config) -> Result<Self> {
+        let (tx, frames) = queue::bounded(QueueConfig::default());
+        let engine = ThermalEngine::new(tx).with_stream_mode(config.device.stream);
+        engine.apply_profile(&config.active()?, &StageRegistry::new())?;
+        let recycler = engine.recycler();
+        Ok(Self {
+            engine,
+            frames,
+            recycler,
+        })
+    }
+
+    fn camera(config: &Config) -> Result<Self> {
+        let headless = Self::new(config)?;
+        config.device.start(&headless.engine);
+        Ok(headless)
+    }
+
+    /// The next processed frame; fails if none arrives within `FRAME_TIMEOUT`.
+    fn next(&self) -> Result<ProcessedFrame> {
+        let deadline = Instant::now() + FRAME_TIMEOUT;
+        loop {
+            if let Some(frame) = self.frames.try_recv() {
+                return Ok(frame);
+            }
+            if Instant::now() > deadline {
+                bail!("No frames for {} s", FRAME_TIMEOUT.as_secs());
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    fn recycle(&self, frame: ProcessedFrame) {
+        let _ = self.recycler.push(frame);
+    }
+}
+
+/// Set once Ctrl-C was pressed.
+fn interrupted() -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    let set = flag.clone();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build();
+        if let Ok(runtime) = runtime
+            && runtime.block_on(tokio::signal::ctrl_c()).is_ok()
+        {
+            set.store(true, Ordering::Relaxed);
+        }
+    });
+    flag
+}
+
+/// Periodic status lines.
+struct Monitor {
+    args: MonitorArgs,
+    start: Instant,
+    last: Option<Instant>,
+    frames: u32,
+}
+
+impl Monitor {
+    fn new(args: MonitorArgs) -> Self {
+        if args.csv {
+            println!("time_s,frame,min_c,max_c,centre_c,fps,alarms");
+        }
+        Self {
+            args,
+            start: Instant::now(),
+            last: None,
+            frames: 0,
+        }
+    }
+
+    fn report(&mut self, frame: &ProcessedFrame) {
+        self.frames += 1;
+        let now = Instant::now();
+        let since = now
+            .duration_since(self.last.unwrap_or(self.start))
+            .as_secs_f64();
+        if self.last.is_some() && since < self.args.every {
+            return;
+        }
+        let fps = if since > 0.0 {
+            self.frames as f64 / since
+        } else {
+            0.0
+        };
+        let (time, spots) = (now.duration_since(self.start).as_secs_f64(), &frame.spots);
+        let alarms = frame.alarms.active.join(";");
+        if self.args.csv {
+            println!(
+                "{:.3},{},{:.2},{:.2},{:.2},{:.1},{}",
+                time,
+                frame.frame.sequence,
+                spots.cold.temperature,
+                spots.hot.temperature,
+                spots.center.temperature,
+                fps,
+                alarms
+            );
+        } else {
+            let unit = frame.legend.unit;
+            println!(
+                "{:7.1} s  #{:<6} min {:>8}  max {:>8}  centre {:>8}  {:5.1} fps{}",
+                time,
+                frame.frame.sequence,
+                unit.format(spots.cold.temperature),
+                unit.format(spots.hot.temperature),
+                unit.format(spots.center.temperature),
+                fps,
+                if alarms.is_empty() {
+                    String::new()
+                } else {
+                    format!("  ⚠ {}", alarms)
+                }
+            );
+        }
+        self.last = Some(now);
+        self.frames = 0;
+    }
+}
+
+fn stream(config: &Config, duration: Option<f64>, monitor: MonitorArgs) -> Result<()> {
+    let headless = Headless::camera(config)?;
+    let stop = interrupted();
+    let end = duration.map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    let mut monitor = Monitor::new(monitor);
+    while !stop.load(Ordering::Relaxed) && end.is_none_or(|end| Instant::now() < end) {
+        let frame = headless.next()?;
+        monitor.report(&frame);
+        headless.recycle(frame);
+    }
+    Ok(())
+}
+
+fn serve(
+    config: &Config,
+    bind: Option<&str>,
+    control: Option<bool>,
+    monitor: MonitorArgs,
+) -> Result<()> {
+    let headless = Headless::camera(config)?;
+    let controller = headless.engine.controller(&config.device.name());
+    let server = StreamServer::start(
+        bind.unwrap_or(&config.server.bind),
+        headless.engine.bus(),
+        control
+            .unwrap_or(config.server.control)
+            .then(|| controller.clone()),
+    )?;
+    let stop = interrupted();
+    let mut monitor = Monitor::new(monitor);
+    while !stop.load(Ordering::Relaxed) {
+        let frame = headless.next()?;
+        monitor.report(&frame);
+        headless.recycle(frame);
+    }
+    server.stop();
+    // Recordings started through the API.
+    controller.execute(Action::StopRecording { kind: None })?;
+    controller.wait()
+}
+
+fn snapshot(config: &Config, format: SnapshotFormat, out: &Path, skip: usize) -> Result<()> {
+    let headless = Headless::camera(config)?;
+    for _ in 0..skip {
+        let frame = headless.next()?;
+        headless.recycle(frame);
+    }
+    let frame = headless.next()?;
+    let path = Snapshot::capture(&frame, &config.device.name()).save(
+        &frame.export_image(),
+        out,
+        format,
+    )?;
+    println!("📸 Snapshot saved to {}", path.display());
+    Ok(())
+}
+
+fn record(
+    config: &Config,
+    kind: RecordingKind,
+    duration: Option<f64>,
+    fps: f32,
+    burn_overlays: bool,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let headless = Headless::camera(config)?;
+    // Only start writing once the camera delivers.
+    let frame = headless.next()?;
+    headless.recycle(frame);
+
+    let controller = headless.engine.controller(&config.device.name());
+    controller.start_recording(kind, output, Some(fps), Some(burn_overlays))?;
+
+    let recorder = headless.engine.recorder();
+    let stop = interrupted();
+    let end = duration.map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    while !stop.load(Ordering::Relaxed) && end.is_none_or(|end| Instant::now() < end) {
+        let frame = headless.next()?;
+        headless.recycle(frame);
+        if recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|r| r.is_full())
+        {
+            break;
+        }
+    }
+
+    controller.execute(Action::StopRecording { kind: None })?;
+    controller.wait()
+}
+
+fn replay(
+    config: &Config,
+    path: &Path,
+    speed: f32,
+    looping: bool,
+    monitor: MonitorArgs,
+) -> Result<()> {
+    let headless = Headless::new(config)?;
+    let player = headless.engine.player();
+    let playback = player.open(path)?;
+    {
+        let mut state = playback.lock().unwrap();
+        state.speed = speed;
+        state.looping = looping;
+    }
+    let stop = interrupted();
+    let mut monitor = Monitor::new(monitor);
+    while !stop.load(Ordering::Relaxed) {
+        let frame = headless.next()?;
+        monitor.report(&frame);
+        headless.recycle(frame);
+        let state = playback.lock().unwrap();
+        if state.is_closed() || (!state.playing && state.position + 1 >= state.len) {
+            break;
+        }
+    }
+    player.close();
+    Ok(())
+}
+
+fn export(
+    config: &Config,
+    path: &Path,
+    format: MatrixFormat,
+    options: ExportOptions,
+    (from, to, step): (usize, Option<usize>, usize),
+    out: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(out).with_context(|| format!("Cannot create {}", out.display()))?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if !is_sequence(path) {
+        let snapshot = Snapshot::load(path)?;
+        let target = out.join(format!("{}.{}", stem, format.extension()));
+        let (width, height) = (snapshot.frame.width, snapshot.frame.height);
+        export::write_matrix(
+            &target,
+            &snapshot.temperature(),
+            width,
+            height,
+            format,
+            options,
+        )?;
+        println!("📄 Exported {}", target.display());
+        return Ok(());
+    }
+
+    let mut reader = SequenceReader::open(path)?;
+    if reader.is_empty() {
+        bail!("{} contains no frames", path.display());
+    }
+    let to = to.unwrap_or(reader.len() - 1).min(reader.len() - 1);
+    if from > to {
+        bail!("No frames in {}..={}", from, to);
+    }
+    // The same processing as playback, on a private set of settings.
+    let settings = SharedSettings::default();
+    let mut pipeline = config.active()?.build(&settings, &StageRegistry::new())?;
+    let mut written = 0;
+    for n in from..=to {
+        if let Some(calibration) = reader.calibration(n) {
+            *settings.calibration.lock().unwrap() = calibration;
+        }
+        let mut ctx = FrameContext {
+            frame: reader.frame(n)?,
+            ..FrameContext::default()
+        };
+        pipeline.process(&mut ctx);
+        if (n - from) % step.max(1) != 0 {
+            continue;
+        }
+        let target = out.join(format!("{}_{:06}.{}", stem, n, format.extension()));
+        let (width, height) = (ctx.frame.width, ctx.frame.height);
+        export::write_matrix(&target, &ctx.temperature, width, height, format, options)?;
+        written += 1;
+    }
+    println!("📄 Exported {} frames to {}", written, out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(args: &[&str]) -> Option<bool> {
+        let cli = Cli::try_parse_from(["thermoscope", "serve"].iter().chain(args)).unwrap();
+        match cli.command {
+            Some(Command::Serve { control, .. }) => control,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn control_flag_overrides_the_configuration_both_ways() {
+        assert_eq!(control(&[]), None);
+        assert_eq!(control(&["--control"]), Some(true));
+        assert_eq!(control(&["--control=false"]), Some(false));
+        assert_eq!(control(&["--control=true"]), Some(true));
+        // Without `=` the next word is not taken as the value.
+        assert!(Cli::try_parse_from(["thermoscope", "serve", "--control", "false"]).is_err());
+    }
+
+    #[test]
+    fn palette_and_unit_parse_into_the_display_types() {
+        let cli =
+            Cli::try_parse_from(["thermoscope", "--palette", "black-hot", "--unit", "f"]).unwrap();
+        assert_eq!(cli.options.palette, Some(Palette::BlackHot));
+        assert_eq!(cli.options.unit, Some(TemperatureUnit::Fahrenheit));
+        assert!(Cli::try_parse_from(["thermoscope", "--unit", "celsius"]).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+// Persistent configuration and named profiles.
+//
+// `config.toml` in the platform config directory holds the camera selection,
+// the window layout, `[defaults]` and any number of `[profiles.<name>]`. A
+// profile bundles what depends on the job at hand: pipeline, calibration,
+// palette, unit, range and alarm rules. The effective settings are, from weakest to
+// strongest: built-in defaults, `[defaults]`, the selected profile, then
+// environment and command-line overrides (see `cli.rs`).
+
+use crate::ThermalEngine;
+use crate::alarm::AlarmRule;
+use crate::calibration::{Calibration, TemperatureUnit};
+use crate::display::RangeMode;
+use crate::palette::Palette;
+use crate::pipeline::{Pipeline, PipelineConfig, SharedSettings, StageRegistry};
+use crate::uvc_adapter::StreamMode;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Settings that depend on what is being inspected. Unset fields fall back to
+/// `[defaults]`, then to the built-in values.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub description: String,
+    pub palette: Option<Palette>,
+    pub unit: Option<TemperatureUnit>,
+    pub range: Option<RangeMode>,
+    pub calibration: Option<Calibration>,
+    /// `None` runs the standard pipeline.
+    pub pipeline: Option<PipelineConfig>,
+    /// `None` runs without alarms. Region rules refer to ROIs by id.
+    pub alarms: Option<Vec<AlarmRule>>,
+}
+
+impl Profile {
+    /// `self`, with unset fields taken from `base`.
+    pub fn over(&self, base: &Profile) -> Profile {
+        Profile {
+            description: if self.description.is_empty() {
+                base.description.clone()
+            } else {
+                self.description.clone()
+            },
+            palette: self.palette.or(base.palette),
+            unit: self.unit.or(base.unit),
+            range: self.range.or(base.range),
+            calibration: self.calibration.or(base.calibration),
+            pipeline: self.pipeline.clone().or_else(|| base.pipeline.clone()),
+            alarms: self.alarms.clone().or_else(|| base.alarms.clone()),
+        }
+    }
+
+    /// Builds the profile's pipeline on `settings` and applies its calibration
+    /// and display settings. Whatever the profile leaves unset goes back to
+    /// the built-in default, so switching profiles does not depend on the
+    /// previous one. On error `settings` are left as they were.
+    pub fn build(&self, settings: &SharedSettings, registry: &StageRegistry) -> Result<Pipeline> {
+        let reset = || {
+            *settings.calibration.lock().unwrap() = Calibration::default();
+            let mut display = settings.display.lock().unwrap();
+            display.palette = Palette::default();
+            display.unit = TemperatureUnit::default();
+            display.range = RangeMode::default();
+        };
+        // Stages from the pipeline config may set calibration and display
+        // settings themselves; the explicit fields below win.
+        let pipeline = match &self.pipeline {
+            Some(config) => Pipeline::from_config_with(config, settings, registry, reset)?,
+            None => {
+                reset();
+                Pipeline::standard(settings)
+            }
+        };
+        if let Some(calibration) = self.calibration {
+            *settings.calibration.lock().unwrap() = calibration;
+        }
+        let mut display = settings.display.lock().unwrap();
+        if let Some(palette) = self.palette {
+            display.palette = palette;
+        }
+        if let Some(unit) = self.unit {
+            display.unit = unit;
+        }
+        if let Some(range) = self.range {
+            display.range = range;
+        }
+        Ok(pipeline)
+    }
+
+    /// The current state of `pipeline`, `settings` and the alarm `rules`.
+    /// Locks the pipeline before the settings, like the engine.
+    pub fn capture(
+        pipeline: &Pipeline,
+        settings: &SharedSettings,
+        rules: &[AlarmRule],
+        description: &str,
+    ) -> Self {
+        let config = pipeline.config();
+        let display = settings.display.lock().unwrap().clone();
+        Profile {
+            description: description.to_string(),
+            palette: Some(display.palette),
+            unit: Some(display.unit),
+            range: Some(display.range),
+            calibration: Some(*settings.calibration.lock().unwrap()),
+            pipeline: Some(config),
+            alarms: Some(rules.to_vec()),
+        }
+    }
+}
+
+/// Profiles that exist even without a config file. A profile of the same name
+/// in the file replaces the built-in one.
+pub fn builtin_profiles() -> BTreeMap<String, Profile> {
+    let profile = |description: &str, palette, range, emissivity, reflected_temp| Profile {
+        description: description.to_string(),
+        palette: Some(palette),
+        unit: Some(TemperatureUnit::Celsius),
+        range: Some(range),
+        calibration: Some(Calibration {
+            emissivity,
+            reflected_temp,
+            ..Calibration::default()
+        }),
+        pipeline: None,
+        alarms: None,
+    };
+    BTreeMap::from([
+        (
+            "electrical".to_string(),
+            profile(
+                "Switchgear, cables and connections: hot-spot search on painted or insulated parts",
+                Palette::Ironbow,
+                RangeMode::Auto,
+                0.95,
+                20.0,
+            ),
+        ),
+        (
+            "building".to_string(),
+            profile(
+                "Walls, windows and insulation: small differences around room temperature",
+                Palette::Ironbow,
+                RangeMode::manual(18.0, 12.0),
+                0.93,
+                20.0,
+            ),
+        ),
+        (
+            "medical".to_string(),
+            profile(
+                "Skin temperature, fixed 31-39 °C range",
+                Palette::WhiteHot,
+                RangeMode::manual(35.0, 8.0),
+                0.98,
+                23.0,
+            ),
+        ),
+    ])
+}
+
+/// Which camera to open and how.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// USB vendor and product id, `"vvvv:pppp"` in hex.
+    #[serde(with = "usb_id")]
+    pub id: (u16, u16),
+    /// Generated frames instead of the camera (`SyntheticSource`).
+    pub synthetic: bool,
+    #[serde(flatten)]
+    pub stream: StreamMode,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            id: (0x0bda, 0x5830),
+            synthetic: false,
+            stream: StreamMode::default(),
+        }
+    }
+}
+
+impl DeviceConfig {
+    /// Starts `engine` on the camera or on generated frames.
+    pub fn start(&self, engine: &ThermalEngine) {
+        if self.synthetic {
+            engine.start_synthetic();
+        } else {
+            engine.start(self.id.0, self.id.1);
+        }
+    }
+
+    /// `0bda:5830` style name, as stored in file metadata.
+    pub fn name(&self) -> String {
+        format!("{:04x}:{:04x}", self.id.0, self.id.1)
+    }
+}
+
+/// Parses `VID:PID` in hex.
+pub fn parse_usb_id(text: &str) -> Result<(u16, u16)> {
+    let (vid, pid) = text
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected VID:PID, got {:?}", text))?;
+    let hex = |s: &str| u16::from_str_radix(s, 16).with_context(|| format!("{:?}", s));
+    Ok((hex(vid)?, hex(pid)?))
+}
+
+mod usb_id {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &(u16, u16), serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:04x}:{:04x}", id.0, id.1))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(u16, u16), D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_usb_id(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The embedded HTTP server (`server.rs`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address and port to listen on; 0.0.0.0 makes it reachable on the LAN.
+    pub bind: String,
+    /// Also serve the control API (`api.rs`), which lets anyone who can reach
+    /// the server change settings and start recordings.
+    pub control: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:8080".to_string(),
+            control: false,
+        }
+    }
+}
+
+/// Window size and which overlays are shown.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    pub window: [f32; 2],
+    pub show_spots: bool,
+    pub show_center: bool,
+    pub show_legend: bool,
+    pub show_stats: bool,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            window: [1024.0, 768.0],
+            show_spots: true,
+            show_center: true,
+            show_legend: true,
+            show_stats: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Profile applied at start-up; `None` uses `[defaults]` alone.
+    pub profile: Option<String>,
+    pub device: DeviceConfig,
+    pub ui: UiConfig,
+    pub server: ServerConfig,
+    /// Under every profile.
+    pub defaults: Profile,
+    pub profiles: BTreeMap<String, Profile>,
+    /// Environment and command-line overrides, above every profile. Not saved.
+    #[serde(skip)]
+    pub overrides: Profile,
+    /// Where the configuration was loaded from and is saved to.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            device: DeviceConfig::default(),
+            ui: UiConfig::default(),
+            server: ServerConfig::default(),
+            defaults: Profile::default(),
+            profiles: builtin_profiles(),
+            overrides: Profile::default(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    pub const FILE: &'static str = "config.toml";
+
+    /// `thermoscope` in the platform's configuration directory.
+    pub fn dir() -> Option<PathBuf> {
+        let var = |name| std::env::var_os(name).map(PathBuf::from);
+        let base = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library/Application Support"))
+        } else {
+            var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+        };
+        base.map(|dir| dir.join("thermoscope"))
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        Self::dir().map(|dir| dir.join(Self::FILE))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let mut config: Config = toml::from_str(text)?;
+        for (name, profile) in builtin_profiles() {
+            config.profiles.entry(name).or_insert(profile);
+        }
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        let mut config =
+            Self::from_toml(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// `path` if given, else the file in `dir()`; built-in defaults when that
+    /// file does not exist yet.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::load(path);
+        }
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            path => Ok(Config {
+                path,
+                ..Config::default()
+            }),
+        }
+    }
+
+    /// Writes to `path`, creating its directory.
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| anyhow!("No configuration directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        std::fs::write(&path, self.to_toml()?)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The effective settings under profile `name` (`None` for `[defaults]`
+    /// alone), with the overrides on top.
+    pub fn resolve(&self, name: Option<&str>) -> Result<Profile> {
+        let profile = match name {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown profile '{}'", name))?
+                .over(&self.defaults),
+            None => self.defaults.clone(),
+        };
+        Ok(self.overrides.over(&profile))
+    }
+
+    /// The effective settings under the selected profile.
+    pub fn active(&self) -> Result<Profile> {
+        self.resolve(self.profile.as_deref())
+    }
+}
@@ -0,0 +1,792 @@
+// Command layer shared by the window, the command line and the control API.
+//
+// Every change a client can make to a running engine is an `Action`, carried
+// out by a `Controller` on the engine's shared handles. The window, `record`
+// and the REST API (`api.rs`) all go through it, so a palette switch or a
+// recording behaves the same whichever side asked for it. Actions are tagged
+// for JSON: `{"action": "set_palette", "palette": "WhiteHot"}`.
+//
+// `Ffc` runs the pipeline's software NUC (`NucStage`). The supported cores
+// have no shutter command over USB, so the lens must see a uniform scene
+// (lens cap) while it collects its frames.
+
+use crate::ThermalEngine;
+use crate::alarm::{AlarmAction, AlarmEngine, AlarmRule, AlarmSource, Condition};
+use crate::bus::{BusFrame, FrameBus};
+use crate::calibration::{Calibration, TemperatureUnit};
+use crate::display::RangeMode;
+use crate::filters::FilterConfig;
+use crate::isotherm::{Isotherm, IsothermBackground, IsothermMode};
+use crate::measure::{Roi, RoiSet, RoiStats, Shape};
+use crate::orientation::Orientation;
+use crate::palette::Palette;
+use crate::pipeline::{Pipeline, SharedSettings};
+use crate::recording::{Recorder, RecordingConfig, RecordingFormat};
+use crate::sequence::SequenceRecorder;
+use crate::stages::NucStage;
+use crate::upscale::Upscaler;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Where recordings go unless a path is given.
+const RECORDINGS: &str = "recordings";
+
+/// Frame that ROIs are checked against before the first frame is published.
+const DEFAULT_FRAME: (usize, usize) = (640, 512);
+
+/// Most points a line ROI may have.
+const MAX_LINE_POINTS: usize = 256;
+
+/// What a recording writes.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingKind {
+    /// Lossless raw sequence.
+    #[default]
+    Tseq,
+    Avi,
+    Gif,
+    Apng,
+}
+
+impl RecordingKind {
+    /// The video format; `None` for raw sequences.
+    pub fn format(self) -> Option<RecordingFormat> {
+        match self {
+            RecordingKind::Tseq => None,
+            RecordingKind::Avi => Some(RecordingFormat::MjpegAvi),
+            RecordingKind::Gif => Some(RecordingFormat::Gif),
+            RecordingKind::Apng => Some(RecordingFormat::Apng),
+        }
+    }
+}
+
+impl From<RecordingFormat> for RecordingKind {
+    fn from(format: RecordingFormat) -> Self {
+        match format {
+            RecordingFormat::MjpegAvi => RecordingKind::Avi,
+            RecordingFormat::Gif => RecordingKind::Gif,
+            RecordingFormat::Apng => RecordingKind::Apng,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    SetPalette {
+        palette: Palette,
+    },
+    SetUnit {
+        unit: TemperatureUnit,
+    },
+    SetRange {
+        range: RangeMode,
+    },
+    /// Unset fields keep their value.
+    SetCalibration {
+        #[serde(default)]
+        emissivity: Option<f32>,
+        /// °C.
+        #[serde(default)]
+        reflected_temp: Option<f32>,
+    },
+    /// Denoising of the raw field; unset fields keep their value.
+    SetDenoise {
+        /// The filter chain in order; empty turns denoising off.
+        #[serde(default)]
+        filters: Option<Vec<FilterConfig>>,
+        /// Measurements read the denoised field instead of the raw one.
+        #[serde(default)]
+        use_for_measurement: Option<bool>,
+    },
+    /// Replaces the isotherms; an empty list turns them off.
+    SetIsotherms {
+        isotherms: Vec<Isotherm>,
+        #[serde(default)]
+        background: Option<IsothermBackground>,
+    },
+    /// Burn the colour legend into exported images.
+    SetBurnLegend {
+        burn_legend: bool,
+    },
+    /// Unset fields keep their value.
+    SetUpscaling {
+        #[serde(default)]
+        scale: Option<usize>,
+        #[serde(default)]
+        upscaler: Option<Upscaler>,
+    },
+    SetOrientation {
+        orientation: Orientation,
+    },
+    /// Hot/cold spot tracking; unset fields keep their value.
+    SetTracker {
+        #[serde(default)]
+        subpixel: Option<bool>,
+        #[serde(default)]
+        smoothing: Option<f32>,
+    },
+    /// Turns the pipeline stage called `stage` on or off.
+    EnableStage {
+        stage: String,
+        enabled: bool,
+    },
+    /// Moves the pipeline stage at index `from` to index `to`.
+    MoveStage {
+        from: usize,
+        to: usize,
+    },
+    /// Flat-field correction: starts a software NUC.
+    Ffc,
+    /// Drops the NUC offsets.
+    ClearNuc,
+    /// Video formats and raw sequences record independently of each other.
+    /// Unset fields take the `RecordingConfig` defaults and a time-stamped
+    /// file name. Files always go to `recordings/`.
+    StartRecording {
+        #[serde(default)]
+        kind: RecordingKind,
+        /// File name; path separators are dropped.
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        fps: Option<f32>,
+        #[serde(default)]
+        burn_overlays: Option<bool>,
+    },
+    /// Stops the recording of `kind`, or every recording.
+    StopRecording {
+        #[serde(default)]
+        kind: Option<RecordingKind>,
+    },
+    AddRoi {
+        /// Defaults to the kind and id, e.g. "Box3".
+        #[serde(default)]
+        name: Option<String>,
+        shape: Shape,
+    },
+    RemoveRoi {
+        id: u32,
+    },
+    ClearRois,
+    /// Fields of the rule itself, e.g. `"name": "Fuse", "source": {"Region":
+    /// 1}, "condition": {"Above": 80}`. Snapshot and log files go to
+    /// `recordings/`; commands can only be set up locally.
+    AddAlarm {
+        #[serde(flatten)]
+        rule: AlarmRule,
+    },
+    RemoveAlarm {
+        id: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    Done,
+    /// A recording was started.
+    Started {
+        path: PathBuf,
+    },
+    /// The files being finished by the recordings that were stopped.
+    Stopped {
+        paths: Vec<PathBuf>,
+    },
+    /// A ROI was added.
+    Added {
+        id: u32,
+    },
+}
+
+/// Why an action was refused.
+#[derive(Debug)]
+pub enum ControlError {
+    /// Malformed or out-of-range arguments.
+    Invalid(String),
+    NotFound(String),
+    /// Not possible in the current state, e.g. already recording.
+    Conflict(String),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::Invalid(text)
+            | ControlError::NotFound(text)
+            | ControlError::Conflict(text) => f.write_str(text),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+/// Snapshot of what the actions control.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct State {
+    pub palette: Palette,
+    pub unit: TemperatureUnit,
+    pub range: RangeMode,
+    pub calibration: Calibration,
+    pub orientation: Orientation,
+    pub denoise: DenoiseState,
+    /// Pipeline stages in processing order.
+    pub stages: Vec<StageState>,
+    pub nuc: NucState,
+    pub recording: RecordingState,
+    pub rois: Vec<Roi>,
+    pub alarms: Vec<AlarmRuleState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AlarmRuleState {
+    #[serde(flatten)]
+    pub rule: AlarmRule,
+    /// Raised and not yet cleared.
+    pub active: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DenoiseState {
+    pub filters: Vec<FilterConfig>,
+    pub use_for_measurement: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StageState {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct NucState {
+    /// Whether the pipeline has a NUC stage at all.
+    pub available: bool,
+    pub calibrated: bool,
+    pub calibrating: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecordingState {
+    /// The active video recording.
+    pub video: Option<PathBuf>,
+    /// The active raw sequence.
+    pub sequence: Option<PathBuf>,
+}
+
+/// A ROI with its statistics on one frame (°C); `stats` is `None` if the ROI
+/// lies outside the frame.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RoiReading {
+    #[serde(flatten)]
+    pub roi: Roi,
+    pub sequence: u64,
+    pub stats: Option<RoiStats>,
+}
+
+/// Carries out actions on an engine; clones share it. See
+/// `ThermalEngine::controller`.
+#[derive(Clone)]
+pub struct Controller {
+    settings: SharedSettings,
+    pipeline: Arc<Mutex<Pipeline>>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    sequence: Arc<Mutex<Option<SequenceRecorder>>>,
+    rois: Arc<Mutex<RoiSet>>,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    bus: FrameBus,
+    device: String,
+    /// Stopped recordings still writing their files.
+    finishing: Arc<Mutex<Vec<JoinHandle<Result<PathBuf>>>>>,
+}
+
+impl Controller {
+    pub(crate) fn new(engine: &ThermalEngine, device: &str) -> Self {
+        Self {
+            settings: engine.settings(),
+            pipeline: engine.pipeline(),
+            recorder: engine.recorder(),
+            sequence: engine.sequence_recorder(),
+            rois: engine.rois(),
+            alarms: engine.alarms(),
+            bus: engine.bus(),
+            device: device.to_string(),
+            finishing: Arc::default(),
+        }
+    }
+
+    pub fn execute(&self, action: Action) -> Result<Outcome> {
+        match action {
+            Action::SetPalette { palette } => {
+                self.settings.display.lock().unwrap().palette = palette;
+            }
+            Action::SetUnit { unit } => {
+                self.settings.display.lock().unwrap().unit = unit;
+            }
+            Action::SetRange { range } => {
+                if let RangeMode::Manual { level, span } = range
+                    && !(level.is_finite() && span.is_finite() && span > 0.0)
+                {
+                    return Err(invalid("Range needs a finite level and a positive span"));
+                }
+                self.settings.display.lock().unwrap().range = match range {
+                    RangeMode::Manual { level, span } => RangeMode::manual(level, span),
+                    RangeMode::Auto => RangeMode::Auto,
+                };
+            }
+            Action::SetCalibration {
+                emissivity,
+                reflected_temp,
+            } => {
+                if let Some(e) = emissivity
+                    && !(0.01..=1.0).contains(&e)
+                {
+                    return Err(invalid("Emissivity must be within 0.01..=1.0"));
+                }
+                if reflected_temp.is_some_and(|t| !t.is_finite()) {
+                    return Err(invalid("Reflected temperature must be finite"));
+                }
+                let mut calibration = self.settings.calibration.lock().unwrap();
+                if let Some(e) = emissivity {
+                    calibration.emissivity = e;
+                }
+                if let Some(t) = reflected_temp {
+                    calibration.reflected_temp = t;
+                }
+            }
+            Action::SetDenoise {
+                filters,
+                use_for_measurement,
+            } => {
+                let built = filters
+                    .iter()
+                    .flatten()
+                    .map(FilterConfig::build)
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .map_err(|e| ControlError::Invalid(e.to_string()))?;
+                let mut chain = self.settings.filters.lock().unwrap();
+                if filters.is_some() {
+                    chain.clear();
+                    for filter in built {
+                        chain.push(filter);
+                    }
+                }
+                if let Some(on) = use_for_measurement {
+                    chain.use_for_measurement = on;
+                }
+            }
+            Action::SetIsotherms {
+                isotherms,
+                background,
+            } => {
+                let finite = isotherms.iter().all(|iso| match iso.mode {
+                    IsothermMode::Above(t) | IsothermMode::Below(t) => t.is_finite(),
+                    IsothermMode::Interval(low, high) => low.is_finite() && high.is_finite(),
+                });
+                if !finite {
+                    return Err(invalid("Isotherm thresholds must be finite"));
+                }
+                let mut display = self.settings.display.lock().unwrap();
+                display.isotherms = isotherms;
+                if let Some(background) = background {
+                    display.isotherm_background = background;
+                }
+            }
+            Action::SetBurnLegend { burn_legend } => {
+                self.settings.display.lock().unwrap().burn_legend = burn_legend;
+            }
+            Action::SetUpscaling { scale, upscaler } => {
+                if scale.is_some_and(|scale| !(1..=8).contains(&scale)) {
+                    return Err(invalid("Scale must be within 1..=8"));
+                }
+                let mut display = self.settings.display.lock().unwrap();
+                if let Some(scale) = scale {
+                    display.scale = scale;
+                }
+                if let Some(upscaler) = upscaler {
+                    display.upscaler = upscaler;
+                }
+            }
+            Action::SetOrientation { orientation } => {
+                let before =
+                    std::mem::replace(&mut *self.settings.orientation.lock().unwrap(), orientation);
+                self.rois.lock().unwrap().reorient(&before, &orientation);
+            }
+            Action::SetTracker {
+                subpixel,
+                smoothing,
+            } => {
+                if smoothing.is_some_and(|s| !(0.0..1.0).contains(&s)) {
+                    return Err(invalid("Smoothing must be within 0..1"));
+                }
+                let mut tracker = self.settings.tracker.lock().unwrap();
+                if let Some(subpixel) = subpixel {
+                    tracker.subpixel = subpixel;
+                }
+                if let Some(smoothing) = smoothing {
+                    tracker.smoothing = smoothing;
+                }
+            }
+            Action::EnableStage { stage, enabled } => {
+                if !self.pipeline.lock().unwrap().set_enabled(&stage, enabled) {
+                    return Err(ControlError::NotFound(format!("No stage {:?}", stage)).into());
+                }
+            }
+            Action::MoveStage { from, to } => {
+                let mut pipeline = self.pipeline.lock().unwrap();
+                if from >= pipeline.len() || to >= pipeline.len() {
+                    return Err(invalid(&format!(
+                        "The pipeline has {} stages",
+                        pipeline.len()
+                    )));
+                }
+                pipeline.move_stage(from, to);
+            }
+            Action::Ffc => {
+                let mut pipeline = self.pipeline.lock().unwrap();
+                let nuc = pipeline
+                    .stage_mut::<NucStage>()
+                    .ok_or_else(|| conflict("The pipeline has no NUC stage"))?;
+                nuc.calibrate();
+                println!("🎯 NUC: collecting {} frames", nuc.frames);
+            }
+            Action::ClearNuc => {
+                self.pipeline
+                    .lock()
+                    .unwrap()
+                    .stage_mut::<NucStage>()
+                    .ok_or_else(|| conflict("The pipeline has no NUC stage"))?
+                    .clear();
+            }
+            Action::StartRecording {
+                kind,
+                name,
+                fps,
+                burn_overlays,
+            } => {
+                let path = name
+                    .map(|name| file_name(&name).map(|name| Path::new(RECORDINGS).join(name)))
+                    .transpose()?;
+                return self.start_recording(kind, path, fps, burn_overlays);
+            }
+            Action::StopRecording { kind } => return Ok(self.stop_recording(kind)),
+            Action::AddRoi { name, shape } => {
+                let frame = self
+                    .bus
+                    .latest()
+                    .map_or(DEFAULT_FRAME, |f| (f.width, f.height));
+                validate(&shape, frame)?;
+                let mut rois = self.rois.lock().unwrap();
+                let id = rois.add(shape);
+                if let Some(name) = name
+                    && let Some(roi) = rois.get_mut(id)
+                {
+                    roi.name = name;
+                }
+                return Ok(Outcome::Added { id });
+            }
+            Action::RemoveRoi { id } => {
+                if !self.rois.lock().unwrap().remove(id) {
+                    return Err(ControlError::NotFound(format!("No ROI {}", id)).into());
+                }
+            }
+            Action::ClearRois => self.rois.lock().unwrap().clear(),
+            Action::AddAlarm { mut rule } => {
+                validate_alarm(&mut rule)?;
+                // ROIs before alarms, like the engine.
+                let rois = self.rois.lock().unwrap();
+                if let AlarmSource::Region(id) = rule.source
+                    && rois.get(id).is_none()
+                {
+                    return Err(ControlError::NotFound(format!("No ROI {}", id)).into());
+                }
+                let id = self.alarms.lock().unwrap().add(rule);
+                return Ok(Outcome::Added { id });
+            }
+            Action::RemoveAlarm { id } => {
+                if !self.alarms.lock().unwrap().remove(id) {
+                    return Err(ControlError::NotFound(format!("No alarm {}", id)).into());
+                }
+            }
+        }
+        Ok(Outcome::Done)
+    }
+
+    /// `StartRecording` to any `path`, for callers that may write anywhere
+    /// (the command line); `None` picks a time-stamped file in `recordings/`.
+    pub fn start_recording(
+        &self,
+        kind: RecordingKind,
+        path: Option<PathBuf>,
+        fps: Option<f32>,
+        burn_overlays: Option<bool>,
+    ) -> Result<Outcome> {
+        let dir = Path::new(RECORDINGS);
+        let path = match kind.format() {
+            Some(format) => {
+                let mut slot = self.recorder.lock().unwrap();
+                if let Some(recorder) = slot.as_ref() {
+                    return Err(conflict(&format!(
+                        "Already recording to {}",
+                        recorder.path().display()
+                    )));
+                }
+                let defaults = RecordingConfig::default();
+                let config = RecordingConfig {
+                    format,
+                    fps: fps.unwrap_or(defaults.fps),
+                    burn_overlays: burn_overlays.unwrap_or(defaults.burn_overlays),
+                };
+                if !(1.0..=60.0).contains(&config.fps) {
+                    return Err(invalid("Frame rate must be within 1..=60 fps"));
+                }
+                let path = path.unwrap_or_else(|| Recorder::default_path(dir, format));
+                *slot = Some(Recorder::start(&path, config)?);
+                path
+            }
+            None => {
+                let mut slot = self.sequence.lock().unwrap();
+                if let Some(sequence) = slot.as_ref() {
+                    return Err(conflict(&format!(
+                        "Already recording to {}",
+                        sequence.path().display()
+                    )));
+                }
+                let path = path.unwrap_or_else(|| SequenceRecorder::default_path(dir));
+                *slot = Some(SequenceRecorder::start(&path, &self.device)?);
+                path
+            }
+        };
+        Ok(Outcome::Started { path })
+    }
+
+    /// Files are finished on their own threads; see `wait`.
+    fn stop_recording(&self, kind: Option<RecordingKind>) -> Outcome {
+        let video = kind.is_none_or(|kind| kind.format().is_some());
+        let sequence = kind.is_none_or(|kind| kind == RecordingKind::Tseq);
+        let mut paths = Vec::new();
+        let mut finishing = self.finishing.lock().unwrap();
+        if video && let Some(recorder) = self.recorder.lock().unwrap().take() {
+            paths.push(recorder.path().to_path_buf());
+            finishing.push(thread::spawn(move || recorder.finish()));
+        }
+        if sequence && let Some(sequence) = self.sequence.lock().unwrap().take() {
+            paths.push(sequence.path().to_path_buf());
+            finishing.push(thread::spawn(move || sequence.finish()));
+        }
+        Outcome::Stopped { paths }
+    }
+
+    /// Blocks until every stopped recording has been written; the first
+    /// error, if any.
+    pub fn wait(&self) -> Result<()> {
+        let handles = std::mem::take(&mut *self.finishing.lock().unwrap());
+        let mut result = Ok(());
+        for handle in handles {
+            let finished = handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Recording thread panicked")));
+            if let Err(e) = finished {
+                eprintln!("❌ Failed to finish recording: {}", e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    pub fn state(&self) -> State {
+        let (nuc, stages, calibration) = {
+            // Pipeline before settings, like the engine.
+            let mut pipeline = self.pipeline.lock().unwrap();
+            let stages = pipeline
+                .stages()
+                .into_iter()
+                .map(|(name, enabled)| StageState { name, enabled })
+                .collect();
+            let nuc = match pipeline.stage_mut::<NucStage>() {
+                Some(nuc) => NucState {
+                    available: true,
+                    calibrated: nuc.is_calibrated(),
+                    calibrating: nuc.is_calibrating(),
+                },
+                None => NucState {
+                    available: false,
+                    calibrated: false,
+                    calibrating: false,
+                },
+            };
+            (nuc, stages, *self.settings.calibration.lock().unwrap())
+        };
+        let display = self.settings.display.lock().unwrap().clone();
+        State {
+            palette: display.palette,
+            unit: display.unit,
+            range: display.range,
+            calibration,
+            orientation: *self.settings.orientation.lock().unwrap(),
+            denoise: {
+                let chain = self.settings.filters.lock().unwrap();
+                DenoiseState {
+                    filters: chain.configs(),
+                    use_for_measurement: chain.use_for_measurement,
+                }
+            },
+            stages,
+            nuc,
+            recording: RecordingState {
+                video: self
+                    .recorder
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|r| r.path().to_path_buf()),
+                sequence: self
+                    .sequence
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|s| s.path().to_path_buf()),
+            },
+            rois: self.rois.lock().unwrap().as_slice().to_vec(),
+            alarms: {
+                let alarms = self.alarms.lock().unwrap();
+                alarms
+                    .rules
+                    .iter()
+                    .map(|rule| AlarmRuleState {
+                        rule: rule.clone(),
+                        active: alarms.is_active(rule.id),
+                    })
+                    .collect()
+            },
+        }
+    }
+
+    /// Every ROI, measured on `frame`.
+    pub fn readings(&self, frame: &BusFrame) -> Vec<RoiReading> {
+        let rois = self.rois.lock().unwrap();
+        rois.iter().map(|roi| reading(roi, frame)).collect()
+    }
+
+    /// ROI `id`, measured on `frame`.
+    pub fn reading(&self, id: u32, frame: &BusFrame) -> Result<RoiReading> {
+        let rois = self.rois.lock().unwrap();
+        let roi = rois
+            .get(id)
+            .ok_or_else(|| ControlError::NotFound(format!("No ROI {}", id)))?;
+        Ok(reading(roi, frame))
+    }
+
+    /// The frames that ROI readings are taken from.
+    pub fn bus(&self) -> &FrameBus {
+        &self.bus
+    }
+}
+
+fn reading(roi: &Roi, frame: &BusFrame) -> RoiReading {
+    RoiReading {
+        roi: roi.clone(),
+        sequence: frame.sequence,
+        stats: roi.stats(&frame.temperature, frame.width, frame.height),
+    }
+}
+
+/// Coordinates must be finite and no further than one frame `size` outside
+/// the frame; lines need two to `MAX_LINE_POINTS` points.
+fn validate(shape: &Shape, (width, height): (usize, usize)) -> Result<()> {
+    let (width, height) = (width as f32, height as f32);
+    let (points, radii) = match shape {
+        Shape::Spot { x, y } => (vec![(*x, *y)], vec![]),
+        Shape::Rect { x0, y0, x1, y1 } => (vec![(*x0, *y0), (*x1, *y1)], vec![]),
+        Shape::Ellipse { cx, cy, rx, ry } => (vec![(*cx, *cy)], vec![*rx, *ry]),
+        Shape::Line { points } => {
+            if points.len() < 2 {
+                return Err(invalid("A line needs at least two points"));
+            }
+            if points.len() > MAX_LINE_POINTS {
+                return Err(invalid(&format!(
+                    "A line has at most {} points",
+                    MAX_LINE_POINTS
+                )));
+            }
+            (points.clone(), vec![])
+        }
+    };
+    if !points.iter().all(|p| p.0.is_finite() && p.1.is_finite())
+        || !radii.iter().all(|r| r.is_finite())
+    {
+        return Err(invalid("ROI coordinates must be finite"));
+    }
+    let near = |(x, y): &(f32, f32)| {
+        (-width..=2.0 * width).contains(x) && (-height..=2.0 * height).contains(y)
+    };
+    if !points.iter().all(near) || radii.iter().any(|r| r.abs() > 2.0 * width.max(height)) {
+        return Err(invalid(&format!(
+            "ROI lies far outside the {}x{} frame",
+            width, height
+        )));
+    }
+    Ok(())
+}
+
+/// Values must be finite. Files are kept in `recordings/`, and commands are
+/// refused: whoever can reach the API must not run programs or write
+/// anywhere.
+fn validate_alarm(rule: &mut AlarmRule) -> Result<()> {
+    let values = match rule.condition {
+        Condition::Above(limit) | Condition::Below(limit) => [limit, rule.hysteresis],
+        Condition::RateOfRise { per_minute, window } => {
+            if window.is_zero() || window.as_secs() > 3600 {
+                return Err(invalid("The rate window must be within 0..=3600 s"));
+            }
+            [per_minute, rule.hysteresis]
+        }
+    };
+    if !values.iter().all(|v| v.is_finite()) {
+        return Err(invalid("Alarm thresholds must be finite"));
+    }
+    for action in &mut rule.actions {
+        match action {
+            AlarmAction::RunCommand(_) => {
+                return Err(invalid(
+                    "Commands can only be set up in the window or the configuration",
+                ));
+            }
+            AlarmAction::Snapshot(path) | AlarmAction::EventLog(path) => {
+                let name = file_name(&path.to_string_lossy())?;
+                *path = Path::new(RECORDINGS).join(name);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `name` without path separators, so it cannot leave the directory it is
+/// joined to.
+fn file_name(name: &str) -> Result<String> {
+    let name: String = name
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | ':') && !c.is_control())
+        .collect();
+    if name.chars().all(|c| c == '.' || c.is_whitespace()) {
+        return Err(invalid("Not a file name"));
+    }
+    Ok(name)
+}
+
+fn invalid(text: &str) -> anyhow::Error {
+    ControlError::Invalid(text.to_string()).into()
+}
+
+fn conflict(text: &str) -> anyhow::Error {
+    ControlError::Conflict(text.to_string()).into()
+}
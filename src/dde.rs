@@ -0,0 +1,138 @@
+// Digital detail enhancement (DDE).
+//
+// The field is split into a base layer (edge-preserving smooth) and a detail layer
+// (field - base). The base layer carries the large temperature differences and is
+// compressed with plateau histogram equalisation; the detail layer carries fine
+// texture and is amplified. The result is a normalised 0..1 image for display
+// only -- it has no radiometric meaning.
+
+use crate::filters::GuidedFilter;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dde {
+    /// Radius of the guided filter that extracts the base layer.
+    pub radius: usize,
+    /// Guided filter regularisation in squared counts. Larger = more goes into detail.
+    pub eps: f64,
+    /// Amplification of the detail layer (1.0 = unchanged).
+    pub detail_gain: f32,
+    /// Histogram plateau as a fraction of the mean bin count; limits how much
+    /// large uniform areas (sky, walls) can dominate the grey levels.
+    pub plateau: f32,
+}
+
+impl Default for Dde {
+    fn default() -> Self {
+        Self {
+            radius: 3,
+            eps: 2500.0,
+            detail_gain: 2.0,
+            plateau: 4.0,
+        }
+    }
+}
+
+const AGC_BINS: usize = 1024;
+
+impl Dde {
+    pub fn enhance(&self, field: &[u16], width: usize, height: usize) -> Vec<f32> {
+        if field.is_empty() {
+            return Vec::new();
+        }
+        let base = GuidedFilter::new(self.radius, self.eps).smooth(field, width, height);
+        let base_eq = plateau_equalize(&base, self.plateau);
+
+        // Normalise detail by its spread so the gain is scene independent.
+        let n = field.len() as f64;
+        let detail: Vec<f32> = field
+            .iter()
+            .zip(&base)
+            .map(|(&v, &b)| (v as f64 - b) as f32)
+            .collect();
+        let sigma = (detail.iter().map(|d| (*d as f64).powi(2)).sum::<f64>() / n).sqrt() as f32;
+        let scale = if sigma > 0.0 {
+            self.detail_gain / (8.0 * sigma)
+        } else {
+            0.0
+        };
+
+        base_eq
+            .into_iter()
+            .zip(detail)
+            .map(|(b, d)| (b + d * scale).clamp(0.0, 1.0))
+            .collect()
+    }
+}
+
+/// Histogram equalisation with a plateau, the usual AGC of thermal cores.
+fn plateau_equalize(values: &[f64], plateau: f32) -> Vec<f32> {
+    let (lo, hi) = values
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if hi <= lo {
+        return vec![0.5; values.len()];
+    }
+    let bin_scale = (AGC_BINS - 1) as f64 / (hi - lo);
+    let bin_of = |v: f64| ((v - lo) * bin_scale) as usize;
+
+    let mut hist = [0u32; AGC_BINS];
+    for &v in values {
+        hist[bin_of(v)] += 1;
+    }
+    let occupied = hist.iter().filter(|&&c| c > 0).count().max(1);
+    let limit = ((values.len() as f32 / occupied as f32) * plateau).max(1.0) as u32;
+
+    let mut cdf = [0f32; AGC_BINS];
+    let mut acc = 0u32;
+    for (c, &h) in cdf.iter_mut().zip(&hist) {
+        acc += h.min(limit);
+        *c = acc as f32;
+    }
+    let total = acc as f32;
+    values.iter().map(|&v| cdf[bin_of(v)] / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 32;
+    const H: usize = 24;
+
+    fn mean(values: &[f32]) -> f32 {
+        values.iter().sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn uniform_field_is_mid_grey() {
+        let out = Dde::default().enhance(&vec![18_000; W * H], W, H);
+        assert_eq!(out.len(), W * H);
+        assert!(out.iter().all(|&v| v == 0.5));
+    }
+
+    #[test]
+    fn keeps_the_mean_and_the_order() {
+        // A ramp with a fine checkerboard texture on top.
+        let field: Vec<u16> = (0..W * H)
+            .map(|i| {
+                let (x, y) = (i % W, i / W);
+                17_000 + 60 * x as u16 + if (x + y) % 2 == 0 { 8 } else { 0 }
+            })
+            .collect();
+        let out = Dde::default().enhance(&field, W, H);
+        assert!((mean(&out) - 0.5).abs() < 0.05, "{}", mean(&out));
+        assert!(out.iter().all(|v| (0.0..=1.0).contains(v)));
+
+        // Left stays darker than right, and the texture is amplified.
+        let column = |x: usize| mean(&(0..H).map(|y| out[y * W + x]).collect::<Vec<_>>());
+        assert!(column(2) < column(W / 2) && column(W / 2) < column(W - 3));
+        let flat = Dde {
+            detail_gain: 0.0,
+            ..Dde::default()
+        }
+        .enhance(&field, W, H);
+        let contrast = |image: &[f32]| (image[W * 10 + 10] - image[W * 10 + 11]).abs();
+        assert!(contrast(&out) > contrast(&flat));
+    }
+}
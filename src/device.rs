@@ -113,19 +113,17 @@ impl ThermalDevice {
                 rusb::Recipient::Interface,
             );
 
-            match handle.read_control(request_type, 0x44, 0x78, 0x200, &mut status, timeout) {
-                Ok(_) => {
-                    if (status[0] & 1) == 0
-                        && (((status[0] as i32) << 30) < 0 || (status[0] & 0xfc) != 0)
-                    {
-                        println!(
-                            "✓ Handshake acknowledged! (status: 0x{:02x}, iteration: {})",
-                            status[0], i
-                        );
-                        break;
-                    }
-                }
-                Err(_) => {}
+            if handle
+                .read_control(request_type, 0x44, 0x78, 0x200, &mut status, timeout)
+                .is_ok()
+                && (status[0] & 1) == 0
+                && (((status[0] as i32) << 30) < 0 || (status[0] & 0xfc) != 0)
+            {
+                println!(
+                    "✓ Handshake acknowledged! (status: 0x{:02x}, iteration: {})",
+                    status[0], i
+                );
+                break;
             }
 
             if i == 999 {
@@ -308,7 +306,7 @@ impl UVCStream {
     /// Supports both Bulk and Isochronous (simulated via high-speed read)
     pub fn read_frame(&mut self, handle: &rusb::DeviceHandle<GlobalContext>) -> Result<Vec<u8>> {
         let timeout = Duration::from_millis(1000);
-        let frame_size = 256 * 192 * 2; // 256x192 pixels, 2 bytes per pixel (Y16 format)
+        let frame_size = self.frame_size;
         let mut frame_data = vec![0u8; frame_size];
 
         // Try endpoint 0x81 if 0x82 fails, and vice versa.
@@ -331,6 +329,8 @@ impl UVCStream {
             }
         }
 
-        anyhow::bail!("No data received from video endpoints 0x81 or 0x82. Device may require isochronous transfers.")
+        anyhow::bail!(
+            "No data received from video endpoints 0x81 or 0x82. Device may require isochronous transfers."
+        )
     }
 }
@@ -0,0 +1,264 @@
+use crate::calibration::{Calibration, TemperatureUnit};
+use crate::dde::Dde;
+use crate::frame::ThermalFrame;
+use crate::isotherm::{self, Isotherm, IsothermBackground};
+use crate::legend::Legend;
+use crate::palette::{self, Palette};
+use crate::upscale::Upscaler;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+/// How the temperature range spanned by the palette is chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum RangeMode {
+    /// Scene min/max of every frame.
+    #[default]
+    Auto,
+    /// Fixed range in °C, so frames (and cameras) stay visually comparable.
+    Manual { level: f32, span: f32 },
+}
+
+impl RangeMode {
+    /// Narrowest span the controls allow (°C).
+    pub const MIN_SPAN: f32 = 0.2;
+
+    pub fn manual(level: f32, span: f32) -> Self {
+        RangeMode::Manual {
+            level,
+            span: span.max(Self::MIN_SPAN),
+        }
+    }
+
+    /// Locks the range currently shown by `legend`.
+    pub fn locked(legend: &Legend) -> Self {
+        Self::manual(legend.level(), legend.span())
+    }
+
+    pub fn is_manual(&self) -> bool {
+        matches!(self, RangeMode::Manual { .. })
+    }
+
+    /// Shifts the level by `delta` °C and scales the span by `factor`. An
+    /// automatic range is locked at `current` first.
+    pub fn adjusted(self, current: &Legend, delta: f32, factor: f32) -> Self {
+        let (level, span) = match self {
+            RangeMode::Auto => (current.level(), current.span()),
+            RangeMode::Manual { level, span } => (level, span),
+        };
+        Self::manual(level + delta, span * factor)
+    }
+
+    /// (min, max) in °C, or `None` for automatic ranging.
+    pub fn bounds(&self) -> Option<(f32, f32)> {
+        match *self {
+            RangeMode::Auto => None,
+            RangeMode::Manual { level, span } => Some((level - span / 2.0, level + span / 2.0)),
+        }
+    }
+}
+
+/// Settings of the display path. Nothing here ever touches the temperature field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    /// Digital detail enhancement; `None` = plain min/max stretch.
+    pub dde: Option<Dde>,
+    pub palette: Palette,
+    /// Saved with the AGC stage rather than with the rest of the display settings.
+    #[serde(skip)]
+    pub range: RangeMode,
+    pub isotherms: Vec<Isotherm>,
+    pub isotherm_background: IsothermBackground,
+    pub upscaler: Upscaler,
+    /// Integer output scale; 1 leaves the sensor resolution untouched.
+    pub scale: usize,
+    pub unit: TemperatureUnit,
+    /// Burn the colour legend into exported images.
+    pub burn_legend: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            dde: None,
+            palette: Palette::default(),
+            range: RangeMode::default(),
+            isotherms: Vec::new(),
+            isotherm_background: IsothermBackground::default(),
+            upscaler: Upscaler::Bicubic,
+            scale: 1,
+            unit: TemperatureUnit::default(),
+            burn_legend: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Palette actually used for the background, taking isotherms into account.
+    pub fn effective_palette(&self) -> Palette {
+        if !self.isotherms.is_empty() && self.isotherm_background == IsothermBackground::Greyscale {
+            Palette::WhiteHot
+        } else {
+            self.palette
+        }
+    }
+
+    /// `temperature` is the measurement field of `frame` (°C), used for isotherms;
+    /// `calibration` turns a manual range into counts.
+    /// Returns the image and the raw range that the palette spans.
+    pub fn render(
+        &self,
+        frame: &ThermalFrame,
+        temperature: &[f32],
+        calibration: &Calibration,
+    ) -> (egui::ColorImage, (u16, u16)) {
+        let mut image = egui::ColorImage::default();
+        let range = self.render_into(frame, temperature, calibration, &mut image);
+        (image, range)
+    }
+
+    /// `render` into `image`, reusing its pixel buffer. Plain colourisation at
+    /// scale 1 does not allocate; DDE and upscaling still produce new buffers.
+    pub fn render_into(
+        &self,
+        frame: &ThermalFrame,
+        temperature: &[f32],
+        calibration: &Calibration,
+        image: &mut egui::ColorImage,
+    ) -> (u16, u16) {
+        let range = self.agc_range(frame.field(true), calibration);
+        self.colorize_into(frame, temperature, range, image);
+        range
+    }
+
+    /// Raw range the palette should span: the scene min/max, or the manual
+    /// range converted to counts.
+    pub fn agc_range(&self, field: &[u16], calibration: &Calibration) -> (u16, u16) {
+        match self.range.bounds() {
+            Some((min, max)) => (calibration.to_raw(min), calibration.to_raw(max)),
+            None => ThermalFrame::min_max(field),
+        }
+    }
+
+    /// Colour mapping over `range`, isotherms and upscaling.
+    pub fn colorize_into(
+        &self,
+        frame: &ThermalFrame,
+        temperature: &[f32],
+        range: (u16, u16),
+        image: &mut egui::ColorImage,
+    ) {
+        let field = frame.field(true);
+        let palette = self.effective_palette();
+        match &self.dde {
+            // DDE is non-linear; a manual range only clips its input.
+            Some(dde) => {
+                let values = if self.range.is_manual() {
+                    let clipped: Vec<u16> =
+                        field.iter().map(|&v| v.clamp(range.0, range.1)).collect();
+                    dde.enhance(&clipped, frame.width, frame.height)
+                } else {
+                    dde.enhance(field, frame.width, frame.height)
+                };
+                *image = palette::colorize_normalized(&values, frame.width, frame.height, palette);
+            }
+            None => palette::colorize_into(field, frame.width, frame.height, palette, range, image),
+        }
+        isotherm::apply(image, temperature, &self.isotherms);
+        if self.scale > 1 {
+            *image = self.upscaler.upscale(image, self.scale);
+        }
+    }
+
+    /// Legend for an image rendered over the raw `range`.
+    pub fn legend(&self, (lo, hi): (u16, u16), calibration: &Calibration) -> Legend {
+        // A manual range is reported as set, not as rounded to counts.
+        let (min, max) = self
+            .range
+            .bounds()
+            .unwrap_or((calibration.to_celsius(lo), calibration.to_celsius(hi)));
+        Legend {
+            palette: self.effective_palette(),
+            min,
+            max,
+            unit: self.unit,
+            locked: self.range.is_manual(),
+            in_exports: self.burn_legend,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_and_auto_range_round_trip() {
+        let calibration = Calibration::default();
+        let field: Vec<u16> = [15.0, 22.0, 35.0]
+            .iter()
+            .map(|&c| calibration.to_raw(c))
+            .collect();
+        let mut settings = DisplaySettings::default();
+
+        let auto = settings.agc_range(&field, &calibration);
+        assert_eq!(auto, ThermalFrame::min_max(&field));
+        let legend = settings.legend(auto, &calibration);
+        assert!(!legend.locked);
+        assert!((legend.min - 15.0).abs() < 0.1 && (legend.max - 35.0).abs() < 0.1);
+
+        // Locking keeps what was shown, in counts and in the legend.
+        settings.range = RangeMode::locked(&legend);
+        assert!(settings.range.is_manual());
+        let locked = settings.agc_range(&field, &calibration);
+        assert!(locked.0.abs_diff(auto.0) <= 1 && locked.1.abs_diff(auto.1) <= 1);
+        let shown = settings.legend(locked, &calibration);
+        assert!(shown.locked);
+        assert_eq!((shown.min, shown.max), (legend.min, legend.max));
+        assert_eq!(shown.ticks(3), [legend.max, legend.level(), legend.min]);
+
+        // A manual range ignores the scene.
+        settings.range = RangeMode::manual(30.0, 10.0);
+        let hot: Vec<u16> = field.iter().map(|&v| v + 6400).collect();
+        let range = settings.agc_range(&hot, &calibration);
+        let shown = settings.legend(range, &calibration);
+        assert_eq!((shown.min, shown.max), (25.0, 35.0));
+        assert!((calibration.to_celsius(range.0) - 25.0).abs() < 0.1);
+        assert!((calibration.to_celsius(range.1) - 35.0).abs() < 0.1);
+
+        settings.range = RangeMode::Auto;
+        assert_eq!(
+            settings.agc_range(&hot, &calibration),
+            ThermalFrame::min_max(&hot)
+        );
+    }
+
+    #[test]
+    fn adjusting_locks_and_limits_the_span() {
+        let legend = Legend {
+            min: 20.0,
+            max: 30.0,
+            ..Legend::default()
+        };
+        assert_eq!(
+            RangeMode::Auto.adjusted(&legend, 2.0, 0.5),
+            RangeMode::Manual {
+                level: 27.0,
+                span: 5.0
+            }
+        );
+        let manual = RangeMode::manual(10.0, 4.0);
+        assert_eq!(
+            manual.adjusted(&legend, -1.0, 2.0).bounds(),
+            Some((5.0, 13.0))
+        );
+        assert_eq!(
+            manual.adjusted(&legend, 0.0, 0.0),
+            RangeMode::Manual {
+                level: 10.0,
+                span: RangeMode::MIN_SPAN
+            }
+        );
+        assert_eq!(RangeMode::Auto.bounds(), None);
+    }
+}
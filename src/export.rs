@@ -0,0 +1,333 @@
+// Numeric export of temperatures for analysis in spreadsheets, MATLAB or
+// NumPy.
+//
+// `write_matrix` writes one frame's temperature field (one text row per image
+// row for CSV/TSV, a float32 `(height, width)` array for `.npy`). `RoiLogger`
+// appends ROI statistics to a CSV time series, one row per ROI per frame, so
+// ROIs can come and go while it runs.
+
+use crate::calibration::TemperatureUnit;
+use crate::frame::ProcessedFrame;
+use crate::measure::Roi;
+use anyhow::{Context, Result, ensure};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixFormat {
+    Csv,
+    Tsv,
+    /// NumPy `.npy`, little-endian float32.
+    Npy,
+}
+
+impl MatrixFormat {
+    pub const ALL: [MatrixFormat; 3] = [MatrixFormat::Csv, MatrixFormat::Tsv, MatrixFormat::Npy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatrixFormat::Csv => "CSV",
+            MatrixFormat::Tsv => "TSV",
+            MatrixFormat::Npy => "NumPy",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MatrixFormat::Csv => "csv",
+            MatrixFormat::Tsv => "tsv",
+            MatrixFormat::Npy => "npy",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub unit: TemperatureUnit,
+    /// Decimal places in text output; `.npy` always stores full float32.
+    pub precision: usize,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            unit: TemperatureUnit::Celsius,
+            precision: 2,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Column-name suffix for the unit, e.g. `min_c`.
+    fn suffix(&self) -> &'static str {
+        match self.unit {
+            TemperatureUnit::Celsius => "c",
+            TemperatureUnit::Fahrenheit => "f",
+            TemperatureUnit::Kelvin => "k",
+        }
+    }
+}
+
+/// Writes a `width` x `height` field of °C values to `path` in `format`,
+/// converted to `options.unit`.
+pub fn write_matrix(
+    path: &Path,
+    temperature: &[f32],
+    width: usize,
+    height: usize,
+    format: MatrixFormat,
+    options: ExportOptions,
+) -> Result<()> {
+    ensure!(
+        width > 0 && temperature.len() == width * height,
+        "Field of {} values is not {}x{}",
+        temperature.len(),
+        width,
+        height
+    );
+    let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    match format {
+        MatrixFormat::Csv => write_text(&mut out, temperature, width, ',', options)?,
+        MatrixFormat::Tsv => write_text(&mut out, temperature, width, '\t', options)?,
+        MatrixFormat::Npy => write_npy(&mut out, temperature, width, height, options.unit)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes the frame's temperature field to `<dir>/field_<unix ms>.<ext>`.
+pub fn export_frame(
+    frame: &ProcessedFrame,
+    dir: &Path,
+    format: MatrixFormat,
+    options: ExportOptions,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    let millis = (frame.frame.unix_time() * 1000.0) as u64;
+    let path = dir.join(format!("field_{}.{}", millis, format.extension()));
+    write_matrix(
+        &path,
+        &frame.temperature,
+        frame.frame.width,
+        frame.frame.height,
+        format,
+        options,
+    )?;
+    Ok(path)
+}
+
+fn write_text(
+    out: &mut impl Write,
+    temperature: &[f32],
+    width: usize,
+    separator: char,
+    options: ExportOptions,
+) -> Result<()> {
+    let mut line = String::new();
+    for row in temperature.chunks(width) {
+        line.clear();
+        for (i, &t) in row.iter().enumerate() {
+            if i > 0 {
+                line.push(separator);
+            }
+            line.push_str(&format!(
+                "{:.*}",
+                options.precision,
+                options.unit.from_celsius(t)
+            ));
+        }
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// NPY format version 1.0: magic, version, header length, a Python dict
+/// literal padded so the data starts on a 64-byte boundary, then the data.
+fn write_npy(
+    out: &mut impl Write,
+    temperature: &[f32],
+    width: usize,
+    height: usize,
+    unit: TemperatureUnit,
+) -> Result<()> {
+    const PREAMBLE: usize = 10;
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+        height, width
+    );
+    let padding = (64 - (PREAMBLE + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for &t in temperature {
+        out.write_all(&unit.from_celsius(t).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// ROI statistics appended to a CSV file frame by frame.
+pub struct RoiLogger {
+    path: PathBuf,
+    out: BufWriter<File>,
+    options: ExportOptions,
+    rows: u64,
+}
+
+impl RoiLogger {
+    /// Creates `path` and writes the header; temperatures are written in
+    /// `options.unit`.
+    pub fn create(path: &Path, options: ExportOptions) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let u = options.suffix();
+        writeln!(
+            out,
+            "timestamp,frame,roi,name,shape,pixels,min_{u},max_{u},mean_{u},std_dev_{u},\
+             hottest_x,hottest_y,coldest_x,coldest_y"
+        )?;
+        println!("📈 Logging ROI statistics to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            out,
+            options,
+            rows: 0,
+        })
+    }
+
+    /// `<dir>/roi_<unix secs>.csv`.
+    pub fn default_path(dir: &Path) -> PathBuf {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        dir.join(format!("roi_{}.csv", secs))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rows written so far.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Appends one row per ROI that covers at least one pixel of `frame`.
+    pub fn record(&mut self, frame: &ProcessedFrame, rois: &[Roi]) -> Result<()> {
+        let timestamp = frame.frame.unix_time();
+        let (width, height) = (frame.frame.width, frame.frame.height);
+        let (unit, precision) = (self.options.unit, self.options.precision);
+        for roi in rois {
+            let Some(stats) = roi.stats(&frame.temperature, width, height) else {
+                continue;
+            };
+            writeln!(
+                self.out,
+                "{:.3},{},{},{},{},{},{:.p$},{:.p$},{:.p$},{:.p$},{},{},{},{}",
+                timestamp,
+                frame.frame.sequence,
+                roi.id,
+                csv_field(&roi.name),
+                roi.shape.kind(),
+                stats.count,
+                unit.from_celsius(stats.min),
+                unit.from_celsius(stats.max),
+                unit.from_celsius(stats.mean),
+                unit.delta_from_celsius(stats.std_dev),
+                stats.hottest.0,
+                stats.hottest.1,
+                stats.coldest.0,
+                stats.coldest.1,
+                p = precision,
+            )?;
+            self.rows += 1;
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes the file.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.out.flush()?;
+        println!(
+            "📈 ROI log saved: {} ({} rows)",
+            self.path.display(),
+            self.rows
+        );
+        Ok(self.path)
+    }
+}
+
+/// Quotes `s` if it contains CSV metacharacters.
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_header_is_aligned_and_data_follows() {
+        let field: Vec<f32> = (0..6).map(|i| i as f32 * 10.0).collect();
+        let mut out = Vec::new();
+        write_npy(&mut out, &field, 3, 2, TemperatureUnit::Kelvin).unwrap();
+
+        assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+        let data_start = 10 + header_len;
+        assert!(data_start.is_multiple_of(64));
+        let header = std::str::from_utf8(&out[10..data_start]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        let data: Vec<f32> = out[data_start..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let expected: Vec<f32> = field.iter().map(|&t| t + 273.15).collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn text_rows_use_the_unit_and_precision() {
+        let field = [0.0, 100.0, -40.0, 36.6];
+        let mut out = Vec::new();
+        let options = ExportOptions {
+            unit: TemperatureUnit::Fahrenheit,
+            precision: 1,
+        };
+        write_text(&mut out, &field, 2, ',', options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "32.0,212.0\n-40.0,97.9\n");
+
+        let mut out = Vec::new();
+        write_text(&mut out, &field, 4, '\t', ExportOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0.00\t100.00\t-40.00\t36.60\n"
+        );
+    }
+
+    #[test]
+    fn mismatched_fields_are_refused() {
+        let path = std::env::temp_dir().join(format!("export-{}.csv", std::process::id()));
+        let options = ExportOptions::default();
+        assert!(write_matrix(&path, &[0.0; 5], 2, 3, MatrixFormat::Csv, options).is_err());
+        assert!(write_matrix(&path, &[], 0, 0, MatrixFormat::Npy, options).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,679 @@
+// Denoising filters for the Y16 field.
+//
+// Every filter works on the raw u16 counts (before any normalisation or colour
+// mapping), so the output is still a valid radiometric field and can be used for
+// measurements as well as for display.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+pub trait DenoiseFilter: Send {
+    fn name(&self) -> &'static str;
+    fn apply(&mut self, field: &mut [u16], width: usize, height: usize);
+    /// Drops any history (temporal state). Called when the stream restarts.
+    fn reset(&mut self) {}
+    /// Parameters for saving the chain; `None` for filters that cannot be
+    /// recreated from a config file.
+    fn config(&self) -> Option<FilterConfig> {
+        None
+    }
+}
+
+/// Serialisable parameters of a built-in filter.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "filter", rename_all = "snake_case")]
+pub enum FilterConfig {
+    Temporal {
+        alpha: f32,
+        motion_threshold: u16,
+    },
+    Bilateral {
+        radius: usize,
+        sigma_spatial: f32,
+        sigma_range: f32,
+    },
+    Guided {
+        radius: usize,
+        eps: f64,
+    },
+    NonLocalMeans {
+        patch_radius: usize,
+        search_radius: usize,
+        depth: usize,
+        h: f32,
+    },
+}
+
+impl FilterConfig {
+    /// Checks the parameters, then creates the filter.
+    pub fn build(&self) -> anyhow::Result<Box<dyn DenoiseFilter>> {
+        self.validate()?;
+        Ok(match *self {
+            FilterConfig::Temporal {
+                alpha,
+                motion_threshold,
+            } => Box::new(TemporalFilter::new(alpha, motion_threshold)),
+            FilterConfig::Bilateral {
+                radius,
+                sigma_spatial,
+                sigma_range,
+            } => Box::new(BilateralFilter::new(radius, sigma_spatial, sigma_range)),
+            FilterConfig::Guided { radius, eps } => Box::new(GuidedFilter::new(radius, eps)),
+            FilterConfig::NonLocalMeans {
+                patch_radius,
+                search_radius,
+                depth,
+                h,
+            } => Box::new(NonLocalMeans3D::new(patch_radius, search_radius, depth, h)),
+        })
+    }
+
+    /// Radii and depth are capped at 8 to bound the cost per frame. The
+    /// weights divide by the sigmas, `eps` and `h`, so zero would turn the
+    /// field into NaN.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let (radii, values) = match *self {
+            FilterConfig::Temporal { alpha, .. } => (vec![], vec![alpha as f64]),
+            FilterConfig::Bilateral {
+                radius,
+                sigma_spatial,
+                sigma_range,
+            } => (vec![radius], vec![sigma_spatial as f64, sigma_range as f64]),
+            FilterConfig::Guided { radius, eps } => (vec![radius], vec![eps]),
+            FilterConfig::NonLocalMeans {
+                patch_radius,
+                search_radius,
+                depth,
+                h,
+            } => (vec![patch_radius, search_radius, depth], vec![h as f64]),
+        };
+        if radii.iter().any(|&r| r > 8) {
+            anyhow::bail!("{}: radii and depth must be at most 8", self.name());
+        }
+        if !values.iter().all(|v| v.is_finite() && *v > 0.0) {
+            anyhow::bail!("{}: parameters must be positive and finite", self.name());
+        }
+        Ok(())
+    }
+
+    /// Every built-in filter with its default parameters, in the usual chain
+    /// order.
+    pub fn defaults() -> [FilterConfig; 4] {
+        [
+            TemporalFilter::default().config(),
+            BilateralFilter::default().config(),
+            GuidedFilter::default().config(),
+            NonLocalMeans3D::default().config(),
+        ]
+        .map(Option::unwrap)
+    }
+
+    /// Same as the built filter's `DenoiseFilter::name`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterConfig::Temporal { .. } => "temporal",
+            FilterConfig::Bilateral { .. } => "bilateral",
+            FilterConfig::Guided { .. } => "guided",
+            FilterConfig::NonLocalMeans { .. } => "nlm3d",
+        }
+    }
+}
+
+/// Ordered list of filters applied to each frame.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn DenoiseFilter>>,
+    /// When true, measurements read the denoised field instead of the raw one.
+    pub use_for_measurement: bool,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, filter: impl DenoiseFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    pub fn push(&mut self, filter: Box<dyn DenoiseFilter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Box<dyn DenoiseFilter>> {
+        (index < self.filters.len()).then(|| self.filters.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.filters.iter().map(|f| f.name()).collect()
+    }
+
+    /// Parameters of every filter that can be saved; others are skipped.
+    pub fn configs(&self) -> Vec<FilterConfig> {
+        self.filters.iter().filter_map(|f| f.config()).collect()
+    }
+
+    pub fn reset(&mut self) {
+        for f in &mut self.filters {
+            f.reset();
+        }
+    }
+
+    /// Runs all filters on a copy of `raw`. Returns `None` if the chain is empty.
+    pub fn process(&mut self, raw: &[u16], width: usize, height: usize) -> Option<Vec<u16>> {
+        let mut out = None;
+        self.process_into(raw, width, height, &mut out);
+        out
+    }
+
+    /// `process` into `out`, reusing the buffer it already holds.
+    pub fn process_into(
+        &mut self,
+        raw: &[u16],
+        width: usize,
+        height: usize,
+        out: &mut Option<Vec<u16>>,
+    ) {
+        if self.filters.is_empty() {
+            *out = None;
+            return;
+        }
+        let field = out.get_or_insert_with(Vec::new);
+        field.clear();
+        field.extend_from_slice(raw);
+        for f in &mut self.filters {
+            f.apply(field, width, height);
+        }
+    }
+}
+
+/// Recursive (IIR) temporal averaging.
+///
+/// Each pixel is blended into a running average with weight `alpha`. If a pixel
+/// changes by more than `motion_threshold` counts it is treated as motion and the
+/// average is reset to the new value, which avoids ghost trails behind moving
+/// objects.
+pub struct TemporalFilter {
+    pub alpha: f32,
+    pub motion_threshold: u16,
+    state: Vec<f32>,
+}
+
+impl TemporalFilter {
+    pub fn new(alpha: f32, motion_threshold: u16) -> Self {
+        Self {
+            alpha: alpha.clamp(0.01, 1.0),
+            motion_threshold,
+            state: Vec::new(),
+        }
+    }
+}
+
+impl Default for TemporalFilter {
+    fn default() -> Self {
+        // ~0.5 K at the usual 1/64 K per count
+        Self::new(0.25, 32)
+    }
+}
+
+impl DenoiseFilter for TemporalFilter {
+    fn name(&self) -> &'static str {
+        "temporal"
+    }
+
+    fn config(&self) -> Option<FilterConfig> {
+        Some(FilterConfig::Temporal {
+            alpha: self.alpha,
+            motion_threshold: self.motion_threshold,
+        })
+    }
+
+    fn apply(&mut self, field: &mut [u16], _width: usize, _height: usize) {
+        if self.state.len() != field.len() {
+            self.state = field.iter().map(|&v| v as f32).collect();
+            return;
+        }
+        let threshold = self.motion_threshold as f32;
+        for (avg, px) in self.state.iter_mut().zip(field.iter_mut()) {
+            let v = *px as f32;
+            if (v - *avg).abs() > threshold {
+                *avg = v;
+            } else {
+                *avg += self.alpha * (v - *avg);
+            }
+            *px = avg.round() as u16;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.clear();
+    }
+}
+
+/// Edge-preserving bilateral filter.
+///
+/// `sigma_range` is in raw counts; neighbours that differ by more than ~3 sigma
+/// contribute nothing, so edges between objects of different temperature stay
+/// sharp.
+pub struct BilateralFilter {
+    radius: usize,
+    sigma_spatial: f32,
+    sigma_range: f32,
+    spatial: Vec<f32>,
+    range_lut: Vec<f32>,
+    scratch: Vec<u16>,
+}
+
+impl BilateralFilter {
+    pub fn new(radius: usize, sigma_spatial: f32, sigma_range: f32) -> Self {
+        let r = radius as isize;
+        let mut spatial = Vec::with_capacity((2 * radius + 1).pow(2));
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let d2 = (dx * dx + dy * dy) as f32;
+                spatial.push((-d2 / (2.0 * sigma_spatial * sigma_spatial)).exp());
+            }
+        }
+        let lut_len = (3.0 * sigma_range).ceil().max(1.0) as usize + 1;
+        let range_lut = (0..lut_len)
+            .map(|d| {
+                let d = d as f32;
+                (-d * d / (2.0 * sigma_range * sigma_range)).exp()
+            })
+            .collect();
+        Self {
+            radius,
+            sigma_spatial,
+            sigma_range,
+            spatial,
+            range_lut,
+            scratch: Vec::new(),
+        }
+    }
+}
+
+impl Default for BilateralFilter {
+    fn default() -> Self {
+        Self::new(2, 1.5, 24.0)
+    }
+}
+
+impl DenoiseFilter for BilateralFilter {
+    fn name(&self) -> &'static str {
+        "bilateral"
+    }
+
+    fn config(&self) -> Option<FilterConfig> {
+        Some(FilterConfig::Bilateral {
+            radius: self.radius,
+            sigma_spatial: self.sigma_spatial,
+            sigma_range: self.sigma_range,
+        })
+    }
+
+    fn apply(&mut self, field: &mut [u16], width: usize, height: usize) {
+        self.scratch.clear();
+        self.scratch.extend_from_slice(field);
+        let src = &self.scratch;
+        let r = self.radius as isize;
+        let side = 2 * self.radius + 1;
+
+        for y in 0..height {
+            for x in 0..width {
+                let center = src[y * width + x] as i32;
+                let mut sum = 0.0f32;
+                let mut wsum = 0.0f32;
+                for dy in -r..=r {
+                    let ny = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+                    for dx in -r..=r {
+                        let nx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+                        let v = src[ny * width + nx] as i32;
+                        let diff = (v - center).unsigned_abs() as usize;
+                        let Some(&wr) = self.range_lut.get(diff) else {
+                            continue;
+                        };
+                        let w = wr * self.spatial[(dy + r) as usize * side + (dx + r) as usize];
+                        sum += w * v as f32;
+                        wsum += w;
+                    }
+                }
+                field[y * width + x] = (sum / wsum).round() as u16;
+            }
+        }
+    }
+}
+
+/// Self-guided filter (He et al.), computed with box sums over integral images.
+///
+/// Cheaper than the bilateral filter for larger radii and free of its gradient
+/// reversal artefacts. `eps` is the regularisation in squared counts: regions
+/// whose local variance is well below `eps` are smoothed, edges are kept.
+pub struct GuidedFilter {
+    pub radius: usize,
+    pub eps: f64,
+}
+
+impl GuidedFilter {
+    pub fn new(radius: usize, eps: f64) -> Self {
+        Self { radius, eps }
+    }
+
+    /// Returns the smoothed field as f64, used by other stages (e.g. DDE) that
+    /// need the base layer without rounding.
+    pub fn smooth(&self, field: &[u16], width: usize, height: usize) -> Vec<f64> {
+        let p: Vec<f64> = field.iter().map(|&v| v as f64).collect();
+        let pp: Vec<f64> = p.iter().map(|v| v * v).collect();
+        let mean_p = box_mean(&p, width, height, self.radius);
+        let mean_pp = box_mean(&pp, width, height, self.radius);
+
+        let mut a = Vec::with_capacity(p.len());
+        let mut b = Vec::with_capacity(p.len());
+        for (&m, &mm) in mean_p.iter().zip(&mean_pp) {
+            let var = (mm - m * m).max(0.0);
+            let ai = var / (var + self.eps);
+            a.push(ai);
+            b.push(m - ai * m);
+        }
+        let mean_a = box_mean(&a, width, height, self.radius);
+        let mean_b = box_mean(&b, width, height, self.radius);
+
+        p.iter()
+            .zip(mean_a.iter().zip(&mean_b))
+            .map(|(&v, (&ma, &mb))| ma * v + mb)
+            .collect()
+    }
+}
+
+impl Default for GuidedFilter {
+    fn default() -> Self {
+        Self::new(2, 400.0)
+    }
+}
+
+impl DenoiseFilter for GuidedFilter {
+    fn name(&self) -> &'static str {
+        "guided"
+    }
+
+    fn config(&self) -> Option<FilterConfig> {
+        Some(FilterConfig::Guided {
+            radius: self.radius,
+            eps: self.eps,
+        })
+    }
+
+    fn apply(&mut self, field: &mut [u16], width: usize, height: usize) {
+        let out = self.smooth(field, width, height);
+        for (px, v) in field.iter_mut().zip(out) {
+            *px = v.round().clamp(0.0, u16::MAX as f64) as u16;
+        }
+    }
+}
+
+/// Mean over a (2r+1)^2 window with edge clamping, via a summed-area table.
+pub fn box_mean(src: &[f64], width: usize, height: usize, radius: usize) -> Vec<f64> {
+    let stride = width + 1;
+    let mut sat = vec![0.0f64; stride * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0;
+        for x in 0..width {
+            row += src[y * width + x];
+            sat[(y + 1) * stride + x + 1] = sat[y * stride + x + 1] + row;
+        }
+    }
+    let mut out = Vec::with_capacity(src.len());
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = (x + radius + 1).min(width);
+            let sum = sat[y1 * stride + x1] - sat[y0 * stride + x1] - sat[y1 * stride + x0]
+                + sat[y0 * stride + x0];
+            out.push(sum / ((y1 - y0) * (x1 - x0)) as f64);
+        }
+    }
+    out
+}
+
+/// Spatio-temporal non-local means.
+///
+/// For every pixel, patches in a small search window of the current frame and
+/// the last `depth - 1` frames are compared with the reference patch; similar
+/// patches are averaged with weights `exp(-d^2 / h^2)`. This is a lightweight
+/// cousin of V-BM3D and the strongest (and slowest) filter in the chain.
+pub struct NonLocalMeans3D {
+    pub patch_radius: usize,
+    pub search_radius: usize,
+    pub depth: usize,
+    /// Filtering strength in counts.
+    pub h: f32,
+    history: VecDeque<Vec<u16>>,
+}
+
+impl NonLocalMeans3D {
+    pub fn new(patch_radius: usize, search_radius: usize, depth: usize, h: f32) -> Self {
+        Self {
+            patch_radius,
+            search_radius,
+            depth: depth.max(1),
+            h,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Default for NonLocalMeans3D {
+    fn default() -> Self {
+        Self::new(1, 3, 3, 20.0)
+    }
+}
+
+impl DenoiseFilter for NonLocalMeans3D {
+    fn name(&self) -> &'static str {
+        "nlm3d"
+    }
+
+    fn config(&self) -> Option<FilterConfig> {
+        Some(FilterConfig::NonLocalMeans {
+            patch_radius: self.patch_radius,
+            search_radius: self.search_radius,
+            depth: self.depth,
+            h: self.h,
+        })
+    }
+
+    fn apply(&mut self, field: &mut [u16], width: usize, height: usize) {
+        if self.history.front().is_some_and(|f| f.len() != field.len()) {
+            self.history.clear();
+        }
+        self.history.push_front(field.to_vec());
+        self.history.truncate(self.depth);
+
+        let current = &self.history[0];
+        let pr = self.patch_radius as isize;
+        let sr = self.search_radius as isize;
+        let patch_len = ((2 * pr + 1) * (2 * pr + 1)) as f32;
+        let inv_h2 = 1.0 / (self.h * self.h);
+        let (w, h) = (width as isize, height as isize);
+        let at = |f: &[u16], x: isize, y: isize| -> f32 {
+            f[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize] as f32
+        };
+
+        for y in 0..h {
+            for x in 0..w {
+                let mut sum = 0.0f32;
+                let mut wsum = 0.0f32;
+                for frame in &self.history {
+                    for sy in -sr..=sr {
+                        for sx in -sr..=sr {
+                            let mut d2 = 0.0f32;
+                            for py in -pr..=pr {
+                                for px in -pr..=pr {
+                                    let a = at(current, x + px, y + py);
+                                    let b = at(frame, x + sx + px, y + sy + py);
+                                    d2 += (a - b) * (a - b);
+                                }
+                            }
+                            let weight = (-(d2 / patch_len) * inv_h2).exp();
+                            sum += weight * at(frame, x + sx, y + sy);
+                            wsum += weight;
+                        }
+                    }
+                }
+                field[(y * w + x) as usize] = (sum / wsum).round() as u16;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 24;
+    const H: usize = 16;
+
+    /// `base` counts with ±`amplitude` of deterministic noise.
+    fn noisy(base: u16, amplitude: u16, seed: &mut u32) -> Vec<u16> {
+        (0..W * H)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                base - amplitude + (*seed >> 16) as u16 % (2 * amplitude + 1)
+            })
+            .collect()
+    }
+
+    fn variance(field: &[u16]) -> f64 {
+        let mean = field.iter().map(|&v| v as f64).sum::<f64>() / field.len() as f64;
+        field
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / field.len() as f64
+    }
+
+    /// Left half `low`, right half `high`.
+    fn step(low: u16, high: u16) -> Vec<u16> {
+        (0..W * H)
+            .map(|i| if i % W < W / 2 { low } else { high })
+            .collect()
+    }
+
+    #[test]
+    fn temporal_averages_noise_and_follows_motion() {
+        let mut filter = TemporalFilter::default();
+        let mut seed = 1;
+        let mut field = Vec::new();
+        for _ in 0..20 {
+            field = noisy(18_000, 16, &mut seed);
+            filter.apply(&mut field, W, H);
+        }
+        assert!(variance(&field) < variance(&noisy(18_000, 16, &mut seed)) / 2.0);
+
+        // A jump past the motion threshold shows at once, without a trail.
+        let mut moved = vec![19_000; W * H];
+        filter.apply(&mut moved, W, H);
+        assert!(moved.iter().all(|&v| v == 19_000));
+    }
+
+    #[test]
+    fn spatial_filters_smooth_flat_areas() {
+        let filters: [Box<dyn DenoiseFilter>; 3] = [
+            Box::new(BilateralFilter::default()),
+            Box::new(GuidedFilter::default()),
+            Box::new(NonLocalMeans3D::default()),
+        ];
+        for mut filter in filters {
+            let mut seed = 2;
+            let mut field = noisy(18_000, 16, &mut seed);
+            let before = variance(&field);
+            filter.apply(&mut field, W, H);
+            assert!(variance(&field) < before / 2.0, "{}", filter.name());
+
+            filter.reset();
+            let mut flat = vec![18_000; W * H];
+            filter.apply(&mut flat, W, H);
+            assert!(flat.iter().all(|&v| v == 18_000), "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn edge_preserving_filters_keep_edges() {
+        let filters: [Box<dyn DenoiseFilter>; 2] = [
+            Box::new(BilateralFilter::default()),
+            Box::new(NonLocalMeans3D::default()),
+        ];
+        for mut filter in filters {
+            let mut field = step(17_000, 19_000);
+            filter.apply(&mut field, W, H);
+            assert_eq!(field, step(17_000, 19_000), "{}", filter.name());
+        }
+    }
+
+    #[test]
+    fn parameters_that_divide_by_zero_are_refused() {
+        for config in FilterConfig::defaults() {
+            config.validate().unwrap();
+        }
+        let bad = [
+            FilterConfig::Temporal {
+                alpha: f32::NAN,
+                motion_threshold: 100,
+            },
+            FilterConfig::Bilateral {
+                radius: 2,
+                sigma_spatial: 0.0,
+                sigma_range: 50.0,
+            },
+            FilterConfig::Guided {
+                radius: 2,
+                eps: 0.0,
+            },
+            FilterConfig::NonLocalMeans {
+                patch_radius: 1,
+                search_radius: 9,
+                depth: 2,
+                h: 10.0,
+            },
+        ];
+        for config in bad {
+            assert!(config.build().is_err(), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn chain_runs_in_order_and_round_trips() {
+        let mut chain = FilterChain::new();
+        assert!(chain.process(&[1, 2, 3], 3, 1).is_none());
+
+        for config in FilterConfig::defaults() {
+            chain.push(config.build().unwrap());
+        }
+        assert_eq!(chain.names(), ["temporal", "bilateral", "guided", "nlm3d"]);
+        assert_eq!(chain.configs(), FilterConfig::defaults());
+        let names: Vec<_> = FilterConfig::defaults().iter().map(|f| f.name()).collect();
+        assert_eq!(chain.names(), names);
+
+        let raw = vec![18_000; W * H];
+        let mut out = Some(Vec::with_capacity(W * H));
+        chain.process_into(&raw, W, H, &mut out);
+        assert_eq!(out.as_deref(), Some(&raw[..]));
+        chain.clear();
+        chain.process_into(&raw, W, H, &mut out);
+        assert!(out.is_none());
+    }
+}
@@ -0,0 +1,427 @@
+// FLIR-style radiometric JPEGs.
+//
+// The thermal data travels in an FFF container split over APP1 segments tagged
+// "FLIR\0". We write two records: RawData (the field as 16-bit counts) and
+// CameraInfo (Planck constants, emissivity, reflected/atmospheric temperature),
+// at the offsets ExifTool and the usual thermography tools read.
+//
+// FLIR counts are radiance-like: T = B / ln(R1 / (R2 * (S + O)) + F). Our Y16
+// is linear in Kelvin, so the exporter re-encodes every pixel with Planck
+// constants fitted to the frame's range and the importer converts back to
+// Kelvin * 64. For typical scenes the round trip is exact; on very wide scenes
+// (hundreds of K) the cold end loses a few counts.
+
+use crate::calibration::{Calibration, KELVIN};
+use crate::frame::ThermalFrame;
+use crate::palette::Palette;
+use crate::snapshot::{self, Snapshot, SnapshotMetadata};
+use anyhow::{Context, Result, bail, ensure};
+use eframe::egui;
+use std::path::Path;
+
+const APP1_TAG: &[u8] = b"FLIR\0";
+/// Marker, length, tag, version, segment index, last index.
+const APP1_HEADER_LEN: usize = 2 + 2 + 5 + 3;
+const APP1_MAX_CHUNK: usize = 0xFFFF - (APP1_HEADER_LEN - 2);
+
+const FFF_MAGIC: &[u8] = b"FFF\0";
+const FFF_VERSION: u32 = 100;
+const FFF_HEADER_LEN: usize = 64;
+const DIR_ENTRY_LEN: usize = 32;
+
+const RECORD_RAW_DATA: u16 = 0x0001;
+const RECORD_CAMERA_INFO: u16 = 0x0020;
+
+const RAW_HEADER_LEN: usize = 32;
+const CAMERA_INFO_LEN: usize = 0x500;
+
+// CameraInfo offsets (little-endian floats unless noted).
+const EMISSIVITY: usize = 0x20;
+const OBJECT_DISTANCE: usize = 0x24;
+const REFLECTED_TEMP: usize = 0x28;
+const ATMOSPHERIC_TEMP: usize = 0x2c;
+const IR_WINDOW_TEMP: usize = 0x30;
+const IR_WINDOW_TRANSMISSION: usize = 0x34;
+const RELATIVE_HUMIDITY: usize = 0x3c;
+const PLANCK_R1: usize = 0x58;
+const PLANCK_B: usize = 0x5c;
+const PLANCK_F: usize = 0x60;
+const ATMOSPHERIC_TRANS: usize = 0x70;
+const TEMP_RANGE_MAX: usize = 0x90;
+const TEMP_RANGE_MIN: usize = 0x94;
+const CAMERA_MODEL: usize = 0xd4;
+const PLANCK_O: usize = 0x308; // i32
+const PLANCK_R2: usize = 0x30c;
+const RAW_RANGE_MIN: usize = 0x310; // u16
+const RAW_RANGE_MAX: usize = 0x312; // u16
+const DATE_TIME: usize = 0x384; // u32 seconds, u32 milliseconds, i16 zone
+
+/// Atmospheric transmission defaults (Alpha1, Alpha2, Beta1, Beta2, X) as
+/// FLIR cameras ship them.
+const ATMOSPHERIC_DEFAULTS: [f32; 5] = [0.006569, 0.01262, -0.002276, -0.00667, 1.9];
+
+/// Planck constants mapping counts S to Kelvin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Planck {
+    pub r1: f32,
+    pub r2: f32,
+    pub b: f32,
+    pub f: f32,
+    pub o: i32,
+}
+
+impl Planck {
+    /// Typical LWIR value; any B works mathematically, this keeps the
+    /// emissivity correction of third-party tools physically sensible.
+    const B: f32 = 1428.0;
+    /// Counts the fitted range maps to, leaving headroom on both sides.
+    const LOW: f64 = 1024.0;
+    const HIGH: f64 = 64511.0;
+    const MIN_KELVIN: f64 = 150.0;
+    const MIN_SPAN: f64 = 10.0;
+
+    /// Constants that spread `min_k..=max_k` over most of the 16-bit range.
+    pub fn fit(min_k: f64, max_k: f64) -> Self {
+        let min_k = min_k.max(Self::MIN_KELVIN);
+        let max_k = max_k.max(min_k + Self::MIN_SPAN);
+        let b = Self::B as f64;
+        let u = |k: f64| 1.0 / ((b / k).exp() - 1.0);
+        let r1 = ((Self::HIGH - Self::LOW) / (u(max_k) - u(min_k))) as f32;
+        let o = (r1 as f64 * u(min_k) - Self::LOW).round() as i32;
+        Self {
+            r1,
+            r2: 1.0,
+            b: Self::B,
+            f: 1.0,
+            o,
+        }
+    }
+
+    pub fn to_counts(&self, kelvin: f64) -> f64 {
+        let (r1, r2, b, f) = (self.r1 as f64, self.r2 as f64, self.b as f64, self.f as f64);
+        r1 / (r2 * ((b / kelvin).exp() - f)) - self.o as f64
+    }
+
+    pub fn to_kelvin(&self, counts: f64) -> f64 {
+        let (r1, r2, b, f) = (self.r1 as f64, self.r2 as f64, self.b as f64, self.f as f64);
+        b / (r1 / (r2 * (counts + self.o as f64)) + f).ln()
+    }
+}
+
+/// Writes `image` as a JPEG carrying the raw field and calibration of
+/// `snapshot` in FLIR APP1 segments.
+pub fn write(path: &Path, snapshot: &Snapshot, image: &egui::ColorImage) -> Result<()> {
+    let jpeg = snapshot::encode_jpeg(image)?;
+    let fff = encode_fff(snapshot);
+    std::fs::write(
+        path,
+        snapshot::insert_segments(&jpeg, &app1_segments(&fff)?),
+    )?;
+    Ok(())
+}
+
+/// Reads a radiometric JPEG back. The field comes back as Kelvin * 64 with
+/// the file's emissivity and reflected temperature; palette and range are
+/// not stored in the file and take their defaults.
+pub fn read(path: &Path) -> Result<Snapshot> {
+    let data = std::fs::read(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    decode_fff(&extract_fff(&data)?)
+}
+
+/// The FFF container, split into numbered APP1 segments.
+fn app1_segments(fff: &[u8]) -> Result<Vec<u8>> {
+    let chunks: Vec<&[u8]> = fff.chunks(APP1_MAX_CHUNK).collect();
+    ensure!(
+        chunks.len() <= 256,
+        "Frame too large for FLIR APP1 segments"
+    );
+    let last = (chunks.len() - 1) as u8;
+    let mut out = Vec::with_capacity(fff.len() + chunks.len() * APP1_HEADER_LEN);
+    for (i, chunk) in chunks.iter().enumerate() {
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&((APP1_HEADER_LEN - 2 + chunk.len()) as u16).to_be_bytes());
+        out.extend_from_slice(APP1_TAG);
+        out.extend_from_slice(&[0x01, i as u8, last]);
+        out.extend_from_slice(chunk);
+    }
+    Ok(out)
+}
+
+/// Reassembles the FFF container from the APP1 segments of `jpeg`.
+fn extract_fff(jpeg: &[u8]) -> Result<Vec<u8>> {
+    ensure!(jpeg.starts_with(&[0xFF, 0xD8]), "Not a JPEG file");
+    let mut chunks = Vec::new();
+    let mut pos = 2;
+    // Metadata segments all precede the scan.
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && jpeg[pos + 1] != 0xDA {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let segment = jpeg
+            .get(pos + 4..pos + 2 + len)
+            .context("Truncated JPEG segment")?;
+        if jpeg[pos + 1] == 0xE1 && segment.starts_with(APP1_TAG) && segment.len() >= 8 {
+            chunks.push((segment[6], &segment[8..]));
+        }
+        pos += 2 + len;
+    }
+    ensure!(!chunks.is_empty(), "No FLIR radiometric data in this JPEG");
+    chunks.sort_by_key(|&(index, _)| index);
+    Ok(chunks
+        .into_iter()
+        .flat_map(|(_, chunk)| chunk.iter().copied())
+        .collect())
+}
+
+fn encode_fff(snapshot: &Snapshot) -> Vec<u8> {
+    let frame = &snapshot.frame;
+    let calibration = &snapshot.metadata.calibration;
+    let (lo, hi) = ThermalFrame::min_max(&frame.raw);
+    let (min_k, max_k) = (
+        calibration.apparent_kelvin(lo),
+        calibration.apparent_kelvin(hi),
+    );
+    let planck = Planck::fit(min_k as f64, max_k as f64);
+    let counts: Vec<u16> = frame
+        .raw
+        .iter()
+        .map(|&v| {
+            let kelvin = calibration.apparent_kelvin(v) as f64;
+            planck.to_counts(kelvin).round().clamp(0.0, u16::MAX as f64) as u16
+        })
+        .collect();
+
+    let mut raw = vec![0; RAW_HEADER_LEN];
+    put(&mut raw, 0, &2u16.to_le_bytes());
+    put(&mut raw, 2, &(frame.width as u16).to_le_bytes());
+    put(&mut raw, 4, &(frame.height as u16).to_le_bytes());
+    raw.extend(counts.iter().flat_map(|v| v.to_le_bytes()));
+
+    let (s_min, s_max) = ThermalFrame::min_max(&counts);
+    let reflected_k = calibration.reflected_temp + KELVIN;
+    let mut info = vec![0; CAMERA_INFO_LEN];
+    put(&mut info, 0, &2u16.to_le_bytes());
+    for (offset, value) in [
+        (EMISSIVITY, calibration.emissivity),
+        (OBJECT_DISTANCE, 1.0),
+        (REFLECTED_TEMP, reflected_k),
+        (ATMOSPHERIC_TEMP, reflected_k),
+        (IR_WINDOW_TEMP, reflected_k),
+        (IR_WINDOW_TRANSMISSION, 1.0),
+        (RELATIVE_HUMIDITY, 0.5),
+        (PLANCK_R1, planck.r1),
+        (PLANCK_B, planck.b),
+        (PLANCK_F, planck.f),
+        (PLANCK_R2, planck.r2),
+        (TEMP_RANGE_MAX, max_k),
+        (TEMP_RANGE_MIN, min_k),
+    ] {
+        put(&mut info, offset, &value.to_le_bytes());
+    }
+    for (i, value) in ATMOSPHERIC_DEFAULTS.iter().enumerate() {
+        put(&mut info, ATMOSPHERIC_TRANS + 4 * i, &value.to_le_bytes());
+    }
+    put(&mut info, PLANCK_O, &planck.o.to_le_bytes());
+    put(&mut info, RAW_RANGE_MIN, &s_min.to_le_bytes());
+    put(&mut info, RAW_RANGE_MAX, &s_max.to_le_bytes());
+    let model = format!("Thermoscope {}", snapshot.metadata.device);
+    let model = &model.as_bytes()[..model.len().min(31)];
+    put(&mut info, CAMERA_MODEL, model);
+    let timestamp = snapshot.metadata.timestamp;
+    put(&mut info, DATE_TIME, &(timestamp as u32).to_le_bytes());
+    put(
+        &mut info,
+        DATE_TIME + 4,
+        &((timestamp.fract() * 1000.0) as u32).to_le_bytes(),
+    );
+
+    let records = [(RECORD_RAW_DATA, raw), (RECORD_CAMERA_INFO, info)];
+    let mut fff = vec![0; FFF_HEADER_LEN + DIR_ENTRY_LEN * records.len()];
+    put(&mut fff, 0, FFF_MAGIC);
+    put(&mut fff, 4, b"Thermoscope");
+    put(&mut fff, 20, &FFF_VERSION.to_be_bytes());
+    put(&mut fff, 24, &(FFF_HEADER_LEN as u32).to_be_bytes());
+    put(&mut fff, 28, &(records.len() as u32).to_be_bytes());
+    put(&mut fff, 32, &(records.len() as u32 + 1).to_be_bytes());
+    for (i, (kind, data)) in records.into_iter().enumerate() {
+        let entry = FFF_HEADER_LEN + DIR_ENTRY_LEN * i;
+        let offset = fff.len() as u32;
+        put(&mut fff, entry, &kind.to_be_bytes());
+        put(&mut fff, entry + 4, &FFF_VERSION.to_be_bytes());
+        put(&mut fff, entry + 8, &(i as u32 + 1).to_be_bytes());
+        put(&mut fff, entry + 12, &offset.to_be_bytes());
+        put(&mut fff, entry + 16, &(data.len() as u32).to_be_bytes());
+        fff.extend(data);
+    }
+    fff
+}
+
+fn decode_fff(fff: &[u8]) -> Result<Snapshot> {
+    ensure!(fff.starts_with(FFF_MAGIC), "Missing FFF header");
+    let header = Bytes::new(fff, fff.get(20..24) == Some(&FFF_VERSION.to_be_bytes()[..]));
+    let dir = header.u32(24)? as usize;
+    let entries = header.u32(28)? as usize;
+    let (mut raw, mut info) = (None, None);
+    for i in 0..entries {
+        let entry = dir + DIR_ENTRY_LEN * i;
+        let (offset, len) = (
+            header.u32(entry + 12)? as usize,
+            header.u32(entry + 16)? as usize,
+        );
+        let data = fff
+            .get(offset..offset + len)
+            .context("FFF record outside the container")?;
+        match header.u16(entry)? {
+            RECORD_RAW_DATA => raw = Some(data),
+            RECORD_CAMERA_INFO => info = Some(data),
+            _ => {}
+        }
+    }
+    let raw = raw.context("No RawData record")?;
+    let info = Bytes::record(info.context("No CameraInfo record")?)?;
+
+    let planck = Planck {
+        r1: info.f32(PLANCK_R1)?,
+        r2: info.f32(PLANCK_R2)?,
+        b: info.f32(PLANCK_B)?,
+        f: info.f32(PLANCK_F)?,
+        o: info.u32(PLANCK_O)? as i32,
+    };
+    let calibration = Calibration {
+        emissivity: info.f32(EMISSIVITY)?,
+        reflected_temp: info.f32(REFLECTED_TEMP)? - KELVIN,
+        ..Calibration::default()
+    };
+    let device = info
+        .data
+        .get(CAMERA_MODEL..CAMERA_MODEL + 32)
+        .map(|s| {
+            String::from_utf8_lossy(s)
+                .trim_end_matches('\0')
+                .to_string()
+        })
+        .unwrap_or_default();
+    let timestamp = match (info.u32(DATE_TIME), info.u32(DATE_TIME + 4)) {
+        (Ok(secs), Ok(ms)) => secs as f64 + (ms % 1000) as f64 / 1000.0,
+        _ => 0.0,
+    };
+
+    let (width, height, counts) = decode_raw(raw)?;
+    let raw = counts
+        .iter()
+        .map(|&s| calibration.raw_for_apparent(planck.to_kelvin(s as f64) as f32))
+        .collect();
+    let frame = ThermalFrame {
+        width,
+        height,
+        raw,
+        ..Default::default()
+    };
+    let (lo, hi) = ThermalFrame::min_max(&frame.raw);
+    Ok(Snapshot {
+        metadata: SnapshotMetadata {
+            timestamp,
+            device,
+            sequence: 0,
+            width,
+            height,
+            palette: Palette::default(),
+            unit: Default::default(),
+            range: [calibration.to_celsius(lo), calibration.to_celsius(hi)],
+            range_locked: false,
+            calibration,
+        },
+        frame,
+    })
+}
+
+/// Width, height and counts of a RawData record. Cameras store the counts
+/// either uncompressed or as a 16-bit PNG with swapped bytes.
+fn decode_raw(record: &[u8]) -> Result<(usize, usize, Vec<u16>)> {
+    let header = Bytes::record(record)?;
+    let (width, height) = (header.u16(2)? as usize, header.u16(4)? as usize);
+    let data = &record[RAW_HEADER_LEN.min(record.len())..];
+    let counts: Vec<u16> = if data.starts_with(b"\x89PNG") {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let output = reader.next_frame(&mut buf)?;
+        ensure!(
+            output.bit_depth == png::BitDepth::Sixteen,
+            "RawData PNG is not 16-bit"
+        );
+        buf[..output.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    } else {
+        data.chunks_exact(2)
+            .map(|b| header.u16_from([b[0], b[1]]))
+            .collect()
+    };
+    if counts.len() < width * height {
+        bail!(
+            "RawData holds {} of {}x{} pixels",
+            counts.len(),
+            width,
+            height
+        );
+    }
+    Ok((width, height, counts[..width * height].to_vec()))
+}
+
+fn put(buf: &mut [u8], pos: usize, bytes: &[u8]) {
+    buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Bounds-checked reads in the byte order of a container or record.
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> Self {
+        Self { data, big_endian }
+    }
+
+    /// Records start with the u16 value 2 in their own byte order.
+    fn record(data: &'a [u8]) -> Result<Self> {
+        match data.get(..2) {
+            Some([2, 0]) => Ok(Self::new(data, false)),
+            Some([0, 2]) => Ok(Self::new(data, true)),
+            _ => bail!("Unknown FFF record byte order"),
+        }
+    }
+
+    fn array<const N: usize>(&self, pos: usize) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(pos..pos + N)
+            .context("FFF record too short")?;
+        let mut out: [u8; N] = bytes.try_into()?;
+        if self.big_endian {
+            out.reverse();
+        }
+        Ok(out)
+    }
+
+    fn u16(&self, pos: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array(pos)?))
+    }
+
+    fn u16_from(&self, bytes: [u8; 2]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array(pos)?))
+    }
+
+    fn f32(&self, pos: usize) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.array(pos)?))
+    }
+}
@@ -0,0 +1,139 @@
+use crate::alarm::AlarmStatus;
+use crate::calibration::Calibration;
+use crate::legend::{self, Legend};
+use crate::orientation::Orientation;
+use crate::tracking::HotColdSpots;
+use eframe::egui;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// One decoded Y16 frame from the sensor.
+///
+/// `raw` is the field exactly as the camera delivered it. `denoised` is filled in
+/// by the filter chain (if any) so that consumers can pick which one they trust.
+#[derive(Clone, Debug)]
+pub struct ThermalFrame {
+    pub width: usize,
+    pub height: usize,
+    pub sequence: u64,
+    /// When the frame left the camera driver; decode time unless set by the caller.
+    pub captured: Instant,
+    pub raw: Vec<u16>,
+    pub denoised: Option<Vec<u16>>,
+}
+
+impl Default for ThermalFrame {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            sequence: 0,
+            captured: Instant::now(),
+            raw: Vec::new(),
+            denoised: None,
+        }
+    }
+}
+
+impl ThermalFrame {
+    /// Decodes little-endian Y16 bytes. Returns `None` if the buffer is too short.
+    pub fn from_y16(data: &[u8], width: usize, height: usize, sequence: u64) -> Option<Self> {
+        let mut frame = Self::default();
+        frame
+            .decode_y16(data, width, height, sequence)
+            .then_some(frame)
+    }
+
+    /// Decodes into this frame, reusing the `raw` buffer. Returns `false` (and
+    /// leaves the frame untouched) if the buffer is too short.
+    pub fn decode_y16(&mut self, data: &[u8], width: usize, height: usize, sequence: u64) -> bool {
+        if data.len() < width * height * 2 {
+            return false;
+        }
+        self.raw.clear();
+        self.raw.extend(
+            data.chunks_exact(2)
+                .take(width * height)
+                .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]])),
+        );
+        (self.width, self.height, self.sequence) = (width, height, sequence);
+        self.captured = Instant::now();
+        true
+    }
+
+    /// The field to use for display and (optionally) measurement:
+    /// the denoised one when requested and available, the raw one otherwise.
+    pub fn field(&self, use_denoised: bool) -> &[u16] {
+        match &self.denoised {
+            Some(d) if use_denoised => d,
+            _ => &self.raw,
+        }
+    }
+
+    /// Reorients the frame in place (raw and denoised fields, dimensions).
+    pub fn orient(&mut self, orientation: &Orientation) {
+        self.orient_with(orientation, &mut Vec::new());
+    }
+
+    /// `orient` using `scratch` as the work buffer; buffers are swapped rather
+    /// than allocated, so a long-lived scratch makes this allocation-free.
+    pub fn orient_with(&mut self, orientation: &Orientation, scratch: &mut Vec<u16>) {
+        if orientation.is_identity() {
+            return;
+        }
+        orientation.apply_into(&self.raw, self.width, self.height, scratch);
+        std::mem::swap(&mut self.raw, scratch);
+        if let Some(d) = &mut self.denoised {
+            orientation.apply_into(d, self.width, self.height, scratch);
+            std::mem::swap(d, scratch);
+        }
+        (self.width, self.height) = orientation.output_size(self.width, self.height);
+    }
+
+    /// Wall-clock capture time (Unix seconds); `captured` is monotonic, so
+    /// this is dated relative to now.
+    pub fn unix_time(&self) -> f64 {
+        SystemTime::now()
+            .checked_sub(self.captured.elapsed())
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+
+    pub fn min_max(field: &[u16]) -> (u16, u16) {
+        field
+            .iter()
+            .fold((u16::MAX, u16::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+    }
+}
+
+/// What the engine hands to consumers (UI, exporters) for every frame.
+pub struct ProcessedFrame {
+    pub frame: ThermalFrame,
+    /// Temperature in °C for every pixel of `frame`, same layout.
+    pub temperature: Vec<f32>,
+    /// Conversion that produced `temperature`.
+    pub calibration: Calibration,
+    pub spots: HotColdSpots,
+    /// Colourised (and possibly upscaled) display image.
+    pub image: egui::ColorImage,
+    pub legend: Legend,
+    pub alarms: AlarmStatus,
+}
+
+impl ProcessedFrame {
+    /// The display image as it should be exported, with the legend burnt in
+    /// when that is enabled.
+    pub fn export_image(&self) -> egui::ColorImage {
+        if self.legend.in_exports {
+            legend::burn_in(&self.image, &self.legend, &self.spots)
+        } else {
+            self.image.clone()
+        }
+    }
+
+    pub fn temperature_at(&self, x: usize, y: usize) -> Option<f32> {
+        (x < self.frame.width && y < self.frame.height)
+            .then(|| self.temperature[y * self.frame.width + x])
+    }
+}
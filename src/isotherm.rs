@@ -0,0 +1,117 @@
+// Isotherms: pixels inside a temperature condition are painted in a highlight
+// colour on top of the normal (or greyscale) image.
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IsothermMode {
+    /// Everything at or above the threshold (°C).
+    Above(f32),
+    /// Everything at or below the threshold (°C).
+    Below(f32),
+    /// Everything within [low, high] (°C).
+    Interval(f32, f32),
+}
+
+impl IsothermMode {
+    pub fn contains(&self, t: f32) -> bool {
+        match *self {
+            IsothermMode::Above(threshold) => t >= threshold,
+            IsothermMode::Below(threshold) => t <= threshold,
+            IsothermMode::Interval(low, high) => t >= low.min(high) && t <= low.max(high),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Isotherm {
+    pub mode: IsothermMode,
+    pub color: egui::Color32,
+}
+
+impl Isotherm {
+    pub fn above(threshold: f32) -> Self {
+        Self {
+            mode: IsothermMode::Above(threshold),
+            color: egui::Color32::from_rgb(255, 0, 255),
+        }
+    }
+
+    pub fn below(threshold: f32) -> Self {
+        Self {
+            mode: IsothermMode::Below(threshold),
+            color: egui::Color32::from_rgb(0, 200, 255),
+        }
+    }
+
+    pub fn interval(low: f32, high: f32) -> Self {
+        Self {
+            mode: IsothermMode::Interval(low, high),
+            color: egui::Color32::from_rgb(0, 255, 0),
+        }
+    }
+}
+
+/// What the non-matching pixels look like while isotherms are active.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IsothermBackground {
+    #[default]
+    Greyscale,
+    Palette,
+}
+
+/// Paints matching pixels. Later isotherms win where bands overlap.
+pub fn apply(image: &mut egui::ColorImage, temperature: &[f32], isotherms: &[Isotherm]) {
+    if isotherms.is_empty() {
+        return;
+    }
+    for (pixel, &t) in image.pixels.iter_mut().zip(temperature) {
+        if let Some(iso) = isotherms.iter().rev().find(|iso| iso.mode.contains(t)) {
+            *pixel = iso.color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: egui::Color32 = egui::Color32::GRAY;
+
+    /// Colours `apply` leaves on a 0, 10, .., 50 °C row.
+    fn painted(isotherms: &[Isotherm]) -> Vec<egui::Color32> {
+        let temperature: Vec<f32> = (0..6).map(|i| i as f32 * 10.0).collect();
+        let mut image = egui::ColorImage::new([6, 1], GREY);
+        apply(&mut image, &temperature, isotherms);
+        image.pixels
+    }
+
+    /// Which pixels of the row `isotherm` painted.
+    fn mask(isotherm: Isotherm) -> Vec<bool> {
+        painted(&[isotherm])
+            .into_iter()
+            .map(|p| p == isotherm.color)
+            .collect()
+    }
+
+    #[test]
+    fn band_masks() {
+        let (x, o) = (true, false);
+        assert_eq!(mask(Isotherm::above(30.0)), [o, o, o, x, x, x]);
+        assert_eq!(mask(Isotherm::below(10.0)), [x, x, o, o, o, o]);
+        assert_eq!(mask(Isotherm::interval(15.0, 40.0)), [o, o, x, x, x, o]);
+        // Reversed bounds are the same band.
+        assert_eq!(mask(Isotherm::interval(40.0, 15.0)), [o, o, x, x, x, o]);
+    }
+
+    #[test]
+    fn later_isotherms_win() {
+        let (above, band) = (Isotherm::above(20.0), Isotherm::interval(30.0, 40.0));
+        assert_eq!(
+            painted(&[above, band]),
+            [GREY, GREY, above.color, band.color, band.color, above.color]
+        );
+        assert!(painted(&[]).iter().all(|&p| p == GREY));
+    }
+}
@@ -0,0 +1,166 @@
+// Colour scale legend: what the engine tells consumers about the colour mapping
+// of a frame, and a burn-in renderer for exported images.
+
+use crate::calibration::TemperatureUnit;
+use crate::palette::Palette;
+use crate::tracking::HotColdSpots;
+use eframe::egui;
+
+/// Colour mapping of one frame: `palette` spans `min`..`max` (°C).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Legend {
+    pub palette: Palette,
+    pub min: f32,
+    pub max: f32,
+    pub unit: TemperatureUnit,
+    /// The range is fixed rather than following the scene.
+    pub locked: bool,
+    /// Whether exported images should carry the legend burnt in.
+    pub in_exports: bool,
+}
+
+impl Legend {
+    /// Centre of the displayed range (°C).
+    pub fn level(&self) -> f32 {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Width of the displayed range (°C).
+    pub fn span(&self) -> f32 {
+        self.max - self.min
+    }
+
+    /// `count` evenly spaced tick values (°C), from max down to min.
+    pub fn ticks(&self, count: usize) -> Vec<f32> {
+        let count = count.max(2);
+        (0..count)
+            .map(|i| self.max - self.span() * i as f32 / (count - 1) as f32)
+            .collect()
+    }
+}
+
+/// Returns a copy of `image` with a colour bar, tick labels and a
+/// max/min/centre block appended on the right.
+pub fn burn_in(
+    image: &egui::ColorImage,
+    legend: &Legend,
+    spots: &HotColdSpots,
+) -> egui::ColorImage {
+    let [w, h] = image.size;
+    let s = ((h as f32 / 120.0).round() as usize).max(1);
+    let margin = 4 * s;
+    let bar_w = 6 * s;
+    let label_chars = 8;
+    let panel_w = margin + bar_w + margin + label_chars * GLYPH_ADVANCE * s + margin;
+
+    let mut out = egui::ColorImage::new([w + panel_w, h], egui::Color32::BLACK);
+    for y in 0..h {
+        let row = y * (w + panel_w);
+        out.pixels[row..row + w].copy_from_slice(&image.pixels[y * w..(y + 1) * w]);
+    }
+
+    // The bar leaves room for the text block at the bottom.
+    let text_h = 3 * GLYPH_LINE * s + margin;
+    let bar_top = margin + GLYPH_HEIGHT * s / 2;
+    let bar_bottom = h.saturating_sub(text_h + margin).max(bar_top + 1);
+    let bar_x = w + margin;
+    for y in bar_top..bar_bottom {
+        let t = 1.0 - (y - bar_top) as f32 / (bar_bottom - bar_top - 1).max(1) as f32;
+        let color = legend.palette.color(t);
+        for x in bar_x..bar_x + bar_w {
+            out.pixels[y * (w + panel_w) + x] = color;
+        }
+    }
+
+    let label_x = bar_x + bar_w + margin;
+    let ticks = legend.ticks(5);
+    for (i, &value) in ticks.iter().enumerate() {
+        let y = bar_top + (bar_bottom - bar_top - 1) * i / (ticks.len() - 1);
+        for x in bar_x + bar_w..label_x - s {
+            out.pixels[y * (w + panel_w) + x] = egui::Color32::WHITE;
+        }
+        let text = format_value(legend.unit, value);
+        draw_text(
+            &mut out,
+            label_x,
+            y.saturating_sub(GLYPH_HEIGHT * s / 2),
+            s,
+            &text,
+        );
+    }
+
+    let block_y = h.saturating_sub(text_h);
+    for (i, (tag, value)) in [
+        ('H', spots.hot.temperature),
+        ('L', spots.cold.temperature),
+        ('+', spots.center.temperature),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let text = format!("{} {}", tag, format_value(legend.unit, value));
+        draw_text(&mut out, w + margin, block_y + i * GLYPH_LINE * s, s, &text);
+    }
+    out
+}
+
+fn format_value(unit: TemperatureUnit, celsius: f32) -> String {
+    format!("{:.1}{}", unit.from_celsius(celsius), unit.symbol())
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const GLYPH_ADVANCE: usize = GLYPH_WIDTH + 1;
+const GLYPH_LINE: usize = GLYPH_HEIGHT + 2;
+
+/// 3x5 bitmap glyphs, one row per 3 bits (MSB = left), top row first.
+fn glyph(c: char) -> u16 {
+    let rows: [u8; 5] = match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '°' => [0b010, 0b101, 0b010, 0b000, 0b000],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b111, 0b100, 0b100],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        _ => [0; 5],
+    };
+    rows.iter().fold(0, |acc, &r| (acc << 3) | r as u16)
+}
+
+/// Draws `text` with its top-left corner at (x, y), each glyph pixel as an
+/// `s` x `s` block. Characters outside the font render as blanks.
+pub fn draw_text(image: &mut egui::ColorImage, x: usize, y: usize, s: usize, text: &str) {
+    let [w, h] = image.size;
+    for (i, c) in text.chars().enumerate() {
+        let bits = glyph(c);
+        let gx = x + i * GLYPH_ADVANCE * s;
+        for row in 0..GLYPH_HEIGHT {
+            for col in 0..GLYPH_WIDTH {
+                let bit = 14 - (row * GLYPH_WIDTH + col);
+                if bits >> bit & 1 == 0 {
+                    continue;
+                }
+                for py in y + row * s..y + (row + 1) * s {
+                    for px in gx + col * s..gx + (col + 1) * s {
+                        if px < w && py < h {
+                            image.pixels[py * w + px] = egui::Color32::WHITE;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,24 +1,298 @@
+pub mod alarm;
+pub mod api;
+pub mod bus;
+pub mod calibration;
+pub mod cli;
+pub mod config;
+pub mod control;
+pub mod dde;
 pub mod device;
+pub mod display;
+pub mod export;
+pub mod filters;
+pub mod flir;
+pub mod frame;
+pub mod isotherm;
+pub mod legend;
+pub mod measure;
+pub mod orientation;
+pub mod palette;
+pub mod pipeline;
+pub mod playback;
+pub mod queue;
+pub mod recording;
+pub mod sequence;
+pub mod server;
+pub mod snapshot;
+pub mod source;
+pub mod stages;
+pub mod stats;
+pub mod timelapse;
+pub mod tracking;
+pub mod ui;
+pub mod upscale;
 pub mod uvc_adapter;
 
+use crate::alarm::{AlarmEngine, AlarmStatus};
+use crate::bus::FrameBus;
+use crate::calibration::Calibration;
+use crate::config::Profile;
+use crate::control::Controller;
 use crate::device::ThermalDevice;
-use crate::uvc_adapter::UvcAdapter;
-use eframe::egui;
-use std::sync::mpsc::{channel, Sender};
+use crate::display::DisplaySettings;
+use crate::filters::FilterChain;
+use crate::frame::ProcessedFrame;
+use crate::measure::RoiSet;
+use crate::orientation::Orientation;
+use crate::pipeline::{FrameContext, Pipeline, PipelineConfig, SharedSettings, StageRegistry};
+use crate::playback::Player;
+use crate::queue::{BufferPool, OverflowPolicy, QueueConfig, QueueReceiver, QueueSender};
+use crate::recording::Recorder;
+use crate::sequence::SequenceRecorder;
+use crate::source::{CameraSource, FrameSource, SyntheticSource};
+use crate::stats::PipelineStats;
+use crate::timelapse::TimeLapse;
+use crate::tracking::SpotTracker;
+use crate::uvc_adapter::{RawFrame, StreamMode, UvcAdapter};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct ThermalEngine {
-    frame_tx: Sender<egui::ColorImage>,
+    frame_tx: QueueSender<ProcessedFrame>,
+    raw_queue: QueueConfig,
+    stream: StreamMode,
+    recycle_tx: QueueSender<ProcessedFrame>,
+    recycle_rx: Arc<QueueReceiver<ProcessedFrame>>,
+    settings: SharedSettings,
+    pipeline: Arc<Mutex<Pipeline>>,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    stats: Arc<Mutex<PipelineStats>>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    sequence: Arc<Mutex<Option<SequenceRecorder>>>,
+    timelapse: Arc<Mutex<Option<TimeLapse>>>,
+    bus: FrameBus,
+    rois: Arc<Mutex<RoiSet>>,
+    /// Id of the playback that owns the pipeline; 0 while the camera does.
+    playback: Arc<AtomicU64>,
 }
 
 impl ThermalEngine {
-    pub fn new(frame_tx: Sender<egui::ColorImage>) -> Self {
-        Self { frame_tx }
+    /// `frame_tx` is the caller's end of a `queue::bounded` queue; its policy
+    /// decides what happens when the consumer falls behind.
+    pub fn new(frame_tx: QueueSender<ProcessedFrame>) -> Self {
+        let (recycle_tx, recycle_rx) = queue::bounded(QueueConfig {
+            capacity: 4,
+            policy: OverflowPolicy::DropNewest,
+        });
+        let settings = SharedSettings::default();
+        Self {
+            frame_tx,
+            raw_queue: QueueConfig::default(),
+            stream: StreamMode::default(),
+            recycle_tx,
+            recycle_rx: Arc::new(recycle_rx),
+            pipeline: Arc::new(Mutex::new(Pipeline::standard(&settings))),
+            settings,
+            alarms: Arc::new(Mutex::new(AlarmEngine::new())),
+            stats: Arc::new(Mutex::new(PipelineStats::default())),
+            recorder: Arc::new(Mutex::new(None)),
+            sequence: Arc::new(Mutex::new(None)),
+            timelapse: Arc::new(Mutex::new(None)),
+            bus: FrameBus::new(),
+            rois: Arc::new(Mutex::new(RoiSet::default())),
+            playback: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Capacity and overflow policy of the queue between the libuvc callback
+    /// and the engine thread.
+    pub fn with_raw_queue(mut self, config: QueueConfig) -> Self {
+        self.raw_queue = config;
+        self
+    }
+
+    /// Resolution and frame rate to request from the camera.
+    pub fn with_stream_mode(mut self, mode: StreamMode) -> Self {
+        self.stream = mode;
+        self
+    }
+
+    pub fn with_filters(self, chain: FilterChain) -> Self {
+        *self.settings.filters.lock().unwrap() = chain;
+        self
+    }
+
+    /// Shared handle to the denoising chain; it can be edited while streaming.
+    pub fn filters(&self) -> Arc<Mutex<FilterChain>> {
+        self.settings.filters.clone()
+    }
+
+    pub fn with_display(self, settings: DisplaySettings) -> Self {
+        *self.settings.display.lock().unwrap() = settings;
+        self
+    }
+
+    /// Shared handle to the display settings (DDE etc.).
+    pub fn display(&self) -> Arc<Mutex<DisplaySettings>> {
+        self.settings.display.clone()
+    }
+
+    pub fn with_orientation(self, orientation: Orientation) -> Self {
+        *self.settings.orientation.lock().unwrap() = orientation;
+        self
+    }
+
+    /// Shared handle to the frame orientation (mirror/flip/rotate).
+    pub fn orientation(&self) -> Arc<Mutex<Orientation>> {
+        self.settings.orientation.clone()
+    }
+
+    pub fn with_calibration(self, calibration: Calibration) -> Self {
+        *self.settings.calibration.lock().unwrap() = calibration;
+        self
+    }
+
+    /// Shared handle to the raw-to-temperature conversion parameters.
+    pub fn calibration(&self) -> Arc<Mutex<Calibration>> {
+        self.settings.calibration.clone()
+    }
+
+    /// Shared handle to the hot/cold spot tracker settings.
+    pub fn tracker(&self) -> Arc<Mutex<SpotTracker>> {
+        self.settings.tracker.clone()
+    }
+
+    /// All settings handles shared by the built-in pipeline stages.
+    pub fn settings(&self) -> SharedSettings {
+        self.settings.clone()
+    }
+
+    pub fn with_pipeline(self, pipeline: Pipeline) -> Self {
+        *self.pipeline.lock().unwrap() = pipeline;
+        self
+    }
+
+    /// Shared handle to the processing pipeline; stages can be added, removed,
+    /// reordered and toggled while streaming.
+    pub fn pipeline(&self) -> Arc<Mutex<Pipeline>> {
+        self.pipeline.clone()
+    }
+
+    /// Replaces the pipeline with the one described by `config`.
+    pub fn load_pipeline(
+        &self,
+        config: &PipelineConfig,
+        registry: &StageRegistry,
+    ) -> anyhow::Result<()> {
+        let pipeline = Pipeline::from_config(config, &self.settings, registry)?;
+        *self.pipeline.lock().unwrap() = pipeline;
+        Ok(())
+    }
+
+    /// Switches to `profile`: its pipeline (or the standard one), calibration,
+    /// display settings and alarm rules.
+    pub fn apply_profile(&self, profile: &Profile, registry: &StageRegistry) -> anyhow::Result<()> {
+        let pipeline = profile.build(&self.settings, registry)?;
+        *self.pipeline.lock().unwrap() = pipeline;
+        let rules = profile.alarms.clone().unwrap_or_default();
+        self.alarms.lock().unwrap().set_rules(rules);
+        Ok(())
+    }
+
+    /// Shared handle to the alarm rules; evaluated in the engine thread.
+    pub fn alarms(&self) -> Arc<Mutex<AlarmEngine>> {
+        self.alarms.clone()
+    }
+
+    /// Consumers hand finished frames back here so that their buffers are
+    /// reused for later frames instead of being reallocated.
+    pub fn recycler(&self) -> QueueSender<ProcessedFrame> {
+        self.recycle_tx.clone()
+    }
+
+    /// Shared pipeline statistics; the UI adds its display-side figures.
+    pub fn stats(&self) -> Arc<Mutex<PipelineStats>> {
+        self.stats.clone()
+    }
+
+    /// The active recording, if any. Every processed frame is offered to it
+    /// from the engine thread; put a `Recorder` here to start recording.
+    pub fn recorder(&self) -> Arc<Mutex<Option<Recorder>>> {
+        self.recorder.clone()
+    }
+
+    /// The active raw sequence recording (`.tseq`), if any; fed every
+    /// processed frame from the engine thread.
+    pub fn sequence_recorder(&self) -> Arc<Mutex<Option<SequenceRecorder>>> {
+        self.sequence.clone()
+    }
+
+    /// The active time-lapse, if any; sampled from the engine thread and
+    /// cleared once its duration has passed.
+    pub fn timelapse(&self) -> Arc<Mutex<Option<TimeLapse>>> {
+        self.timelapse.clone()
+    }
+
+    /// Every processed frame, for any number of readers besides the frame
+    /// queue's consumer (e.g. the HTTP server).
+    pub fn bus(&self) -> FrameBus {
+        self.bus.clone()
+    }
+
+    /// The measurement ROIs, shared by the window's tools and the control
+    /// API.
+    pub fn rois(&self) -> Arc<Mutex<RoiSet>> {
+        self.rois.clone()
+    }
+
+    /// The command layer on top of the engine's handles; `device` goes into
+    /// the metadata of recordings it starts.
+    pub fn controller(&self, device: &str) -> Controller {
+        Controller::new(self, device)
+    }
+
+    /// Opens recordings and replays them through the pipeline in place of the
+    /// camera.
+    pub fn player(&self) -> Player {
+        Player::new(self.worker(), self.playback.clone())
+    }
+
+    fn worker(&self) -> Worker {
+        Worker {
+            tx: self.frame_tx.clone(),
+            recycled: self.recycle_rx.clone(),
+            settings: self.settings.clone(),
+            pipeline: self.pipeline.clone(),
+            alarms: self.alarms.clone(),
+            rois: self.rois.clone(),
+            stats: self.stats.clone(),
+            recorder: self.recorder.clone(),
+            sequence: self.sequence.clone(),
+            timelapse: self.timelapse.clone(),
+            bus: self.bus.clone(),
+        }
+    }
+
+    /// Streams generated frames instead of the camera's (see
+    /// `SyntheticSource`), for demos and tests without hardware.
+    pub fn start_synthetic(&self) {
+        let worker = self.worker();
+        let mut source =
+            SyntheticSource::new(self.stream, self.stats.clone(), self.playback.clone());
+        thread::spawn(move || {
+            println!("🎬 Synthetic source: Streaming active!");
+            worker.run(&mut source);
+        });
     }
 
     pub fn start(&self, vid: u16, pid: u16) {
-        let tx = self.frame_tx.clone();
+        let worker = self.worker();
+        let stats = self.stats.clone();
+        let playback = self.playback.clone();
+        let raw_queue = self.raw_queue;
+        let mode = self.stream;
 
         thread::spawn(move || match ThermalDevice::standalone_unlock(vid, pid) {
             Ok(_) => {
@@ -36,85 +310,21 @@ impl ThermalEngine {
                 match adapter.open_device(vid as i32, pid as i32) {
                     Ok(_) => {
                         println!("✅ libuvc: Device opened.");
-                        let (raw_tx, raw_rx) = channel::<Vec<u8>>();
-                        match adapter.start_streaming(raw_tx) {
+                        let (raw_tx, raw_rx) = queue::bounded::<RawFrame>(raw_queue);
+                        // One buffer per queue slot, plus the ones being filled
+                        // by the callback and decoded by this thread.
+                        let pool = BufferPool::new(raw_queue.capacity + 2, mode.frame_bytes());
+                        match adapter.start_streaming(raw_tx, pool.clone(), mode) {
                             Ok(_) => {
                                 println!("🎬 libuvc: Streaming active!");
-                                let width = 256;
-                                let height = 192;
-
-                                let mut local_count = 0;
-                                while let Ok(frame_data) = raw_rx.recv() {
-                                    if frame_data.len() >= width * height * 2 {
-                                        local_count += 1;
-                                        if local_count % 30 == 0 {
-                                            println!(
-                                                "🔥 Background: Processed frame {}...",
-                                                local_count
-                                            );
-                                        }
-
-                                        let mut min = u16::MAX;
-                                        let mut max = u16::MIN;
-
-                                        let raw_values: Vec<u16> = frame_data
-                                            .chunks_exact(2)
-                                            .take(width * height)
-                                            .map(|chunk| {
-                                                let val = u16::from_le_bytes([chunk[0], chunk[1]]);
-                                                if val < min {
-                                                    min = val;
-                                                }
-                                                if val > max {
-                                                    max = val;
-                                                }
-                                                val
-                                            })
-                                            .collect();
-
-                                        let mut rgb_image = egui::ColorImage::new(
-                                            [width, height],
-                                            egui::Color32::BLACK,
-                                        );
-                                        let range = (max - min) as f32;
-                                        if range > 0.0 {
-                                            let inv_range = 1.0 / range;
-                                            for (i, pixel) in
-                                                rgb_image.pixels.iter_mut().enumerate()
-                                            {
-                                                let t = (raw_values[i] - min) as f32 * inv_range;
-                                                *pixel = if t < 0.25 {
-                                                    egui::Color32::from_rgb(
-                                                        0,
-                                                        0,
-                                                        (t * 1020.0) as u8,
-                                                    )
-                                                } else if t < 0.5 {
-                                                    egui::Color32::from_rgb(
-                                                        ((t - 0.25) * 1020.0) as u8,
-                                                        0,
-                                                        (255.0 - (t - 0.25) * 1020.0) as u8,
-                                                    )
-                                                } else if t < 0.75 {
-                                                    egui::Color32::from_rgb(
-                                                        255,
-                                                        ((t - 0.5) * 1020.0) as u8,
-                                                        0,
-                                                    )
-                                                } else {
-                                                    egui::Color32::from_rgb(
-                                                        255,
-                                                        255,
-                                                        ((t - 0.75) * 1020.0) as u8,
-                                                    )
-                                                };
-                                            }
-                                        }
-                                        if tx.send(rgb_image).is_err() {
-                                            break;
-                                        }
-                                    }
-                                }
+                                let mut source = CameraSource::new(
+                                    raw_rx,
+                                    pool,
+                                    (mode.width, mode.height),
+                                    stats,
+                                    playback,
+                                );
+                                worker.run(&mut source);
                             }
                             Err(e) => eprintln!("❌ libuvc: Failed to start streaming: {}", e),
                         }
@@ -126,3 +336,104 @@ impl ThermalEngine {
         });
     }
 }
+
+/// The handles the processing loop works with; one copy per source thread.
+#[derive(Clone)]
+pub(crate) struct Worker {
+    tx: QueueSender<ProcessedFrame>,
+    recycled: Arc<QueueReceiver<ProcessedFrame>>,
+    settings: SharedSettings,
+    pipeline: Arc<Mutex<Pipeline>>,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    rois: Arc<Mutex<RoiSet>>,
+    stats: Arc<Mutex<PipelineStats>>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    sequence: Arc<Mutex<Option<SequenceRecorder>>>,
+    timelapse: Arc<Mutex<Option<TimeLapse>>>,
+    bus: FrameBus,
+}
+
+impl Worker {
+    /// Processes frames from `source` until it ends or the consumer is gone.
+    pub(crate) fn run(&self, source: &mut dyn FrameSource) {
+        self.pipeline.lock().unwrap().reset();
+        self.stats.lock().unwrap().reset();
+
+        let mut local_count = 0;
+        // Buffers of frames the consumer dropped or handed back; decoding
+        // into them avoids per-frame allocation.
+        let mut spare: Option<ProcessedFrame> = None;
+        // Shown again on refreshed frames, which are not evaluated.
+        let mut alarms = AlarmStatus::default();
+        loop {
+            let mut ctx = match spare.take().or_else(|| self.recycled.try_recv()) {
+                Some(old) => FrameContext::recycle(old),
+                None => FrameContext::default(),
+            };
+            if !source.next_frame(&mut ctx.frame) {
+                break;
+            }
+            if source.discontinuity() {
+                self.pipeline.lock().unwrap().reset();
+            }
+            if source.is_refresh() {
+                self.pipeline.lock().unwrap().process(&mut ctx);
+                let mut processed = ctx.into_processed();
+                processed.alarms = AlarmStatus {
+                    events: Vec::new(),
+                    ..alarms.clone()
+                };
+                self.bus.publish(&processed);
+                match self.tx.push(processed) {
+                    Ok(dropped) => spare = dropped,
+                    Err(_) => break,
+                }
+                continue;
+            }
+            local_count += 1;
+            if local_count % 30 == 0 {
+                println!("🔥 Background: Processed frame {}...", local_count);
+            }
+
+            // Sensor frames, before the pipeline, so that playback
+            // reprocesses them exactly like live ones.
+            if let Some(sequence) = self.sequence.lock().unwrap().as_mut() {
+                sequence.offer(&ctx.frame, &self.settings.calibration.lock().unwrap());
+            }
+            let sensor = (ctx.frame.width, ctx.frame.height);
+            self.pipeline.lock().unwrap().process(&mut ctx);
+            let mut processed = ctx.into_processed();
+            let now = Instant::now();
+            processed.alarms = {
+                // ROIs before alarms, like the alarm editor.
+                let mut rois = self.rois.lock().unwrap();
+                rois.set_sensor(sensor.0, sensor.1);
+                self.alarms.lock().unwrap().evaluate(&processed, &rois, now)
+            };
+            alarms.clone_from(&processed.alarms);
+            if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+                recorder.offer(&processed);
+            }
+            {
+                let mut slot = self.timelapse.lock().unwrap();
+                if let Some(lapse) = slot.as_mut() {
+                    lapse.offer(&processed, &self.settings.display);
+                    if lapse.is_finished() {
+                        println!("⏱ Time-lapse finished: {} samples.", lapse.samples());
+                        *slot = None;
+                    }
+                }
+            }
+            self.bus.publish(&processed);
+            {
+                let mut stats = self.stats.lock().unwrap();
+                stats.record_processed(processed.frame.captured, now);
+                stats.record_queue_drops(source.dropped(), self.tx.dropped());
+            }
+            match self.tx.push(processed) {
+                Ok(dropped) => spare = dropped,
+                Err(_) => break,
+            }
+        }
+    }
+}
@@ -1,59 +1,214 @@
+use clap::Parser;
 use eframe::egui;
-use std::sync::mpsc::{channel, Receiver};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use thermoscope_app::ThermalEngine;
+use thermoscope_app::alarm::AlarmEngine;
+use thermoscope_app::bus::FrameBus;
+use thermoscope_app::calibration::{Calibration, TemperatureUnit};
+use thermoscope_app::cli::{self, Cli, Command};
+use thermoscope_app::config::{Config, Profile, UiConfig};
+use thermoscope_app::control::{Action, Controller, RecordingKind, State};
+use thermoscope_app::display::{DisplaySettings, RangeMode};
+use thermoscope_app::export::{self, ExportOptions, MatrixFormat, RoiLogger};
+use thermoscope_app::filters::FilterConfig;
+use thermoscope_app::frame::ProcessedFrame;
+use thermoscope_app::isotherm::{Isotherm, IsothermBackground, IsothermMode};
+use thermoscope_app::orientation::{Orientation, Rotation};
+use thermoscope_app::palette::Palette;
+use thermoscope_app::pipeline::{Pipeline, PipelineConfig, SharedSettings, StageRegistry};
+use thermoscope_app::playback::{Playback, Player};
+use thermoscope_app::queue::{self, QueueConfig, QueueReceiver, QueueSender};
+use thermoscope_app::recording::{Recorder, RecordingConfig, RecordingFormat};
+use thermoscope_app::sequence::{self, SequenceRecorder};
+use thermoscope_app::server::StreamServer;
+use thermoscope_app::snapshot::{Snapshot, SnapshotFormat};
+use thermoscope_app::stats::PipelineStats;
+use thermoscope_app::timelapse::{TimeLapse, TimeLapseConfig};
+use thermoscope_app::tracking::SpotTracker;
+use thermoscope_app::ui::tools::MeasurementTools;
+use thermoscope_app::ui::{alarms, legend, overlay};
+use thermoscope_app::upscale::Upscaler;
 
 fn main() -> eframe::Result<()> {
     env_logger::init();
 
+    let cli = Cli::parse();
+    let config = match cli.options.load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    match cli.command {
+        None
+        | Some(Command::Stream {
+            headless: false, ..
+        }) => {}
+        Some(command) => {
+            if let Err(e) = cli::run(command, &config) {
+                eprintln!("❌ {:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1024.0, 768.0])
+            .with_inner_size(config.ui.window)
             .with_title("Thermoscope Pro"),
         ..Default::default()
     };
     eframe::run_native(
         "Thermoscope",
         options,
-        Box::new(|_cc| Box::new(MyApp::default())),
+        Box::new(move |_cc| Box::new(MyApp::new(config))),
     )
 }
 
 struct MyApp {
-    frame_rx: Receiver<egui::ColorImage>,
+    frame_rx: QueueReceiver<ProcessedFrame>,
+    recycler: QueueSender<ProcessedFrame>,
     texture: Option<egui::TextureHandle>,
-    frame_count: u64,
+    latest: Option<ProcessedFrame>,
+    tools: MeasurementTools,
     status: String,
+    display: Arc<Mutex<DisplaySettings>>,
+    orientation: Arc<Mutex<Orientation>>,
+    tracker: Arc<Mutex<SpotTracker>>,
+    show_spots: bool,
+    show_center: bool,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    show_alarms: bool,
+    show_legend: bool,
+    stats: Arc<Mutex<PipelineStats>>,
+    show_stats: bool,
+    pipeline: Arc<Mutex<Pipeline>>,
+    settings: SharedSettings,
+    /// Palette, calibration, NUC and recording changes go through here, as
+    /// they do from the control API.
+    control: Controller,
+    /// Save the next frame that arrives.
+    snapshot_request: Option<SnapshotFormat>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    recording: RecordingConfig,
+    sequence: Arc<Mutex<Option<SequenceRecorder>>>,
+    timelapse: Arc<Mutex<Option<TimeLapse>>>,
+    timelapse_config: TimeLapseConfig,
+    player: Player,
+    playback: Option<Arc<Mutex<Playback>>>,
+    /// Path typed into the Playback menu.
+    playback_path: String,
+    thumbnails: Vec<(usize, egui::TextureHandle)>,
+    export: ExportOptions,
+    /// ROI statistics of every displayed frame, while logging.
+    roi_log: Option<RoiLogger>,
+    bus: FrameBus,
+    server: Option<StreamServer>,
+    /// Address typed into the Web server menu.
+    server_bind: String,
+    /// Serve the control API along with the stream.
+    server_control: bool,
+    /// How the camera is identified in saved metadata.
+    device: String,
+    config: Config,
+    /// Name typed into the Profile menu.
+    profile_name: String,
 }
 
-impl Default for MyApp {
-    fn default() -> Self {
-        let (tx, rx) = channel();
-        let app = Self {
-            frame_rx: rx,
-            texture: None,
-            frame_count: 0,
-            status: "Initializing...".to_string(),
-        };
+impl MyApp {
+    fn new(config: Config) -> Self {
+        let (tx, rx) = queue::bounded(QueueConfig::default());
 
         // Start the thermal engine
-        let engine = ThermalEngine::new(tx);
-        engine.start(0x0bda, 0x5830);
+        let engine = ThermalEngine::new(tx).with_stream_mode(config.device.stream);
+        if let Err(e) = config
+            .active()
+            .and_then(|profile| engine.apply_profile(&profile, &StageRegistry::new()))
+        {
+            eprintln!("❌ Failed to apply profile: {}", e);
+        }
+        config.device.start(&engine);
 
-        app
+        Self {
+            frame_rx: rx,
+            recycler: engine.recycler(),
+            texture: None,
+            latest: None,
+            tools: MeasurementTools::new(engine.rois()),
+            status: "Initializing...".to_string(),
+            display: engine.display(),
+            orientation: engine.orientation(),
+            tracker: engine.tracker(),
+            show_spots: config.ui.show_spots,
+            show_center: config.ui.show_center,
+            alarms: engine.alarms(),
+            show_alarms: false,
+            show_legend: config.ui.show_legend,
+            stats: engine.stats(),
+            show_stats: config.ui.show_stats,
+            pipeline: engine.pipeline(),
+            settings: engine.settings(),
+            control: engine.controller(&config.device.name()),
+            snapshot_request: None,
+            recorder: engine.recorder(),
+            recording: RecordingConfig::default(),
+            sequence: engine.sequence_recorder(),
+            timelapse: engine.timelapse(),
+            timelapse_config: TimeLapseConfig::default(),
+            player: engine.player(),
+            playback: None,
+            playback_path: String::new(),
+            thumbnails: Vec::new(),
+            export: ExportOptions::default(),
+            roi_log: None,
+            bus: engine.bus(),
+            server: None,
+            server_bind: config.server.bind.clone(),
+            server_control: config.server.control,
+            device: config.device.name(),
+            profile_name: config.profile.clone().unwrap_or_default(),
+            config,
+        }
     }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Drain frames and only keep the last one
-        let mut latest_image = None;
-        while let Ok(image) = self.frame_rx.try_recv() {
-            latest_image = Some(image);
-            self.frame_count += 1;
+        let mut latest_frame = None;
+        let mut drained = 0;
+        while let Some(frame) = self.frame_rx.try_recv() {
+            if let Some(superseded) = latest_frame.replace(frame) {
+                let _ = self.recycler.push(superseded);
+            }
+            drained += 1;
         }
 
-        if let Some(image) = latest_image {
+        if let Some(mut frame) = latest_frame {
+            // The colour image moves into the texture below, so save it first.
+            if let Some(format) = self.snapshot_request.take() {
+                save_snapshot(&frame, format, &self.device);
+            }
+            let image = std::mem::take(&mut frame.image);
             self.texture = Some(ctx.load_texture("thermal_feed", image, Default::default()));
+            self.stats.lock().unwrap().record_display(
+                frame.frame.captured,
+                drained - 1,
+                Instant::now(),
+            );
+            if let Some(log) = &mut self.roi_log
+                && let Err(e) = log.record(&frame, self.tools.rois.lock().unwrap().as_slice())
+            {
+                eprintln!("❌ ROI log failed, stopping: {}", e);
+                self.roi_log = None;
+            }
+            if let Some(previous) = self.latest.replace(frame) {
+                let _ = self.recycler.push(previous);
+            }
             self.status = "✔ ACTIVE".to_string();
         }
 
@@ -61,32 +216,1065 @@ impl eframe::App for MyApp {
         ctx.request_repaint();
 
         // MINIMALISTIC UI
+        self.player_panel(ctx);
         egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(egui::Color32::BLACK))
             .show(ctx, |ui| {
-                if let Some(texture) = &self.texture {
+                if let Some(texture) = self.texture.clone()
+                    && self.latest.is_some()
+                {
                     // Full window video scaling
-                    ui.add(egui::Image::new(texture).fit_to_exact_size(ui.available_size()));
+                    let response = ui.add(
+                        egui::Image::new(&texture)
+                            .fit_to_exact_size(ui.available_size())
+                            .sense(egui::Sense::click_and_drag()),
+                    );
+                    response.context_menu(|ui| self.settings_menu(ui));
+                    if let Some(frame) = &self.latest {
+                        self.tools
+                            .handle_input(&response, [frame.frame.width, frame.frame.height]);
+                        let size = [frame.frame.width, frame.frame.height];
+                        let unit = frame.legend.unit;
+                        if self.show_spots {
+                            overlay::paint_spots(
+                                ui.painter(),
+                                response.rect,
+                                size,
+                                &frame.spots,
+                                self.show_center,
+                                unit,
+                            );
+                        }
+                        if self.show_center {
+                            overlay::paint_center_readout(
+                                ui.painter(),
+                                response.rect,
+                                &frame.spots,
+                                unit,
+                            );
+                        }
+                        if self.show_legend {
+                            let bar = legend::colorbar(
+                                ui,
+                                response.rect,
+                                &frame.legend,
+                                egui::Sense::click_and_drag(),
+                            );
+                            let before = self.display.lock().unwrap().range;
+                            let mut range = before;
+                            legend::range_drag(&bar, &frame.legend, &mut range);
+                            if range != before
+                                && let Err(e) = self.control.execute(Action::SetRange { range })
+                            {
+                                eprintln!("❌ {}", e);
+                            }
+                            legend::readouts(
+                                ui.painter(),
+                                response.rect,
+                                &frame.legend,
+                                &frame.spots,
+                            );
+                        }
+                        self.tools.paint(ui.painter(), response.rect, frame);
+                        alarms::paint_status(
+                            ui.painter(),
+                            response.rect,
+                            &frame.alarms,
+                            ui.input(|i| i.time),
+                        );
+                    }
                 } else {
                     ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(&self.status)
-                                .color(egui::Color32::LIGHT_GRAY)
-                                .size(20.0),
-                        );
+                        ui.add(
+                            egui::Label::new(
+                                egui::RichText::new(&self.status)
+                                    .color(egui::Color32::LIGHT_GRAY)
+                                    .size(20.0),
+                            )
+                            .sense(egui::Sense::click()),
+                        )
+                        // Recordings can be played without a camera.
+                        .context_menu(|ui| self.playback_menu(ui));
                     });
                 }
 
                 // Overlay status
+                let snapshot = self.stats.lock().unwrap().snapshot();
                 let painter = ui.painter();
                 let rect = ui.max_rect();
                 painter.text(
                     rect.left_top() + egui::vec2(10.0, 10.0),
                     egui::Align2::LEFT_TOP,
-                    format!("FPS: ~{}", self.frame_count / 30), // Extremely rough estimate
+                    format!("FPS: {:.1}", snapshot.display_fps),
                     egui::FontId::proportional(12.0),
                     egui::Color32::from_white_alpha(100),
                 );
+                if self.show_stats {
+                    overlay::paint_stats(painter, rect, &snapshot);
+                }
+                let recording = self
+                    .recorder
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|r| r.elapsed())
+                    .or_else(|| self.sequence.lock().unwrap().as_ref().map(|s| s.elapsed()));
+                if let Some(elapsed) = recording {
+                    let secs = elapsed.as_secs();
+                    painter.text(
+                        rect.left_top() + egui::vec2(80.0, 10.0),
+                        egui::Align2::LEFT_TOP,
+                        format!("● REC {:02}:{:02}", secs / 60, secs % 60),
+                        egui::FontId::proportional(12.0),
+                        egui::Color32::RED,
+                    );
+                }
+            });
+
+        egui::Area::new(egui::Id::new("tools"))
+            .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+            .show(ctx, |ui| self.tools.toolbar(ui));
+        if let Some(frame) = &self.latest {
+            self.tools.results_window(ctx, frame);
+            let before = self.display.lock().unwrap().range;
+            let mut range = before;
+            legend::range_keys(ctx, &frame.legend, &mut range);
+            if range != before
+                && let Err(e) = self.control.execute(Action::SetRange { range })
+            {
+                eprintln!("❌ {}", e);
+            }
+        }
+        if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::S)) {
+            self.snapshot_request = Some(SnapshotFormat::Png);
+        }
+        if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::R)) {
+            self.toggle_recording();
+        }
+        if let Some(playback) = &self.playback
+            && !ctx.wants_keyboard_input()
+        {
+            let mut state = playback.lock().unwrap();
+            ctx.input(|i| {
+                if i.key_pressed(egui::Key::Space) {
+                    state.toggle();
+                }
+                if i.key_pressed(egui::Key::ArrowLeft) {
+                    state.step(-1);
+                }
+                if i.key_pressed(egui::Key::ArrowRight) {
+                    state.step(1);
+                }
             });
+        }
+        // Clip formats have a maximum length.
+        let full = self
+            .recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|r| r.is_full());
+        if full {
+            self.toggle_recording();
+        }
+        if self.show_alarms {
+            let rois = self.tools.rois.lock().unwrap();
+            let selected = self.tools.selected.and_then(|id| rois.get(id));
+            alarms::alarm_window(
+                ctx,
+                &mut self.show_alarms,
+                &mut self.alarms.lock().unwrap(),
+                selected,
+            );
+        }
     }
 }
+
+impl MyApp {
+    fn settings_menu(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Alarms…").clicked() {
+            self.show_alarms = true;
+            ui.close_menu();
+        }
+        ui.menu_button("Statistics", |ui| {
+            ui.checkbox(&mut self.show_stats, "Debug overlay");
+            if ui.button("Export to stats.jsonl").clicked() {
+                let snapshot = self.stats.lock().unwrap().snapshot();
+                if let Err(e) = snapshot.append_to(Path::new("stats.jsonl")) {
+                    eprintln!("❌ Failed to export statistics: {}", e);
+                }
+                ui.close_menu();
+            }
+        });
+        ui.menu_button("Snapshot", |ui| {
+            for format in SnapshotFormat::ALL {
+                if ui.button(format!("Save {} + raw", format.name())).clicked() {
+                    self.snapshot_request = Some(format);
+                    ui.close_menu();
+                }
+            }
+        });
+        ui.menu_button("Playback", |ui| self.playback_menu(ui));
+        ui.menu_button("Recording", |ui| self.recording_menu(ui));
+        ui.menu_button("Raw sequence", |ui| self.sequence_menu(ui));
+        ui.menu_button("Time-lapse", |ui| self.timelapse_menu(ui));
+        ui.menu_button("Export", |ui| self.export_menu(ui));
+        ui.menu_button("Profile", |ui| self.profile_menu(ui));
+        ui.menu_button("Web server", |ui| self.server_menu(ui));
+        // Changes go through the controller, like those from the control API;
+        // it locks the settings itself, so they are collected here and run
+        // once the menus are done.
+        let mut actions = Vec::new();
+        ui.menu_button("Markers", |ui| {
+            ui.checkbox(&mut self.show_spots, "Hot/cold spots");
+            ui.checkbox(&mut self.show_center, "Centre spot");
+            let tracker = self.tracker.lock().unwrap();
+            let (mut subpixel, mut smoothing) = (tracker.subpixel, tracker.smoothing);
+            drop(tracker);
+            if ui.checkbox(&mut subpixel, "Sub-pixel").changed() {
+                actions.push(Action::SetTracker {
+                    subpixel: Some(subpixel),
+                    smoothing: None,
+                });
+            }
+            if ui
+                .add(egui::Slider::new(&mut smoothing, 0.0..=0.9).text("Smoothing"))
+                .changed()
+            {
+                actions.push(Action::SetTracker {
+                    subpixel: None,
+                    smoothing: Some(smoothing),
+                });
+            }
+        });
+
+        let display = self.display.lock().unwrap().clone();
+        ui.menu_button("Palette", |ui| {
+            for palette in Palette::ALL {
+                if ui
+                    .radio(display.palette == palette, palette.name())
+                    .clicked()
+                {
+                    actions.push(Action::SetPalette { palette });
+                }
+            }
+        });
+        ui.menu_button("Isotherm", |ui| actions.extend(isotherm_menu(ui, &display)));
+        ui.menu_button("Range", |ui| {
+            let mut range = display.range;
+            range_menu(ui, &mut range, display.unit, self.latest.as_ref());
+            if range != display.range {
+                actions.push(Action::SetRange { range });
+            }
+        });
+        ui.menu_button("Legend", |ui| {
+            ui.checkbox(&mut self.show_legend, "Show colour bar");
+            let mut burn_legend = display.burn_legend;
+            if ui.checkbox(&mut burn_legend, "Burn into exports").changed() {
+                actions.push(Action::SetBurnLegend { burn_legend });
+            }
+            ui.separator();
+            for unit in TemperatureUnit::ALL {
+                if ui.radio(display.unit == unit, unit.symbol()).clicked() {
+                    actions.push(Action::SetUnit { unit });
+                }
+            }
+        });
+        ui.menu_button("Upscaling", |ui| {
+            for scale in [1, 2, 4] {
+                if ui
+                    .radio(display.scale == scale, format!("{}x", scale))
+                    .clicked()
+                {
+                    actions.push(Action::SetUpscaling {
+                        scale: Some(scale),
+                        upscaler: None,
+                    });
+                }
+            }
+            ui.separator();
+            for upscaler in Upscaler::ALL {
+                if ui
+                    .radio(display.upscaler == upscaler, upscaler.name())
+                    .clicked()
+                {
+                    actions.push(Action::SetUpscaling {
+                        scale: None,
+                        upscaler: Some(upscaler),
+                    });
+                }
+            }
+        });
+        ui.menu_button("Denoise", |ui| actions.extend(self.denoise_menu(ui)));
+        ui.menu_button("Calibration", |ui| {
+            let calibration = *self.settings.calibration.lock().unwrap();
+            actions.extend(calibration_menu(ui, calibration, display.unit));
+        });
+        ui.menu_button("Pipeline", |ui| self.pipeline_menu(ui, &mut actions));
+
+        let before = *self.orientation.lock().unwrap();
+        let mut orientation = before;
+        ui.menu_button("Orientation", |ui| {
+            ui.checkbox(&mut orientation.mirror, "Mirror");
+            ui.checkbox(&mut orientation.flip, "Flip");
+            ui.separator();
+            for rotation in Rotation::ALL {
+                ui.radio_value(&mut orientation.rotation, rotation, rotation.name());
+            }
+        });
+        if orientation != before {
+            self.tools.cancel_line();
+            actions.push(Action::SetOrientation { orientation });
+        }
+
+        for action in actions {
+            if let Err(e) = self.control.execute(action) {
+                eprintln!("❌ {}", e);
+            }
+        }
+    }
+
+    /// The filter chain and whether measurements use its output.
+    fn denoise_menu(&self, ui: &mut egui::Ui) -> Option<Action> {
+        let (current, use_for_measurement) = {
+            let chain = self.settings.filters.lock().unwrap();
+            (chain.configs(), chain.use_for_measurement)
+        };
+        let mut action = None;
+        for filter in FilterConfig::defaults() {
+            let label = match filter {
+                FilterConfig::Temporal { .. } => "Temporal",
+                FilterConfig::Bilateral { .. } => "Bilateral",
+                FilterConfig::Guided { .. } => "Guided",
+                FilterConfig::NonLocalMeans { .. } => "Non-local means (3D)",
+            };
+            let mut on = current.iter().any(|f| f.name() == filter.name());
+            if ui.checkbox(&mut on, label).changed() {
+                // The usual order; filters that stay keep their parameters.
+                let filters = FilterConfig::defaults()
+                    .into_iter()
+                    .filter_map(|default| {
+                        if default.name() == filter.name() {
+                            on.then_some(default)
+                        } else {
+                            current.iter().find(|f| f.name() == default.name()).cloned()
+                        }
+                    })
+                    .collect();
+                action = Some(Action::SetDenoise {
+                    filters: Some(filters),
+                    use_for_measurement: None,
+                });
+            }
+        }
+        ui.separator();
+        let mut measure = use_for_measurement;
+        if ui
+            .add_enabled(
+                !current.is_empty(),
+                egui::Checkbox::new(&mut measure, "Use for measurements"),
+            )
+            .changed()
+        {
+            action = Some(Action::SetDenoise {
+                filters: None,
+                use_for_measurement: Some(measure),
+            });
+        }
+        action
+    }
+
+    fn recording_menu(&mut self, ui: &mut egui::Ui) {
+        let active = self.recorder.lock().unwrap().as_ref().map(|r| {
+            (
+                r.path().display().to_string(),
+                r.elapsed().as_secs(),
+                r.dropped(),
+            )
+        });
+        if let Some((path, secs, dropped)) = active {
+            ui.label(format!("⏺ {} — {}:{:02}", path, secs / 60, secs % 60));
+            if dropped > 0 {
+                ui.label(format!("{} frames dropped by the encoder", dropped));
+            }
+            if ui.button("⏹ Stop (R)").clicked() {
+                self.toggle_recording();
+                ui.close_menu();
+            }
+            return;
+        }
+        for format in RecordingFormat::ALL {
+            ui.radio_value(&mut self.recording.format, format, format.name());
+        }
+        ui.add(
+            egui::Slider::new(&mut self.recording.fps, 1.0..=30.0)
+                .text("fps")
+                .integer(),
+        );
+        ui.checkbox(
+            &mut self.recording.burn_overlays,
+            "Burn in legend and spots",
+        );
+        if ui.button("⏺ Start (R)").clicked() {
+            self.toggle_recording();
+            ui.close_menu();
+        }
+    }
+
+    fn playback_menu(&mut self, ui: &mut egui::Ui) {
+        if self.playback.is_some() && ui.button("⏏ Close playback").clicked() {
+            self.player.close();
+            ui.close_menu();
+        }
+        let mut recent: Vec<_> = std::fs::read_dir("recordings")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|e| {
+                e.path()
+                    .extension()
+                    .is_some_and(|x| x == sequence::EXTENSION)
+            })
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+            .collect();
+        recent.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        let mut open = None;
+        for (_, path) in recent.into_iter().take(10) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if ui.button(format!("▶ {}", name)).clicked() {
+                open = Some(path.clone());
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.playback_path)
+                    .hint_text("path/to/recording.tseq"),
+            );
+            if ui.button("Open").clicked() {
+                open = Some(self.playback_path.trim().into());
+            }
+        });
+        if let Some(path) = open {
+            match self.player.open(&path) {
+                Ok(playback) => {
+                    self.playback = Some(playback);
+                    self.thumbnails.clear();
+                }
+                Err(e) => eprintln!("❌ Cannot play {}: {}", path.display(), e),
+            }
+            ui.close_menu();
+        }
+    }
+
+    /// Timeline and transport controls while a recording plays.
+    fn player_panel(&mut self, ctx: &egui::Context) {
+        let Some(playback) = self.playback.clone() else {
+            return;
+        };
+        let mut state = playback.lock().unwrap();
+        if state.is_closed() {
+            self.playback = None;
+            self.thumbnails.clear();
+            return;
+        }
+        for (n, image) in state.thumbnails.drain(..) {
+            let texture = ctx.load_texture(format!("thumbnail_{}", n), image, Default::default());
+            self.thumbnails.push((n, texture));
+        }
+        egui::TopBottomPanel::bottom("player").show(ctx, |ui| {
+            if !self.thumbnails.is_empty() {
+                ui.horizontal(|ui| {
+                    for (n, texture) in &self.thumbnails {
+                        let thumbnail =
+                            egui::Image::new(texture).fit_to_exact_size(egui::vec2(64.0, 48.0));
+                        if ui
+                            .add(egui::ImageButton::new(thumbnail).selected(*n == state.position))
+                            .on_hover_text(format!("Frame {}", n + 1))
+                            .clicked()
+                        {
+                            state.seek(*n);
+                        }
+                    }
+                });
+            }
+            let last = state.len - 1;
+            let mut position = state.position;
+            ui.spacing_mut().slider_width = ui.available_width();
+            if ui
+                .add(egui::Slider::new(&mut position, 0..=last).show_value(false))
+                .changed()
+            {
+                state.seek(position);
+            }
+            ui.horizontal(|ui| {
+                let play = if state.playing { "⏸" } else { "▶" };
+                if ui
+                    .button(play)
+                    .on_hover_text("Play/pause (Space)")
+                    .clicked()
+                {
+                    state.toggle();
+                }
+                if ui.button("⏮").on_hover_text("Previous frame (←)").clicked() {
+                    state.step(-1);
+                }
+                if ui.button("⏭").on_hover_text("Next frame (→)").clicked() {
+                    state.step(1);
+                }
+                ui.label(format!(
+                    "{} / {}   frame {}/{}",
+                    clock(state.time),
+                    clock(state.duration),
+                    state.position + 1,
+                    state.len
+                ));
+                egui::ComboBox::from_id_source("playback_speed")
+                    .selected_text(format!("{}×", state.speed))
+                    .width(60.0)
+                    .show_ui(ui, |ui| {
+                        for speed in Playback::SPEEDS {
+                            ui.selectable_value(&mut state.speed, speed, format!("{}×", speed));
+                        }
+                    });
+                ui.separator();
+                ui.checkbox(&mut state.looping, "Loop");
+                let (start, end) = state.loop_region;
+                if ui
+                    .button("[")
+                    .on_hover_text("Loop from this frame")
+                    .clicked()
+                {
+                    state.loop_region = (state.position, end.max(state.position));
+                }
+                ui.label(format!("{}–{}", start + 1, end + 1));
+                if ui
+                    .button("]")
+                    .on_hover_text("Loop up to this frame")
+                    .clicked()
+                {
+                    state.loop_region = (start.min(state.position), state.position);
+                }
+                if ui
+                    .button("↔")
+                    .on_hover_text("Loop the whole recording")
+                    .clicked()
+                {
+                    state.loop_region = (0, last);
+                }
+                ui.separator();
+                ui.label(state.path.file_name().unwrap_or_default().to_string_lossy());
+                if ui.button("⏏").on_hover_text("Close playback").clicked() {
+                    state.close();
+                }
+            });
+        });
+    }
+
+    fn sequence_menu(&mut self, ui: &mut egui::Ui) {
+        let slot = self.sequence.lock().unwrap();
+        if let Some(sequence) = slot.as_ref() {
+            let secs = sequence.elapsed().as_secs();
+            ui.label(format!(
+                "⏺ {} — {}:{:02}",
+                sequence.path().display(),
+                secs / 60,
+                secs % 60
+            ));
+            ui.label(format!(
+                "{} frames, {:.1} MB ({:.1}:1)",
+                sequence.frames(),
+                sequence.size() as f64 / 1e6,
+                sequence.compression()
+            ));
+            if sequence.dropped() > 0 {
+                ui.label(format!(
+                    "{} frames dropped by the encoder",
+                    sequence.dropped()
+                ));
+            }
+            if ui.button("⏹ Stop").clicked() {
+                drop(slot);
+                let stop = Action::StopRecording {
+                    kind: Some(RecordingKind::Tseq),
+                };
+                if let Err(e) = self.control.execute(stop) {
+                    eprintln!("❌ Failed to stop sequence: {}", e);
+                }
+                ui.close_menu();
+            }
+            return;
+        }
+        drop(slot);
+        ui.label("Lossless raw frames with calibration, seekable (.tseq)");
+        if ui.button("⏺ Start").clicked() {
+            let start = Action::StartRecording {
+                kind: RecordingKind::Tseq,
+                name: None,
+                fps: None,
+                burn_overlays: None,
+            };
+            if let Err(e) = self.control.execute(start) {
+                eprintln!("❌ Failed to start sequence: {}", e);
+            }
+            ui.close_menu();
+        }
+    }
+
+    fn timelapse_menu(&mut self, ui: &mut egui::Ui) {
+        let mut slot = self.timelapse.lock().unwrap();
+        if let Some(lapse) = slot.as_ref() {
+            ui.label(format!(
+                "⏱ {} samples in {}, next in {} s",
+                lapse.samples(),
+                lapse.config().dir.display(),
+                lapse.next_in().as_secs()
+            ));
+            if ui.button("⏹ Stop").clicked() {
+                println!("⏱ Time-lapse stopped: {} samples.", lapse.samples());
+                *slot = None;
+                ui.close_menu();
+            }
+            return;
+        }
+        let config = &mut self.timelapse_config;
+        let mut interval = config.interval.as_secs_f32();
+        ui.add(
+            egui::DragValue::new(&mut interval)
+                .clamp_range(1.0..=86_400.0)
+                .suffix(" s interval"),
+        );
+        config.interval = std::time::Duration::from_secs_f32(interval);
+        let mut minutes = config.duration.map_or(0.0, |d| d.as_secs_f32() / 60.0);
+        ui.add(
+            egui::DragValue::new(&mut minutes)
+                .clamp_range(0.0..=10_080.0)
+                .suffix(" min (0 = until stopped)"),
+        );
+        config.duration =
+            (minutes > 0.0).then(|| std::time::Duration::from_secs_f32(minutes * 60.0));
+        ui.add(egui::Slider::new(&mut config.average, 1..=32).text("frames averaged"));
+        ui.separator();
+        ui.radio_value(&mut config.image, None, "Raw only");
+        for format in SnapshotFormat::ALL {
+            ui.radio_value(&mut config.image, Some(format), format.name());
+        }
+        if ui.button("⏱ Start").clicked() {
+            let mut config = config.clone();
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            config.dir = Path::new("timelapse").join(secs.to_string());
+            match TimeLapse::start(config, &self.device) {
+                Ok(lapse) => *slot = Some(lapse),
+                Err(e) => eprintln!("❌ Failed to start time-lapse: {}", e),
+            }
+            ui.close_menu();
+        }
+    }
+
+    fn server_menu(&mut self, ui: &mut egui::Ui) {
+        if let Some(server) = &self.server {
+            let url = format!("http://{}/", server.addr());
+            ui.hyperlink_to(&url, &url);
+            ui.label(format!("{} connection(s)", server.clients()));
+            if ui.button("⏹ Stop").clicked() {
+                if let Some(server) = self.server.take() {
+                    server.stop();
+                }
+                ui.close_menu();
+            }
+            return;
+        }
+        ui.label("MJPEG stream, JSON statistics and raw Y16 over HTTP");
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            ui.text_edit_singleline(&mut self.server_bind);
+        });
+        ui.checkbox(&mut self.server_control, "Allow remote control (REST API)")
+            .on_hover_text("Anyone who can reach the server can change settings and record");
+        if ui.button("▶ Start").clicked() {
+            let control = self.server_control.then(|| self.control.clone());
+            match StreamServer::start(self.server_bind.trim(), self.bus.clone(), control) {
+                Ok(server) => {
+                    self.config.server.bind = self.server_bind.trim().to_string();
+                    self.config.server.control = self.server_control;
+                    self.server = Some(server);
+                }
+                Err(e) => eprintln!("❌ Failed to start HTTP server: {:#}", e),
+            }
+            ui.close_menu();
+        }
+    }
+
+    fn export_menu(&mut self, ui: &mut egui::Ui) {
+        for unit in TemperatureUnit::ALL {
+            ui.radio_value(&mut self.export.unit, unit, unit.symbol());
+        }
+        ui.add(egui::Slider::new(&mut self.export.precision, 0..=4).text("decimals"));
+        ui.separator();
+        for format in MatrixFormat::ALL {
+            let button = egui::Button::new(format!("Temperature field as {}", format.name()));
+            if ui.add_enabled(self.latest.is_some(), button).clicked() {
+                if let Some(frame) = &self.latest {
+                    match export::export_frame(frame, Path::new("exports"), format, self.export) {
+                        Ok(path) => println!("📄 Exported {}", path.display()),
+                        Err(e) => eprintln!("❌ Export failed: {}", e),
+                    }
+                }
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        if let Some(log) = &self.roi_log {
+            ui.label(format!("📈 {} — {} rows", log.path().display(), log.rows()));
+            if ui.button("⏹ Stop ROI log").clicked() {
+                if let Some(log) = self.roi_log.take()
+                    && let Err(e) = log.finish()
+                {
+                    eprintln!("❌ Failed to finish ROI log: {}", e);
+                }
+                ui.close_menu();
+            }
+        } else if ui
+            .add_enabled(
+                !self.tools.rois.lock().unwrap().is_empty(),
+                egui::Button::new("📈 Log ROI statistics to CSV"),
+            )
+            .clicked()
+        {
+            let path = RoiLogger::default_path(Path::new("exports"));
+            match RoiLogger::create(&path, self.export) {
+                Ok(log) => self.roi_log = Some(log),
+                Err(e) => eprintln!("❌ Failed to start ROI log: {}", e),
+            }
+            ui.close_menu();
+        }
+    }
+
+    /// Starts a recording with the current settings, or finishes the active
+    /// one in the background.
+    fn toggle_recording(&mut self) {
+        let kind = RecordingKind::from(self.recording.format);
+        let action = if self.recorder.lock().unwrap().is_some() {
+            Action::StopRecording { kind: Some(kind) }
+        } else {
+            Action::StartRecording {
+                kind,
+                name: None,
+                fps: Some(self.recording.fps),
+                burn_overlays: Some(self.recording.burn_overlays),
+            }
+        };
+        if let Err(e) = self.control.execute(action) {
+            eprintln!("❌ Recording: {}", e);
+        }
+    }
+
+    /// Switching applies the profile's pipeline, calibration, display settings
+    /// and alarm rules; saving stores the current ones under the typed name,
+    /// along with the window layout.
+    fn profile_menu(&mut self, ui: &mut egui::Ui) {
+        let names: Vec<String> = self.config.profiles.keys().cloned().collect();
+        for name in names {
+            let active = self.config.profile.as_ref() == Some(&name);
+            let description = self.config.profiles[&name].description.clone();
+            if ui.radio(active, &name).on_hover_text(description).clicked() {
+                // Building locks the settings; the pipeline is only locked to
+                // swap it in, as the engine locks settings while holding it.
+                match self.config.resolve(Some(&name)).and_then(|profile| {
+                    let pipeline = profile.build(&self.settings, &StageRegistry::new())?;
+                    Ok((pipeline, profile.alarms.unwrap_or_default()))
+                }) {
+                    Ok((pipeline, rules)) => {
+                        *self.pipeline.lock().unwrap() = pipeline;
+                        self.alarms.lock().unwrap().set_rules(rules);
+                        println!("✅ Profile '{}' applied", name);
+                        self.profile_name = name.clone();
+                        self.config.profile = Some(name);
+                    }
+                    Err(e) => eprintln!("❌ Failed to apply profile '{}': {}", name, e),
+                }
+                ui.close_menu();
+            }
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.profile_name);
+        });
+        let name = self.profile_name.trim().to_string();
+        if ui
+            .add_enabled(!name.is_empty(), egui::Button::new("Save current settings"))
+            .clicked()
+        {
+            let rules = self.alarms.lock().unwrap().rules.clone();
+            let profile = {
+                let pipeline = self.pipeline.lock().unwrap();
+                let description = self
+                    .config
+                    .profiles
+                    .get(&name)
+                    .map(|p| p.description.clone())
+                    .unwrap_or_default();
+                Profile::capture(&pipeline, &self.settings, &rules, &description)
+            };
+            self.config.profiles.insert(name.clone(), profile);
+            self.config.profile = Some(name);
+            self.save_config(ui.ctx());
+            ui.close_menu();
+        }
+        if ui.button("Save layout").clicked() {
+            self.save_config(ui.ctx());
+            ui.close_menu();
+        }
+    }
+
+    /// Writes the configuration file with the current window layout.
+    fn save_config(&mut self, ctx: &egui::Context) {
+        let size = ctx.screen_rect().size();
+        self.config.ui = UiConfig {
+            window: [size.x, size.y],
+            show_spots: self.show_spots,
+            show_center: self.show_center,
+            show_legend: self.show_legend,
+            show_stats: self.show_stats,
+        };
+        match self.config.save() {
+            Ok(path) => println!("✅ Configuration saved to {}", path.display()),
+            Err(e) => eprintln!("❌ Failed to save configuration: {}", e),
+        }
+    }
+
+    fn pipeline_menu(&mut self, ui: &mut egui::Ui, actions: &mut Vec<Action>) {
+        let State { stages, nuc, .. } = self.control.state();
+        let last = stages.len().saturating_sub(1);
+        for (i, stage) in stages.into_iter().enumerate() {
+            ui.horizontal(|ui| {
+                let mut enabled = stage.enabled;
+                if ui.checkbox(&mut enabled, stage.name.as_str()).changed() {
+                    actions.push(Action::EnableStage {
+                        stage: stage.name.clone(),
+                        enabled,
+                    });
+                }
+                if ui.add_enabled(i > 0, egui::Button::new("⏶")).clicked() {
+                    actions.push(Action::MoveStage { from: i, to: i - 1 });
+                }
+                if ui.add_enabled(i < last, egui::Button::new("⏷")).clicked() {
+                    actions.push(Action::MoveStage { from: i, to: i + 1 });
+                }
+            });
+        }
+        if nuc.available {
+            ui.separator();
+            let label = if nuc.calibrating {
+                "Calibrating…"
+            } else {
+                "NUC: calibrate (uniform scene)"
+            };
+            if ui
+                .add_enabled(!nuc.calibrating, egui::Button::new(label))
+                .clicked()
+            {
+                actions.push(Action::Ffc);
+            }
+            if ui
+                .add_enabled(nuc.calibrated, egui::Button::new("NUC: clear"))
+                .clicked()
+            {
+                actions.push(Action::ClearNuc);
+            }
+        }
+        ui.separator();
+        if ui.button("Save pipeline.toml").clicked() {
+            let config = self.pipeline.lock().unwrap().config();
+            match config.save(Path::new("pipeline.toml")) {
+                Ok(()) => println!("✅ Pipeline saved to pipeline.toml"),
+                Err(e) => eprintln!("❌ Failed to save pipeline: {}", e),
+            }
+            ui.close_menu();
+        }
+        if ui.button("Load pipeline.toml").clicked() {
+            // Building the stages writes into the shared settings, which the
+            // engine locks while holding the pipeline: build first, then swap.
+            let loaded = PipelineConfig::load(Path::new("pipeline.toml")).and_then(|config| {
+                Pipeline::from_config(&config, &self.settings, &StageRegistry::new())
+            });
+            match loaded {
+                Ok(loaded) => {
+                    *self.pipeline.lock().unwrap() = loaded;
+                    println!("✅ Pipeline loaded from pipeline.toml");
+                }
+                Err(e) => eprintln!("❌ Failed to load pipeline: {}", e),
+            }
+            ui.close_menu();
+        }
+    }
+}
+
+/// Writes `frame` to `snapshots/` in the background.
+fn save_snapshot(frame: &ProcessedFrame, format: SnapshotFormat, device: &str) {
+    let snapshot = Snapshot::capture(frame, device);
+    let image = frame.export_image();
+    std::thread::spawn(
+        move || match snapshot.save(&image, Path::new("snapshots"), format) {
+            Ok(path) => println!("📸 Snapshot saved to {}", path.display()),
+            Err(e) => eprintln!("❌ Failed to save snapshot: {}", e),
+        },
+    );
+}
+
+fn range_menu(
+    ui: &mut egui::Ui,
+    range: &mut RangeMode,
+    unit: TemperatureUnit,
+    latest: Option<&ProcessedFrame>,
+) {
+    if ui.radio(!range.is_manual(), "Auto").clicked() {
+        *range = RangeMode::Auto;
+    }
+    let lock = ui.radio(range.is_manual(), "Locked (L)");
+    if lock.clicked()
+        && !range.is_manual()
+        && let Some(frame) = latest
+    {
+        *range = RangeMode::locked(&frame.legend);
+    }
+    if let RangeMode::Manual { level, span } = range {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Level");
+            ui.add(temp_value(level, unit));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Span");
+            ui.add(temp_span(span, unit).clamp_range(RangeMode::MIN_SPAN..=1000.0));
+        });
+    }
+}
+
+/// Emissivity and reflected temperature; the edit, if any, as an action.
+fn calibration_menu(
+    ui: &mut egui::Ui,
+    mut calibration: Calibration,
+    unit: TemperatureUnit,
+) -> Option<Action> {
+    let before = calibration;
+    ui.add(egui::Slider::new(&mut calibration.emissivity, 0.01..=1.0).text("Emissivity"));
+    ui.horizontal(|ui| {
+        ui.label("Reflected");
+        ui.add(temp_value(&mut calibration.reflected_temp, unit));
+    });
+    (calibration != before).then_some(Action::SetCalibration {
+        emissivity: Some(calibration.emissivity),
+        reflected_temp: Some(calibration.reflected_temp),
+    })
+}
+
+/// The isotherm settings, as an action if they were changed.
+fn isotherm_menu(ui: &mut egui::Ui, display: &DisplaySettings) -> Option<Action> {
+    let mut isotherms = display.isotherms.clone();
+    let mut background = display.isotherm_background;
+    isotherm_controls(ui, &mut isotherms, &mut background, display.unit);
+    (isotherms != display.isotherms || background != display.isotherm_background).then_some(
+        Action::SetIsotherms {
+            isotherms,
+            background: Some(background),
+        },
+    )
+}
+
+fn isotherm_controls(
+    ui: &mut egui::Ui,
+    isotherms: &mut Vec<Isotherm>,
+    background: &mut IsothermBackground,
+    unit: TemperatureUnit,
+) {
+    let current = isotherms.first().map(|iso| iso.mode);
+    let is = |f: fn(&IsothermMode) -> bool| current.as_ref().is_some_and(f);
+    if ui.radio(current.is_none(), "Off").clicked() {
+        isotherms.clear();
+    }
+    if ui
+        .radio(is(|m| matches!(m, IsothermMode::Above(_))), "Above")
+        .clicked()
+    {
+        *isotherms = vec![Isotherm::above(60.0)];
+    }
+    if ui
+        .radio(is(|m| matches!(m, IsothermMode::Below(_))), "Below")
+        .clicked()
+    {
+        *isotherms = vec![Isotherm::below(15.0)];
+    }
+    if ui
+        .radio(is(|m| matches!(m, IsothermMode::Interval(..))), "Interval")
+        .clicked()
+    {
+        *isotherms = vec![Isotherm::interval(30.0, 40.0)];
+    }
+
+    let Some(iso) = isotherms.first_mut() else {
+        return;
+    };
+    ui.separator();
+    match &mut iso.mode {
+        IsothermMode::Above(t) | IsothermMode::Below(t) => {
+            ui.add(temp_value(t, unit));
+        }
+        IsothermMode::Interval(low, high) => {
+            ui.horizontal(|ui| {
+                ui.add(temp_value(low, unit));
+                ui.label("–");
+                ui.add(temp_value(high, unit));
+            });
+        }
+    }
+    ui.horizontal(|ui| {
+        ui.label("Colour");
+        ui.color_edit_button_srgba(&mut iso.color);
+    });
+    ui.separator();
+    ui.radio_value(
+        background,
+        IsothermBackground::Greyscale,
+        "Greyscale background",
+    );
+    ui.radio_value(
+        background,
+        IsothermBackground::Palette,
+        "Palette background",
+    );
+}
+
+/// Edits a temperature kept in °C, shown and typed in `unit`.
+fn temp_value(v: &mut f32, unit: TemperatureUnit) -> egui::DragValue<'_> {
+    egui::DragValue::new(v)
+        .speed(0.1)
+        .custom_formatter(move |c, _| format!("{:.1}", unit.from_celsius(c as f32)))
+        .custom_parser(move |text| {
+            let value: f32 = text.trim().parse().ok()?;
+            Some(unit.to_celsius(value) as f64)
+        })
+        .suffix(format!(" {}", unit.symbol()))
+}
+
+/// `temp_value` for a temperature difference, e.g. the range span.
+fn temp_span(v: &mut f32, unit: TemperatureUnit) -> egui::DragValue<'_> {
+    let per_degree = unit.delta_from_celsius(1.0);
+    egui::DragValue::new(v)
+        .speed(0.1)
+        .custom_formatter(move |c, _| format!("{:.1}", unit.delta_from_celsius(c as f32)))
+        .custom_parser(move |text| {
+            let value: f32 = text.trim().parse().ok()?;
+            Some((value / per_degree) as f64)
+        })
+        .suffix(format!(" {}", unit.symbol()))
+}
+
+/// `m:ss.s`
+fn clock(time: std::time::Duration) -> String {
+    let secs = time.as_secs_f32();
+    format!("{}:{:04.1}", (secs / 60.0) as u32, secs % 60.0)
+}
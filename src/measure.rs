@@ -0,0 +1,482 @@
+// Measurement tools: spot meters, box/ellipse areas and polyline profiles.
+//
+// All coordinates are continuous pixel coordinates of the oriented frame (see
+// `orientation.rs`): pixel (x, y) covers [x, x+1) x [y, y+1).
+
+use crate::orientation::Orientation;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    /// 3x3 neighbourhood around a point, like a handheld spot meter.
+    Spot {
+        x: f32,
+        y: f32,
+    },
+    Rect {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+    },
+    Ellipse {
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+    },
+    /// Polyline; stats cover the pixels along it, and it yields a profile.
+    Line {
+        points: Vec<(f32, f32)>,
+    },
+}
+
+impl Shape {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Shape::Spot { .. } => "Spot",
+            Shape::Rect { .. } => "Box",
+            Shape::Ellipse { .. } => "Ellipse",
+            Shape::Line { .. } => "Line",
+        }
+    }
+
+    /// Pixels covered by the shape, clipped to the frame.
+    pub fn pixels(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let inside =
+            |x: isize, y: isize| x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height;
+        let mut out = Vec::new();
+        match self {
+            Shape::Spot { x, y } => {
+                let (cx, cy) = (x.floor() as isize, y.floor() as isize);
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        if inside(cx + dx, cy + dy) {
+                            out.push(((cx + dx) as usize, (cy + dy) as usize));
+                        }
+                    }
+                }
+            }
+            Shape::Rect { x0, y0, x1, y1 } => {
+                let (xa, xb) = (x0.min(*x1).floor() as isize, x0.max(*x1).ceil() as isize);
+                let (ya, yb) = (y0.min(*y1).floor() as isize, y0.max(*y1).ceil() as isize);
+                for y in clip(ya, yb.max(ya + 1), height) {
+                    for x in clip(xa, xb.max(xa + 1), width) {
+                        out.push((x as usize, y as usize));
+                    }
+                }
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                let (rx, ry) = (rx.abs().max(0.5), ry.abs().max(0.5));
+                let rows = clip(
+                    (cy - ry).floor() as isize,
+                    (cy + ry).ceil() as isize + 1,
+                    height,
+                );
+                let columns = clip(
+                    (cx - rx).floor() as isize,
+                    (cx + rx).ceil() as isize + 1,
+                    width,
+                );
+                for y in rows {
+                    for x in columns.clone() {
+                        let nx = (x as f32 + 0.5 - cx) / rx;
+                        let ny = (y as f32 + 0.5 - cy) / ry;
+                        if nx * nx + ny * ny <= 1.0 {
+                            out.push((x as usize, y as usize));
+                        }
+                    }
+                }
+            }
+            Shape::Line { points } => {
+                for (x, y) in polyline_samples(points, width, height) {
+                    let p = (x.floor() as isize, y.floor() as isize);
+                    if inside(p.0, p.1) && out.last() != Some(&(p.0 as usize, p.1 as usize)) {
+                        out.push((p.0 as usize, p.1 as usize));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Moves every coordinate through `f` (e.g. for an orientation change).
+    pub fn map_points(&mut self, f: impl Fn(f32, f32) -> (f32, f32)) {
+        match self {
+            Shape::Spot { x, y } => (*x, *y) = f(*x, *y),
+            Shape::Rect { x0, y0, x1, y1 } => {
+                (*x0, *y0) = f(*x0, *y0);
+                (*x1, *y1) = f(*x1, *y1);
+            }
+            Shape::Ellipse { cx, cy, rx, ry } => {
+                // Radii follow the axes: a 90° turn swaps them.
+                let (ex, ey) = f(*cx + *rx, *cy);
+                (*cx, *cy) = f(*cx, *cy);
+                if (ex - *cx).abs() < (ey - *cy).abs() {
+                    std::mem::swap(rx, ry);
+                }
+            }
+            Shape::Line { points } => {
+                for p in points.iter_mut() {
+                    *p = f(p.0, p.1);
+                }
+            }
+        }
+    }
+}
+
+/// `start..end` limited to `0..len`, so shapes far larger than the frame cost
+/// no more than the frame.
+fn clip(start: isize, end: isize, len: usize) -> std::ops::Range<isize> {
+    let len = len.min(isize::MAX as usize) as isize;
+    start.clamp(0, len)..end.clamp(0, len)
+}
+
+/// Points every ~1 pixel along the parts of a polyline inside a
+/// `width` x `height` frame.
+fn polyline_samples(points: &[(f32, f32)], width: usize, height: usize) -> Vec<(f32, f32)> {
+    let mut out: Vec<(f32, f32)> = Vec::new();
+    if let [only] = points
+        && clip_segment(*only, *only, width, height).is_some()
+    {
+        out.push(*only);
+    }
+    for pair in points.windows(2) {
+        let Some(((ax, ay), (bx, by))) = clip_segment(pair[0], pair[1], width, height) else {
+            continue;
+        };
+        if out.last() != Some(&(ax, ay)) {
+            out.push((ax, ay));
+        }
+        let steps = (bx - ax).abs().max((by - ay).abs()).ceil().max(1.0) as usize;
+        for i in 1..=steps {
+            let t = i as f32 / steps as f32;
+            out.push((ax + (bx - ax) * t, ay + (by - ay) * t));
+        }
+    }
+    out
+}
+
+/// The part of segment a-b inside [0, width] x [0, height] (Liang-Barsky).
+fn clip_segment(
+    a: (f32, f32),
+    b: (f32, f32),
+    width: usize,
+    height: usize,
+) -> Option<((f32, f32), (f32, f32))> {
+    let (ax, ay) = (a.0 as f64, a.1 as f64);
+    let (dx, dy) = (b.0 as f64 - ax, b.1 as f64 - ay);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-dx, ax),
+        (dx, width as f64 - ax),
+        (-dy, ay),
+        (dy, height as f64 - ay),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    let at = |t: f64| ((ax + dx * t) as f32, (ay + dy * t) as f32);
+    (t0 <= t1).then(|| (at(t0), at(t1)))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct RoiStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+    pub coldest: (usize, usize),
+    pub hottest: (usize, usize),
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Roi {
+    pub id: u32,
+    pub name: String,
+    pub shape: Shape,
+}
+
+impl Roi {
+    pub fn new(id: u32, shape: Shape) -> Self {
+        Self {
+            id,
+            name: format!("{}{}", shape.kind(), id),
+            shape,
+        }
+    }
+
+    /// Statistics over a temperature field; `None` if the ROI lies outside it.
+    pub fn stats(&self, temperature: &[f32], width: usize, height: usize) -> Option<RoiStats> {
+        let pixels = self.shape.pixels(width, height);
+        if pixels.is_empty() {
+            return None;
+        }
+        let mut stats = RoiStats {
+            min: f32::MAX,
+            max: f32::MIN,
+            count: pixels.len(),
+            ..Default::default()
+        };
+        let (mut sum, mut sum_sq) = (0.0f64, 0.0f64);
+        for &(x, y) in &pixels {
+            let t = temperature[y * width + x];
+            if t < stats.min {
+                stats.min = t;
+                stats.coldest = (x, y);
+            }
+            if t > stats.max {
+                stats.max = t;
+                stats.hottest = (x, y);
+            }
+            sum += t as f64;
+            sum_sq += (t as f64) * (t as f64);
+        }
+        let n = pixels.len() as f64;
+        let mean = sum / n;
+        stats.mean = mean as f32;
+        stats.std_dev = (sum_sq / n - mean * mean).max(0.0).sqrt() as f32;
+        Some(stats)
+    }
+
+    /// Temperature profile along a line ROI, bilinearly sampled every ~1 pixel.
+    pub fn profile(&self, temperature: &[f32], width: usize, height: usize) -> Vec<f32> {
+        let Shape::Line { points } = &self.shape else {
+            return Vec::new();
+        };
+        polyline_samples(points, width, height)
+            .into_iter()
+            .filter_map(|(x, y)| sample_bilinear(temperature, width, height, x - 0.5, y - 0.5))
+            .collect()
+    }
+}
+
+/// The measurement ROIs of a session. The window's tools and the control API
+/// share one set; see `ThermalEngine::rois`.
+#[derive(Clone, Debug, Default)]
+pub struct RoiSet {
+    rois: Vec<Roi>,
+    /// Last id handed out; ids are not reused.
+    last_id: u32,
+    /// Sensor size of the latest frame, which `reorient` needs.
+    sensor: Option<(usize, usize)>,
+}
+
+impl RoiSet {
+    /// Called by the engine with the size of every frame before orientation.
+    pub fn set_sensor(&mut self, width: usize, height: usize) {
+        self.sensor = Some((width, height));
+    }
+
+    /// Keeps the ROIs on the same scene pixels after the orientation changed
+    /// from `from` to `to`. Nothing moves before the first frame.
+    pub fn reorient(&mut self, from: &Orientation, to: &Orientation) {
+        let Some((width, height)) = self.sensor else {
+            return;
+        };
+        let f = |x: f32, y: f32| {
+            // Orientation works on pixel indices, ROIs on continuous coordinates.
+            let (nx, ny) = Orientation::remap_point(from, to, x - 0.5, y - 0.5, width, height);
+            (nx + 0.5, ny + 0.5)
+        };
+        for roi in &mut self.rois {
+            roi.shape.map_points(f);
+        }
+    }
+
+    /// Adds a ROI named after its kind and id, e.g. "Box3".
+    pub fn add(&mut self, shape: Shape) -> u32 {
+        self.last_id += 1;
+        self.rois.push(Roi::new(self.last_id, shape));
+        self.last_id
+    }
+
+    /// `false` if there was no ROI `id`.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let before = self.rois.len();
+        self.rois.retain(|r| r.id != id);
+        self.rois.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.rois.clear();
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Roi> {
+        self.rois.iter().find(|r| r.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Roi> {
+        self.rois.iter_mut().find(|r| r.id == id)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Roi> {
+        self.rois.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Roi> {
+        self.rois.iter_mut()
+    }
+
+    pub fn as_slice(&self) -> &[Roi] {
+        &self.rois
+    }
+
+    pub fn len(&self) -> usize {
+        self.rois.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rois.is_empty()
+    }
+}
+
+fn sample_bilinear(field: &[f32], width: usize, height: usize, x: f32, y: f32) -> Option<f32> {
+    if width == 0
+        || height == 0
+        || x < -0.5
+        || y < -0.5
+        || x > width as f32 - 0.5
+        || y > height as f32 - 0.5
+    {
+        return None;
+    }
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let at = |x: usize, y: usize| field[y * width + x];
+    let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+    let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+    Some(top * (1.0 - fy) + bottom * fy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 8;
+    const H: usize = 6;
+
+    /// t = x + 10 y, so every pixel is identified by its reading.
+    fn field() -> Vec<f32> {
+        (0..W * H).map(|i| (i % W + 10 * (i / W)) as f32).collect()
+    }
+
+    fn stats(shape: Shape) -> Option<RoiStats> {
+        Roi::new(1, shape).stats(&field(), W, H)
+    }
+
+    #[test]
+    fn rect_stats() {
+        let s = stats(Shape::Rect {
+            x0: 5.0,
+            y0: 3.0,
+            x1: 2.0,
+            y1: 1.0,
+        })
+        .unwrap();
+        assert_eq!(s.count, 6);
+        assert_eq!((s.min, s.coldest), (12.0, (2, 1)));
+        assert_eq!((s.max, s.hottest), (24.0, (4, 2)));
+        assert_eq!(s.mean, 18.0);
+        // Variance of x over 2..=4 plus 100 times that of y over 1..=2.
+        assert!((s.std_dev - (2.0f32 / 3.0 + 25.0).sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn spot_and_ellipse_are_clipped_and_centred() {
+        let corner = stats(Shape::Spot { x: 0.5, y: 0.5 }).unwrap();
+        assert_eq!((corner.count, corner.min, corner.max), (4, 0.0, 11.0));
+
+        let s = stats(Shape::Ellipse {
+            cx: 4.0,
+            cy: 3.0,
+            rx: 2.0,
+            ry: 2.0,
+        })
+        .unwrap();
+        assert_eq!(s.count, 12);
+        assert_eq!(s.mean, 28.5);
+
+        let outside = Shape::Rect {
+            x0: 20.0,
+            y0: 20.0,
+            x1: 30.0,
+            y1: 30.0,
+        };
+        assert_eq!(stats(outside), None);
+    }
+
+    #[test]
+    fn line_profile_and_pixels() {
+        let roi = Roi::new(
+            2,
+            Shape::Line {
+                points: vec![(0.5, 0.5), (4.5, 0.5)],
+            },
+        );
+        assert_eq!(roi.profile(&field(), W, H), [0.0, 1.0, 2.0, 3.0, 4.0]);
+        let s = roi.stats(&field(), W, H).unwrap();
+        assert_eq!((s.count, s.min, s.max), (5, 0.0, 4.0));
+    }
+
+    #[test]
+    fn oversized_shapes_cost_no_more_than_the_frame() {
+        let huge = [
+            Shape::Rect {
+                x0: -1e9,
+                y0: -1e9,
+                x1: 1e9,
+                y1: 1e9,
+            },
+            Shape::Ellipse {
+                cx: 4.0,
+                cy: 3.0,
+                rx: 1e7,
+                ry: 1e7,
+            },
+        ];
+        for shape in huge {
+            let s = stats(shape).unwrap();
+            assert_eq!((s.count, s.min, s.max), (W * H, 0.0, 57.0));
+        }
+
+        // Only the part of the line inside the frame is sampled.
+        let roi = Roi::new(
+            3,
+            Shape::Line {
+                points: vec![(-1e9, 2.5), (1e9, 2.5), (1e9, -1e9)],
+            },
+        );
+        let s = roi.stats(&field(), W, H).unwrap();
+        assert_eq!((s.count, s.min, s.max), (W, 20.0, 27.0));
+        assert!(roi.profile(&field(), W, H).len() <= W + 2);
+        let outside = Shape::Line {
+            points: vec![(-1e9, -5.0), (1e9, -5.0)],
+        };
+        assert_eq!(stats(outside), None);
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let mut rois = RoiSet::default();
+        let a = rois.add(Shape::Spot { x: 1.0, y: 1.0 });
+        let b = rois.add(Shape::Spot { x: 2.0, y: 2.0 });
+        assert!(rois.remove(b));
+        assert!(!rois.remove(b));
+        let c = rois.add(Shape::Spot { x: 3.0, y: 3.0 });
+        assert_eq!((a, b, c), (1, 2, 3));
+        assert_eq!(rois.get(c).unwrap().name, "Spot3");
+    }
+}
@@ -0,0 +1,233 @@
+// Image orientation: mirror, flip and 90 degree rotations.
+//
+// Applied to the raw field right after decoding, so the temperature field, the
+// display image and every ROI all live in the same (oriented) coordinate space.
+// Always done in software: the supported cores have no known flip command.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Cw90,
+        Rotation::Cw180,
+        Rotation::Cw270,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rotation::None => "0°",
+            Rotation::Cw90 => "90°",
+            Rotation::Cw180 => "180°",
+            Rotation::Cw270 => "270°",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Orientation {
+    /// Left/right swap, applied before rotation.
+    pub mirror: bool,
+    /// Top/bottom swap, applied before rotation.
+    pub flip: bool,
+    pub rotation: Rotation,
+}
+
+impl Orientation {
+    pub fn is_identity(&self) -> bool {
+        !self.mirror && !self.flip && self.rotation == Rotation::None
+    }
+
+    /// Size of the oriented image for a sensor of `width` x `height`.
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.rotation {
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// Maps a sensor coordinate to the oriented image.
+    pub fn map_point(&self, x: f32, y: f32, width: usize, height: usize) -> (f32, f32) {
+        let (w, h) = (width as f32 - 1.0, height as f32 - 1.0);
+        let x = if self.mirror { w - x } else { x };
+        let y = if self.flip { h - y } else { y };
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (h - y, x),
+            Rotation::Cw180 => (w - x, h - y),
+            Rotation::Cw270 => (y, w - x),
+        }
+    }
+
+    /// Maps a coordinate of the oriented image back to the sensor.
+    pub fn unmap_point(&self, x: f32, y: f32, width: usize, height: usize) -> (f32, f32) {
+        let (w, h) = (width as f32 - 1.0, height as f32 - 1.0);
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (y, h - x),
+            Rotation::Cw180 => (w - x, h - y),
+            Rotation::Cw270 => (w - y, x),
+        };
+        let x = if self.mirror { w - x } else { x };
+        let y = if self.flip { h - y } else { y };
+        (x, y)
+    }
+
+    /// Moves a point placed under orientation `from` so that it stays on the same
+    /// scene pixel under orientation `to`. Used to keep ROIs in place.
+    pub fn remap_point(
+        from: &Orientation,
+        to: &Orientation,
+        x: f32,
+        y: f32,
+        width: usize,
+        height: usize,
+    ) -> (f32, f32) {
+        let (sx, sy) = from.unmap_point(x, y, width, height);
+        to.map_point(sx, sy, width, height)
+    }
+
+    /// Reorients a row-major buffer. Works for any pixel type (raw counts,
+    /// temperatures, colours).
+    pub fn apply<T: Copy>(&self, src: &[T], width: usize, height: usize) -> Vec<T> {
+        let mut out = Vec::new();
+        self.apply_into(src, width, height, &mut out);
+        out
+    }
+
+    /// `apply` into `dst`, reusing its allocation.
+    pub fn apply_into<T: Copy>(&self, src: &[T], width: usize, height: usize, dst: &mut Vec<T>) {
+        dst.clear();
+        if self.is_identity() {
+            dst.extend_from_slice(src);
+            return;
+        }
+        let (ow, oh) = self.output_size(width, height);
+        for oy in 0..oh {
+            for ox in 0..ow {
+                let (sx, sy) = self.unmap_point(ox as f32, oy as f32, width, height);
+                dst.push(src[sy as usize * width + sx as usize]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::{RoiSet, Shape};
+
+    const W: usize = 5;
+    const H: usize = 3;
+
+    /// Every mirror/flip/rotation combination; each of the 8 distinct
+    /// orientations shows up twice.
+    fn all() -> Vec<Orientation> {
+        let mut all = Vec::new();
+        for rotation in Rotation::ALL {
+            for (mirror, flip) in [(false, false), (true, false), (false, true), (true, true)] {
+                all.push(Orientation {
+                    mirror,
+                    flip,
+                    rotation,
+                });
+            }
+        }
+        all
+    }
+
+    /// Sensor pixel index as the value, so every pixel can be traced.
+    fn field() -> Vec<usize> {
+        (0..W * H).collect()
+    }
+
+    #[test]
+    fn map_unmap_and_apply_agree() {
+        let src = field();
+        for o in all() {
+            let out = o.apply(&src, W, H);
+            let (ow, oh) = o.output_size(W, H);
+            assert_eq!(out.len(), ow * oh);
+            for y in 0..H {
+                for x in 0..W {
+                    let (ox, oy) = o.map_point(x as f32, y as f32, W, H);
+                    assert!(ox >= 0.0 && oy >= 0.0 && (ox as usize) < ow && (oy as usize) < oh);
+                    assert_eq!(out[oy as usize * ow + ox as usize], y * W + x, "{o:?}");
+                    assert_eq!(o.unmap_point(ox, oy, W, H), (x as f32, y as f32));
+                }
+            }
+        }
+        let mut distinct: Vec<Vec<usize>> = all().iter().map(|o| o.apply(&src, W, H)).collect();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 8);
+    }
+
+    #[test]
+    fn remapped_points_stay_on_the_same_pixel() {
+        let src = field();
+        for from in all() {
+            let before = from.apply(&src, W, H);
+            let (fw, fh) = from.output_size(W, H);
+            for to in all() {
+                let after = to.apply(&src, W, H);
+                let tw = to.output_size(W, H).0;
+                for y in 0..fh {
+                    for x in 0..fw {
+                        let (nx, ny) =
+                            Orientation::remap_point(&from, &to, x as f32, y as f32, W, H);
+                        assert_eq!(after[ny as usize * tw + nx as usize], before[y * fw + x]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rois_measure_the_same_pixels_in_every_orientation() {
+        let temperature: Vec<f32> = field().iter().map(|&i| 20.0 + i as f32).collect();
+        let mut rois = RoiSet::default();
+        rois.set_sensor(W, H);
+        rois.add(Shape::Rect {
+            x0: 1.0,
+            y0: 0.0,
+            x1: 3.0,
+            y1: 2.0,
+        });
+        rois.add(Shape::Spot { x: 3.5, y: 1.5 });
+        let identity = Orientation::default();
+        let expected: Vec<_> = rois
+            .iter()
+            .map(|roi| roi.stats(&temperature, W, H).unwrap())
+            .collect();
+
+        for o in all() {
+            let mut moved = rois.clone();
+            moved.reorient(&identity, &o);
+            let field = o.apply(&temperature, W, H);
+            let (ow, oh) = o.output_size(W, H);
+            for (roi, expected) in moved.iter().zip(&expected) {
+                let stats = roi.stats(&field, ow, oh).unwrap();
+                assert_eq!(
+                    (stats.count, stats.min, stats.max, stats.mean),
+                    (expected.count, expected.min, expected.max, expected.mean),
+                    "{} {o:?}",
+                    roi.name
+                );
+            }
+            moved.reorient(&o, &identity);
+            for (roi, original) in moved.iter().zip(rois.iter()) {
+                assert_eq!(roi.shape.pixels(W, H), original.shape.pixels(W, H), "{o:?}");
+            }
+        }
+    }
+}
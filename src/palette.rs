@@ -0,0 +1,156 @@
+use clap::ValueEnum;
+use eframe::egui;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Ironbow,
+    WhiteHot,
+    BlackHot,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Ironbow, Palette::WhiteHot, Palette::BlackHot];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Ironbow => "Ironbow",
+            Palette::WhiteHot => "White hot",
+            Palette::BlackHot => "Black hot",
+        }
+    }
+
+    /// Maps a normalised value (0.0 = cold, 1.0 = hot) to a colour.
+    pub fn color(self, t: f32) -> egui::Color32 {
+        match self {
+            Palette::Ironbow => ironbow(t),
+            Palette::WhiteHot => egui::Color32::from_gray((t.clamp(0.0, 1.0) * 255.0) as u8),
+            Palette::BlackHot => egui::Color32::from_gray(255 - (t.clamp(0.0, 1.0) * 255.0) as u8),
+        }
+    }
+}
+
+/// Maps a normalised value (0.0 = cold, 1.0 = hot) to the ironbow-like ramp
+/// used for the live view: black -> blue -> red -> yellow -> white.
+pub fn ironbow(t: f32) -> egui::Color32 {
+    if t < 0.25 {
+        egui::Color32::from_rgb(0, 0, (t * 1020.0) as u8)
+    } else if t < 0.5 {
+        egui::Color32::from_rgb(
+            ((t - 0.25) * 1020.0) as u8,
+            0,
+            (255.0 - (t - 0.25) * 1020.0) as u8,
+        )
+    } else if t < 0.75 {
+        egui::Color32::from_rgb(255, ((t - 0.5) * 1020.0) as u8, 0)
+    } else {
+        egui::Color32::from_rgb(255, 255, ((t - 0.75) * 1020.0) as u8)
+    }
+}
+
+/// Entries per palette lookup table. Fine enough that neighbouring entries
+/// differ by at most one 8-bit step in every channel.
+pub const LUT_SIZE: usize = 1024;
+
+impl Palette {
+    /// Precomputed colours for t = 0..1 in `LUT_SIZE` steps, built once per palette.
+    pub fn lut(self) -> &'static [egui::Color32; LUT_SIZE] {
+        static LUTS: OnceLock<[[egui::Color32; LUT_SIZE]; 3]> = OnceLock::new();
+        let luts = LUTS.get_or_init(|| {
+            Palette::ALL.map(|palette| {
+                std::array::from_fn(|i| palette.color(i as f32 / (LUT_SIZE - 1) as f32))
+            })
+        });
+        &luts[self as usize]
+    }
+}
+
+/// Colourises a Y16 field, stretching the palette over `(min, max)` raw counts.
+pub fn colorize(
+    field: &[u16],
+    width: usize,
+    height: usize,
+    palette: Palette,
+    range: (u16, u16),
+) -> egui::ColorImage {
+    let mut image = egui::ColorImage::default();
+    colorize_into(field, width, height, palette, range, &mut image);
+    image
+}
+
+/// `colorize` into an existing image, reusing its pixel buffer. Uses the
+/// palette LUT with fixed-point indexing, no float math per pixel.
+pub fn colorize_into(
+    field: &[u16],
+    width: usize,
+    height: usize,
+    palette: Palette,
+    (min, max): (u16, u16),
+    image: &mut egui::ColorImage,
+) {
+    resize(image, width, height);
+    let lut = palette.lut();
+    if max <= min {
+        image.pixels.fill(lut[0]);
+        return;
+    }
+    // 16.16 fixed point; (v - min) <= (max - min) keeps the product below 2^26.
+    let scale = (((LUT_SIZE - 1) as u32) << 16) / (max - min) as u32;
+    let map = |pixels: &mut [egui::Color32], values: &[u16]| {
+        for (pixel, &v) in pixels.iter_mut().zip(values) {
+            let offset = (v.clamp(min, max) - min) as u32;
+            *pixel = lut[((offset * scale) >> 16) as usize];
+        }
+    };
+    for_each_row(&mut image.pixels, field, width, map);
+}
+
+/// Colourises values that are already normalised to 0..1 (e.g. DDE output).
+pub fn colorize_normalized(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    palette: Palette,
+) -> egui::ColorImage {
+    let mut image = egui::ColorImage::default();
+    resize(&mut image, width, height);
+    let lut = palette.lut();
+    let map = |pixels: &mut [egui::Color32], values: &[f32]| {
+        for (pixel, &t) in pixels.iter_mut().zip(values) {
+            *pixel = lut[(t.clamp(0.0, 1.0) * (LUT_SIZE - 1) as f32 + 0.5) as usize];
+        }
+    };
+    for_each_row(&mut image.pixels, values, width, map);
+    image
+}
+
+/// Sizes `image` to `width` x `height`, keeping its allocation when possible.
+fn resize(image: &mut egui::ColorImage, width: usize, height: usize) {
+    image.size = [width, height];
+    image.pixels.resize(width * height, egui::Color32::BLACK);
+}
+
+/// Runs `f` over matching rows of `pixels` and `values`, in parallel with the
+/// `parallel` feature.
+fn for_each_row<T: Sync>(
+    pixels: &mut [egui::Color32],
+    values: &[T],
+    width: usize,
+    f: impl Fn(&mut [egui::Color32], &[T]) + Sync,
+) {
+    let width = width.max(1);
+    #[cfg(feature = "parallel")]
+    pixels
+        .par_chunks_mut(width)
+        .zip(values.par_chunks(width))
+        .for_each(|(row, src)| f(row, src));
+    #[cfg(not(feature = "parallel"))]
+    pixels
+        .chunks_mut(width)
+        .zip(values.chunks(width))
+        .for_each(|(row, src)| f(row, src));
+}
@@ -0,0 +1,460 @@
+// Composable frame processing.
+//
+// A `Pipeline` runs an ordered list of `ProcessingStage`s over one
+// `FrameContext` per frame. The built-in stages (`stages.rs`) keep their
+// settings in the `SharedSettings` handles that the engine also hands to the UI,
+// so runtime changes from either side apply to the next frame. A pipeline is
+// saved and loaded as a `PipelineConfig` (TOML); custom stages become loadable
+// by registering a factory in a `StageRegistry`.
+
+use crate::alarm::AlarmStatus;
+use crate::calibration::Calibration;
+use crate::display::{DisplaySettings, RangeMode};
+use crate::filters::{FilterChain, FilterConfig};
+use crate::frame::{ProcessedFrame, ThermalFrame};
+use crate::legend::Legend;
+use crate::orientation::Orientation;
+use crate::stages::{
+    AgcStage, BadPixelStage, CalibrationStage, DenoiseStage, NucStage, OrientationStage,
+    OverlayStage, PaletteStage, SpotStage,
+};
+use crate::tracking::{HotColdSpots, SpotTracker};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Everything the stages read and write for one frame.
+#[derive(Default)]
+pub struct FrameContext {
+    pub frame: ThermalFrame,
+    /// Temperature (°C) of the measurement field, same layout as `frame`.
+    pub temperature: Vec<f32>,
+    /// Measurements read the denoised field rather than the raw one.
+    pub use_denoised: bool,
+    /// Calibration in effect for this frame.
+    pub calibration: Calibration,
+    /// Raw range the palette spans.
+    pub range: (u16, u16),
+    pub spots: HotColdSpots,
+    pub image: egui::ColorImage,
+    pub legend: Legend,
+    /// The frame geometry differs from the previous frame; stages with
+    /// per-pixel history should drop it.
+    pub geometry_changed: bool,
+    /// Allocation of the previous frame's denoised field, for the denoise
+    /// stage to fill again.
+    pub denoise_buffer: Vec<u16>,
+}
+
+impl FrameContext {
+    /// A context that reuses the buffers of a frame the consumer is done with.
+    pub fn recycle(old: ProcessedFrame) -> Self {
+        Self {
+            frame: old.frame,
+            temperature: old.temperature,
+            image: old.image,
+            ..Self::default()
+        }
+    }
+
+    pub fn into_processed(self) -> ProcessedFrame {
+        ProcessedFrame {
+            frame: self.frame,
+            temperature: self.temperature,
+            calibration: self.calibration,
+            spots: self.spots,
+            image: self.image,
+            legend: self.legend,
+            alarms: AlarmStatus::default(),
+        }
+    }
+}
+
+/// One step of the pipeline.
+pub trait ProcessingStage: Any + Send {
+    /// Identifies the stage at runtime; unique within a pipeline.
+    fn name(&self) -> &str;
+    fn process(&mut self, ctx: &mut FrameContext);
+    /// Drops per-frame history. Called when the stream (re)starts.
+    fn reset(&mut self) {}
+    /// How to recreate the stage from a config file.
+    fn config(&self) -> StageConfig {
+        StageConfig::Custom {
+            name: self.name().to_string(),
+            params: toml::Table::new(),
+        }
+    }
+}
+
+/// Engine-wide settings the built-in stages share with the UI.
+#[derive(Clone, Default)]
+pub struct SharedSettings {
+    pub filters: Arc<Mutex<FilterChain>>,
+    pub orientation: Arc<Mutex<Orientation>>,
+    pub calibration: Arc<Mutex<Calibration>>,
+    pub tracker: Arc<Mutex<SpotTracker>>,
+    pub display: Arc<Mutex<DisplaySettings>>,
+}
+
+struct Slot {
+    enabled: bool,
+    stage: Box<dyn ProcessingStage>,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    slots: Vec<Slot>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The stock processing chain: bad pixels, NUC, orientation, denoise,
+    /// calibration, spot tracking, AGC and palette.
+    pub fn standard(shared: &SharedSettings) -> Self {
+        Self::new()
+            .with(BadPixelStage::default())
+            .with(NucStage::default())
+            .with(OrientationStage::new(shared.orientation.clone()))
+            .with(DenoiseStage::new(shared.filters.clone()))
+            .with(CalibrationStage::new(shared.calibration.clone()))
+            .with(SpotStage::new(shared.tracker.clone()))
+            .with(AgcStage::new(shared.display.clone()))
+            .with(PaletteStage::new(shared.display.clone()))
+    }
+
+    pub fn with(mut self, stage: impl ProcessingStage) -> Self {
+        self.push(Box::new(stage));
+        self
+    }
+
+    pub fn push(&mut self, stage: Box<dyn ProcessingStage>) {
+        self.insert(self.slots.len(), stage);
+    }
+
+    /// Inserts at `index` (clamped to the end).
+    pub fn insert(&mut self, index: usize, stage: Box<dyn ProcessingStage>) {
+        let index = index.min(self.slots.len());
+        self.slots.insert(
+            index,
+            Slot {
+                enabled: true,
+                stage,
+            },
+        );
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|s| s.stage.name() == name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn ProcessingStage>> {
+        let index = self.position(name)?;
+        Some(self.slots.remove(index).stage)
+    }
+
+    /// Moves the stage at `from` to position `to` (clamped to the end).
+    pub fn move_stage(&mut self, from: usize, to: usize) {
+        if from < self.slots.len() {
+            let slot = self.slots.remove(from);
+            self.slots.insert(to.min(self.slots.len()), slot);
+        }
+    }
+
+    /// Returns false if there is no stage called `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.slots[index].enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names and enabled flags in processing order.
+    pub fn stages(&self) -> Vec<(String, bool)> {
+        self.slots
+            .iter()
+            .map(|s| (s.stage.name().to_string(), s.enabled))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// The first stage of type `T`, e.g. to start a NUC calibration.
+    pub fn stage_mut<T: ProcessingStage>(&mut self) -> Option<&mut T> {
+        self.slots.iter_mut().find_map(|s| {
+            let stage: &mut dyn Any = s.stage.as_mut();
+            stage.downcast_mut::<T>()
+        })
+    }
+
+    pub fn reset(&mut self) {
+        for slot in &mut self.slots {
+            slot.stage.reset();
+        }
+    }
+
+    pub fn process(&mut self, ctx: &mut FrameContext) {
+        ctx.geometry_changed = false;
+        // A recycled frame still carries the field denoised for an earlier
+        // frame; only the denoise stage may set it, and only for this one.
+        if let Some(stale) = ctx.frame.denoised.take() {
+            ctx.denoise_buffer = stale;
+        }
+        for slot in &mut self.slots {
+            if slot.enabled {
+                slot.stage.process(ctx);
+            }
+        }
+    }
+
+    pub fn config(&self) -> PipelineConfig {
+        PipelineConfig {
+            stages: self
+                .slots
+                .iter()
+                .map(|s| StageEntry {
+                    enabled: s.enabled,
+                    stage: s.stage.config(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Builds the pipeline described by `config`. Built-in stages write their
+    /// saved settings into `shared`; custom ones are created by `registry`.
+    /// Settings are only written once every stage has been built, so a config
+    /// that fails leaves `shared` as it was.
+    pub fn from_config(
+        config: &PipelineConfig,
+        shared: &SharedSettings,
+        registry: &StageRegistry,
+    ) -> anyhow::Result<Self> {
+        Self::from_config_with(config, shared, registry, || {})
+    }
+
+    /// `from_config`, calling `before_apply` after the stages were built and
+    /// before their settings are written.
+    pub(crate) fn from_config_with(
+        config: &PipelineConfig,
+        shared: &SharedSettings,
+        registry: &StageRegistry,
+        before_apply: impl FnOnce(),
+    ) -> anyhow::Result<Self> {
+        let stages = config
+            .stages
+            .iter()
+            .map(|entry| entry.stage.build(shared, registry))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        before_apply();
+        let mut pipeline = Self::new();
+        for (entry, stage) in config.stages.iter().zip(stages) {
+            entry.stage.apply(shared);
+            pipeline.push(stage);
+            pipeline.slots.last_mut().unwrap().enabled = entry.enabled;
+        }
+        Ok(pipeline)
+    }
+}
+
+/// Serialisable description of one stage.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum StageConfig {
+    BadPixel {
+        /// Known defects in sensor coordinates.
+        #[serde(default)]
+        pixels: Vec<[usize; 2]>,
+        /// Also replace pixels that differ from their neighbourhood median by
+        /// more than this many counts.
+        #[serde(default)]
+        threshold: Option<u16>,
+    },
+    Nuc {
+        frames: usize,
+    },
+    Orientation(Orientation),
+    Denoise {
+        use_for_measurement: bool,
+        #[serde(default)]
+        filters: Vec<FilterConfig>,
+    },
+    Calibration(Calibration),
+    Spots(SpotTracker),
+    Agc {
+        range: RangeMode,
+    },
+    Palette(DisplaySettings),
+    Overlay,
+    /// A stage from a `StageRegistry`.
+    Custom {
+        name: String,
+        #[serde(default)]
+        params: toml::Table,
+    },
+}
+
+impl StageConfig {
+    /// Creates the stage, working on the handles in `shared`. Their values are
+    /// left alone; see `apply`.
+    pub fn build(
+        &self,
+        shared: &SharedSettings,
+        registry: &StageRegistry,
+    ) -> anyhow::Result<Box<dyn ProcessingStage>> {
+        Ok(match self {
+            StageConfig::BadPixel { pixels, threshold } => Box::new(BadPixelStage {
+                pixels: pixels.clone(),
+                threshold: *threshold,
+            }),
+            StageConfig::Nuc { frames } => Box::new(NucStage::new(*frames)),
+            StageConfig::Orientation(_) => {
+                Box::new(OrientationStage::new(shared.orientation.clone()))
+            }
+            StageConfig::Denoise { filters, .. } => {
+                filters.iter().try_for_each(FilterConfig::validate)?;
+                Box::new(DenoiseStage::new(shared.filters.clone()))
+            }
+            StageConfig::Calibration(_) => {
+                Box::new(CalibrationStage::new(shared.calibration.clone()))
+            }
+            StageConfig::Spots(_) => Box::new(SpotStage::new(shared.tracker.clone())),
+            StageConfig::Agc { .. } => Box::new(AgcStage::new(shared.display.clone())),
+            StageConfig::Palette(_) => Box::new(PaletteStage::new(shared.display.clone())),
+            StageConfig::Overlay => Box::new(OverlayStage),
+            StageConfig::Custom { name, params } => registry.build(name, params)?,
+        })
+    }
+
+    /// Writes the settings saved with the stage into `shared`. Only for
+    /// configs that `build` accepted, which checks the filter parameters.
+    pub fn apply(&self, shared: &SharedSettings) {
+        match self {
+            StageConfig::Orientation(orientation) => {
+                *shared.orientation.lock().unwrap() = *orientation;
+            }
+            StageConfig::Denoise {
+                use_for_measurement,
+                filters,
+            } => {
+                let mut chain = FilterChain::new();
+                for filter in filters {
+                    chain.push(filter.build().expect("checked by StageConfig::build"));
+                }
+                chain.use_for_measurement = *use_for_measurement;
+                *shared.filters.lock().unwrap() = chain;
+            }
+            StageConfig::Calibration(calibration) => {
+                *shared.calibration.lock().unwrap() = *calibration;
+            }
+            StageConfig::Spots(tracker) => {
+                *shared.tracker.lock().unwrap() = tracker.clone();
+            }
+            StageConfig::Agc { range } => {
+                shared.display.lock().unwrap().range = *range;
+            }
+            StageConfig::Palette(settings) => {
+                let mut display = shared.display.lock().unwrap();
+                // The range belongs to the AGC stage, which may come first.
+                let range = display.range;
+                *display = DisplaySettings {
+                    range,
+                    ..settings.clone()
+                };
+            }
+            StageConfig::BadPixel { .. }
+            | StageConfig::Nuc { .. }
+            | StageConfig::Overlay
+            | StageConfig::Custom { .. } => {}
+        }
+    }
+}
+
+/// A stage and whether it runs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StageEntry {
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub stage: StageConfig,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A saved pipeline: `[[stages]]` tables in processing order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    pub stages: Vec<StageEntry>,
+}
+
+impl PipelineConfig {
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+}
+
+type StageFactory =
+    Box<dyn Fn(&toml::Table) -> anyhow::Result<Box<dyn ProcessingStage>> + Send + Sync>;
+
+/// Factories for custom stages, keyed by the name used in config files.
+#[derive(Default)]
+pub struct StageRegistry {
+    factories: HashMap<String, StageFactory>,
+}
+
+impl StageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        factory: impl Fn(&toml::Table) -> anyhow::Result<Box<dyn ProcessingStage>>
+        + Send
+        + Sync
+        + 'static,
+    ) {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn build(
+        &self,
+        name: &str,
+        params: &toml::Table,
+    ) -> anyhow::Result<Box<dyn ProcessingStage>> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown pipeline stage '{}'", name))?;
+        factory(params)
+    }
+}
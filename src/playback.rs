@@ -0,0 +1,352 @@
+// Playback of raw sequences (`.tseq`) through the live pipeline.
+//
+// `Player::open` starts a thread that reads the recording and feeds its frames
+// to the same processing loop the camera uses; camera frames are dropped until
+// the playback is closed. The UI steers it through the shared `Playback`
+// state: play/pause, speed, seeking, single steps and a loop region.
+
+use crate::Worker;
+use crate::calibration::Calibration;
+use crate::display::DisplaySettings;
+use crate::frame::ThermalFrame;
+use crate::orientation::Orientation;
+use crate::palette;
+use crate::sequence::SequenceReader;
+use crate::source::FrameSource;
+use anyhow::{Result, ensure};
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Thumbnails spread over the timeline.
+const THUMBNAILS: usize = 10;
+/// While paused, the current frame is reprocessed this often so that palette,
+/// range and tool changes show.
+const REFRESH: Duration = Duration::from_millis(200);
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Request {
+    Seek(usize),
+    Step(isize),
+}
+
+/// State of a playback, shared between the UI and the playback thread.
+pub struct Playback {
+    pub path: PathBuf,
+    pub len: usize,
+    pub duration: Duration,
+    /// Frame last delivered to the pipeline.
+    pub position: usize,
+    /// Time of `position` from the first frame.
+    pub time: Duration,
+    pub playing: bool,
+    /// Playback rate; 1.0 is real time.
+    pub speed: f32,
+    /// Wrap around within `loop_region` instead of stopping at the end.
+    pub looping: bool,
+    /// First and last frame (inclusive) of the loop.
+    pub loop_region: (usize, usize),
+    /// Evenly spaced frames for the timeline, filled in once after opening.
+    pub thumbnails: Vec<(usize, egui::ColorImage)>,
+    request: Option<Request>,
+    closed: bool,
+}
+
+impl Playback {
+    pub const SPEEDS: [f32; 7] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+    /// Jumps to frame `n`.
+    pub fn seek(&mut self, n: usize) {
+        self.request = Some(Request::Seek(n.min(self.len.saturating_sub(1))));
+    }
+
+    /// Pauses and moves `delta` frames.
+    pub fn step(&mut self, delta: isize) {
+        self.playing = false;
+        self.request = Some(Request::Step(delta));
+    }
+
+    pub fn toggle(&mut self) {
+        self.playing = !self.playing;
+        if self.playing && !self.looping && self.position + 1 >= self.len {
+            // Play again from the start once the end was reached.
+            self.seek(0);
+        }
+    }
+
+    /// Ends the playback; the camera takes over again.
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Handle for opening recordings; see `ThermalEngine::player`.
+#[derive(Clone)]
+pub struct Player {
+    worker: Worker,
+    /// Id of the playback that owns the engine, 0 for none.
+    active: Arc<AtomicU64>,
+    current: Arc<Mutex<Option<Arc<Mutex<Playback>>>>>,
+    /// The engine's calibration from before the first of a run of playbacks,
+    /// restored when the last of them ends. Also taken while `active` changes
+    /// hands, so that a playback ending meanwhile cannot interleave.
+    live_calibration: Arc<Mutex<Calibration>>,
+}
+
+impl Player {
+    pub(crate) fn new(worker: Worker, active: Arc<AtomicU64>) -> Self {
+        Self {
+            worker,
+            active,
+            current: Arc::new(Mutex::new(None)),
+            live_calibration: Arc::default(),
+        }
+    }
+
+    /// Opens `path` and starts playing it, closing any earlier playback.
+    pub fn open(&self, path: &Path) -> Result<Arc<Mutex<Playback>>> {
+        let reader = SequenceReader::open(path)?;
+        ensure!(!reader.is_empty(), "{} contains no frames", path.display());
+        let len = reader.len();
+        let state = Arc::new(Mutex::new(Playback {
+            path: path.to_path_buf(),
+            len,
+            duration: reader.duration(),
+            position: 0,
+            time: Duration::ZERO,
+            playing: true,
+            speed: 1.0,
+            looping: false,
+            loop_region: (0, len - 1),
+            thumbnails: Vec::new(),
+            request: Some(Request::Seek(0)),
+            closed: false,
+        }));
+
+        self.close();
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut live = self.live_calibration.lock().unwrap();
+            if self.active.swap(id, Ordering::Relaxed) == 0 {
+                *live = *self.worker.settings.calibration.lock().unwrap();
+            }
+        }
+        *self.current.lock().unwrap() = Some(state.clone());
+
+        let mut source = SequenceSource {
+            reader,
+            state: state.clone(),
+            calibration: self.worker.settings.calibration.clone(),
+            applied: None,
+            clock: None,
+            discontinuity: false,
+            refresh: false,
+            delivered: Instant::now(),
+        };
+        {
+            // Own reader, so that the first frame does not wait for them.
+            let state = state.clone();
+            let path = path.to_path_buf();
+            let display = self.worker.settings.display.lock().unwrap().clone();
+            let orientation = *self.worker.settings.orientation.lock().unwrap();
+            thread::spawn(move || match SequenceReader::open(&path) {
+                Ok(mut reader) => {
+                    let thumbnails = thumbnails(&mut reader, &display, &orientation);
+                    state.lock().unwrap().thumbnails = thumbnails;
+                }
+                Err(e) => eprintln!("❌ Cannot read thumbnails: {}", e),
+            });
+        }
+        let worker = self.worker.clone();
+        let active = self.active.clone();
+        let live_calibration = self.live_calibration.clone();
+        println!("▶ Playing {} ({} frames)", path.display(), len);
+        thread::spawn(move || {
+            worker.run(&mut source);
+            // Hand the engine back to the camera, unless a newer playback
+            // has taken over in the meantime.
+            let live = live_calibration.lock().unwrap();
+            if active
+                .compare_exchange(id, 0, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                *worker.settings.calibration.lock().unwrap() = *live;
+            }
+            drop(live);
+            println!("⏹ Playback ended.");
+        });
+        Ok(state)
+    }
+
+    /// Closes the current playback, if any.
+    pub fn close(&self) {
+        if let Some(state) = self.current.lock().unwrap().take() {
+            state.lock().unwrap().close();
+        }
+    }
+
+    /// The current playback, if it has not been closed.
+    pub fn current(&self) -> Option<Arc<Mutex<Playback>>> {
+        let mut current = self.current.lock().unwrap();
+        if current
+            .as_ref()
+            .is_some_and(|state| state.lock().unwrap().is_closed())
+        {
+            *current = None;
+        }
+        current.clone()
+    }
+}
+
+/// Colourised frames for the timeline, with the current palette and
+/// orientation and an automatic range.
+fn thumbnails(
+    reader: &mut SequenceReader,
+    display: &DisplaySettings,
+    orientation: &Orientation,
+) -> Vec<(usize, egui::ColorImage)> {
+    let len = reader.len();
+    let mut positions: Vec<usize> = (0..THUMBNAILS)
+        .map(|k| k * len.saturating_sub(1) / (THUMBNAILS - 1))
+        .collect();
+    positions.dedup();
+    positions
+        .into_iter()
+        .filter_map(|n| {
+            let mut frame = reader.frame(n).ok()?;
+            frame.orient(orientation);
+            let range = ThermalFrame::min_max(&frame.raw);
+            let image = palette::colorize(
+                &frame.raw,
+                frame.width,
+                frame.height,
+                display.effective_palette(),
+                range,
+            );
+            Some((n, image))
+        })
+        .collect()
+}
+
+/// Frames of a recording, paced by their timestamps.
+struct SequenceSource {
+    reader: SequenceReader,
+    state: Arc<Mutex<Playback>>,
+    /// The engine's calibration; set to the recorded one whenever that
+    /// changes, so edits made during playback stick until then.
+    calibration: Arc<Mutex<Calibration>>,
+    applied: Option<Calibration>,
+    /// Wall time and recording timestamp that pacing counts from, and the
+    /// speed it was set up for.
+    clock: Option<(Instant, f64, f32)>,
+    discontinuity: bool,
+    /// The frame just delivered repeats the paused one.
+    refresh: bool,
+    delivered: Instant,
+}
+
+impl SequenceSource {
+    /// The frame to deliver now, `None` to wait. Called with the state locked.
+    fn due(&mut self, state: &mut Playback) -> Option<usize> {
+        let last = state.len - 1;
+        self.refresh = false;
+        match state.request.take() {
+            Some(Request::Seek(n)) => {
+                self.clock = None;
+                self.discontinuity = n != state.position + 1;
+                return Some(n.min(last));
+            }
+            Some(Request::Step(delta)) => {
+                let n = state.position.saturating_add_signed(delta).min(last);
+                self.discontinuity = delta != 1;
+                return Some(n);
+            }
+            None => {}
+        }
+        if !state.playing {
+            self.clock = None;
+            self.refresh = self.delivered.elapsed() >= REFRESH;
+            return self.refresh.then_some(state.position);
+        }
+
+        let (start, end) = state.loop_region;
+        let mut next = state.position + 1;
+        if state.looping && (next > end.min(last) || next < start) {
+            next = start.min(last);
+            self.clock = None;
+            self.discontinuity = true;
+        } else if next > last {
+            state.playing = false;
+            return None;
+        }
+        let timestamp = self.reader.timestamp(next).unwrap_or_default();
+        let speed = state.speed.max(0.01);
+        let (wall, recorded, clock_speed) =
+            *self.clock.get_or_insert((Instant::now(), timestamp, speed));
+        if clock_speed != speed {
+            // Re-anchor at the current frame so the speed change is smooth.
+            let current = self.reader.timestamp(state.position).unwrap_or(timestamp);
+            self.clock = Some((self.delivered, current, speed));
+            return None;
+        }
+        let due = wall + Duration::from_secs_f64(((timestamp - recorded) / speed as f64).max(0.0));
+        (Instant::now() >= due).then_some(next)
+    }
+}
+
+impl FrameSource for SequenceSource {
+    fn next_frame(&mut self, frame: &mut ThermalFrame) -> bool {
+        loop {
+            let n = {
+                let state = self.state.clone();
+                let mut state = state.lock().unwrap();
+                if state.closed {
+                    return false;
+                }
+                self.due(&mut state)
+            };
+            let Some(n) = n else {
+                thread::sleep(POLL);
+                continue;
+            };
+            match self.reader.frame(n) {
+                Ok(decoded) => *frame = decoded,
+                Err(e) => {
+                    eprintln!("❌ Playback failed at frame {}: {}", n, e);
+                    self.state.lock().unwrap().close();
+                    return false;
+                }
+            }
+            if let Some(recorded) = self.reader.calibration(n)
+                && self.applied != Some(recorded)
+            {
+                *self.calibration.lock().unwrap() = recorded;
+                self.applied = Some(recorded);
+            }
+            let first = self.reader.timestamp(0).unwrap_or_default();
+            let timestamp = self.reader.timestamp(n).unwrap_or(first);
+            let mut state = self.state.lock().unwrap();
+            state.position = n;
+            state.time = Duration::from_secs_f64((timestamp - first).max(0.0));
+            self.delivered = Instant::now();
+            return true;
+        }
+    }
+
+    fn discontinuity(&mut self) -> bool {
+        std::mem::take(&mut self.discontinuity)
+    }
+
+    fn is_refresh(&self) -> bool {
+        self.refresh
+    }
+}
@@ -0,0 +1,367 @@
+// Bounded frame queues and a buffer pool.
+//
+// Both hops of the pipeline (libuvc callback → engine, engine → UI) go through a
+// `bounded` queue, so a stalled consumer can never make memory grow. What
+// happens when a queue is full is an explicit `OverflowPolicy`. Raw USB buffers
+// are recycled through a `BufferPool` instead of being allocated per frame.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// What `QueueSender::push` does when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued item; consumers always see the freshest frames.
+    #[default]
+    DropOldest,
+    /// Reject the new item; keeps a contiguous run of old frames.
+    DropNewest,
+    /// Wait until the consumer makes room. Never loses frames, but stalls the
+    /// producer (for the raw queue: the libuvc callback thread).
+    Block,
+}
+
+impl OverflowPolicy {
+    pub const ALL: [OverflowPolicy; 3] = [
+        OverflowPolicy::DropOldest,
+        OverflowPolicy::DropNewest,
+        OverflowPolicy::Block,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "Drop oldest",
+            OverflowPolicy::DropNewest => "Drop newest",
+            OverflowPolicy::Block => "Block",
+        }
+    }
+}
+
+/// Capacity and policy of one queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub policy: OverflowPolicy,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 4,
+            policy: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+/// The receiving side is gone; the item is handed back.
+#[derive(Debug)]
+pub struct Disconnected<T>(pub T);
+
+struct State<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receiver: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    config: QueueConfig,
+    dropped: AtomicU64,
+}
+
+/// Creates a bounded queue. The capacity is at least 1.
+pub fn bounded<T>(config: QueueConfig) -> (QueueSender<T>, QueueReceiver<T>) {
+    let config = QueueConfig {
+        capacity: config.capacity.max(1),
+        ..config
+    };
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(config.capacity),
+            senders: 1,
+            receiver: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        config,
+        dropped: AtomicU64::new(0),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Queues `item` according to the overflow policy. Returns the item that
+    /// was dropped to make room (or the rejected `item` itself), so that the
+    /// caller can recycle its buffers.
+    pub fn push(&self, item: T) -> Result<Option<T>, Disconnected<T>> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if shared.config.policy == OverflowPolicy::Block {
+            while state.receiver && state.items.len() >= shared.config.capacity {
+                state = shared.not_full.wait(state).unwrap();
+            }
+        }
+        if !state.receiver {
+            return Err(Disconnected(item));
+        }
+        let evicted = if state.items.len() < shared.config.capacity {
+            state.items.push_back(item);
+            None
+        } else if shared.config.policy == OverflowPolicy::DropNewest {
+            Some(item)
+        } else {
+            let oldest = state.items.pop_front();
+            state.items.push_back(item);
+            oldest
+        };
+        if evicted.is_some() {
+            shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        shared.not_empty.notify_one();
+        Ok(evicted)
+    }
+
+    /// Items lost to the overflow policy so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Waits for the next item. `None` once every sender is gone and the queue
+    /// is empty.
+    pub fn recv(&self) -> Option<T> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                shared.not_full.notify_one();
+                return Some(item);
+            }
+            if state.senders == 0 {
+                return None;
+            }
+            state = shared.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Next item if one is queued.
+    pub fn try_recv(&self) -> Option<T> {
+        let item = self.shared.state.lock().unwrap().items.pop_front();
+        if item.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Items lost to the overflow policy so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver = false;
+        state.items.clear();
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Recycles byte buffers between the capture callback and the engine.
+///
+/// `take` hands out a pooled buffer when one is available and only allocates
+/// otherwise; `give` returns a buffer, keeping at most `max_pooled` of them.
+#[derive(Clone)]
+pub struct BufferPool {
+    free: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_pooled: usize,
+}
+
+impl BufferPool {
+    /// Pool with `count` preallocated buffers of `size` bytes.
+    pub fn new(count: usize, size: usize) -> Self {
+        Self {
+            free: Arc::new(Mutex::new(
+                (0..count).map(|_| Vec::with_capacity(size)).collect(),
+            )),
+            max_pooled: count,
+        }
+    }
+
+    /// A buffer holding a copy of `data`.
+    pub fn take_copy(&self, data: &[u8]) -> Vec<u8> {
+        let mut buffer = self.free.lock().unwrap().pop().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    pub fn give(&self, buffer: Vec<u8>) {
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_pooled {
+            free.push(buffer);
+        }
+    }
+
+    /// Buffers currently waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn queue(capacity: usize, policy: OverflowPolicy) -> (QueueSender<u32>, QueueReceiver<u32>) {
+        bounded(QueueConfig { capacity, policy })
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest() {
+        let (tx, rx) = queue(2, OverflowPolicy::DropOldest);
+        assert_eq!(tx.push(1).unwrap(), None);
+        assert_eq!(tx.push(2).unwrap(), None);
+        assert_eq!(tx.push(3).unwrap(), Some(1));
+        assert_eq!(tx.push(4).unwrap(), Some(2));
+        assert_eq!((tx.dropped(), rx.dropped()), (2, 2));
+        assert_eq!(rx.try_recv(), Some(3));
+        assert_eq!(rx.try_recv(), Some(4));
+        assert_eq!(rx.try_recv(), None);
+    }
+
+    #[test]
+    fn drop_newest_rejects_the_new_item() {
+        let (tx, rx) = queue(2, OverflowPolicy::DropNewest);
+        tx.push(1).unwrap();
+        tx.push(2).unwrap();
+        assert_eq!(tx.push(3).unwrap(), Some(3));
+        assert_eq!(rx.dropped(), 1);
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv(), Some(1));
+        assert_eq!(rx.recv(), Some(2));
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (tx, rx) = queue(1, OverflowPolicy::Block);
+        tx.push(1).unwrap();
+        let (done_tx, done) = mpsc::channel();
+        let producer = thread::spawn(move || {
+            let result = tx.push(2);
+            done_tx.send(()).unwrap();
+            result.unwrap()
+        });
+        // Still blocked while the queue is full.
+        assert!(done.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(rx.recv(), Some(1));
+        done.recv_timeout(Duration::from_secs(5))
+            .expect("push did not resume");
+        assert_eq!(producer.join().unwrap(), None);
+        assert_eq!(rx.recv(), Some(2));
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[test]
+    fn dropping_the_receiver_unblocks_and_fails_pushes() {
+        let (tx, rx) = queue(1, OverflowPolicy::Block);
+        tx.push(1).unwrap();
+        let producer = thread::spawn(move || tx.push(2));
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        match producer.join().unwrap() {
+            Err(Disconnected(item)) => assert_eq!(item, 2),
+            Ok(_) => panic!("push succeeded without a receiver"),
+        }
+    }
+
+    #[test]
+    fn recv_ends_once_every_sender_is_gone() {
+        let (tx, rx) = queue(4, OverflowPolicy::DropOldest);
+        let second = tx.clone();
+        tx.push(1).unwrap();
+        drop(tx);
+        let consumer = thread::spawn(move || (rx.recv(), rx.recv(), rx.recv()));
+        second.push(2).unwrap();
+        drop(second);
+        assert_eq!(consumer.join().unwrap(), (Some(1), Some(2), None));
+    }
+
+    #[test]
+    fn capacity_is_at_least_one() {
+        let (tx, rx) = queue(0, OverflowPolicy::DropNewest);
+        assert_eq!(tx.push(1).unwrap(), None);
+        assert_eq!(tx.push(2).unwrap(), Some(2));
+        assert_eq!(rx.try_recv(), Some(1));
+    }
+
+    #[test]
+    fn buffer_pool_reuses_allocations() {
+        let pool = BufferPool::new(2, 64);
+        assert_eq!(pool.available(), 2);
+        let buffer = pool.take_copy(&[1, 2, 3]);
+        assert_eq!(buffer, [1, 2, 3]);
+        assert!(buffer.capacity() >= 64);
+        assert_eq!(pool.available(), 1);
+
+        let address = buffer.as_ptr();
+        pool.give(buffer);
+        let again = pool.take_copy(&[4]);
+        assert_eq!(again.as_ptr(), address);
+        assert_eq!(again, [4]);
+
+        // At most `count` buffers are kept.
+        pool.give(again);
+        pool.give(Vec::new());
+        pool.give(Vec::new());
+        assert_eq!(pool.available(), 2);
+    }
+}
@@ -0,0 +1,566 @@
+// Recording of the colourised stream to MJPEG-in-AVI, animated GIF or APNG.
+//
+// The engine offers every processed frame to the active `Recorder`, which
+// resamples the stream to the recording frame rate (skipping or repeating
+// frames by capture time) and hands images to a writer thread through a bounded
+// queue, so a slow encoder never stalls capture.
+
+use crate::frame::ProcessedFrame;
+use crate::legend;
+use crate::queue::{self, OverflowPolicy, QueueConfig, QueueReceiver, QueueSender};
+use crate::snapshot;
+use anyhow::{Context, Result, bail};
+use eframe::egui;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Motion JPEG in an AVI container; plays everywhere, any length.
+    #[default]
+    MjpegAvi,
+    /// Animated GIF, 256 colours per frame. For short clips.
+    Gif,
+    /// Animated PNG, lossless. Frames are kept in memory until the clip ends.
+    Apng,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 3] = [
+        RecordingFormat::MjpegAvi,
+        RecordingFormat::Gif,
+        RecordingFormat::Apng,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordingFormat::MjpegAvi => "MJPEG (AVI)",
+            RecordingFormat::Gif => "Animated GIF",
+            RecordingFormat::Apng => "Animated PNG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::MjpegAvi => "avi",
+            RecordingFormat::Gif => "gif",
+            RecordingFormat::Apng => "png",
+        }
+    }
+
+    /// Clip formats stop accepting frames after this much video.
+    pub fn max_duration(self) -> Option<Duration> {
+        match self {
+            RecordingFormat::MjpegAvi => None,
+            RecordingFormat::Gif | RecordingFormat::Apng => Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordingConfig {
+    pub format: RecordingFormat,
+    /// Output frame rate, independent of the capture rate.
+    pub fps: f32,
+    /// Burn the colour legend and spot readouts into every frame.
+    pub burn_overlays: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            format: RecordingFormat::MjpegAvi,
+            fps: 25.0,
+            burn_overlays: true,
+        }
+    }
+}
+
+/// An active recording.
+pub struct Recorder {
+    path: PathBuf,
+    config: RecordingConfig,
+    tx: Option<QueueSender<egui::ColorImage>>,
+    worker: Option<JoinHandle<Result<u64>>>,
+    /// Capture time of the first frame.
+    start: Option<Instant>,
+    /// Video time of the next output frame.
+    next_due: Duration,
+    frames: u64,
+}
+
+impl Recorder {
+    /// At most this many copies of one frame when the capture rate is below
+    /// the recording rate (or capture stalled).
+    const MAX_REPEAT: usize = 10;
+
+    /// Creates `path` and starts the writer thread.
+    pub fn start(path: &Path, config: RecordingConfig) -> Result<Self> {
+        if config.fps.is_nan() || config.fps <= 0.0 {
+            bail!("Recording frame rate must be positive");
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        let (tx, rx) = queue::bounded(QueueConfig {
+            capacity: 32,
+            policy: OverflowPolicy::DropNewest,
+        });
+        let worker = thread::spawn(move || write_frames(file, config, rx));
+        println!("⏺ Recording to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            config,
+            tx: Some(tx),
+            worker: Some(worker),
+            start: None,
+            next_due: Duration::ZERO,
+            frames: 0,
+        })
+    }
+
+    /// Default file name for a recording started now.
+    pub fn default_path(dir: &Path, format: RecordingFormat) -> PathBuf {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        dir.join(format!("recording_{}.{}", secs, format.extension()))
+    }
+
+    /// Queues `frame` as often as the recording rate asks for it.
+    pub fn offer(&mut self, frame: &ProcessedFrame) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let start = *self.start.get_or_insert(frame.frame.captured);
+        let at = frame.frame.captured.saturating_duration_since(start);
+        let period = Duration::from_secs_f32(1.0 / self.config.fps);
+        let mut repeats = 0;
+        while at >= self.next_due && repeats < Self::MAX_REPEAT && !self.is_full() {
+            repeats += 1;
+            self.next_due += period;
+        }
+        // A long stall: resynchronise instead of catching up later.
+        if at >= self.next_due {
+            self.next_due = at + period;
+        }
+        if repeats == 0 {
+            return;
+        }
+        let image = if self.config.burn_overlays {
+            legend::burn_in(&frame.image, &frame.legend, &frame.spots)
+        } else {
+            frame.image.clone()
+        };
+        for _ in 1..repeats {
+            if tx.push(image.clone()).is_err() {
+                break;
+            }
+        }
+        match tx.push(image) {
+            Ok(_) => self.frames += repeats as u64,
+            Err(_) => {
+                // The writer thread failed; `finish` reports why.
+                eprintln!("❌ Recording stopped: writer failed.");
+                self.tx = None;
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn config(&self) -> RecordingConfig {
+        self.config
+    }
+
+    /// Length of the recorded video so far.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.config.fps as f64)
+    }
+
+    /// Frames the writer could not keep up with.
+    pub fn dropped(&self) -> u64 {
+        self.tx.as_ref().map_or(0, |tx| tx.dropped())
+    }
+
+    /// A clip format reached its maximum length.
+    pub fn is_full(&self) -> bool {
+        self.config
+            .format
+            .max_duration()
+            .is_some_and(|max| self.elapsed() >= max)
+    }
+
+    /// Flushes the queue and finalises the file. Returns its path.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        // Closing the queue ends the writer loop.
+        self.tx = None;
+        let frames = match self.worker.take().map(|worker| worker.join()) {
+            Some(Ok(result)) => result?,
+            _ => bail!("Recording thread panicked"),
+        };
+        println!("⏹ Recorded {} frames to {}", frames, self.path.display());
+        Ok(self.path)
+    }
+}
+
+fn write_frames(
+    file: File,
+    config: RecordingConfig,
+    rx: QueueReceiver<egui::ColorImage>,
+) -> Result<u64> {
+    let mut file = Some(file);
+    let mut writer: Option<(Box<dyn VideoWriter>, [usize; 2])> = None;
+    let mut count = 0;
+    while let Some(image) = rx.recv() {
+        let (writer, size) = match &mut writer {
+            Some(w) => w,
+            None => {
+                let file = file.take().context("Recording file already closed")?;
+                writer.insert((new_writer(file, config, image.size)?, image.size))
+            }
+        };
+        if image.size == *size {
+            writer.write(&image)?;
+        } else {
+            // Scale or orientation changed mid-recording.
+            writer.write(&fit_into(&image, *size))?;
+        }
+        count += 1;
+    }
+    match writer {
+        Some((writer, _)) => writer.finish()?,
+        None => bail!("No frames were recorded"),
+    }
+    Ok(count)
+}
+
+fn new_writer(
+    file: File,
+    config: RecordingConfig,
+    size: [usize; 2],
+) -> Result<Box<dyn VideoWriter>> {
+    Ok(match config.format {
+        RecordingFormat::MjpegAvi => Box::new(AviWriter::new(file, size, config.fps)?),
+        RecordingFormat::Gif => Box::new(GifWriter::new(file, config.fps)?),
+        RecordingFormat::Apng => Box::new(ApngWriter {
+            file,
+            size,
+            fps: config.fps,
+            frames: Vec::new(),
+        }),
+    })
+}
+
+/// Scales `image` into `size` (nearest neighbour, aspect kept, black bars).
+fn fit_into(image: &egui::ColorImage, size: [usize; 2]) -> egui::ColorImage {
+    let [sw, sh] = image.size;
+    let [w, h] = size;
+    let mut out = egui::ColorImage::new(size, egui::Color32::BLACK);
+    if sw == 0 || sh == 0 {
+        return out;
+    }
+    let scale = (w as f32 / sw as f32).min(h as f32 / sh as f32);
+    let (fw, fh) = ((sw as f32 * scale) as usize, (sh as f32 * scale) as usize);
+    let (x0, y0) = ((w - fw) / 2, (h - fh) / 2);
+    for y in 0..fh {
+        let src_row = ((y as f32 / scale) as usize).min(sh - 1) * sw;
+        for x in 0..fw {
+            let sx = ((x as f32 / scale) as usize).min(sw - 1);
+            out.pixels[(y0 + y) * w + x0 + x] = image.pixels[src_row + sx];
+        }
+    }
+    out
+}
+
+trait VideoWriter: Send {
+    fn write(&mut self, image: &egui::ColorImage) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// MJPEG AVI 1.0: a fixed header, one `00dc` chunk per JPEG and an `idx1`
+/// index. Frame count and sizes are patched into the header on `finish`.
+struct AviWriter {
+    out: BufWriter<File>,
+    /// Offset and length of every frame chunk, relative to the `movi` tag.
+    index: Vec<(u32, u32)>,
+    /// Bytes written after the `movi` tag.
+    movi_len: u64,
+    max_frame: u32,
+}
+
+impl AviWriter {
+    /// AVI 1.0 keeps sizes in 32 bits; stay well clear of the 2 GiB readers
+    /// commonly choke on.
+    const MAX_BYTES: u64 = 1 << 30;
+    const HEADER_LEN: usize = 224;
+    const MOVI_TAG: u64 = 220;
+
+    fn new(file: File, [w, h]: [usize; 2], fps: f32) -> Result<Self> {
+        let (w, h) = (w as u32, h as u32);
+        let mut header = Vec::with_capacity(Self::HEADER_LEN);
+        // Sizes and counts are zero here and patched in `finish`.
+        fourcc(&mut header, b"RIFF", &[0]);
+        fourcc(&mut header, b"AVI ", &[]);
+        fourcc(&mut header, b"LIST", &[192]);
+        fourcc(&mut header, b"hdrl", &[]);
+        let us_per_frame = (1e6 / fps) as u32;
+        fourcc(
+            &mut header,
+            b"avih",
+            &[56, us_per_frame, 0, 0, 0x10, 0, 0, 1, 0, w, h, 0, 0, 0, 0],
+        );
+        fourcc(&mut header, b"LIST", &[116]);
+        fourcc(&mut header, b"strl", &[]);
+        fourcc(&mut header, b"strh", &[56]);
+        fourcc(&mut header, b"vids", &[]);
+        let rate = (fps * 1000.0) as u32;
+        fourcc(
+            &mut header,
+            b"MJPG",
+            &[0, 0, 0, 1000, rate, 0, 0, 0, u32::MAX, 0],
+        );
+        // rcFrame: left, top, right, bottom as i16
+        for v in [0, 0, w as u16, h as u16] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        fourcc(&mut header, b"strf", &[40, 40, w, h]);
+        for v in [1u16, 24] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        fourcc(&mut header, b"MJPG", &[w * h * 3, 0, 0, 0, 0]);
+        fourcc(&mut header, b"LIST", &[0]);
+        fourcc(&mut header, b"movi", &[]);
+        debug_assert_eq!(header.len(), Self::HEADER_LEN);
+
+        let mut out = BufWriter::new(file);
+        out.write_all(&header)?;
+        Ok(Self {
+            out,
+            index: Vec::new(),
+            movi_len: 4,
+            max_frame: 0,
+        })
+    }
+
+    fn patch(&mut self, pos: u64, value: u32) -> Result<()> {
+        self.out.seek(SeekFrom::Start(pos))?;
+        self.out.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl VideoWriter for AviWriter {
+    fn write(&mut self, image: &egui::ColorImage) -> Result<()> {
+        let jpeg = snapshot::encode_jpeg(image)?;
+        let padded = jpeg.len() + jpeg.len() % 2;
+        if Self::MOVI_TAG + self.movi_len + 8 + padded as u64 > Self::MAX_BYTES {
+            bail!("AVI size limit (1 GiB) reached");
+        }
+        self.index.push((self.movi_len as u32, jpeg.len() as u32));
+        self.out.write_all(b"00dc")?;
+        self.out.write_all(&(jpeg.len() as u32).to_le_bytes())?;
+        self.out.write_all(&jpeg)?;
+        if padded > jpeg.len() {
+            self.out.write_all(&[0])?;
+        }
+        self.movi_len += 8 + padded as u64;
+        self.max_frame = self.max_frame.max(jpeg.len() as u32);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.write_all(b"idx1")?;
+        self.out
+            .write_all(&(self.index.len() as u32 * 16).to_le_bytes())?;
+        for &(offset, len) in &self.index {
+            self.out.write_all(b"00dc")?;
+            // AVIIF_KEYFRAME: every MJPEG frame stands alone.
+            for field in [0x10, offset, len] {
+                self.out.write_all(&field.to_le_bytes())?;
+            }
+        }
+        let end = self.out.stream_position()?;
+        let frames = self.index.len() as u32;
+        self.patch(4, (end - 8) as u32)?;
+        self.patch(48, frames)?; // avih.dwTotalFrames
+        self.patch(60, self.max_frame)?; // avih.dwSuggestedBufferSize
+        self.patch(140, frames)?; // strh.dwLength
+        self.patch(144, self.max_frame)?; // strh.dwSuggestedBufferSize
+        self.patch(216, self.movi_len as u32)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// A four-character code followed by little-endian u32 fields.
+fn fourcc(buf: &mut Vec<u8>, tag: &[u8; 4], fields: &[u32]) {
+    buf.extend_from_slice(tag);
+    for field in fields {
+        buf.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+struct GifWriter {
+    encoder: image::codecs::gif::GifEncoder<BufWriter<File>>,
+    delay: image::Delay,
+}
+
+impl GifWriter {
+    fn new(file: File, fps: f32) -> Result<Self> {
+        // Speed 10 of 1..=30: a reasonable palette at a tolerable encode cost.
+        let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(BufWriter::new(file), 10);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            delay: image::Delay::from_numer_denom_ms(100_000, (fps * 100.0) as u32),
+        })
+    }
+}
+
+impl VideoWriter for GifWriter {
+    fn write(&mut self, image: &egui::ColorImage) -> Result<()> {
+        let [w, h] = image.size;
+        let buffer = image::RgbaImage::from_raw(w as u32, h as u32, image.as_raw().to_vec())
+            .context("Image size mismatch")?;
+        self.encoder
+            .encode_frame(image::Frame::from_parts(buffer, 0, 0, self.delay))?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        // The trailer is written when the encoder is dropped.
+        drop(self.encoder);
+        Ok(())
+    }
+}
+
+/// APNG needs the frame count before the first frame, so frames are buffered
+/// and encoded on `finish`.
+struct ApngWriter {
+    file: File,
+    size: [usize; 2],
+    fps: f32,
+    frames: Vec<Vec<u8>>,
+}
+
+impl VideoWriter for ApngWriter {
+    fn write(&mut self, image: &egui::ColorImage) -> Result<()> {
+        self.frames.push(image.as_raw().to_vec());
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let [w, h] = self.size;
+        let mut encoder = png::Encoder::new(BufWriter::new(self.file), w as u32, h as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        encoder.set_frame_delay(100, (self.fps * 100.0) as u16)?;
+        let mut writer = encoder.write_header()?;
+        for frame in &self.frames {
+            writer.write_image_data(frame)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+
+    const FRAMES: usize = 5;
+    const SIZE: [usize; 2] = [32, 24];
+
+    /// Writes `FRAMES` images, the last one at a different size, and returns
+    /// the file contents.
+    fn record(format: RecordingFormat) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "recording-{}.{}",
+            std::process::id(),
+            format.extension()
+        ));
+        let (tx, rx) = queue::bounded(QueueConfig {
+            capacity: FRAMES,
+            policy: OverflowPolicy::DropNewest,
+        });
+        for i in 0..FRAMES {
+            let size = if i + 1 == FRAMES { [64, 64] } else { SIZE };
+            let colour = egui::Color32::from_rgb(40 * i as u8, 100, 200);
+            tx.push(egui::ColorImage::new(size, colour)).unwrap();
+        }
+        drop(tx);
+        let config = RecordingConfig {
+            format,
+            fps: 10.0,
+            burn_overlays: false,
+        };
+        let written = write_frames(File::create(&path).unwrap(), config, rx).unwrap();
+        assert_eq!(written, FRAMES as u64);
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        data
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn avi_index_points_at_every_frame() {
+        let data = record(RecordingFormat::MjpegAvi);
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+        assert_eq!(u32_at(&data, 48) as usize, FRAMES);
+        assert_eq!(u32_at(&data, 140) as usize, FRAMES);
+
+        let movi = AviWriter::MOVI_TAG as usize;
+        let idx1 = movi + u32_at(&data, movi - 4) as usize;
+        assert_eq!(&data[idx1..idx1 + 4], b"idx1");
+        assert_eq!(u32_at(&data, idx1 + 4) as usize, FRAMES * 16);
+        for entry in data[idx1 + 8..].chunks_exact(16) {
+            assert_eq!(&entry[..4], b"00dc");
+            let (offset, len) = (u32_at(entry, 8) as usize, u32_at(entry, 12) as usize);
+            let chunk = movi + offset;
+            assert_eq!(&data[chunk..chunk + 4], b"00dc");
+            let jpeg = image::load_from_memory(&data[chunk + 8..chunk + 8 + len]).unwrap();
+            assert_eq!([jpeg.width(), jpeg.height()], [32, 24]);
+        }
+    }
+
+    #[test]
+    fn gif_decodes_to_every_frame() {
+        let data = record(RecordingFormat::Gif);
+        let decoder = image::codecs::gif::GifDecoder::new(&data[..]).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), FRAMES);
+        for frame in &frames {
+            assert_eq!(frame.buffer().dimensions(), (32, 24));
+            assert_eq!(frame.delay().numer_denom_ms(), (100, 1));
+        }
+    }
+
+    #[test]
+    fn apng_decodes_to_every_frame() {
+        let data = record(RecordingFormat::Apng);
+        let mut reader = png::Decoder::new(&data[..]).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames as usize, FRAMES);
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for _ in 0..FRAMES {
+            let output = reader.next_frame(&mut buffer).unwrap();
+            assert_eq!((output.width, output.height), (32, 24));
+        }
+        assert!(reader.next_frame(&mut buffer).is_err());
+    }
+}
@@ -0,0 +1,944 @@
+// Seekable raw radiometric sequences (`.tseq`).
+//
+// After an 8-byte magic the file is a run of blocks, each
+// `tag (4 bytes) | length (u32 LE) | payload`:
+//
+// - `INFO`, first: TOML `SequenceInfo`.
+// - `CALB`: TOML `Calibration`, in effect for the frames after it. Written
+//   before the first frame and again whenever the conversion changes.
+// - `FRAM`: one frame, see `encode_frame`.
+// - `INDX`, last: calibration block offsets and, per frame, its offset,
+//   timestamp, sequence number and calibration. A 16-byte trailer (offset of
+//   the `INDX` block, then `TSEQIDX\0`) ends the file.
+//
+// Every `chunk`-th frame is a key frame predicted from its own neighbours
+// (LOCO-I median predictor); the frames in between use whichever of that or
+// the difference to the previous frame codes smaller. Residuals are Rice coded
+// in blocks of 32 with a per-block parameter. Reading a frame decodes forward
+// from the key frame before it, so a seek costs at most one chunk. A file
+// whose recording was cut short has no index; the reader rebuilds it by
+// scanning the blocks.
+
+use crate::calibration::Calibration;
+use crate::frame::ThermalFrame;
+use crate::queue::{self, OverflowPolicy, QueueConfig, QueueReceiver, QueueSender};
+use anyhow::{Context, Result, bail, ensure};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const EXTENSION: &str = "tseq";
+
+const MAGIC: &[u8; 8] = b"TSEQ\0\x01\r\n";
+const TRAILER: &[u8; 8] = b"TSEQIDX\0";
+const VERSION: u32 = 1;
+/// Largest block the reader accepts; guards against corrupt lengths.
+const MAX_BLOCK: u32 = 64 << 20;
+
+const TAG_INFO: &[u8; 4] = b"INFO";
+const TAG_CALIBRATION: &[u8; 4] = b"CALB";
+const TAG_FRAME: &[u8; 4] = b"FRAM";
+const TAG_INDEX: &[u8; 4] = b"INDX";
+
+/// Bytes of a `FRAM` payload before the coded data.
+const FRAME_HEADER: usize = 8 + 8 + 2 + 2 + 1;
+
+/// Written once at the start of a sequence.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SequenceInfo {
+    pub version: u32,
+    pub device: String,
+    /// Unix time the recording started.
+    pub started: f64,
+    /// Frames per key frame.
+    pub chunk: usize,
+}
+
+impl SequenceInfo {
+    pub fn new(device: &str) -> Self {
+        Self {
+            version: VERSION,
+            device: device.to_string(),
+            started: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            chunk: 25,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Codec {
+    /// Little-endian u16, uncompressed.
+    Stored = 0,
+    /// Median-predicted residuals, no reference frame.
+    Spatial = 1,
+    /// Difference to the previous frame.
+    Temporal = 2,
+}
+
+impl Codec {
+    fn from_u8(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => Codec::Stored,
+            1 => Codec::Spatial,
+            2 => Codec::Temporal,
+            _ => bail!("Unknown frame codec {}", value),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct IndexEntry {
+    offset: u64,
+    timestamp: f64,
+    sequence: u64,
+    calibration: u32,
+    key: bool,
+}
+
+const INDEX_ENTRY: usize = 8 + 8 + 8 + 4 + 1;
+
+/// Writes a sequence frame by frame; `finish` adds the index.
+pub struct SequenceWriter {
+    out: BufWriter<File>,
+    position: u64,
+    info: SequenceInfo,
+    calibrations: Vec<u64>,
+    calibration: Option<Calibration>,
+    frames: Vec<IndexEntry>,
+    /// Size and raw field of the last frame written.
+    previous: Option<(usize, usize, Vec<u16>)>,
+    raw_bytes: u64,
+}
+
+impl SequenceWriter {
+    pub fn create(path: &Path, info: SequenceInfo) -> Result<Self> {
+        ensure!(info.chunk > 0, "Key frame interval must be positive");
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        let mut writer = Self {
+            out: BufWriter::new(file),
+            position: 0,
+            info,
+            calibrations: Vec::new(),
+            calibration: None,
+            frames: Vec::new(),
+            previous: None,
+            raw_bytes: 0,
+        };
+        writer.out.write_all(MAGIC)?;
+        writer.position = MAGIC.len() as u64;
+        let info = toml::to_string(&writer.info)?;
+        writer.write_block(TAG_INFO, info.as_bytes())?;
+        Ok(writer)
+    }
+
+    /// Appends `frame.raw`, captured at `timestamp` (Unix seconds) and
+    /// converted with `calibration`.
+    pub fn write(
+        &mut self,
+        frame: &ThermalFrame,
+        timestamp: f64,
+        calibration: &Calibration,
+    ) -> Result<()> {
+        let (width, height) = (frame.width, frame.height);
+        ensure!(
+            width > 0 && width <= u16::MAX as usize && height <= u16::MAX as usize,
+            "Unsupported frame size {}x{}",
+            width,
+            height
+        );
+        ensure!(
+            frame.raw.len() == width * height,
+            "Frame field size mismatch"
+        );
+        if self.calibration != Some(*calibration) {
+            let offset =
+                self.write_block(TAG_CALIBRATION, toml::to_string(calibration)?.as_bytes())?;
+            self.calibrations.push(offset);
+            self.calibration = Some(*calibration);
+        }
+        let previous = match &self.previous {
+            Some((w, h, raw)) if (*w, *h) == (width, height) => Some(raw.as_slice()),
+            _ => None,
+        };
+        let previous = previous.filter(|_| !self.frames.len().is_multiple_of(self.info.chunk));
+        let (codec, data) = encode_frame(&frame.raw, width, height, previous);
+
+        let mut payload = Vec::with_capacity(FRAME_HEADER + data.len());
+        payload.extend_from_slice(&frame.sequence.to_le_bytes());
+        payload.extend_from_slice(&timestamp.to_le_bytes());
+        payload.extend_from_slice(&(width as u16).to_le_bytes());
+        payload.extend_from_slice(&(height as u16).to_le_bytes());
+        payload.push(codec as u8);
+        payload.extend_from_slice(&data);
+        let offset = self.write_block(TAG_FRAME, &payload)?;
+        self.frames.push(IndexEntry {
+            offset,
+            timestamp,
+            sequence: frame.sequence,
+            calibration: self.calibrations.len() as u32 - 1,
+            key: codec != Codec::Temporal,
+        });
+        match &mut self.previous {
+            Some((w, h, raw)) => {
+                (*w, *h) = (width, height);
+                raw.clone_from(&frame.raw);
+            }
+            None => self.previous = Some((width, height, frame.raw.clone())),
+        }
+        self.raw_bytes += frame.raw.len() as u64 * 2;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Bytes written so far.
+    pub fn size(&self) -> u64 {
+        self.position
+    }
+
+    /// Uncompressed size of the frames written so far.
+    pub fn raw_size(&self) -> u64 {
+        self.raw_bytes
+    }
+
+    /// Writes the index and trailer. Returns the file size.
+    pub fn finish(mut self) -> Result<u64> {
+        let mut index =
+            Vec::with_capacity(8 + self.calibrations.len() * 8 + self.frames.len() * INDEX_ENTRY);
+        index.extend_from_slice(&(self.calibrations.len() as u32).to_le_bytes());
+        for offset in &self.calibrations {
+            index.extend_from_slice(&offset.to_le_bytes());
+        }
+        index.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for entry in &self.frames {
+            index.extend_from_slice(&entry.offset.to_le_bytes());
+            index.extend_from_slice(&entry.timestamp.to_le_bytes());
+            index.extend_from_slice(&entry.sequence.to_le_bytes());
+            index.extend_from_slice(&entry.calibration.to_le_bytes());
+            index.push(entry.key as u8);
+        }
+        let offset = self.write_block(TAG_INDEX, &index)?;
+        self.out.write_all(&offset.to_le_bytes())?;
+        self.out.write_all(TRAILER)?;
+        self.out.flush()?;
+        Ok(self.position + 16)
+    }
+
+    /// Returns the block's offset.
+    fn write_block(&mut self, tag: &[u8; 4], payload: &[u8]) -> Result<u64> {
+        let offset = self.position;
+        self.out.write_all(tag)?;
+        self.out.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.out.write_all(payload)?;
+        self.position += 8 + payload.len() as u64;
+        Ok(offset)
+    }
+}
+
+/// Random access to the frames of a sequence.
+pub struct SequenceReader {
+    file: BufReader<File>,
+    info: SequenceInfo,
+    calibrations: Vec<Calibration>,
+    frames: Vec<IndexEntry>,
+    indexed: bool,
+    /// Last decoded frame, so that sequential reads decode one frame each.
+    cache: Option<(usize, ThermalFrame)>,
+}
+
+impl SequenceReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+        let mut file = BufReader::new(file);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        ensure!(
+            &magic == MAGIC,
+            "{} is not a .tseq sequence",
+            path.display()
+        );
+        let (tag, payload) =
+            read_block(&mut file, MAGIC.len() as u64)?.context("Missing header")?;
+        ensure!(&tag == TAG_INFO, "Missing sequence header");
+        let info: SequenceInfo = toml::from_str(std::str::from_utf8(&payload)?)?;
+        ensure!(
+            info.version <= VERSION,
+            "Sequence version {} is newer than supported ({})",
+            info.version,
+            VERSION
+        );
+
+        let mut reader = Self {
+            file,
+            info,
+            calibrations: Vec::new(),
+            frames: Vec::new(),
+            indexed: false,
+            cache: None,
+        };
+        match reader.read_index() {
+            Ok(true) => reader.indexed = true,
+            Ok(false) => {
+                eprintln!(
+                    "⚠ {} has no index (recording interrupted?), scanning.",
+                    path.display()
+                );
+                reader.scan(MAGIC.len() as u64 + 8 + payload.len() as u64)?;
+            }
+            Err(e) => {
+                eprintln!("⚠ {}: damaged index ({}), scanning.", path.display(), e);
+                reader.calibrations.clear();
+                reader.frames.clear();
+                reader.scan(MAGIC.len() as u64 + 8 + payload.len() as u64)?;
+            }
+        }
+        Ok(reader)
+    }
+
+    pub fn info(&self) -> &SequenceInfo {
+        &self.info
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The file ended with an index (it was closed properly).
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Capture time of frame `n` (Unix seconds).
+    pub fn timestamp(&self, n: usize) -> Option<f64> {
+        self.frames.get(n).map(|e| e.timestamp)
+    }
+
+    /// Time from the first to the last frame.
+    pub fn duration(&self) -> Duration {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(first), Some(last)) => {
+                Duration::from_secs_f64((last.timestamp - first.timestamp).max(0.0))
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The last frame captured at or before `offset` from the first frame.
+    pub fn position(&self, offset: Duration) -> usize {
+        let Some(first) = self.frames.first() else {
+            return 0;
+        };
+        let at = first.timestamp + offset.as_secs_f64();
+        self.frames
+            .partition_point(|e| e.timestamp <= at)
+            .saturating_sub(1)
+    }
+
+    /// Conversion in effect for frame `n`.
+    pub fn calibration(&self, n: usize) -> Option<Calibration> {
+        let entry = self.frames.get(n)?;
+        self.calibrations.get(entry.calibration as usize).copied()
+    }
+
+    /// Decodes frame `n`. `captured` is the time of decoding; use
+    /// `timestamp` for the capture time.
+    pub fn frame(&mut self, n: usize) -> Result<ThermalFrame> {
+        ensure!(n < self.frames.len(), "Frame {} out of range", n);
+        if let Some((cached, frame)) = &self.cache
+            && *cached == n
+        {
+            return Ok(frame.clone());
+        }
+        let start = match &self.cache {
+            Some((cached, _)) if *cached + 1 == n && !self.frames[n].key => n,
+            _ => (0..=n).rev().find(|&i| self.frames[i].key).unwrap_or(0),
+        };
+        for i in start..=n {
+            let (tag, payload) = read_block(&mut self.file, self.frames[i].offset)?
+                .with_context(|| format!("Frame {} is truncated", i))?;
+            ensure!(&tag == TAG_FRAME, "Index points to a {:?} block", tag);
+            let previous = self.cache.take().map(|(_, frame)| frame);
+            let frame = decode_frame(&payload, previous)?;
+            self.cache = Some((i, frame));
+        }
+        Ok(self
+            .cache
+            .as_ref()
+            .map(|(_, f)| f.clone())
+            .unwrap_or_default())
+    }
+
+    /// Reads the trailer and `INDX` block. `Ok(false)` if there is none.
+    fn read_index(&mut self) -> Result<bool> {
+        let end = self.file.seek(SeekFrom::End(0))?;
+        if end < MAGIC.len() as u64 + 16 {
+            return Ok(false);
+        }
+        self.file.seek(SeekFrom::End(-16))?;
+        let mut trailer = [0; 16];
+        self.file.read_exact(&mut trailer)?;
+        if &trailer[8..] != TRAILER {
+            return Ok(false);
+        }
+        let offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        let (tag, index) = read_block(&mut self.file, offset)?.context("Index is truncated")?;
+        ensure!(&tag == TAG_INDEX, "Trailer does not point to the index");
+
+        let mut cursor = Cursor::new(&index);
+        let calibrations = cursor.u32()? as usize;
+        let mut offsets = Vec::with_capacity(calibrations.min(index.len() / 8));
+        for _ in 0..calibrations {
+            offsets.push(cursor.u64()?);
+        }
+        let frames = cursor.u32()? as usize;
+        self.frames.reserve(frames.min(index.len() / INDEX_ENTRY));
+        for _ in 0..frames {
+            let entry = IndexEntry {
+                offset: cursor.u64()?,
+                timestamp: f64::from_bits(cursor.u64()?),
+                sequence: cursor.u64()?,
+                calibration: cursor.u32()?,
+                key: cursor.u8()? != 0,
+            };
+            ensure!(
+                (entry.calibration as usize) < calibrations,
+                "Index refers to a missing calibration"
+            );
+            self.frames.push(entry);
+        }
+        for offset in offsets {
+            let (tag, payload) =
+                read_block(&mut self.file, offset)?.context("Calibration block is truncated")?;
+            ensure!(&tag == TAG_CALIBRATION, "Index points to a {:?} block", tag);
+            self.calibrations
+                .push(toml::from_str(std::str::from_utf8(&payload)?)?);
+        }
+        Ok(true)
+    }
+
+    /// Rebuilds the index from the blocks after `offset`, stopping at the
+    /// first incomplete one.
+    fn scan(&mut self, mut offset: u64) -> Result<()> {
+        while let Some((tag, payload)) = read_block(&mut self.file, offset).ok().flatten() {
+            match &tag {
+                TAG_CALIBRATION => self
+                    .calibrations
+                    .push(toml::from_str(std::str::from_utf8(&payload)?)?),
+                TAG_FRAME if payload.len() >= FRAME_HEADER && !self.calibrations.is_empty() => {
+                    let mut cursor = Cursor::new(&payload);
+                    let sequence = cursor.u64()?;
+                    let timestamp = f64::from_bits(cursor.u64()?);
+                    let codec = Codec::from_u8(payload[FRAME_HEADER - 1])?;
+                    self.frames.push(IndexEntry {
+                        offset,
+                        timestamp,
+                        sequence,
+                        calibration: self.calibrations.len() as u32 - 1,
+                        key: codec != Codec::Temporal,
+                    });
+                }
+                TAG_INDEX => break,
+                _ => {}
+            }
+            offset += 8 + payload.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the block at `offset`; `None` if the file ends inside it.
+fn read_block(file: &mut BufReader<File>, offset: u64) -> Result<Option<([u8; 4], Vec<u8>)>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut header = [0; 8];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+    let tag: [u8; 4] = header[..4].try_into().unwrap();
+    let length = u32::from_le_bytes(header[4..].try_into().unwrap());
+    ensure!(
+        length <= MAX_BLOCK,
+        "Block {:?} claims {} bytes",
+        tag,
+        length
+    );
+    let mut payload = vec![0; length as usize];
+    if file.read_exact(&mut payload).is_err() {
+        return Ok(None);
+    }
+    Ok(Some((tag, payload)))
+}
+
+/// Little-endian reads from a block payload.
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + N)
+            .context("Block is truncated")?;
+        self.position += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+}
+
+// --- Frame coding ---------------------------------------------------------
+
+/// Values per Rice parameter.
+const RICE_BLOCK: usize = 32;
+/// Quotients this large are escaped and stored in `ESCAPE_BITS` bits.
+const ESCAPE: u32 = 24;
+/// Enough for any zigzagged difference of two u16 values.
+const ESCAPE_BITS: u32 = 17;
+
+/// Codes `raw` against its own neighbours and, if given, against `previous`;
+/// returns the smaller (or the field as is, if neither compresses).
+fn encode_frame(
+    raw: &[u16],
+    width: usize,
+    height: usize,
+    previous: Option<&[u16]>,
+) -> (Codec, Vec<u8>) {
+    let mut residuals = Vec::with_capacity(raw.len());
+    spatial_residuals(raw, width, height, &mut residuals);
+    let mut best = (Codec::Spatial, rice_encode(&residuals));
+    if let Some(previous) = previous {
+        residuals.clear();
+        residuals.extend(
+            raw.iter()
+                .zip(previous)
+                .map(|(&v, &p)| zigzag(v as i32 - p as i32)),
+        );
+        let temporal = rice_encode(&residuals);
+        if temporal.len() < best.1.len() {
+            best = (Codec::Temporal, temporal);
+        }
+    }
+    if best.1.len() >= raw.len() * 2 {
+        let stored = raw.iter().flat_map(|v| v.to_le_bytes()).collect();
+        // A stored frame does not depend on the previous one either.
+        return (Codec::Stored, stored);
+    }
+    best
+}
+
+fn decode_frame(payload: &[u8], previous: Option<ThermalFrame>) -> Result<ThermalFrame> {
+    let mut cursor = Cursor::new(payload);
+    let sequence = cursor.u64()?;
+    let _timestamp = cursor.u64()?;
+    let width = cursor.u16()? as usize;
+    let height = cursor.u16()? as usize;
+    let codec = Codec::from_u8(cursor.u8()?)?;
+    let data = &payload[FRAME_HEADER..];
+    let count = width * height;
+    // Coded values take at least one bit each.
+    ensure!(
+        codec == Codec::Stored || count <= data.len() * 8,
+        "Frame data is truncated"
+    );
+
+    let mut frame = previous.unwrap_or_default();
+    let raw = match codec {
+        Codec::Stored => {
+            ensure!(data.len() == count * 2, "Stored frame has the wrong size");
+            data.chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect()
+        }
+        Codec::Spatial => {
+            let residuals = rice_decode(data, count)?;
+            spatial_reconstruct(&residuals, width, height)
+        }
+        Codec::Temporal => {
+            ensure!(
+                frame.raw.len() == count && (frame.width, frame.height) == (width, height),
+                "Delta frame without a matching previous frame"
+            );
+            let residuals = rice_decode(data, count)?;
+            frame
+                .raw
+                .iter()
+                .zip(&residuals)
+                .map(|(&p, &r)| (p as i32 + unzigzag(r)) as u16)
+                .collect()
+        }
+    };
+    frame.raw = raw;
+    frame.width = width;
+    frame.height = height;
+    frame.sequence = sequence;
+    frame.captured = Instant::now();
+    frame.denoised = None;
+    Ok(frame)
+}
+
+/// LOCO-I median edge detector from the left (`a`), upper (`b`) and
+/// upper-left (`c`) neighbours.
+fn predict(a: u16, b: u16, c: u16) -> u16 {
+    if c >= a.max(b) {
+        a.min(b)
+    } else if c <= a.min(b) {
+        a.max(b)
+    } else {
+        (a as i32 + b as i32 - c as i32) as u16
+    }
+}
+
+/// Prediction for pixel `(x, y)` from already known pixels of `raw`.
+fn neighbours(raw: &[u16], width: usize, x: usize, y: usize) -> u16 {
+    let i = y * width + x;
+    match (x, y) {
+        (0, 0) => 0,
+        (_, 0) => raw[i - 1],
+        (0, _) => raw[i - width],
+        _ => predict(raw[i - 1], raw[i - width], raw[i - width - 1]),
+    }
+}
+
+fn spatial_residuals(raw: &[u16], width: usize, height: usize, out: &mut Vec<u32>) {
+    for y in 0..height {
+        for x in 0..width {
+            let p = neighbours(raw, width, x, y);
+            out.push(zigzag(raw[y * width + x] as i32 - p as i32));
+        }
+    }
+}
+
+fn spatial_reconstruct(residuals: &[u32], width: usize, height: usize) -> Vec<u16> {
+    let mut raw = vec![0u16; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let p = neighbours(&raw, width, x, y);
+            raw[y * width + x] = (p as i32 + unzigzag(residuals[y * width + x])) as u16;
+        }
+    }
+    raw
+}
+
+fn zigzag(v: i32) -> u32 {
+    ((v << 1) ^ (v >> 31)) as u32
+}
+
+fn unzigzag(v: u32) -> i32 {
+    (v >> 1) as i32 ^ -((v & 1) as i32)
+}
+
+/// Bits needed for `values` with Rice parameter `k`.
+fn rice_cost(values: &[u32], k: u32) -> u32 {
+    values
+        .iter()
+        .map(|&v| match v >> k {
+            q if q < ESCAPE => q + 1 + k,
+            _ => ESCAPE + ESCAPE_BITS,
+        })
+        .sum()
+}
+
+fn rice_encode(values: &[u32]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    for block in values.chunks(RICE_BLOCK) {
+        let k = (0..=16).min_by_key(|&k| rice_cost(block, k)).unwrap_or(0);
+        bits.put(k, 5);
+        for &v in block {
+            let q = v >> k;
+            if q < ESCAPE {
+                bits.ones(q);
+                bits.put(0, 1);
+                bits.put(v, k);
+            } else {
+                bits.ones(ESCAPE);
+                bits.put(v, ESCAPE_BITS);
+            }
+        }
+    }
+    bits.finish()
+}
+
+fn rice_decode(data: &[u8], count: usize) -> Result<Vec<u32>> {
+    let mut bits = BitReader::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        let k = bits.get(5)?;
+        ensure!(k <= 16, "Corrupt Rice parameter {}", k);
+        for _ in 0..RICE_BLOCK.min(count - values.len()) {
+            let mut q = 0;
+            while q < ESCAPE && bits.get(1)? == 1 {
+                q += 1;
+            }
+            values.push(if q < ESCAPE {
+                (q << k) | bits.get(k)?
+            } else {
+                bits.get(ESCAPE_BITS)?
+            });
+        }
+    }
+    Ok(values)
+}
+
+/// MSB-first bit packing.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    /// Appends the low `n` (at most 32) bits of `value`.
+    fn put(&mut self, value: u32, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.acc = (self.acc << n) | (value as u64 & ((1 << n) - 1));
+        self.bits += n;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+    }
+
+    fn ones(&mut self, mut n: u32) {
+        while n > 0 {
+            let chunk = n.min(32);
+            self.put(u32::MAX, chunk);
+            n -= chunk;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push((self.acc << (8 - self.bits)) as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    acc: u64,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    /// Reads `n` (at most 32) bits.
+    fn get(&mut self, n: u32) -> Result<u32> {
+        if n == 0 {
+            return Ok(0);
+        }
+        while self.bits < n {
+            let &byte = self
+                .data
+                .get(self.position)
+                .context("Frame data is truncated")?;
+            self.acc = (self.acc << 8) | byte as u64;
+            self.bits += 8;
+            self.position += 1;
+        }
+        self.bits -= n;
+        Ok(((self.acc >> self.bits) & ((1 << n) - 1)) as u32)
+    }
+}
+
+// --- Recording from the engine --------------------------------------------
+
+struct Sample {
+    frame: ThermalFrame,
+    timestamp: f64,
+    calibration: Calibration,
+}
+
+#[derive(Default)]
+struct Progress {
+    frames: AtomicU64,
+    written: AtomicU64,
+    raw: AtomicU64,
+}
+
+/// A raw sequence recording in progress. Like `Recorder`, the engine offers
+/// it every frame and it encodes on its own thread.
+pub struct SequenceRecorder {
+    path: PathBuf,
+    tx: Option<QueueSender<Sample>>,
+    worker: Option<JoinHandle<Result<u64>>>,
+    progress: Arc<Progress>,
+    start: Option<Instant>,
+    at: Duration,
+}
+
+impl SequenceRecorder {
+    /// Creates `path` and starts the writer thread.
+    pub fn start(path: &Path, device: &str) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let writer = SequenceWriter::create(path, SequenceInfo::new(device))?;
+        let (tx, rx) = queue::bounded(QueueConfig {
+            capacity: 32,
+            policy: OverflowPolicy::DropNewest,
+        });
+        let progress = Arc::new(Progress::default());
+        let worker = {
+            let progress = progress.clone();
+            thread::spawn(move || write_samples(writer, rx, &progress))
+        };
+        println!("⏺ Recording raw sequence to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            tx: Some(tx),
+            worker: Some(worker),
+            progress,
+            start: None,
+            at: Duration::ZERO,
+        })
+    }
+
+    /// Default file name for a sequence started now.
+    pub fn default_path(dir: &Path) -> PathBuf {
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        dir.join(format!("sequence_{}.{}", secs, EXTENSION))
+    }
+
+    /// Queues `frame`, converted with `calibration`. The engine offers sensor
+    /// frames before the pipeline, so that playback can reprocess them.
+    pub fn offer(&mut self, frame: &ThermalFrame, calibration: &Calibration) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let start = *self.start.get_or_insert(frame.captured);
+        self.at = frame.captured.saturating_duration_since(start);
+        let sample = Sample {
+            frame: ThermalFrame {
+                width: frame.width,
+                height: frame.height,
+                sequence: frame.sequence,
+                captured: frame.captured,
+                raw: frame.raw.clone(),
+                denoised: None,
+            },
+            timestamp: frame.unix_time(),
+            calibration: *calibration,
+        };
+        if tx.push(sample).is_err() {
+            // The writer thread failed; `finish` reports why.
+            eprintln!("❌ Sequence recording stopped: writer failed.");
+            self.tx = None;
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Capture time covered so far.
+    pub fn elapsed(&self) -> Duration {
+        self.at
+    }
+
+    /// Frames written so far.
+    pub fn frames(&self) -> u64 {
+        self.progress.frames.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written so far.
+    pub fn size(&self) -> u64 {
+        self.progress.written.load(Ordering::Relaxed)
+    }
+
+    /// Uncompressed over compressed size of the frames written so far.
+    pub fn compression(&self) -> f32 {
+        let written = self.size();
+        if written == 0 {
+            return 1.0;
+        }
+        self.progress.raw.load(Ordering::Relaxed) as f32 / written as f32
+    }
+
+    /// Frames the writer could not keep up with.
+    pub fn dropped(&self) -> u64 {
+        self.tx.as_ref().map_or(0, |tx| tx.dropped())
+    }
+
+    /// Flushes the queue and writes the index. Returns the file's path.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.tx = None;
+        let size = match self.worker.take().map(|worker| worker.join()) {
+            Some(Ok(result)) => result?,
+            _ => bail!("Sequence recording thread panicked"),
+        };
+        println!(
+            "⏹ Recorded {} raw frames to {} ({:.1} MB, {:.1}:1)",
+            self.frames(),
+            self.path.display(),
+            size as f64 / 1e6,
+            self.compression()
+        );
+        Ok(self.path)
+    }
+}
+
+fn write_samples(
+    mut writer: SequenceWriter,
+    rx: QueueReceiver<Sample>,
+    progress: &Progress,
+) -> Result<u64> {
+    while let Some(sample) = rx.recv() {
+        writer.write(&sample.frame, sample.timestamp, &sample.calibration)?;
+        progress
+            .frames
+            .store(writer.frames() as u64, Ordering::Relaxed);
+        progress.written.store(writer.size(), Ordering::Relaxed);
+        progress.raw.store(writer.raw_size(), Ordering::Relaxed);
+    }
+    writer.finish()
+}
@@ -0,0 +1,456 @@
+// Embedded HTTP server for watching the camera from a browser on the LAN.
+//
+// Serves the frames of a `FrameBus`, one thread per connection:
+//
+//   /              page showing the stream and the live readings
+//   /stream.mjpg   multipart/x-mixed-replace MJPEG of the display image
+//   /snapshot.jpg  the next display image as a single JPEG
+//   /stats.json    spot readings, range and alarms of the next frame (°C)
+//   /raw.y16       the next frame's counts, little-endian u16, row-major;
+//                  size and calibration in X-Frame-* headers
+//   /api/...       control API, if started with a `Controller` (`api.rs`)
+//
+// Plain HTTP/1.1 without keep-alive: every response ends the connection. The
+// read-only endpoints may be embedded by any page; the control API is
+// same-origin only and takes JSON bodies, so that other sites cannot drive it
+// from a visitor's browser.
+
+use crate::api;
+use crate::bus::{BusFrame, FrameBus, Subscription};
+use crate::control::Controller;
+use crate::tracking::TrackedSpot;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a request waits for the next frame before answering 503.
+pub(crate) const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often idle loops look at the stop flag.
+const POLL: Duration = Duration::from_millis(50);
+/// Longest request head accepted.
+const MAX_HEAD: usize = 8 * 1024;
+/// Longest request body accepted.
+const MAX_BODY: usize = 64 * 1024;
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>Thermoscope</title>
+<style>body{background:#111;color:#ddd;font:14px sans-serif;margin:0;text-align:center}
+img{max-width:100vw;max-height:90vh;image-rendering:pixelated}#s{padding:8px}</style></head>
+<body><img src="/stream.mjpg" alt="stream"><div id="s"></div>
+<script>
+async function poll(){try{const r=await fetch('/stats.json');const j=await r.json();
+document.getElementById('s').textContent=`min ${j.cold.temperature.toFixed(1)} °C   max ${j.hot.temperature.toFixed(1)} °C   centre ${j.center.temperature.toFixed(1)} °C`+(j.alarms.length?`   ⚠ ${j.alarms.join(', ')}`:'');}catch(e){}
+setTimeout(poll,1000);}
+poll();
+</script></body></html>
+"#;
+
+pub struct StreamServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    clients: Arc<AtomicUsize>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StreamServer {
+    /// Listens on `bind` (e.g. `0.0.0.0:8080`; port 0 picks a free one).
+    /// With `control`, clients can also change settings through /api.
+    pub fn start(bind: &str, bus: FrameBus, control: Option<Controller>) -> Result<Self> {
+        let addr = bind
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid address {:?}", bind))?
+            .next()
+            .with_context(|| format!("Invalid address {:?}", bind))?;
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("Cannot listen on {}", addr))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let clients = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let (stop, clients) = (stop.clone(), clients.clone());
+            let control = control.clone();
+            thread::spawn(move || accept(listener, bus, control, stop, clients))
+        };
+        println!("🌐 HTTP server listening on http://{}/", addr);
+        if control.is_some() {
+            println!("🎛 Control API at http://{}/api/openapi.json", addr);
+        }
+        Ok(Self {
+            addr,
+            stop,
+            clients,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Connections currently open.
+    pub fn clients(&self) -> usize {
+        self.clients.load(Ordering::Relaxed)
+    }
+
+    /// Closes the listener and every stream.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+            println!("🌐 HTTP server stopped.");
+        }
+    }
+}
+
+impl Drop for StreamServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept(
+    listener: TcpListener,
+    bus: FrameBus,
+    control: Option<Controller>,
+    stop: Arc<AtomicBool>,
+    clients: Arc<AtomicUsize>,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let (bus, control) = (bus.clone(), control.clone());
+                let (stop, clients) = (stop.clone(), clients.clone());
+                clients.fetch_add(1, Ordering::Relaxed);
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &bus, control.as_ref(), &stop)
+                        && !is_disconnect(&e)
+                    {
+                        eprintln!("❌ HTTP: {}", e);
+                    }
+                    clients.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+            Err(e) => {
+                eprintln!("❌ HTTP: accept failed: {}", e);
+                thread::sleep(POLL);
+            }
+        }
+    }
+}
+
+/// The client went away; not worth a message.
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    )
+}
+
+fn handle(
+    stream: TcpStream,
+    bus: &FrameBus,
+    control: Option<&Controller>,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(FRAME_TIMEOUT))?;
+    stream.set_write_timeout(Some(FRAME_TIMEOUT))?;
+    let Request {
+        method,
+        path,
+        content_type,
+        body,
+    } = read_request(&stream)?;
+    let mut out = stream;
+    // Query strings (cache busters) are ignored.
+    let path = path.split('?').next().unwrap_or_default();
+    if path == "/api" || path.starts_with("/api/") {
+        let Some(body) = body else {
+            return respond(
+                &mut out,
+                "413 Content Too Large",
+                "text/plain",
+                &[],
+                b"Body too large\n",
+            );
+        };
+        let response = api::handle(&method, path, content_type.as_deref(), &body, control);
+        write_head(
+            &mut out,
+            response.status,
+            "application/json",
+            &[],
+            response.body.len(),
+        )?;
+        if method != "HEAD" {
+            out.write_all(response.body.as_bytes())?;
+        }
+        return out.flush();
+    }
+    if method != "GET" && method != "HEAD" {
+        return respond(
+            &mut out,
+            "405 Method Not Allowed",
+            "text/plain",
+            &[],
+            b"GET only\n",
+        );
+    }
+    let head = method == "HEAD";
+    match path {
+        "/" | "/index.html" => respond_to(
+            &mut out,
+            head,
+            "text/html; charset=utf-8",
+            &[],
+            INDEX.as_bytes(),
+        ),
+        "/stream.mjpg" => stream_mjpeg(&mut out, bus, stop, head),
+        "/snapshot.jpg" | "/stats.json" | "/raw.y16" => {
+            let Some(frame) = bus.subscribe().next(FRAME_TIMEOUT) else {
+                return respond(
+                    &mut out,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    &[],
+                    b"No frames\n",
+                );
+            };
+            match path {
+                "/snapshot.jpg" => respond_to(&mut out, head, "image/jpeg", &[], frame.jpeg()),
+                "/stats.json" => respond_to(
+                    &mut out,
+                    head,
+                    "application/json",
+                    &[],
+                    stats_json(&frame).as_bytes(),
+                ),
+                _ => {
+                    let headers = raw_headers(&frame);
+                    let body: Vec<u8> = frame.raw.iter().flat_map(|v| v.to_le_bytes()).collect();
+                    respond_to(&mut out, head, "application/octet-stream", &headers, &body)
+                }
+            }
+        }
+        _ => respond(&mut out, "404 Not Found", "text/plain", &[], b"Not found\n"),
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    content_type: Option<String>,
+    /// `None` if longer than `MAX_BODY`.
+    body: Option<Vec<u8>>,
+}
+
+/// Request line, Content-Type and body; other headers are skipped.
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut head = reader.by_ref().take(MAX_HEAD as u64);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+    let mut length = 0;
+    let mut content_type = None;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Request head incomplete or too long",
+            ));
+        }
+        if header == "\r\n" || header == "\n" {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.to_string());
+            }
+        }
+    }
+    let body = if length > MAX_BODY {
+        None
+    } else {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        Some(body)
+    };
+    match (method, path) {
+        (Some(method), Some(path)) => Ok(Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            content_type,
+            body,
+        }),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Malformed request",
+        )),
+    }
+}
+
+/// 200 with `body`, readable from any origin; for HEAD requests only the
+/// headers.
+fn respond_to(
+    out: &mut TcpStream,
+    head: bool,
+    content_type: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    let mut headers = headers.to_vec();
+    headers.push(("Access-Control-Allow-Origin", "*".to_string()));
+    write_head(out, "200 OK", content_type, &headers, body.len())?;
+    if !head {
+        out.write_all(body)?;
+    }
+    out.flush()
+}
+
+fn respond(
+    out: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    write_head(out, status, content_type, headers, body.len())?;
+    out.write_all(body)?;
+    out.flush()
+}
+
+fn write_head(
+    out: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    headers: &[(&str, String)],
+    length: usize,
+) -> io::Result<()> {
+    let mut text = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n",
+        status, content_type, length
+    );
+    for (name, value) in headers {
+        text.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    text.push_str("\r\n");
+    out.write_all(text.as_bytes())
+}
+
+/// Writes one part per new frame until the client disconnects or the server
+/// stops. Slow clients skip frames rather than queueing them.
+fn stream_mjpeg(
+    out: &mut TcpStream,
+    bus: &FrameBus,
+    stop: &AtomicBool,
+    head: bool,
+) -> io::Result<()> {
+    out.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary=frame\r\nCache-Control: no-cache\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+    )?;
+    if head {
+        return out.flush();
+    }
+    let mut subscription: Subscription = bus.subscribe();
+    while !stop.load(Ordering::Relaxed) {
+        let Some(frame) = subscription.next(POLL) else {
+            continue;
+        };
+        let jpeg = frame.jpeg();
+        write!(
+            out,
+            "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\nX-Frame-Sequence: {}\r\n\r\n",
+            jpeg.len(),
+            frame.sequence
+        )?;
+        out.write_all(jpeg)?;
+        out.write_all(b"\r\n")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn raw_headers(frame: &BusFrame) -> Vec<(&'static str, String)> {
+    vec![
+        ("X-Frame-Width", frame.width.to_string()),
+        ("X-Frame-Height", frame.height.to_string()),
+        ("X-Frame-Sequence", frame.sequence.to_string()),
+        ("X-Frame-Timestamp", format!("{:.3}", frame.timestamp)),
+        (
+            "X-Counts-Per-Kelvin",
+            frame.calibration.counts_per_kelvin.to_string(),
+        ),
+        ("X-Offset-Kelvin", frame.calibration.offset.to_string()),
+        ("X-Emissivity", frame.calibration.emissivity.to_string()),
+        (
+            "X-Reflected-Temperature",
+            frame.calibration.reflected_temp.to_string(),
+        ),
+    ]
+}
+
+/// Body of `/stats.json`.
+#[derive(Serialize)]
+struct FrameStats<'a> {
+    sequence: u64,
+    timestamp: f64,
+    width: usize,
+    height: usize,
+    hot: TrackedSpot,
+    cold: TrackedSpot,
+    center: TrackedSpot,
+    mean: f32,
+    range: Range,
+    emissivity: f32,
+    alarms: &'a [String],
+}
+
+#[derive(Serialize)]
+struct Range {
+    min: f32,
+    max: f32,
+    locked: bool,
+}
+
+fn stats_json(frame: &BusFrame) -> String {
+    let stats = FrameStats {
+        sequence: frame.sequence,
+        timestamp: frame.timestamp,
+        width: frame.width,
+        height: frame.height,
+        hot: frame.spots.hot,
+        cold: frame.spots.cold,
+        center: frame.spots.center,
+        mean: frame.mean(),
+        range: Range {
+            min: frame.legend.min,
+            max: frame.legend.max,
+            locked: frame.legend.locked,
+        },
+        emissivity: frame.calibration.emissivity,
+        alarms: &frame.alarms,
+    };
+    serde_json::to_string(&stats).expect("frame stats always serialise")
+}
@@ -0,0 +1,382 @@
+// Snapshots: the colourised image (PNG/JPEG) plus the raw Y16 field as a 16-bit
+// greyscale PNG, both tagged with the capture metadata.
+//
+// The metadata is a small TOML document in a `Thermoscope` tEXt chunk (a COM
+// segment in JPEGs), so any PNG viewer/exiftool can show it. `Snapshot::load`
+// reads the raw PNG back, and `render` re-colours it with any display settings.
+
+use crate::calibration::{Calibration, TemperatureUnit};
+use crate::display::{DisplaySettings, RangeMode};
+use crate::flir;
+use crate::frame::{ProcessedFrame, ThermalFrame};
+use crate::legend::Legend;
+use crate::palette::Palette;
+use anyhow::{Context, Result, bail};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Text chunk keyword the metadata is stored under.
+pub const METADATA_KEY: &str = "Thermoscope";
+
+/// Format of the colourised image. The raw field is always a 16-bit PNG.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// JPEG with the field and calibration in FLIR APP1 segments.
+    Radiometric,
+}
+
+impl SnapshotFormat {
+    pub const ALL: [SnapshotFormat; 3] = [
+        SnapshotFormat::Png,
+        SnapshotFormat::Jpeg,
+        SnapshotFormat::Radiometric,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SnapshotFormat::Png => "PNG",
+            SnapshotFormat::Jpeg => "JPEG",
+            SnapshotFormat::Radiometric => "FLIR radiometric JPEG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Png => "png",
+            SnapshotFormat::Jpeg | SnapshotFormat::Radiometric => "jpg",
+        }
+    }
+}
+
+/// Everything needed to interpret (and re-render) the raw field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    /// Unix time (s) the frame was captured.
+    pub timestamp: f64,
+    /// Camera the frame came from, e.g. `0bda:5830`.
+    pub device: String,
+    pub sequence: u64,
+    pub width: usize,
+    pub height: usize,
+    pub palette: Palette,
+    pub unit: TemperatureUnit,
+    /// Range the palette spanned (°C).
+    pub range: [f32; 2],
+    /// Whether that range was locked rather than following the scene.
+    pub range_locked: bool,
+    pub calibration: Calibration,
+}
+
+impl SnapshotMetadata {
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+/// A raw frame together with its metadata.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub frame: ThermalFrame,
+    pub metadata: SnapshotMetadata,
+}
+
+impl Snapshot {
+    /// Takes the raw field and metadata of `frame`; the filtered field is not
+    /// kept, so a re-render starts from what the sensor delivered.
+    pub fn capture(frame: &ProcessedFrame, device: &str) -> Self {
+        let timestamp = frame.frame.unix_time();
+        let legend = &frame.legend;
+        Self {
+            frame: ThermalFrame {
+                width: frame.frame.width,
+                height: frame.frame.height,
+                sequence: frame.frame.sequence,
+                captured: frame.frame.captured,
+                raw: frame.frame.raw.clone(),
+                denoised: None,
+            },
+            metadata: SnapshotMetadata {
+                timestamp,
+                device: device.to_string(),
+                sequence: frame.frame.sequence,
+                width: frame.frame.width,
+                height: frame.frame.height,
+                palette: legend.palette,
+                unit: legend.unit,
+                range: [legend.min, legend.max],
+                range_locked: legend.locked,
+                calibration: frame.calibration,
+            },
+        }
+    }
+
+    /// Writes `image` as `thermal_<ms>.png|jpg` and the raw field as
+    /// `thermal_<ms>_raw.png` into `dir`. Returns the path of the image.
+    pub fn save(
+        &self,
+        image: &egui::ColorImage,
+        dir: &Path,
+        format: SnapshotFormat,
+    ) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let stem = format!("thermal_{}", (self.metadata.timestamp * 1000.0) as u64);
+        self.save_as(image, &dir.join(stem), format)
+    }
+
+    /// Writes `image` as `<stem>.png|jpg` and the raw field as `<stem>_raw.png`.
+    /// Returns the path of the image.
+    pub fn save_as(
+        &self,
+        image: &egui::ColorImage,
+        stem: &Path,
+        format: SnapshotFormat,
+    ) -> Result<PathBuf> {
+        let name = stem
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let path = stem.with_file_name(format!("{}.{}", name, format.extension()));
+        let metadata = self.metadata.to_toml()?;
+        match format {
+            SnapshotFormat::Png => write_rgba_png(&path, image, &metadata)?,
+            SnapshotFormat::Jpeg => write_jpeg(&path, image, &metadata)?,
+            SnapshotFormat::Radiometric => flir::write(&path, self, image)?,
+        }
+        self.save_raw(&stem.with_file_name(format!("{}_raw.png", name)))?;
+        Ok(path)
+    }
+
+    /// Writes the raw field as a 16-bit greyscale PNG with the metadata.
+    pub fn save_raw(&self, path: &Path) -> Result<()> {
+        let (w, h) = (self.frame.width, self.frame.height);
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(File::create(path)?), w as u32, h as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        encoder.add_text_chunk(METADATA_KEY.to_string(), self.metadata.to_toml()?)?;
+        // PNG samples are big-endian.
+        let data: Vec<u8> = self
+            .frame
+            .raw
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+
+    /// Opens a snapshot from its raw PNG, from the colour image next to it, or
+    /// from a FLIR radiometric JPEG.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw_path = raw_path_for(path);
+        let is_jpeg = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg"));
+        if is_jpeg && !raw_path.exists() {
+            return flir::read(path);
+        }
+        let file =
+            File::open(&raw_path).with_context(|| format!("Cannot open {}", raw_path.display()))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info()?;
+        let info = reader.info();
+        if info.color_type != png::ColorType::Grayscale || info.bit_depth != png::BitDepth::Sixteen
+        {
+            bail!("{} is not a 16-bit greyscale PNG", raw_path.display());
+        }
+        let text = info
+            .uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == METADATA_KEY)
+            .map(|chunk| chunk.text.clone())
+            .with_context(|| format!("{} has no {} metadata", raw_path.display(), METADATA_KEY))?;
+        let metadata = SnapshotMetadata::from_toml(&text)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let output = reader.next_frame(&mut data)?;
+        let (w, h) = (output.width as usize, output.height as usize);
+        let raw = data[..output.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        let frame = ThermalFrame {
+            width: w,
+            height: h,
+            sequence: metadata.sequence,
+            raw,
+            ..Default::default()
+        };
+        Ok(Self { frame, metadata })
+    }
+
+    /// Temperature (°C) of every pixel, using the stored calibration.
+    pub fn temperature(&self) -> Vec<f32> {
+        self.metadata.calibration.temperature_field(&self.frame.raw)
+    }
+
+    /// The range the snapshot was shown with, as a locked range.
+    pub fn saved_range(&self) -> RangeMode {
+        let [min, max] = self.metadata.range;
+        RangeMode::manual((min + max) / 2.0, max - min)
+    }
+
+    /// Re-colours the raw field with `display` (palette, range, isotherms ...).
+    pub fn render(&self, display: &DisplaySettings) -> (egui::ColorImage, Legend) {
+        let calibration = &self.metadata.calibration;
+        let (image, range) = display.render(&self.frame, &self.temperature(), calibration);
+        (image, display.legend(range, calibration))
+    }
+}
+
+/// `thermal_1_raw.png` for `thermal_1.png`/`.jpg`; raw paths map to themselves.
+fn raw_path_for(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    if stem.ends_with("_raw") {
+        path.to_path_buf()
+    } else {
+        path.with_file_name(format!("{}_raw.png", stem))
+    }
+}
+
+fn write_rgba_png(path: &Path, image: &egui::ColorImage, metadata: &str) -> Result<()> {
+    let [w, h] = image.size;
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), w as u32, h as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk(METADATA_KEY.to_string(), metadata.to_string())?;
+    encoder.write_header()?.write_image_data(image.as_raw())?;
+    Ok(())
+}
+
+fn write_jpeg(path: &Path, image: &egui::ColorImage, metadata: &str) -> Result<()> {
+    let text = format!("{}\n{}", METADATA_KEY, metadata);
+    let len = (text.len() + 2).min(u16::MAX as usize);
+    let mut comment = vec![0xFF, 0xFE];
+    comment.extend_from_slice(&(len as u16).to_be_bytes());
+    comment.extend_from_slice(&text.as_bytes()[..len - 2]);
+    std::fs::write(path, insert_segments(&encode_jpeg(image)?, &comment))?;
+    Ok(())
+}
+
+pub(crate) fn encode_jpeg(image: &egui::ColorImage) -> Result<Vec<u8>> {
+    let [w, h] = image.size;
+    let rgb: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|p| [p.r(), p.g(), p.b()])
+        .collect();
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90).encode(
+        &rgb,
+        w as u32,
+        h as u32,
+        image::ColorType::Rgb8,
+    )?;
+    Ok(jpeg)
+}
+
+/// Inserts already-encoded marker segments after SOI and the leading APPn
+/// segments (JFIF requires APP0 to come first).
+pub(crate) fn insert_segments(jpeg: &[u8], segments: &[u8]) -> Vec<u8> {
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    let pos = pos.min(jpeg.len());
+    [&jpeg[..pos], segments, &jpeg[pos..]].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let (width, height) = (40, 30);
+        // Both ends of the u16 range plus a gradient around room temperature.
+        let mut raw: Vec<u16> = (0..width * height)
+            .map(|i| 18_900 + (i % width * 25 + i / width * 7) as u16)
+            .collect();
+        raw[0] = 0;
+        raw[1] = u16::MAX;
+        Snapshot {
+            frame: ThermalFrame {
+                width,
+                height,
+                sequence: 4_321,
+                raw,
+                ..Default::default()
+            },
+            metadata: SnapshotMetadata {
+                timestamp: 1_700_000_123.456,
+                device: "0bda:5830".to_string(),
+                sequence: 4_321,
+                width,
+                height,
+                palette: Palette::Ironbow,
+                unit: TemperatureUnit::Fahrenheit,
+                range: [21.3, 37.9],
+                range_locked: true,
+                calibration: Calibration {
+                    counts_per_kelvin: 64.0,
+                    offset: -0.7,
+                    emissivity: 0.93,
+                    reflected_temp: 23.5,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn saved_snapshots_load_and_render_like_the_original() {
+        let dir = std::env::temp_dir().join(format!("snapshot-{}", std::process::id()));
+        let original = snapshot();
+        let display = DisplaySettings {
+            palette: Palette::Ironbow,
+            range: original.saved_range(),
+            ..Default::default()
+        };
+        let (image, _) = original.render(&display);
+
+        for format in SnapshotFormat::ALL {
+            let path = original.save(&image, &dir, format).unwrap();
+            let loaded = Snapshot::load(&path).unwrap();
+            assert_eq!(loaded.metadata, original.metadata, "{}", format.name());
+            assert_eq!(loaded.frame.raw, original.frame.raw, "{}", format.name());
+            assert_eq!(
+                (loaded.frame.width, loaded.frame.height),
+                (original.frame.width, original.frame.height)
+            );
+
+            // Another palette comes out exactly as it would have live.
+            let other = DisplaySettings {
+                palette: Palette::BlackHot,
+                ..display.clone()
+            };
+            let (expected, legend) = original.render(&other);
+            let (rendered, loaded_legend) = loaded.render(&other);
+            assert_eq!(rendered, expected);
+            assert_ne!(rendered, image);
+            assert_eq!(loaded_legend.palette, Palette::BlackHot);
+            assert_eq!((loaded_legend.min, loaded_legend.max), (21.3, 37.9));
+            assert_eq!(
+                (loaded_legend.min, loaded_legend.max),
+                (legend.min, legend.max)
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+// Where the engine's frames come from.
+//
+// The processing loop pulls frames from a `FrameSource`: the camera while
+// streaming (or generated frames without one), a recording during playback
+// (`playback.rs`). Both go through the
+// same pipeline, alarms and consumers, so a replayed frame is processed exactly
+// like a live one.
+
+use crate::frame::ThermalFrame;
+use crate::queue::{BufferPool, QueueReceiver};
+use crate::stats::PipelineStats;
+use crate::uvc_adapter::{RawFrame, StreamMode};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait FrameSource: Send {
+    /// Fills `frame` with the next frame, reusing its buffers. Returns `false`
+    /// once the source has ended.
+    fn next_frame(&mut self, frame: &mut ThermalFrame) -> bool;
+
+    /// Frames lost before they reached the engine.
+    fn dropped(&self) -> u64 {
+        0
+    }
+
+    /// The frame just returned does not follow the previous one (seek, resume),
+    /// so stages with per-frame history start over.
+    fn discontinuity(&mut self) -> bool {
+        false
+    }
+
+    /// The frame just returned repeats the previous one (a paused playback),
+    /// only so that settings changes show. It is processed and displayed, but
+    /// not recorded, counted or checked for alarms again.
+    fn is_refresh(&self) -> bool {
+        false
+    }
+}
+
+/// Y16 frames from the libuvc callback.
+pub struct CameraSource {
+    rx: QueueReceiver<RawFrame>,
+    pool: BufferPool,
+    width: usize,
+    height: usize,
+    stats: Arc<Mutex<PipelineStats>>,
+    /// Non-zero while a playback owns the engine; camera frames are dropped.
+    playback: Arc<AtomicU64>,
+    count: u64,
+    resumed: bool,
+}
+
+impl CameraSource {
+    pub fn new(
+        rx: QueueReceiver<RawFrame>,
+        pool: BufferPool,
+        (width, height): (usize, usize),
+        stats: Arc<Mutex<PipelineStats>>,
+        playback: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            rx,
+            pool,
+            width,
+            height,
+            stats,
+            playback,
+            count: 0,
+            resumed: false,
+        }
+    }
+}
+
+impl FrameSource for CameraSource {
+    fn next_frame(&mut self, frame: &mut ThermalFrame) -> bool {
+        while let Some(raw) = self.rx.recv() {
+            if self.playback.load(Ordering::Relaxed) != 0 {
+                self.pool.give(raw.data);
+                self.resumed = true;
+                continue;
+            }
+            self.stats
+                .lock()
+                .unwrap()
+                .record_capture(raw.sequence, raw.captured);
+            let decoded = frame.decode_y16(&raw.data, self.width, self.height, self.count);
+            self.pool.give(raw.data);
+            if decoded {
+                frame.captured = raw.captured;
+                self.count += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn dropped(&self) -> u64 {
+        self.rx.dropped()
+    }
+
+    fn discontinuity(&mut self) -> bool {
+        std::mem::take(&mut self.resumed)
+    }
+}
+
+/// Generated frames at the stream rate: a warm spot circling over a 20-24 °C
+/// gradient, with a few counts of noise. Stands in for the camera in demos
+/// and tests.
+pub struct SyntheticSource {
+    mode: StreamMode,
+    stats: Arc<Mutex<PipelineStats>>,
+    playback: Arc<AtomicU64>,
+    start: Instant,
+    count: u64,
+    seed: u32,
+    resumed: bool,
+}
+
+impl SyntheticSource {
+    pub fn new(
+        mode: StreamMode,
+        stats: Arc<Mutex<PipelineStats>>,
+        playback: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            mode,
+            stats,
+            playback,
+            start: Instant::now(),
+            count: 0,
+            seed: 1,
+            resumed: false,
+        }
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frame(&mut self, frame: &mut ThermalFrame) -> bool {
+        let interval = Duration::from_secs_f64(1.0 / self.mode.fps.max(1) as f64);
+        loop {
+            let due = self.start + interval * self.count as u32;
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            self.count += 1;
+            if self.playback.load(Ordering::Relaxed) != 0 {
+                self.resumed = true;
+                continue;
+            }
+            break;
+        }
+
+        let (width, height) = (self.mode.width, self.mode.height);
+        let angle = self.count as f32 * 0.05;
+        let (cx, cy) = (
+            width as f32 * (0.5 + 0.3 * angle.cos()),
+            height as f32 * (0.5 + 0.3 * angle.sin()),
+        );
+        let radius2 = (width.min(height) as f32 * 0.08).powi(2);
+        frame.width = width;
+        frame.height = height;
+        frame.sequence = self.count - 1;
+        frame.captured = Instant::now();
+        frame.raw.clear();
+        frame.raw.extend((0..width * height).map(|i| {
+            let (x, y) = ((i % width) as f32, (i / width) as f32);
+            let background = 20.0 + 4.0 * y / height as f32;
+            let spot = 25.0 * (-((x - cx).powi(2) + (y - cy).powi(2)) / radius2).exp();
+            self.seed = self
+                .seed
+                .wrapping_mul(1_664_525)
+                .wrapping_add(1_013_904_223);
+            let noise = (self.seed >> 30) as f32 - 1.5;
+            ((background + spot + 273.15) * 64.0 + noise) as u16
+        }));
+        self.stats
+            .lock()
+            .unwrap()
+            .record_capture(frame.sequence as u32, frame.captured);
+        true
+    }
+
+    fn discontinuity(&mut self) -> bool {
+        std::mem::take(&mut self.resumed)
+    }
+}
@@ -0,0 +1,387 @@
+// Built-in pipeline stages.
+//
+// Stages that wrap existing engine settings (filters, calibration, display ...)
+// hold the same `Arc<Mutex<_>>` the UI edits, and lock it only for the duration
+// of one frame.
+
+use crate::calibration::Calibration;
+use crate::display::DisplaySettings;
+use crate::filters::FilterChain;
+use crate::legend;
+use crate::orientation::Orientation;
+use crate::pipeline::{FrameContext, ProcessingStage, StageConfig};
+use crate::tracking::SpotTracker;
+use std::sync::{Arc, Mutex};
+
+/// Replaces defective pixels by the median of their neighbours.
+///
+/// Runs on the sensor field before orientation, so `pixels` are sensor
+/// coordinates. With a `threshold`, pixels that stand out from their 3x3
+/// neighbourhood by more than that many counts are treated as defects too.
+#[derive(Clone, Debug, Default)]
+pub struct BadPixelStage {
+    pub pixels: Vec<[usize; 2]>,
+    pub threshold: Option<u16>,
+}
+
+impl ProcessingStage for BadPixelStage {
+    fn name(&self) -> &str {
+        "bad_pixel"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let (w, h) = (ctx.frame.width, ctx.frame.height);
+        let raw = &mut ctx.frame.raw;
+        for &[x, y] in &self.pixels {
+            if x < w && y < h {
+                raw[y * w + x] = neighbour_median(raw, w, h, x, y);
+            }
+        }
+        if let Some(threshold) = self.threshold {
+            for y in 0..h {
+                for x in 0..w {
+                    let median = neighbour_median(raw, w, h, x, y);
+                    if raw[y * w + x].abs_diff(median) > threshold {
+                        raw[y * w + x] = median;
+                    }
+                }
+            }
+        }
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::BadPixel {
+            pixels: self.pixels.clone(),
+            threshold: self.threshold,
+        }
+    }
+}
+
+fn neighbour_median(field: &[u16], w: usize, h: usize, x: usize, y: usize) -> u16 {
+    let mut values = [0u16; 8];
+    let mut n = 0;
+    for ny in y.saturating_sub(1)..(y + 2).min(h) {
+        for nx in x.saturating_sub(1)..(x + 2).min(w) {
+            if (nx, ny) != (x, y) {
+                values[n] = field[ny * w + nx];
+                n += 1;
+            }
+        }
+    }
+    if n == 0 {
+        return field[y * w + x];
+    }
+    let values = &mut values[..n];
+    values.sort_unstable();
+    values[n / 2]
+}
+
+/// One-point non-uniformity correction.
+///
+/// `calibrate` averages the next `frames` frames of a uniform scene (lens cap,
+/// closed shutter) and stores each pixel's offset from the mean; afterwards
+/// the offsets are subtracted from every frame. Offsets are in sensor space and
+/// survive `reset`.
+#[derive(Clone, Debug)]
+pub struct NucStage {
+    pub frames: usize,
+    offsets: Vec<f32>,
+    accumulating: Option<(Vec<u64>, usize)>,
+}
+
+impl Default for NucStage {
+    fn default() -> Self {
+        Self::new(16)
+    }
+}
+
+impl NucStage {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: frames.max(1),
+            offsets: Vec::new(),
+            accumulating: None,
+        }
+    }
+
+    /// Starts collecting a new offset table from the next frames.
+    pub fn calibrate(&mut self) {
+        self.accumulating = Some((Vec::new(), 0));
+    }
+
+    pub fn clear(&mut self) {
+        self.offsets.clear();
+        self.accumulating = None;
+    }
+
+    pub fn is_calibrated(&self) -> bool {
+        !self.offsets.is_empty()
+    }
+
+    pub fn is_calibrating(&self) -> bool {
+        self.accumulating.is_some()
+    }
+}
+
+impl ProcessingStage for NucStage {
+    fn name(&self) -> &str {
+        "nuc"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let raw = &mut ctx.frame.raw;
+        if let Some((sum, count)) = &mut self.accumulating {
+            if sum.len() != raw.len() {
+                sum.clear();
+                sum.resize(raw.len(), 0);
+                *count = 0;
+            }
+            for (s, &v) in sum.iter_mut().zip(raw.iter()) {
+                *s += v as u64;
+            }
+            *count += 1;
+            if *count >= self.frames {
+                let n = *count as f64;
+                let mean = sum.iter().sum::<u64>() as f64 / (n * sum.len() as f64);
+                self.offsets = sum.iter().map(|&s| (s as f64 / n - mean) as f32).collect();
+                self.accumulating = None;
+                println!("✅ NUC: offset table updated from {} frames.", n);
+            }
+        }
+        if self.offsets.len() == raw.len() {
+            for (v, &offset) in raw.iter_mut().zip(&self.offsets) {
+                *v = (*v as f32 - offset).round().clamp(0.0, u16::MAX as f32) as u16;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.accumulating = None;
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Nuc {
+            frames: self.frames,
+        }
+    }
+}
+
+/// Mirror/flip/rotation; flags a geometry change when the orientation changes.
+pub struct OrientationStage {
+    orientation: Arc<Mutex<Orientation>>,
+    last: Option<Orientation>,
+    scratch: Vec<u16>,
+}
+
+impl OrientationStage {
+    pub fn new(orientation: Arc<Mutex<Orientation>>) -> Self {
+        Self {
+            orientation,
+            last: None,
+            scratch: Vec::new(),
+        }
+    }
+}
+
+impl ProcessingStage for OrientationStage {
+    fn name(&self) -> &str {
+        "orientation"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let current = *self.orientation.lock().unwrap();
+        if self.last.is_some_and(|last| last != current) {
+            ctx.geometry_changed = true;
+        }
+        self.last = Some(current);
+        ctx.frame.orient_with(&current, &mut self.scratch);
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Orientation(*self.orientation.lock().unwrap())
+    }
+}
+
+/// Runs the filter chain into `frame.denoised`.
+pub struct DenoiseStage {
+    chain: Arc<Mutex<FilterChain>>,
+}
+
+impl DenoiseStage {
+    pub fn new(chain: Arc<Mutex<FilterChain>>) -> Self {
+        Self { chain }
+    }
+}
+
+impl ProcessingStage for DenoiseStage {
+    fn name(&self) -> &str {
+        "denoise"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let mut chain = self.chain.lock().unwrap();
+        // Filters keep per-pixel history, which is meaningless once the
+        // geometry changes.
+        if ctx.geometry_changed {
+            chain.reset();
+        }
+        let frame = &mut ctx.frame;
+        frame.denoised = Some(std::mem::take(&mut ctx.denoise_buffer));
+        chain.process_into(&frame.raw, frame.width, frame.height, &mut frame.denoised);
+        ctx.use_denoised = chain.use_for_measurement;
+    }
+
+    fn reset(&mut self) {
+        self.chain.lock().unwrap().reset();
+    }
+
+    fn config(&self) -> StageConfig {
+        let chain = self.chain.lock().unwrap();
+        StageConfig::Denoise {
+            use_for_measurement: chain.use_for_measurement,
+            filters: chain.configs(),
+        }
+    }
+}
+
+/// Converts the measurement field to °C.
+pub struct CalibrationStage {
+    calibration: Arc<Mutex<Calibration>>,
+}
+
+impl CalibrationStage {
+    pub fn new(calibration: Arc<Mutex<Calibration>>) -> Self {
+        Self { calibration }
+    }
+}
+
+impl ProcessingStage for CalibrationStage {
+    fn name(&self) -> &str {
+        "calibration"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        ctx.calibration = *self.calibration.lock().unwrap();
+        ctx.calibration
+            .temperature_field_into(ctx.frame.field(ctx.use_denoised), &mut ctx.temperature);
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Calibration(*self.calibration.lock().unwrap())
+    }
+}
+
+/// Hot/cold/centre spot tracking on the temperature field.
+pub struct SpotStage {
+    tracker: Arc<Mutex<SpotTracker>>,
+}
+
+impl SpotStage {
+    pub fn new(tracker: Arc<Mutex<SpotTracker>>) -> Self {
+        Self { tracker }
+    }
+}
+
+impl ProcessingStage for SpotStage {
+    fn name(&self) -> &str {
+        "spots"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let mut tracker = self.tracker.lock().unwrap();
+        if ctx.geometry_changed {
+            tracker.reset();
+        }
+        ctx.spots = tracker.update(&ctx.temperature, ctx.frame.width, ctx.frame.height);
+    }
+
+    fn reset(&mut self) {
+        self.tracker.lock().unwrap().reset();
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Spots(self.tracker.lock().unwrap().clone())
+    }
+}
+
+/// Chooses the raw range the palette spans (auto or locked level/span).
+pub struct AgcStage {
+    display: Arc<Mutex<DisplaySettings>>,
+}
+
+impl AgcStage {
+    pub fn new(display: Arc<Mutex<DisplaySettings>>) -> Self {
+        Self { display }
+    }
+}
+
+impl ProcessingStage for AgcStage {
+    fn name(&self) -> &str {
+        "agc"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        ctx.range = self
+            .display
+            .lock()
+            .unwrap()
+            .agc_range(ctx.frame.field(true), &ctx.calibration);
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Agc {
+            range: self.display.lock().unwrap().range,
+        }
+    }
+}
+
+/// Colour mapping (palette/DDE, isotherms, upscaling) and the legend.
+pub struct PaletteStage {
+    display: Arc<Mutex<DisplaySettings>>,
+}
+
+impl PaletteStage {
+    pub fn new(display: Arc<Mutex<DisplaySettings>>) -> Self {
+        Self { display }
+    }
+}
+
+impl ProcessingStage for PaletteStage {
+    fn name(&self) -> &str {
+        "palette"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        let display = self.display.lock().unwrap();
+        display.colorize_into(&ctx.frame, &ctx.temperature, ctx.range, &mut ctx.image);
+        ctx.legend = display.legend(ctx.range, &ctx.calibration);
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Palette(self.display.lock().unwrap().clone())
+    }
+}
+
+/// Burns the legend and spot readouts into the image itself, for consumers
+/// that only see pixels (streams, recordings).
+pub struct OverlayStage;
+
+impl ProcessingStage for OverlayStage {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn process(&mut self, ctx: &mut FrameContext) {
+        ctx.image = legend::burn_in(&ctx.image, &ctx.legend, &ctx.spots);
+        // Already burnt in; exports must not add a second one.
+        ctx.legend.in_exports = false;
+    }
+
+    fn config(&self) -> StageConfig {
+        StageConfig::Overlay
+    }
+}
@@ -0,0 +1,375 @@
+// Pipeline statistics: frame rates at each stage, latency and lost frames.
+//
+// The engine records captures (timestamped in the libuvc callback) and processed
+// frames, the UI records what it actually puts on screen. Everything is shared
+// through one `Arc<Mutex<PipelineStats>>` and read back as a `StatsSnapshot`.
+
+use serde::{Serialize, Serializer};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Events per second over a sliding window.
+#[derive(Clone, Debug)]
+pub struct RateMeter {
+    window: Duration,
+    stamps: VecDeque<Instant>,
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(2))
+    }
+}
+
+impl RateMeter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            stamps: VecDeque::new(),
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        self.stamps.push_back(now);
+        while let Some(&first) = self.stamps.front() {
+            if now.duration_since(first) <= self.window {
+                break;
+            }
+            self.stamps.pop_front();
+        }
+    }
+
+    /// Rate between the oldest and newest event in the window.
+    pub fn rate(&self) -> f32 {
+        match (self.stamps.front(), self.stamps.back()) {
+            (Some(&first), Some(&last)) if self.stamps.len() > 1 => {
+                let secs = last.duration_since(first).as_secs_f32();
+                if secs > 0.0 {
+                    (self.stamps.len() - 1) as f32 / secs
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stamps.clear();
+    }
+}
+
+/// Last, smoothed and worst latency. Serialised in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Latency {
+    #[serde(serialize_with = "millis")]
+    pub last: Duration,
+    #[serde(serialize_with = "millis")]
+    pub mean: Duration,
+    #[serde(serialize_with = "millis")]
+    pub max: Duration,
+}
+
+impl Latency {
+    pub fn record(&mut self, latency: Duration) {
+        self.mean = if self.mean.is_zero() {
+            latency
+        } else {
+            // Exponential moving average, ~20 frames
+            self.mean.mul_f32(0.95) + latency.mul_f32(0.05)
+        };
+        self.last = latency;
+        self.max = self.max.max(latency);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PipelineStats {
+    started: Option<Instant>,
+    capture: RateMeter,
+    processing: RateMeter,
+    display: RateMeter,
+    /// Callback → processed frame sent to the UI.
+    processing_latency: Latency,
+    /// Callback → texture upload.
+    end_to_end: Latency,
+    captured: u64,
+    processed: u64,
+    displayed: u64,
+    /// Frames superseded in the UI's "keep only the last frame" drain.
+    ui_dropped: u64,
+    /// Frames evicted or rejected by the bounded queues.
+    raw_queue_dropped: u64,
+    frame_queue_dropped: u64,
+    /// Places where the camera sequence number skipped ahead.
+    sequence_gaps: u64,
+    /// Frames missing according to the sequence numbers.
+    sequence_lost: u64,
+    last_sequence: Option<u32>,
+}
+
+impl PipelineStats {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// A frame arrived from the camera. `captured` is the callback time.
+    pub fn record_capture(&mut self, sequence: u32, captured: Instant) {
+        self.started.get_or_insert(captured);
+        self.capture.tick(captured);
+        self.captured += 1;
+        if let Some(last) = self.last_sequence {
+            let step = sequence.wrapping_sub(last);
+            // A step of 0 or a huge one means the counter restarted, not a gap.
+            if step > 1 && step < u32::MAX / 2 {
+                self.sequence_gaps += 1;
+                self.sequence_lost += (step - 1) as u64;
+            }
+        }
+        self.last_sequence = Some(sequence);
+    }
+
+    /// The engine finished a frame captured at `captured`.
+    pub fn record_processed(&mut self, captured: Instant, now: Instant) {
+        self.processing.tick(now);
+        self.processed += 1;
+        self.processing_latency
+            .record(now.saturating_duration_since(captured));
+    }
+
+    /// Current drop counters of the capture → engine and engine → UI queues.
+    pub fn record_queue_drops(&mut self, raw: u64, frames: u64) {
+        self.raw_queue_dropped = raw;
+        self.frame_queue_dropped = frames;
+    }
+
+    /// The UI uploaded a frame captured at `captured`, discarding `dropped`
+    /// older frames that were still queued.
+    pub fn record_display(&mut self, captured: Instant, dropped: u64, now: Instant) {
+        self.display.tick(now);
+        self.displayed += 1;
+        self.ui_dropped += dropped;
+        self.end_to_end
+            .record(now.saturating_duration_since(captured));
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            uptime: self.started.map(|s| s.elapsed()).unwrap_or_default(),
+            capture_fps: self.capture.rate(),
+            processing_fps: self.processing.rate(),
+            display_fps: self.display.rate(),
+            processing_latency: self.processing_latency,
+            end_to_end: self.end_to_end,
+            captured: self.captured,
+            processed: self.processed,
+            displayed: self.displayed,
+            ui_dropped: self.ui_dropped,
+            raw_queue_dropped: self.raw_queue_dropped,
+            frame_queue_dropped: self.frame_queue_dropped,
+            sequence_gaps: self.sequence_gaps,
+            sequence_lost: self.sequence_lost,
+        }
+    }
+}
+
+/// Point-in-time copy of the statistics, for display and export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StatsSnapshot {
+    #[serde(rename = "uptime_s", serialize_with = "secs")]
+    pub uptime: Duration,
+    pub capture_fps: f32,
+    pub processing_fps: f32,
+    pub display_fps: f32,
+    #[serde(rename = "processing_latency_ms")]
+    pub processing_latency: Latency,
+    #[serde(rename = "end_to_end_latency_ms")]
+    pub end_to_end: Latency,
+    pub captured: u64,
+    pub processed: u64,
+    pub displayed: u64,
+    pub ui_dropped: u64,
+    pub raw_queue_dropped: u64,
+    pub frame_queue_dropped: u64,
+    pub sequence_gaps: u64,
+    pub sequence_lost: u64,
+}
+
+impl StatsSnapshot {
+    /// Multi-line summary for the debug overlay.
+    pub fn summary(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+        format!(
+            "Capture   {:5.1} fps\n\
+             Process   {:5.1} fps\n\
+             Display   {:5.1} fps\n\
+             Latency   {:5.1} ms proc, {:.1} ms e2e (max {:.1})\n\
+             Frames    {} captured, {} processed, {} shown\n\
+             Dropped   {} in UI, {}/{} in queues, {} lost in {} gaps",
+            self.capture_fps,
+            self.processing_fps,
+            self.display_fps,
+            ms(self.processing_latency.mean),
+            ms(self.end_to_end.mean),
+            ms(self.end_to_end.max),
+            self.captured,
+            self.processed,
+            self.displayed,
+            self.ui_dropped,
+            self.raw_queue_dropped,
+            self.frame_queue_dropped,
+            self.sequence_lost,
+            self.sequence_gaps,
+        )
+    }
+
+    /// One JSON object, stamped with the current Unix time.
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            timestamp: f64,
+            #[serde(flatten)]
+            stats: &'a StatsSnapshot,
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        serde_json::to_string(&Line {
+            timestamp,
+            stats: self,
+        })
+        .expect("statistics always serialise")
+    }
+
+    /// Appends the snapshot as one JSON line to `path`.
+    pub fn append_to(&self, path: &Path) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self.to_json())?;
+        Ok(())
+    }
+}
+
+fn secs<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64())
+}
+
+fn millis<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn rate_follows_the_window() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(Duration::from_secs(2));
+        meter.tick(start);
+        assert_eq!(meter.rate(), 0.0);
+
+        // 10 fps for 5 s: the window keeps the last 2 s.
+        for i in 1..=50 {
+            meter.tick(start + ms(100 * i));
+        }
+        assert!((meter.rate() - 10.0).abs() < 1e-3, "{}", meter.rate());
+        assert_eq!(meter.stamps.len(), 21);
+
+        // Then 4 fps: after a full window only the new rate is left.
+        for i in 1..=8 {
+            meter.tick(start + ms(5_000 + 250 * i));
+        }
+        assert!((meter.rate() - 4.0).abs() < 1e-3, "{}", meter.rate());
+
+        meter.reset();
+        assert_eq!(meter.rate(), 0.0);
+    }
+
+    #[test]
+    fn latency_is_smoothed_but_the_worst_is_kept() {
+        let mut latency = Latency::default();
+        latency.record(ms(10));
+        assert_eq!(latency.mean, ms(10));
+        latency.record(ms(30));
+        assert_eq!(latency.last, ms(30));
+        assert_eq!(latency.max, ms(30));
+        // 0.95 * 10 + 0.05 * 30
+        assert!((latency.mean.as_secs_f64() - 0.011).abs() < 1e-6);
+        latency.record(ms(5));
+        assert_eq!((latency.last, latency.max), (ms(5), ms(30)));
+    }
+
+    #[test]
+    fn sequence_gaps_count_lost_frames_across_wraparound() {
+        let start = Instant::now();
+        let mut stats = PipelineStats::default();
+        let sequences = [
+            1,
+            2,
+            5,            // 2 lost
+            5,            // repeated
+            u32::MAX - 1, // counter restarted
+            u32::MAX,
+            0, // wrapped
+            2, // 1 lost
+        ];
+        for (i, &sequence) in (0..).zip(&sequences) {
+            let captured = start + ms(40 * i);
+            stats.record_capture(sequence, captured);
+            stats.record_processed(captured, captured + ms(5));
+        }
+        stats.record_display(start, 3, start + ms(50));
+        stats.record_queue_drops(4, 1);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.captured, sequences.len() as u64);
+        assert_eq!(snapshot.processed, sequences.len() as u64);
+        assert_eq!(snapshot.sequence_gaps, 2);
+        assert_eq!(snapshot.sequence_lost, 3);
+        assert!((snapshot.capture_fps - 25.0).abs() < 1e-3);
+        assert_eq!(snapshot.processing_latency.max, ms(5));
+        assert_eq!(snapshot.end_to_end.last, ms(50));
+        assert_eq!(snapshot.displayed, 1);
+        assert_eq!(snapshot.ui_dropped, 3);
+        assert_eq!(
+            (snapshot.raw_queue_dropped, snapshot.frame_queue_dropped),
+            (4, 1)
+        );
+
+        stats.reset();
+        assert_eq!(stats.snapshot(), StatsSnapshot::default());
+    }
+
+    #[test]
+    fn json_lines_use_seconds_and_milliseconds() {
+        let snapshot = StatsSnapshot {
+            uptime: ms(2_500),
+            capture_fps: 25.0,
+            end_to_end: Latency {
+                last: ms(40),
+                mean: ms(30),
+                max: ms(120),
+            },
+            sequence_lost: 3,
+            ..StatsSnapshot::default()
+        };
+        let json: serde_json::Value = serde_json::from_str(&snapshot.to_json()).unwrap();
+        assert!(json["timestamp"].as_f64().unwrap() > 1.6e9);
+        assert_eq!(json["uptime_s"], 2.5);
+        assert_eq!(json["capture_fps"], 25.0);
+        assert_eq!(
+            json["end_to_end_latency_ms"],
+            serde_json::json!({ "last": 40.0, "mean": 30.0, "max": 120.0 })
+        );
+        assert_eq!(json["processing_latency_ms"]["max"], 0.0);
+        assert_eq!(json["sequence_lost"], 3);
+    }
+}
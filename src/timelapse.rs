@@ -0,0 +1,333 @@
+// Time-lapse / interval capture for slow processes.
+//
+// Like the recorder, a `TimeLapse` lives in the engine and is offered every
+// processed frame, so it keeps running with or without the UI. Every
+// `interval` it takes a sample (optionally the average of several consecutive
+// frames), writes it as `frame_000001.png` + `frame_000001_raw.png` (or only
+// the raw field) and appends a line to `index.csv`.
+
+use crate::display::DisplaySettings;
+use crate::frame::ProcessedFrame;
+use crate::snapshot::{Snapshot, SnapshotFormat};
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeLapseConfig {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// Stop after this long; `None` runs until stopped.
+    pub duration: Option<Duration>,
+    /// Consecutive frames averaged into one sample (1 = no averaging).
+    pub average: usize,
+    /// Colour image written next to the raw field; `None` writes raw only.
+    pub image: Option<SnapshotFormat>,
+}
+
+impl Default for TimeLapseConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("timelapse"),
+            interval: Duration::from_secs(60),
+            duration: None,
+            average: 1,
+            image: Some(SnapshotFormat::Png),
+        }
+    }
+}
+
+/// An interval capture in progress.
+pub struct TimeLapse {
+    config: TimeLapseConfig,
+    device: String,
+    index: File,
+    /// Capture time of the first frame.
+    start: Option<Instant>,
+    /// Capture time of the latest frame, relative to `start`.
+    at: Duration,
+    next_due: Duration,
+    /// Raw sums and frame count of the sample being averaged.
+    accumulator: Option<(Vec<u32>, usize)>,
+    samples: u64,
+}
+
+impl TimeLapse {
+    /// Creates the output directory and `index.csv`. `device` is recorded in
+    /// every sample's metadata.
+    pub fn start(config: TimeLapseConfig, device: &str) -> Result<Self> {
+        std::fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Cannot create {}", config.dir.display()))?;
+        let path = config.dir.join("index.csv");
+        let mut index = OpenOptions::new().create(true).append(true).open(&path)?;
+        if index.metadata()?.len() == 0 {
+            writeln!(
+                index,
+                "sample,timestamp,image,raw,frames,min_c,max_c,mean_c,centre_c"
+            )?;
+        }
+        println!(
+            "⏱ Time-lapse: every {:.0} s into {}",
+            config.interval.as_secs_f32(),
+            config.dir.display()
+        );
+        Ok(Self {
+            config,
+            device: device.to_string(),
+            index,
+            start: None,
+            at: Duration::ZERO,
+            next_due: Duration::ZERO,
+            accumulator: None,
+            samples: 0,
+        })
+    }
+
+    pub fn config(&self) -> &TimeLapseConfig {
+        &self.config
+    }
+
+    /// Samples written so far.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Capture time covered so far.
+    pub fn elapsed(&self) -> Duration {
+        self.at
+    }
+
+    /// Time until the next sample starts.
+    pub fn next_in(&self) -> Duration {
+        self.next_due.saturating_sub(self.elapsed())
+    }
+
+    /// The configured duration has passed and no sample is pending.
+    pub fn is_finished(&self) -> bool {
+        self.accumulator.is_none()
+            && self
+                .config
+                .duration
+                .is_some_and(|duration| self.elapsed() >= duration)
+    }
+
+    /// Takes `frame` into the current sample if one is due. `display` renders
+    /// averaged samples; single-frame samples keep the frame's own image.
+    pub fn offer(&mut self, frame: &ProcessedFrame, display: &Mutex<DisplaySettings>) {
+        let start = *self.start.get_or_insert(frame.frame.captured);
+        let at = frame.frame.captured.saturating_duration_since(start);
+        self.at = at;
+        if self.accumulator.is_none() {
+            if at < self.next_due || self.is_finished() {
+                return;
+            }
+            self.accumulator = Some((vec![0; frame.frame.raw.len()], 0));
+            // Skip missed slots (e.g. the camera stalled) rather than bursting.
+            while self.next_due <= at {
+                self.next_due += self.config.interval.max(Duration::from_millis(100));
+            }
+        }
+        let Some((sum, count)) = &mut self.accumulator else {
+            return;
+        };
+        if sum.len() != frame.frame.raw.len() {
+            // Geometry changed mid-sample; start the average over.
+            sum.clear();
+            sum.resize(frame.frame.raw.len(), 0);
+            *count = 0;
+        }
+        for (s, &v) in sum.iter_mut().zip(&frame.frame.raw) {
+            *s += v as u32;
+        }
+        *count += 1;
+        if *count < self.config.average.max(1) {
+            return;
+        }
+        let (sum, count) = self.accumulator.take().unwrap();
+        if let Err(e) = self.write_sample(frame, display, &sum, count) {
+            eprintln!("❌ Time-lapse sample failed: {}", e);
+        }
+    }
+
+    fn write_sample(
+        &mut self,
+        frame: &ProcessedFrame,
+        display: &Mutex<DisplaySettings>,
+        sum: &[u32],
+        count: usize,
+    ) -> Result<()> {
+        let number = self.samples + 1;
+        let stem = self.config.dir.join(format!("frame_{:06}", number));
+        let mut snapshot = Snapshot::capture(frame, &self.device);
+        if count > 1 {
+            let half = count as u32 / 2;
+            snapshot.frame.raw = sum
+                .iter()
+                .map(|&s| ((s + half) / count as u32) as u16)
+                .collect();
+        }
+
+        let raw_name = format!("frame_{:06}_raw.png", number);
+        let image_name = match self.config.image {
+            Some(format) => {
+                let image = if count > 1 {
+                    let display = display.lock().unwrap();
+                    let (image, legend) = snapshot.render(&display);
+                    snapshot.metadata.range = [legend.min, legend.max];
+                    if display.burn_legend {
+                        crate::legend::burn_in(&image, &legend, &frame.spots)
+                    } else {
+                        image
+                    }
+                } else {
+                    frame.export_image()
+                };
+                let path = snapshot.save_as(&image, &stem, format)?;
+                file_name(&path)
+            }
+            None => {
+                snapshot.save_raw(&self.config.dir.join(&raw_name))?;
+                String::new()
+            }
+        };
+
+        let temperature = snapshot.temperature();
+        let (min, max) = temperature
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &t| (lo.min(t), hi.max(t)));
+        let mean = temperature.iter().sum::<f32>() / temperature.len().max(1) as f32;
+        let (w, h) = (snapshot.frame.width, snapshot.frame.height);
+        let centre = temperature
+            .get(h / 2 * w + w / 2)
+            .copied()
+            .unwrap_or(f32::NAN);
+        writeln!(
+            self.index,
+            "{},{:.3},{},{},{},{:.2},{:.2},{:.2},{:.2}",
+            number,
+            snapshot.metadata.timestamp,
+            image_name,
+            raw_name,
+            count,
+            min,
+            max,
+            mean,
+            centre
+        )?;
+        self.samples = number;
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::ThermalFrame;
+    use crate::pipeline::FrameContext;
+    use eframe::egui;
+
+    const W: usize = 8;
+    const H: usize = 6;
+
+    /// A uniform frame captured `secs` after `start`.
+    fn frame(start: Instant, secs: f32, raw: u16) -> ProcessedFrame {
+        FrameContext {
+            frame: ThermalFrame {
+                width: W,
+                height: H,
+                captured: start + Duration::from_secs_f32(secs),
+                raw: vec![raw; W * H],
+                ..Default::default()
+            },
+            image: egui::ColorImage::new([W, H], egui::Color32::GRAY),
+            ..FrameContext::default()
+        }
+        .into_processed()
+    }
+
+    fn config(name: &str, image: Option<SnapshotFormat>) -> TimeLapseConfig {
+        TimeLapseConfig {
+            dir: std::env::temp_dir().join(format!("timelapse-{}-{}", name, std::process::id())),
+            interval: Duration::from_secs(1),
+            duration: None,
+            average: 3,
+            image,
+        }
+    }
+
+    #[test]
+    fn samples_are_averaged_numbered_and_indexed() {
+        let config = config("png", Some(SnapshotFormat::Png));
+        let dir = config.dir.clone();
+        let display = Mutex::new(DisplaySettings::default());
+        let mut lapse = TimeLapse::start(config, "test").unwrap();
+        let start = Instant::now();
+        // 19_202 = round(mean(19_200, 19_201, 19_205)), 300.03 K.
+        let frames = [
+            (0.0, 19_200),
+            (0.1, 19_201),
+            (0.2, 19_205),
+            (0.5, 30_000), // between samples
+            (1.0, 19_200),
+            (1.1, 19_201),
+            (1.2, 19_205),
+            (3.5, 19_200), // the 2 s slot was missed
+            (3.6, 19_201),
+            (3.7, 19_205),
+        ];
+        for (secs, raw) in frames {
+            lapse.offer(&frame(start, secs, raw), &display);
+        }
+        assert_eq!(lapse.samples(), 3);
+        assert_eq!(lapse.next_in(), Duration::from_secs_f32(4.0 - 3.7));
+
+        let index = std::fs::read_to_string(dir.join("index.csv")).unwrap();
+        let lines: Vec<&str> = index.lines().collect();
+        assert_eq!(lines.len(), 4);
+        for (n, line) in (1..).zip(&lines[1..]) {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields[0], n.to_string());
+            assert_eq!(fields[2], format!("frame_{:06}.png", n));
+            assert_eq!(fields[3], format!("frame_{:06}_raw.png", n));
+            assert_eq!(&fields[4..], ["3", "26.88", "26.88", "26.88", "26.88"]);
+            let raw = Snapshot::load(&dir.join(fields[3])).unwrap().frame.raw;
+            assert_eq!(raw, vec![19_202; W * H]);
+            assert!(dir.join(fields[2]).exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn raw_only_samples_leave_the_image_column_empty() {
+        let mut config = config("raw", None);
+        config.average = 1;
+        config.duration = Some(Duration::from_secs(2));
+        let dir = config.dir.clone();
+        let display = Mutex::new(DisplaySettings::default());
+        let mut lapse = TimeLapse::start(config, "test").unwrap();
+        let start = Instant::now();
+        for secs in [0.0, 1.0, 2.0, 3.0] {
+            lapse.offer(&frame(start, secs, 19_200), &display);
+        }
+        assert_eq!(lapse.samples(), 2);
+        assert!(lapse.is_finished());
+
+        let index = std::fs::read_to_string(dir.join("index.csv")).unwrap();
+        let rows: Vec<&str> = index.lines().skip(1).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].starts_with("2,"));
+        assert!(rows[1].contains(",,frame_000002_raw.png,1,"));
+        assert!(!dir.join("frame_000001.png").exists());
+        assert!(dir.join("frame_000002_raw.png").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
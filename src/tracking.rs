@@ -0,0 +1,200 @@
+// Hot/cold spot tracking.
+//
+// Finds the hottest and coldest pixel of every frame, optionally refines them to
+// sub-pixel accuracy and smooths their position over time so the markers do not
+// jitter between neighbouring pixels of equal temperature.
+
+use serde::{Deserialize, Serialize};
+
+/// A tracked location in continuous pixel coordinates (pixel (x, y) covers
+/// [x, x+1) x [y, y+1), as in `measure.rs`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TrackedSpot {
+    pub x: f32,
+    pub y: f32,
+    pub temperature: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HotColdSpots {
+    pub hot: TrackedSpot,
+    pub cold: TrackedSpot,
+    /// Centre pixel, like the crosshair readout of a handheld camera.
+    pub center: TrackedSpot,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpotTracker {
+    pub subpixel: bool,
+    /// Weight of the previous position, 0.0 (off) ..< 1.0.
+    pub smoothing: f32,
+    /// Jumps longer than this (in pixels) are followed immediately.
+    pub snap_distance: f32,
+    #[serde(skip)]
+    last: Option<(TrackedSpot, TrackedSpot)>,
+}
+
+impl Default for SpotTracker {
+    fn default() -> Self {
+        Self {
+            subpixel: true,
+            smoothing: 0.6,
+            snap_distance: 8.0,
+            last: None,
+        }
+    }
+}
+
+impl SpotTracker {
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    pub fn update(&mut self, temperature: &[f32], width: usize, height: usize) -> HotColdSpots {
+        if temperature.is_empty() {
+            return HotColdSpots::default();
+        }
+        let (mut hot_i, mut cold_i) = (0, 0);
+        for (i, &t) in temperature.iter().enumerate() {
+            if t > temperature[hot_i] {
+                hot_i = i;
+            }
+            if t < temperature[cold_i] {
+                cold_i = i;
+            }
+        }
+
+        let mut hot = self.locate(temperature, width, height, hot_i);
+        let mut cold = self.locate(temperature, width, height, cold_i);
+        if let Some((last_hot, last_cold)) = self.last {
+            hot = self.smooth(last_hot, hot);
+            cold = self.smooth(last_cold, cold);
+        }
+        self.last = Some((hot, cold));
+
+        let (cx, cy) = (width / 2, height / 2);
+        HotColdSpots {
+            hot,
+            cold,
+            center: TrackedSpot {
+                x: cx as f32 + 0.5,
+                y: cy as f32 + 0.5,
+                temperature: temperature[cy * width + cx],
+            },
+        }
+    }
+
+    fn locate(&self, t: &[f32], width: usize, height: usize, i: usize) -> TrackedSpot {
+        let (x, y) = (i % width, i / width);
+        let mut spot = TrackedSpot {
+            x: x as f32 + 0.5,
+            y: y as f32 + 0.5,
+            temperature: t[i],
+        };
+        if self.subpixel {
+            if x > 0 && x + 1 < width {
+                spot.x += parabola_offset(t[i - 1], t[i], t[i + 1]);
+            }
+            if y > 0 && y + 1 < height {
+                spot.y += parabola_offset(t[i - width], t[i], t[i + width]);
+            }
+        }
+        spot
+    }
+
+    /// Blends positions; the temperature is always the current one.
+    fn smooth(&self, last: TrackedSpot, new: TrackedSpot) -> TrackedSpot {
+        let dist = ((new.x - last.x).powi(2) + (new.y - last.y).powi(2)).sqrt();
+        if self.smoothing <= 0.0 || dist > self.snap_distance {
+            return new;
+        }
+        let k = self.smoothing.min(0.95);
+        TrackedSpot {
+            x: last.x * k + new.x * (1.0 - k),
+            y: last.y * k + new.y * (1.0 - k),
+            temperature: new.temperature,
+        }
+    }
+}
+
+/// Vertex of the parabola through three samples, relative to the middle one.
+fn parabola_offset(left: f32, center: f32, right: f32) -> f32 {
+    let denom = left - 2.0 * center + right;
+    if denom.abs() < f32::EPSILON {
+        return 0.0;
+    }
+    (0.5 * (left - right) / denom).clamp(-0.5, 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 20;
+    const H: usize = 16;
+
+    /// 20 °C field with a 40 °C pixel at `hot` and a 5 °C pixel at `cold`.
+    fn field(hot: (usize, usize), cold: (usize, usize)) -> Vec<f32> {
+        let mut t = vec![20.0; W * H];
+        t[hot.1 * W + hot.0] = 40.0;
+        t[cold.1 * W + cold.0] = 5.0;
+        t
+    }
+
+    fn at(spot: TrackedSpot) -> (f32, f32) {
+        (spot.x, spot.y)
+    }
+
+    #[test]
+    fn finds_hot_cold_and_centre() {
+        let mut tracker = SpotTracker {
+            subpixel: false,
+            ..SpotTracker::default()
+        };
+        let spots = tracker.update(&field((3, 4), (15, 12)), W, H);
+        assert_eq!((at(spots.hot), spots.hot.temperature), ((3.5, 4.5), 40.0));
+        assert_eq!(
+            (at(spots.cold), spots.cold.temperature),
+            ((15.5, 12.5), 5.0)
+        );
+        assert_eq!(at(spots.center), (10.5, 8.5));
+    }
+
+    #[test]
+    fn subpixel_leans_towards_the_warmer_neighbour() {
+        let mut t = field((6, 6), (0, 0));
+        t[6 * W + 7] = 35.0;
+        let spots = SpotTracker::default().update(&t, W, H);
+        assert!(spots.hot.x > 6.5 && spots.hot.x < 7.0, "{:?}", spots.hot);
+        assert_eq!(spots.hot.y, 6.5);
+    }
+
+    #[test]
+    fn spots_keep_their_identity_across_frames() {
+        let mut tracker = SpotTracker {
+            subpixel: false,
+            ..SpotTracker::default()
+        };
+        tracker.update(&field((5, 5), (14, 10)), W, H);
+
+        // A one-pixel step is smoothed, each marker following its own spot.
+        let spots = tracker.update(&field((6, 5), (13, 10)), W, H);
+        assert!(spots.hot.x > 5.5 && spots.hot.x < 6.5, "{:?}", spots.hot);
+        assert!(
+            spots.cold.x > 13.5 && spots.cold.x < 14.5,
+            "{:?}",
+            spots.cold
+        );
+        assert_eq!((spots.hot.temperature, spots.cold.temperature), (40.0, 5.0));
+
+        // Swapping places is a long jump: both markers snap.
+        let spots = tracker.update(&field((13, 10), (6, 5)), W, H);
+        assert_eq!(at(spots.hot), (13.5, 10.5));
+        assert_eq!(at(spots.cold), (6.5, 5.5));
+
+        // After a reset nothing is blended with the previous frame.
+        tracker.reset();
+        let spots = tracker.update(&field((14, 10), (5, 5)), W, H);
+        assert_eq!(at(spots.hot), (14.5, 10.5));
+    }
+}
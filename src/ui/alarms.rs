@@ -0,0 +1,258 @@
+use crate::alarm::{
+    AlarmAction, AlarmEngine, AlarmRule, AlarmSource, AlarmStatus, Condition, Statistic,
+};
+use crate::measure::Roi;
+use eframe::egui;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Red border while a flashing alarm is active (blinks at ~2 Hz) and the list of
+/// active alarms at the top of the image.
+pub fn paint_status(painter: &egui::Painter, rect: egui::Rect, status: &AlarmStatus, time: f64) {
+    if status.flash && (time * 4.0) as i64 % 2 == 0 {
+        painter.rect_stroke(
+            rect.shrink(3.0),
+            0.0,
+            egui::Stroke::new(6.0, egui::Color32::RED),
+        );
+    }
+    if !status.active.is_empty() {
+        painter.text(
+            rect.center_top() + egui::vec2(0.0, 12.0),
+            egui::Align2::CENTER_TOP,
+            format!("🚨 {}", status.active.join(", ")),
+            egui::FontId::proportional(18.0),
+            egui::Color32::from_rgb(255, 80, 80),
+        );
+    }
+}
+
+/// Editor for the alarm rules. `roi` is the currently selected measurement, if
+/// any, so that an alarm can be attached to it.
+pub fn alarm_window(
+    ctx: &egui::Context,
+    open: &mut bool,
+    alarms: &mut AlarmEngine,
+    roi: Option<&Roi>,
+) {
+    egui::Window::new("Alarms")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("+ Frame max").clicked() {
+                    alarms.add(AlarmRule::new(
+                        0,
+                        "Frame max",
+                        AlarmSource::Frame,
+                        Condition::Above(60.0),
+                    ));
+                }
+                if ui.button("+ Centre").clicked() {
+                    alarms.add(AlarmRule::new(
+                        0,
+                        "Centre",
+                        AlarmSource::Center,
+                        Condition::Above(60.0),
+                    ));
+                }
+                if let Some(roi) = roi
+                    && ui.button(format!("+ {}", roi.name)).clicked()
+                {
+                    alarms.add(AlarmRule::new(
+                        0,
+                        &roi.name,
+                        AlarmSource::Region(roi.id),
+                        Condition::Above(60.0),
+                    ));
+                }
+            });
+            ui.separator();
+
+            let mut remove = None;
+            for rule in &mut alarms.rules {
+                ui.push_id(rule.id, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut rule.enabled, "");
+                        ui.add(egui::TextEdit::singleline(&mut rule.name).desired_width(90.0));
+                        if ui.small_button("✖").clicked() {
+                            remove = Some(rule.id);
+                        }
+                    });
+                    rule_editor(ui, rule);
+                    ui.separator();
+                });
+            }
+            if let Some(id) = remove {
+                alarms.remove(id);
+            }
+
+            ui.collapsing("Recent events", |ui| {
+                for event in alarms.recent.iter().rev().take(10) {
+                    ui.label(format!(
+                        "{} {} ({:.1})",
+                        event.rule_name,
+                        event.state_name(),
+                        event.value
+                    ));
+                }
+            });
+        });
+}
+
+fn rule_editor(ui: &mut egui::Ui, rule: &mut AlarmRule) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("stat")
+            .selected_text(format!("{:?}", rule.statistic))
+            .width(60.0)
+            .show_ui(ui, |ui| {
+                for stat in [Statistic::Max, Statistic::Min, Statistic::Mean] {
+                    ui.selectable_value(&mut rule.statistic, stat, format!("{:?}", stat));
+                }
+            });
+        let kind = match rule.condition {
+            Condition::Above(_) => "above",
+            Condition::Below(_) => "below",
+            Condition::RateOfRise { .. } => "rises faster than",
+        };
+        egui::ComboBox::from_id_source("cond")
+            .selected_text(kind)
+            .show_ui(ui, |ui| {
+                let limit = match rule.condition {
+                    Condition::Above(t) | Condition::Below(t) => t,
+                    Condition::RateOfRise { per_minute, .. } => per_minute,
+                };
+                if ui.selectable_label(kind == "above", "above").clicked() {
+                    rule.condition = Condition::Above(limit);
+                }
+                if ui.selectable_label(kind == "below", "below").clicked() {
+                    rule.condition = Condition::Below(limit);
+                }
+                if ui
+                    .selectable_label(kind == "rises faster than", "rises faster than")
+                    .clicked()
+                {
+                    rule.condition = Condition::RateOfRise {
+                        per_minute: limit,
+                        window: Duration::from_secs(10),
+                    };
+                }
+            });
+        match &mut rule.condition {
+            Condition::Above(t) | Condition::Below(t) => {
+                ui.add(egui::DragValue::new(t).speed(0.1).suffix(" °C"));
+            }
+            Condition::RateOfRise { per_minute, .. } => {
+                ui.add(
+                    egui::DragValue::new(per_minute)
+                        .speed(0.1)
+                        .suffix(" °C/min"),
+                );
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Hysteresis");
+        ui.add(
+            egui::DragValue::new(&mut rule.hysteresis)
+                .speed(0.1)
+                .clamp_range(0.0..=50.0),
+        );
+        ui.label("Hold");
+        let mut secs = rule.min_duration.as_secs_f32();
+        if ui
+            .add(
+                egui::DragValue::new(&mut secs)
+                    .speed(0.1)
+                    .clamp_range(0.0..=3600.0)
+                    .suffix(" s"),
+            )
+            .changed()
+        {
+            rule.min_duration = Duration::from_secs_f32(secs);
+        }
+    });
+    ui.horizontal(|ui| {
+        toggle(ui, &mut rule.actions, AlarmAction::Flash, "Flash");
+        toggle(ui, &mut rule.actions, AlarmAction::Beep, "Beep");
+        toggle(
+            ui,
+            &mut rule.actions,
+            AlarmAction::Snapshot(PathBuf::from("alarms")),
+            "Snapshot",
+        );
+        toggle(
+            ui,
+            &mut rule.actions,
+            AlarmAction::EventLog(PathBuf::from("alarms.log")),
+            "Log",
+        );
+    });
+    let mut remove = None;
+    for (i, action) in rule.actions.iter_mut().enumerate() {
+        let mut editor = |ui: &mut egui::Ui| match action {
+            AlarmAction::RunCommand(cmd) => {
+                ui.label("Command");
+                ui.text_edit_singleline(cmd);
+                true
+            }
+            AlarmAction::Webhook(url) => {
+                ui.label("Webhook");
+                ui.text_edit_singleline(url);
+                true
+            }
+            AlarmAction::Mqtt { host, port, topic } => {
+                ui.label("MQTT");
+                ui.add(egui::TextEdit::singleline(host).desired_width(80.0));
+                ui.add(egui::DragValue::new(port));
+                ui.add(egui::TextEdit::singleline(topic).desired_width(100.0));
+                true
+            }
+            _ => false,
+        };
+        ui.horizontal(|ui| {
+            if editor(ui) && ui.small_button("✖").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        rule.actions.remove(i);
+    }
+    ui.horizontal(|ui| {
+        let has = |actions: &[AlarmAction], f: fn(&AlarmAction) -> bool| actions.iter().any(f);
+        if !has(&rule.actions, |a| matches!(a, AlarmAction::RunCommand(_)))
+            && ui.small_button("+ Command").clicked()
+        {
+            rule.actions.push(AlarmAction::RunCommand(String::new()));
+        }
+        if !has(&rule.actions, |a| matches!(a, AlarmAction::Webhook(_)))
+            && ui.small_button("+ Webhook").clicked()
+        {
+            rule.actions.push(AlarmAction::Webhook(
+                "http://127.0.0.1:8080/alarm".to_string(),
+            ));
+        }
+        if !has(&rule.actions, |a| matches!(a, AlarmAction::Mqtt { .. }))
+            && ui.small_button("+ MQTT").clicked()
+        {
+            rule.actions.push(AlarmAction::Mqtt {
+                host: "127.0.0.1".to_string(),
+                port: 1883,
+                topic: "thermoscope/alarm".to_string(),
+            });
+        }
+    });
+}
+
+fn toggle(ui: &mut egui::Ui, actions: &mut Vec<AlarmAction>, action: AlarmAction, label: &str) {
+    let kind = std::mem::discriminant(&action);
+    let mut on = actions.iter().any(|a| std::mem::discriminant(a) == kind);
+    if ui.checkbox(&mut on, label).changed() {
+        if on {
+            actions.push(action);
+        } else {
+            actions.retain(|a| std::mem::discriminant(a) != kind);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::display::RangeMode;
+use crate::legend::Legend;
+use crate::tracking::HotColdSpots;
+use eframe::egui;
+
+const BAR_WIDTH: f32 = 16.0;
+const PANEL_WIDTH: f32 = 86.0;
+
+/// Where the colour bar goes inside the image rect.
+pub fn bar_rect(image_rect: egui::Rect) -> egui::Rect {
+    let panel = egui::Rect::from_min_max(
+        egui::pos2(image_rect.right() - PANEL_WIDTH, image_rect.top() + 50.0),
+        egui::pos2(image_rect.right() - 10.0, image_rect.bottom() - 60.0),
+    );
+    egui::Rect::from_min_size(panel.min, egui::vec2(BAR_WIDTH, panel.height()))
+}
+
+/// Vertical colour bar with tick labels and level/span readout.
+/// Returns the bar's response so callers can make it interactive.
+pub fn colorbar(
+    ui: &mut egui::Ui,
+    image_rect: egui::Rect,
+    legend: &Legend,
+    sense: egui::Sense,
+) -> egui::Response {
+    let bar = bar_rect(image_rect);
+    let response = ui.interact(bar, ui.id().with("colorbar"), sense);
+    let painter = ui.painter();
+
+    let backdrop = egui::Rect::from_min_max(
+        bar.min - egui::vec2(6.0, 14.0),
+        egui::pos2(image_rect.right() - 4.0, bar.bottom() + 44.0),
+    );
+    painter.rect_filled(backdrop, 4.0, egui::Color32::from_black_alpha(140));
+
+    // Gradient as a mesh: one quad per step.
+    let steps = 64;
+    let mut mesh = egui::Mesh::default();
+    for i in 0..=steps {
+        let t = i as f32 / steps as f32;
+        let y = bar.bottom() - t * bar.height();
+        let color = legend.palette.color(t);
+        mesh.colored_vertex(egui::pos2(bar.left(), y), color);
+        mesh.colored_vertex(egui::pos2(bar.right(), y), color);
+        if i > 0 {
+            let base = 2 * i as u32;
+            mesh.add_triangle(base - 2, base - 1, base);
+            mesh.add_triangle(base - 1, base, base + 1);
+        }
+    }
+    painter.add(egui::Shape::mesh(mesh));
+    painter.rect_stroke(bar, 0.0, egui::Stroke::new(1.0, egui::Color32::GRAY));
+
+    let font = egui::FontId::proportional(11.0);
+    let ticks = legend.ticks(6);
+    for (i, &value) in ticks.iter().enumerate() {
+        let y = bar.top() + bar.height() * i as f32 / (ticks.len() - 1) as f32;
+        painter.line_segment(
+            [egui::pos2(bar.right(), y), egui::pos2(bar.right() + 4.0, y)],
+            egui::Stroke::new(1.0, egui::Color32::WHITE),
+        );
+        painter.text(
+            egui::pos2(bar.right() + 6.0, y),
+            egui::Align2::LEFT_CENTER,
+            legend.unit.format(value),
+            font.clone(),
+            egui::Color32::WHITE,
+        );
+    }
+
+    let unit = legend.unit;
+    painter.text(
+        egui::pos2(bar.left(), bar.bottom() + 8.0),
+        egui::Align2::LEFT_TOP,
+        format!(
+            "{}Level {}\nSpan {:.1}{}",
+            if legend.locked { "🔒 " } else { "" },
+            unit.format(legend.level()),
+            unit.delta_from_celsius(legend.span()),
+            unit.symbol()
+        ),
+        font,
+        egui::Color32::LIGHT_GRAY,
+    );
+    response
+}
+
+/// Mouse control of the range on the colour bar: vertical drag moves the level,
+/// horizontal drag and the scroll wheel change the span, double-click returns
+/// to automatic ranging. `response` must come from `colorbar` with drag sense.
+pub fn range_drag(response: &egui::Response, legend: &Legend, range: &mut RangeMode) {
+    if response.double_clicked() {
+        *range = RangeMode::Auto;
+        return;
+    }
+    if response.dragged() {
+        let delta = response.drag_delta();
+        let per_point = legend.span() / response.rect.height();
+        *range = range.adjusted(legend, -delta.y * per_point, (delta.x * 0.01).exp());
+    }
+    if response.hovered() {
+        let scroll = response.ctx.input(|i| i.smooth_scroll_delta.y);
+        if scroll != 0.0 {
+            *range = range.adjusted(legend, 0.0, (-scroll * 0.003).exp());
+        }
+    }
+}
+
+/// Keyboard shortcuts: L locks/unlocks the current range, A returns to auto,
+/// Up/Down move the level by 5% of the span, Left/Right narrow/widen the span.
+pub fn range_keys(ctx: &egui::Context, legend: &Legend, range: &mut RangeMode) {
+    if ctx.wants_keyboard_input() {
+        return;
+    }
+    let step = legend.span() * 0.05;
+    ctx.input(|i| {
+        if i.key_pressed(egui::Key::L) {
+            *range = if range.is_manual() {
+                RangeMode::Auto
+            } else {
+                RangeMode::locked(legend)
+            };
+        }
+        if i.key_pressed(egui::Key::A) {
+            *range = RangeMode::Auto;
+        }
+        if i.key_pressed(egui::Key::ArrowUp) {
+            *range = range.adjusted(legend, step, 1.0);
+        }
+        if i.key_pressed(egui::Key::ArrowDown) {
+            *range = range.adjusted(legend, -step, 1.0);
+        }
+        if i.key_pressed(egui::Key::ArrowLeft) {
+            *range = range.adjusted(legend, 0.0, 1.0 / 1.1);
+        }
+        if i.key_pressed(egui::Key::ArrowRight) {
+            *range = range.adjusted(legend, 0.0, 1.1);
+        }
+    });
+}
+
+/// Small max/min/centre text block in the top-left corner.
+pub fn readouts(
+    painter: &egui::Painter,
+    image_rect: egui::Rect,
+    legend: &Legend,
+    spots: &HotColdSpots,
+) {
+    let unit = legend.unit;
+    let text = format!(
+        "Max {}\nMin {}\nCtr {}",
+        unit.format(spots.hot.temperature),
+        unit.format(spots.cold.temperature),
+        unit.format(spots.center.temperature)
+    );
+    let galley = painter.layout_no_wrap(text, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+    let pos = image_rect.left_top() + egui::vec2(10.0, 30.0);
+    painter.rect_filled(
+        egui::Rect::from_min_size(pos, galley.size()).expand(4.0),
+        3.0,
+        egui::Color32::from_black_alpha(140),
+    );
+    painter.galley(pos, galley, egui::Color32::WHITE);
+}
@@ -0,0 +1,6 @@
+// egui building blocks used by the desktop app (`src/main.rs`).
+
+pub mod alarms;
+pub mod legend;
+pub mod overlay;
+pub mod tools;
@@ -0,0 +1,89 @@
+use crate::calibration::TemperatureUnit;
+use crate::stats::StatsSnapshot;
+use crate::tracking::{HotColdSpots, TrackedSpot};
+use crate::ui::tools::to_screen;
+use eframe::egui;
+
+/// Crosshair markers for the hottest and coldest pixel, optionally the centre spot.
+pub fn paint_spots(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    size: [usize; 2],
+    spots: &HotColdSpots,
+    show_center: bool,
+    unit: TemperatureUnit,
+) {
+    let hot = egui::Color32::from_rgb(255, 60, 60);
+    let cold = egui::Color32::from_rgb(80, 160, 255);
+    crosshair(painter, rect, size, &spots.hot, hot, unit);
+    crosshair(painter, rect, size, &spots.cold, cold, unit);
+    if show_center {
+        crosshair(
+            painter,
+            rect,
+            size,
+            &spots.center,
+            egui::Color32::WHITE,
+            unit,
+        );
+    }
+}
+
+/// Centre readout in the bottom-left corner, like handheld cameras.
+pub fn paint_center_readout(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    spots: &HotColdSpots,
+    unit: TemperatureUnit,
+) {
+    painter.text(
+        rect.left_bottom() + egui::vec2(10.0, -10.0),
+        egui::Align2::LEFT_BOTTOM,
+        format!("⊕ {}", unit.format(spots.center.temperature)),
+        egui::FontId::proportional(22.0),
+        egui::Color32::WHITE,
+    );
+}
+
+/// Debug block with pipeline rates, latency and drop counters, bottom-left above
+/// the centre readout.
+pub fn paint_stats(painter: &egui::Painter, rect: egui::Rect, stats: &StatsSnapshot) {
+    let galley = painter.layout_no_wrap(
+        stats.summary(),
+        egui::FontId::monospace(11.0),
+        egui::Color32::LIGHT_GREEN,
+    );
+    let pos = rect.left_bottom() + egui::vec2(10.0, -48.0 - galley.size().y);
+    painter.rect_filled(
+        egui::Rect::from_min_size(pos, galley.size()).expand(4.0),
+        3.0,
+        egui::Color32::from_black_alpha(170),
+    );
+    painter.galley(pos, galley, egui::Color32::LIGHT_GREEN);
+}
+
+fn crosshair(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    size: [usize; 2],
+    spot: &TrackedSpot,
+    color: egui::Color32,
+    unit: TemperatureUnit,
+) {
+    let c = to_screen((spot.x, spot.y), rect, size);
+    let shadow = egui::Stroke::new(3.0, egui::Color32::from_black_alpha(160));
+    let stroke = egui::Stroke::new(1.5, color);
+    for s in [shadow, stroke] {
+        painter.line_segment([c - egui::vec2(10.0, 0.0), c - egui::vec2(3.0, 0.0)], s);
+        painter.line_segment([c + egui::vec2(3.0, 0.0), c + egui::vec2(10.0, 0.0)], s);
+        painter.line_segment([c - egui::vec2(0.0, 10.0), c - egui::vec2(0.0, 3.0)], s);
+        painter.line_segment([c + egui::vec2(0.0, 3.0), c + egui::vec2(0.0, 10.0)], s);
+    }
+    painter.text(
+        c + egui::vec2(8.0, 8.0),
+        egui::Align2::LEFT_TOP,
+        unit.format(spot.temperature),
+        egui::FontId::proportional(12.0),
+        color,
+    );
+}
@@ -0,0 +1,427 @@
+use crate::calibration::TemperatureUnit;
+use crate::frame::ProcessedFrame;
+use crate::measure::{RoiSet, RoiStats, Shape};
+use eframe::egui;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Select,
+    Spot,
+    Rect,
+    Ellipse,
+    Line,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 5] = [
+        Tool::Select,
+        Tool::Spot,
+        Tool::Rect,
+        Tool::Ellipse,
+        Tool::Line,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Tool::Select => "↖",
+            Tool::Spot => "✛ Spot",
+            Tool::Rect => "▭ Box",
+            Tool::Ellipse => "◯ Ellipse",
+            Tool::Line => "╱ Line",
+        }
+    }
+}
+
+/// Interactive measurement tools drawn on top of the thermal image.
+pub struct MeasurementTools {
+    pub tool: Tool,
+    /// Shared with the control API, which may add and remove ROIs too.
+    pub rois: Arc<Mutex<RoiSet>>,
+    /// ROI whose details (and profile, for lines) are shown in the results window.
+    pub selected: Option<u32>,
+    drag_start: Option<(f32, f32)>,
+    pending_line: Vec<(f32, f32)>,
+}
+
+/// Screen position -> continuous frame pixel coordinate.
+pub fn to_image(pos: egui::Pos2, rect: egui::Rect, size: [usize; 2]) -> (f32, f32) {
+    (
+        (pos.x - rect.min.x) / rect.width() * size[0] as f32,
+        (pos.y - rect.min.y) / rect.height() * size[1] as f32,
+    )
+}
+
+/// Continuous frame pixel coordinate -> screen position.
+pub fn to_screen(p: (f32, f32), rect: egui::Rect, size: [usize; 2]) -> egui::Pos2 {
+    egui::pos2(
+        rect.min.x + p.0 / size[0] as f32 * rect.width(),
+        rect.min.y + p.1 / size[1] as f32 * rect.height(),
+    )
+}
+
+const ROI_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 255, 160);
+const SELECTED_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 255, 0);
+
+impl MeasurementTools {
+    /// Tools on `rois`; see `ThermalEngine::rois`.
+    pub fn new(rois: Arc<Mutex<RoiSet>>) -> Self {
+        Self {
+            tool: Tool::Select,
+            rois,
+            selected: None,
+            drag_start: None,
+            pending_line: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, shape: Shape) -> u32 {
+        let id = self.rois.lock().unwrap().add(shape);
+        self.selected = Some(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.rois.lock().unwrap().remove(id);
+        if self.selected == Some(id) {
+            self.selected = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rois.lock().unwrap().clear();
+        self.pending_line.clear();
+        self.selected = None;
+    }
+
+    /// Drops the line being drawn, e.g. after the orientation changed (the
+    /// ROIs themselves are moved by `Action::SetOrientation`).
+    pub fn cancel_line(&mut self) {
+        self.pending_line.clear();
+    }
+
+    pub fn toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for tool in Tool::ALL {
+                if ui
+                    .selectable_label(self.tool == tool, tool.label())
+                    .clicked()
+                {
+                    self.tool = tool;
+                    self.pending_line.clear();
+                }
+            }
+            if ui
+                .button("🗑")
+                .on_hover_text("Remove all measurements")
+                .clicked()
+            {
+                self.clear();
+            }
+        });
+    }
+
+    /// Creates ROIs from clicks/drags on the image `response`.
+    pub fn handle_input(&mut self, response: &egui::Response, size: [usize; 2]) {
+        let rect = response.rect;
+        let pointer = response
+            .interact_pointer_pos()
+            .or(response.hover_pos())
+            .map(|p| to_image(p, rect, size));
+
+        match self.tool {
+            Tool::Select => {
+                if response.clicked()
+                    && let Some(p) = pointer
+                {
+                    self.selected = self.hit_test(p, size);
+                }
+            }
+            Tool::Spot => {
+                if response.clicked()
+                    && let Some((x, y)) = pointer
+                {
+                    self.add(Shape::Spot { x, y });
+                }
+            }
+            Tool::Rect | Tool::Ellipse => {
+                if response.drag_started() {
+                    self.drag_start = pointer;
+                }
+                if response.drag_released()
+                    && let (Some(a), Some(b)) = (self.drag_start.take(), pointer)
+                {
+                    let shape = if self.tool == Tool::Rect {
+                        Shape::Rect {
+                            x0: a.0,
+                            y0: a.1,
+                            x1: b.0,
+                            y1: b.1,
+                        }
+                    } else {
+                        Shape::Ellipse {
+                            cx: (a.0 + b.0) / 2.0,
+                            cy: (a.1 + b.1) / 2.0,
+                            rx: (b.0 - a.0).abs() / 2.0,
+                            ry: (b.1 - a.1).abs() / 2.0,
+                        }
+                    };
+                    self.add(shape);
+                }
+            }
+            Tool::Line => {
+                let finish = response.double_clicked()
+                    || response.ctx.input(|i| i.key_pressed(egui::Key::Enter));
+                if finish {
+                    if self.pending_line.len() >= 2 {
+                        let points = std::mem::take(&mut self.pending_line);
+                        self.add(Shape::Line { points });
+                    }
+                    self.pending_line.clear();
+                } else if response.clicked()
+                    && let Some(p) = pointer
+                {
+                    self.pending_line.push(p);
+                }
+                if response.ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    self.pending_line.clear();
+                }
+            }
+        }
+    }
+
+    fn hit_test(&self, p: (f32, f32), [width, height]: [usize; 2]) -> Option<u32> {
+        // Be generous with thin shapes: test the 3x3 block around the click.
+        let probe = Shape::Spot { x: p.0, y: p.1 }.pixels(width, height);
+        self.rois
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|roi| {
+                let pixels = roi.shape.pixels(width, height);
+                probe.iter().any(|q| pixels.contains(q))
+            })
+            .map(|roi| roi.id)
+    }
+
+    /// Draws ROIs, their hottest/coldest markers and max readout.
+    pub fn paint(&self, painter: &egui::Painter, rect: egui::Rect, frame: &ProcessedFrame) {
+        let size = [frame.frame.width, frame.frame.height];
+        let unit = frame.legend.unit;
+        for roi in self.rois.lock().unwrap().iter() {
+            let color = if self.selected == Some(roi.id) {
+                SELECTED_COLOR
+            } else {
+                ROI_COLOR
+            };
+            let stroke = egui::Stroke::new(1.5, color);
+            let anchor = paint_shape(painter, &roi.shape, rect, size, stroke);
+
+            let Some(stats) = roi.stats(&frame.temperature, size[0], size[1]) else {
+                continue;
+            };
+            let label = match roi.shape {
+                Shape::Spot { .. } => format!("{} {}", roi.name, unit.format(stats.mean)),
+                _ => format!("{} ▲{}", roi.name, unit.format(stats.max)),
+            };
+            painter.text(
+                anchor + egui::vec2(4.0, -4.0),
+                egui::Align2::LEFT_BOTTOM,
+                label,
+                egui::FontId::proportional(12.0),
+                color,
+            );
+            if !matches!(roi.shape, Shape::Spot { .. }) {
+                mark(painter, rect, size, stats.hottest, egui::Color32::RED);
+                mark(
+                    painter,
+                    rect,
+                    size,
+                    stats.coldest,
+                    egui::Color32::LIGHT_BLUE,
+                );
+            }
+        }
+
+        // Line being drawn
+        if !self.pending_line.is_empty() {
+            let points = self
+                .pending_line
+                .iter()
+                .map(|&p| to_screen(p, rect, size))
+                .collect::<Vec<_>>();
+            painter.add(egui::Shape::line(
+                points,
+                egui::Stroke::new(1.0, SELECTED_COLOR),
+            ));
+        }
+    }
+
+    /// Table of ROI statistics plus the live profile of the selected line.
+    pub fn results_window(&mut self, ctx: &egui::Context, frame: &ProcessedFrame) {
+        let rois = self.rois.lock().unwrap().clone();
+        if rois.is_empty() {
+            return;
+        }
+        if self.selected.is_some_and(|id| rois.get(id).is_none()) {
+            // Removed through the control API.
+            self.selected = None;
+        }
+        let (w, h) = (frame.frame.width, frame.frame.height);
+        let mut remove = None;
+        egui::Window::new("Measurements")
+            .default_pos(egui::pos2(10.0, 40.0))
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("roi_stats").striped(true).show(ui, |ui| {
+                    for header in ["", "Min", "Max", "Mean", "σ", "Hot @", "Cold @", ""] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for roi in rois.iter() {
+                        if ui
+                            .selectable_label(self.selected == Some(roi.id), &roi.name)
+                            .clicked()
+                        {
+                            self.selected = Some(roi.id);
+                        }
+                        match roi.stats(&frame.temperature, w, h) {
+                            Some(s) => stats_row(ui, &s, frame.legend.unit),
+                            None => {
+                                for _ in 0..6 {
+                                    ui.label("–");
+                                }
+                            }
+                        }
+                        if ui.small_button("✖").clicked() {
+                            remove = Some(roi.id);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                let selected_line = rois
+                    .iter()
+                    .find(|r| Some(r.id) == self.selected && matches!(r.shape, Shape::Line { .. }));
+                if let Some(roi) = selected_line {
+                    ui.separator();
+                    ui.label(format!("Profile: {}", roi.name));
+                    profile_plot(
+                        ui,
+                        &roi.profile(&frame.temperature, w, h),
+                        frame.legend.unit,
+                    );
+                }
+            });
+        if let Some(id) = remove {
+            self.remove(id);
+        }
+    }
+}
+
+fn stats_row(ui: &mut egui::Ui, s: &RoiStats, unit: TemperatureUnit) {
+    ui.label(unit.format(s.min));
+    ui.label(unit.format(s.max));
+    ui.label(unit.format(s.mean));
+    ui.label(format!("{:.2}", unit.delta_from_celsius(s.std_dev)));
+    ui.label(format!("{},{}", s.hottest.0, s.hottest.1));
+    ui.label(format!("{},{}", s.coldest.0, s.coldest.1));
+}
+
+/// Draws the outline of a shape and returns a good spot for its label.
+fn paint_shape(
+    painter: &egui::Painter,
+    shape: &Shape,
+    rect: egui::Rect,
+    size: [usize; 2],
+    stroke: egui::Stroke,
+) -> egui::Pos2 {
+    let sx = rect.width() / size[0] as f32;
+    let sy = rect.height() / size[1] as f32;
+    match shape {
+        Shape::Spot { x, y } => {
+            let c = to_screen((*x, *y), rect, size);
+            painter.line_segment([c - egui::vec2(8.0, 0.0), c + egui::vec2(8.0, 0.0)], stroke);
+            painter.line_segment([c - egui::vec2(0.0, 8.0), c + egui::vec2(0.0, 8.0)], stroke);
+            painter.circle_stroke(c, 1.5 * sx.max(sy), stroke);
+            c
+        }
+        Shape::Rect { x0, y0, x1, y1 } => {
+            let r = egui::Rect::from_two_pos(
+                to_screen((*x0, *y0), rect, size),
+                to_screen((*x1, *y1), rect, size),
+            );
+            painter.rect_stroke(r, 0.0, stroke);
+            r.left_top()
+        }
+        Shape::Ellipse { cx, cy, rx, ry } => {
+            let c = to_screen((*cx, *cy), rect, size);
+            let points = (0..64)
+                .map(|i| {
+                    let a = i as f32 / 64.0 * std::f32::consts::TAU;
+                    c + egui::vec2(a.cos() * rx * sx, a.sin() * ry * sy)
+                })
+                .collect();
+            painter.add(egui::Shape::closed_line(points, stroke));
+            c - egui::vec2(rx * sx, ry * sy)
+        }
+        Shape::Line { points } => {
+            let screen: Vec<_> = points.iter().map(|&p| to_screen(p, rect, size)).collect();
+            let anchor = screen.first().copied().unwrap_or(rect.min);
+            painter.add(egui::Shape::line(screen, stroke));
+            anchor
+        }
+    }
+}
+
+fn mark(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    size: [usize; 2],
+    at: (usize, usize),
+    color: egui::Color32,
+) {
+    let c = to_screen((at.0 as f32 + 0.5, at.1 as f32 + 0.5), rect, size);
+    painter.circle_stroke(c, 3.0, egui::Stroke::new(1.5, color));
+}
+
+fn profile_plot(ui: &mut egui::Ui, profile: &[f32], unit: TemperatureUnit) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(320.0, 120.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_gray(20));
+    if profile.len() < 2 {
+        return;
+    }
+    let (lo, hi) = profile
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &t| (lo.min(t), hi.max(t)));
+    let span = (hi - lo).max(0.1);
+    let plot = rect.shrink(4.0);
+    let points = profile
+        .iter()
+        .enumerate()
+        .map(|(i, &t)| {
+            egui::pos2(
+                plot.left() + i as f32 / (profile.len() - 1) as f32 * plot.width(),
+                plot.bottom() - (t - lo) / span * plot.height(),
+            )
+        })
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, ROI_COLOR)));
+    let font = egui::FontId::proportional(10.0);
+    painter.text(
+        plot.left_top(),
+        egui::Align2::LEFT_TOP,
+        unit.format(hi),
+        font.clone(),
+        egui::Color32::GRAY,
+    );
+    painter.text(
+        plot.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        unit.format(lo),
+        font,
+        egui::Color32::GRAY,
+    );
+}
@@ -0,0 +1,306 @@
+// Upscaling of the colourised image.
+//
+// Runs in the engine thread after colour mapping, so the UI only uploads a
+// ready-made texture, and exports get the same pixels the user sees.
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Upscaler {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos3,
+    /// Interpolates along edges instead of across them. Works in 2x steps;
+    /// factors that are not a power of two finish with a bicubic pass.
+    EdgeDirected,
+}
+
+impl Upscaler {
+    pub const ALL: [Upscaler; 5] = [
+        Upscaler::Nearest,
+        Upscaler::Bilinear,
+        Upscaler::Bicubic,
+        Upscaler::Lanczos3,
+        Upscaler::EdgeDirected,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Upscaler::Nearest => "Nearest",
+            Upscaler::Bilinear => "Bilinear",
+            Upscaler::Bicubic => "Bicubic",
+            Upscaler::Lanczos3 => "Lanczos",
+            Upscaler::EdgeDirected => "Edge-directed",
+        }
+    }
+
+    pub fn upscale(self, image: &egui::ColorImage, factor: usize) -> egui::ColorImage {
+        if factor <= 1 {
+            return image.clone();
+        }
+        match self {
+            Upscaler::Nearest => nearest(image, factor),
+            Upscaler::Bilinear => resample(image, factor, 1.0, triangle),
+            Upscaler::Bicubic => resample(image, factor, 2.0, catmull_rom),
+            Upscaler::Lanczos3 => resample(image, factor, 3.0, lanczos3),
+            Upscaler::EdgeDirected => {
+                let mut out = image.clone();
+                let mut remaining = factor;
+                while remaining.is_multiple_of(2) {
+                    out = edge_directed_2x(&out);
+                    remaining /= 2;
+                }
+                if remaining > 1 {
+                    out = resample(&out, remaining, 2.0, catmull_rom);
+                }
+                out
+            }
+        }
+    }
+}
+
+fn nearest(image: &egui::ColorImage, factor: usize) -> egui::ColorImage {
+    let [w, h] = image.size;
+    let mut out = egui::ColorImage::new([w * factor, h * factor], egui::Color32::BLACK);
+    for y in 0..h * factor {
+        for x in 0..w * factor {
+            out.pixels[y * w * factor + x] = image.pixels[(y / factor) * w + x / factor];
+        }
+    }
+    out
+}
+
+fn triangle(x: f32) -> f32 {
+    (1.0 - x.abs()).max(0.0)
+}
+
+fn catmull_rom(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        1.5 * x * x * x - 2.5 * x * x + 1.0
+    } else if x < 2.0 {
+        -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+    } else {
+        0.0
+    }
+}
+
+fn lanczos3(x: f32) -> f32 {
+    let x = x.abs();
+    if x < 1e-6 {
+        1.0
+    } else if x < 3.0 {
+        let px = std::f32::consts::PI * x;
+        3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+    } else {
+        0.0
+    }
+}
+
+/// Precomputed taps for one output coordinate: (first source index, weights).
+fn taps(
+    src_len: usize,
+    factor: usize,
+    support: f32,
+    kernel: fn(f32) -> f32,
+) -> Vec<(isize, Vec<f32>)> {
+    let support_taps = support.ceil() as isize;
+    (0..src_len * factor)
+        .map(|o| {
+            let center = (o as f32 + 0.5) / factor as f32 - 0.5;
+            let first = center.floor() as isize - support_taps + 1;
+            let mut weights: Vec<f32> = (0..2 * support_taps)
+                .map(|k| kernel(center - (first + k) as f32))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            weights.iter_mut().for_each(|w| *w /= sum);
+            (first, weights)
+        })
+        .collect()
+}
+
+/// Separable resampling in two passes (horizontal then vertical) on RGB.
+fn resample(
+    image: &egui::ColorImage,
+    factor: usize,
+    support: f32,
+    kernel: fn(f32) -> f32,
+) -> egui::ColorImage {
+    let [w, h] = image.size;
+    let (ow, oh) = (w * factor, h * factor);
+    let xt = taps(w, factor, support, kernel);
+    let yt = taps(h, factor, support, kernel);
+    let clamp = |i: isize, len: usize| i.clamp(0, len as isize - 1) as usize;
+
+    let mut horizontal = vec![[0f32; 3]; ow * h];
+    for y in 0..h {
+        for (ox, (first, weights)) in xt.iter().enumerate() {
+            let mut acc = [0f32; 3];
+            for (k, &wt) in weights.iter().enumerate() {
+                let p = image.pixels[y * w + clamp(first + k as isize, w)];
+                acc[0] += wt * p.r() as f32;
+                acc[1] += wt * p.g() as f32;
+                acc[2] += wt * p.b() as f32;
+            }
+            horizontal[y * ow + ox] = acc;
+        }
+    }
+
+    let mut out = egui::ColorImage::new([ow, oh], egui::Color32::BLACK);
+    for (oy, (first, weights)) in yt.iter().enumerate() {
+        for ox in 0..ow {
+            let mut acc = [0f32; 3];
+            for (k, &wt) in weights.iter().enumerate() {
+                let p = horizontal[clamp(first + k as isize, h) * ow + ox];
+                acc[0] += wt * p[0];
+                acc[1] += wt * p[1];
+                acc[2] += wt * p[2];
+            }
+            let c = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+            out.pixels[oy * ow + ox] = egui::Color32::from_rgb(c(acc[0]), c(acc[1]), c(acc[2]));
+        }
+    }
+    out
+}
+
+fn luma(p: egui::Color32) -> i32 {
+    (p.r() as i32 * 299 + p.g() as i32 * 587 + p.b() as i32 * 114) / 1000
+}
+
+fn average(a: egui::Color32, b: egui::Color32) -> egui::Color32 {
+    egui::Color32::from_rgb(
+        ((a.r() as u16 + b.r() as u16) / 2) as u8,
+        ((a.g() as u16 + b.g() as u16) / 2) as u8,
+        ((a.b() as u16 + b.b() as u16) / 2) as u8,
+    )
+}
+
+/// Picks the pair (a/b or c/d) with the smaller luminance difference, i.e. the
+/// direction that runs along the edge, and averages it. Ties average all four.
+fn directional(
+    a: egui::Color32,
+    b: egui::Color32,
+    c: egui::Color32,
+    d: egui::Color32,
+) -> egui::Color32 {
+    let g1 = (luma(a) - luma(b)).abs();
+    let g2 = (luma(c) - luma(d)).abs();
+    if g1 < g2 {
+        average(a, b)
+    } else if g2 < g1 {
+        average(c, d)
+    } else {
+        average(average(a, b), average(c, d))
+    }
+}
+
+/// One 2x step of edge-directed interpolation: original pixels land on even
+/// coordinates, diagonal gaps are filled first, then the remaining ones from
+/// their four axis neighbours.
+fn edge_directed_2x(image: &egui::ColorImage) -> egui::ColorImage {
+    let [w, h] = image.size;
+    let (ow, oh) = (w * 2, h * 2);
+    let mut out = egui::ColorImage::new([ow, oh], egui::Color32::BLACK);
+    let src = |x: usize, y: usize| image.pixels[y.min(h - 1) * w + x.min(w - 1)];
+
+    for y in 0..h {
+        for x in 0..w {
+            out.pixels[2 * y * ow + 2 * x] = src(x, y);
+            // Diagonal position between four originals.
+            out.pixels[(2 * y + 1) * ow + 2 * x + 1] =
+                directional(src(x, y), src(x + 1, y + 1), src(x + 1, y), src(x, y + 1));
+        }
+    }
+
+    // Mirrored at the borders: clamping would read the gap being filled.
+    let mirror = |i: isize, len: usize| match i {
+        ..0 => 1,
+        _ if i as usize >= len => len - 2,
+        _ => i as usize,
+    };
+    let get =
+        |out: &egui::ColorImage, x: isize, y: isize| out.pixels[mirror(y, oh) * ow + mirror(x, ow)];
+    for y in 0..oh as isize {
+        for x in 0..ow as isize {
+            if (x + y) % 2 == 1 {
+                let p = directional(
+                    get(&out, x - 1, y),
+                    get(&out, x + 1, y),
+                    get(&out, x, y - 1),
+                    get(&out, x, y + 1),
+                );
+                out.pixels[y as usize * ow + x as usize] = p;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10x4 image, black on the left, white from column 5 on.
+    fn edge() -> egui::ColorImage {
+        let mut image = egui::ColorImage::new([10, 4], egui::Color32::BLACK);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            if i % 10 >= 5 {
+                *pixel = egui::Color32::WHITE;
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn output_size_and_flat_colour() {
+        let grey = egui::ColorImage::new([7, 3], egui::Color32::from_gray(90));
+        for upscaler in Upscaler::ALL {
+            for factor in [2, 3, 4] {
+                let out = upscaler.upscale(&grey, factor);
+                assert_eq!(out.size, [7 * factor, 3 * factor], "{}", upscaler.name());
+                assert!(
+                    out.pixels.iter().all(|&p| p == grey.pixels[0]),
+                    "{} x{}",
+                    upscaler.name(),
+                    factor
+                );
+            }
+            assert_eq!(upscaler.upscale(&grey, 1), grey);
+        }
+    }
+
+    #[test]
+    fn edge_pixels_keep_their_colour() {
+        let image = edge();
+        for upscaler in Upscaler::ALL {
+            let out = upscaler.upscale(&image, 4);
+            let [w, h] = out.size;
+            for y in [0, h / 2, h - 1] {
+                // Corners and borders come from the clamped edge, not from black.
+                assert_eq!(
+                    out.pixels[y * w],
+                    egui::Color32::BLACK,
+                    "{}",
+                    upscaler.name()
+                );
+                assert_eq!(
+                    out.pixels[y * w + w - 1],
+                    egui::Color32::WHITE,
+                    "{}",
+                    upscaler.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_repeats_pixels() {
+        let out = Upscaler::Nearest.upscale(&edge(), 3);
+        let w = out.size[0];
+        assert_eq!(out.pixels[14], egui::Color32::BLACK);
+        assert_eq!(out.pixels[15], egui::Color32::WHITE);
+        assert_eq!(out.pixels[5 * w + 15], egui::Color32::WHITE);
+    }
+}
@@ -1,7 +1,8 @@
+use crate::queue::{BufferPool, Disconnected, QueueSender};
 use libloading::{Library, Symbol};
-use std::ffi::{c_char, c_void};
-use std::path::PathBuf;
-use std::sync::mpsc::Sender;
+use serde::{Deserialize, Serialize};
+use std::ffi::c_void;
+use std::time::Instant;
 
 type UvcInitFn = unsafe extern "C" fn(ctx: *mut *mut c_void, usb_ctx: *mut c_void) -> i32;
 type UvcFindDeviceFn = unsafe extern "C" fn(
@@ -63,6 +64,46 @@ pub struct UvcFrame {
     // There are more fields, but we only need these for now
 }
 
+/// One frame as handed over by the libuvc callback.
+pub struct RawFrame {
+    pub data: Vec<u8>,
+    pub sequence: u32,
+    /// When the callback ran.
+    pub captured: Instant,
+}
+
+/// Resolution and frame rate requested from the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamMode {
+    pub width: usize,
+    pub height: usize,
+    pub fps: u32,
+}
+
+impl Default for StreamMode {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 192,
+            fps: 25,
+        }
+    }
+}
+
+impl StreamMode {
+    /// Bytes of one Y16 frame.
+    pub fn frame_bytes(&self) -> usize {
+        self.width * self.height * 2
+    }
+}
+
+/// What the libuvc callback gets as its user pointer.
+struct CallbackContext {
+    tx: QueueSender<RawFrame>,
+    pool: BufferPool,
+}
+
 pub struct UvcAdapter {
     lib: Library,
     ctx: *mut c_void,
@@ -108,7 +149,14 @@ impl UvcAdapter {
         Ok(())
     }
 
-    pub fn start_streaming(&self, tx: Sender<Vec<u8>>) -> anyhow::Result<()> {
+    /// Starts the stream. Frames are copied into buffers from `pool` and
+    /// queued on `tx`; buffers of frames dropped by the queue go back to the pool.
+    pub fn start_streaming(
+        &self,
+        tx: QueueSender<RawFrame>,
+        pool: BufferPool,
+        mode: StreamMode,
+    ) -> anyhow::Result<()> {
         unsafe {
             let uvc_get_stream_ctrl: Symbol<UvcGetStreamCtrlFormatSizeFn> =
                 self.lib.get(b"uvc_get_stream_ctrl_format_size")?;
@@ -118,14 +166,15 @@ impl UvcAdapter {
             let mut ctrl = UvcStreamCtrl::default();
             // Y16 is often format 4 in libuvc for these cameras, or we use UVC_FRAME_FORMAT_Y16
             // Based on generic libuvc: enum uvc_frame_format { ... UVC_FRAME_FORMAT_Y16 = 4 }
-            let res = uvc_get_stream_ctrl(self.devh, &mut ctrl, 4, 256, 192, 25);
+            let (width, height, fps) = (mode.width as i32, mode.height as i32, mode.fps as i32);
+            let res = uvc_get_stream_ctrl(self.devh, &mut ctrl, 4, width, height, fps);
             if res < 0 {
                 // Fallback to format 1 (YUY2) if Y16 fails
-                uvc_get_stream_ctrl(self.devh, &mut ctrl, 1, 256, 192, 25);
+                uvc_get_stream_ctrl(self.devh, &mut ctrl, 1, width, height, fps);
             }
 
-            let box_tx = Box::new(tx);
-            let user_ptr = Box::into_raw(box_tx) as *mut c_void;
+            let context = Box::new(CallbackContext { tx, pool });
+            let user_ptr = Box::into_raw(context) as *mut c_void;
 
             let res = uvc_start_streaming(self.devh, &ctrl, frame_callback, user_ptr, 0);
             if res < 0 {
@@ -141,15 +190,24 @@ unsafe extern "C" fn frame_callback(frame: *mut UvcFrame, user_ptr: *mut c_void)
         return;
     }
 
+    let captured = Instant::now();
     let frame = unsafe { &*frame };
-    let tx = unsafe { &*(user_ptr as *const Sender<Vec<u8>>) };
+    let context = unsafe { &*(user_ptr as *const CallbackContext) };
 
     if frame.data.is_null() {
         return;
     }
 
     let data = unsafe { std::slice::from_raw_parts(frame.data, frame.data_bytes) };
-    let _ = tx.send(data.to_vec());
+    let raw = RawFrame {
+        data: context.pool.take_copy(data),
+        sequence: frame.sequence,
+        captured,
+    };
+    match context.tx.push(raw) {
+        Ok(Some(dropped)) | Err(Disconnected(dropped)) => context.pool.give(dropped.data),
+        Ok(None) => {}
+    }
 }
 
 impl Drop for UvcAdapter {
@@ -163,10 +221,10 @@ impl Drop for UvcAdapter {
                     uvc_close(self.devh);
                 }
             }
-            if !self.ctx.is_null() {
-                if let Ok(uvc_exit) = self.lib.get::<UvcExitFn>(b"uvc_exit") {
-                    uvc_exit(self.ctx);
-                }
+            if !self.ctx.is_null()
+                && let Ok(uvc_exit) = self.lib.get::<UvcExitFn>(b"uvc_exit")
+            {
+                uvc_exit(self.ctx);
             }
         }
     }
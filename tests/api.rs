@@ -0,0 +1,382 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+use thermoscope_app::ThermalEngine;
+use thermoscope_app::control::{Action, Outcome, RecordingKind};
+use thermoscope_app::filters::FilterConfig;
+use thermoscope_app::measure::Shape;
+use thermoscope_app::palette::Palette;
+use thermoscope_app::queue::{self, QueueConfig};
+use thermoscope_app::server::StreamServer;
+
+/// Status code and JSON body of one request.
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: test\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut data = String::new();
+    stream.read_to_string(&mut data).unwrap();
+    let (head, body) = data.split_once("\r\n\r\n").unwrap();
+    let status = head[9..12].parse().unwrap();
+    let json = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_str(body).unwrap()
+    };
+    (status, json)
+}
+
+#[test]
+fn controls_the_engine_over_http() {
+    let (tx, rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    engine.start_synthetic();
+    let server =
+        StreamServer::start("127.0.0.1:0", engine.bus(), Some(engine.controller("test"))).unwrap();
+    let addr = server.addr();
+
+    let (status, _) = request(addr, "PUT", "/api/palette", r#"{"palette": "BlackHot"}"#);
+    assert_eq!(status, 200);
+    assert_eq!(engine.display().lock().unwrap().palette, Palette::BlackHot);
+
+    let (status, _) = request(addr, "PUT", "/api/calibration", r#"{"emissivity": 0.8}"#);
+    assert_eq!(status, 200);
+    let calibration = *engine.calibration().lock().unwrap();
+    assert_eq!(
+        (calibration.emissivity, calibration.reflected_temp),
+        (0.8, 20.0)
+    );
+    let (status, error) = request(addr, "PUT", "/api/calibration", r#"{"emissivity": 0}"#);
+    assert_eq!(status, 400, "{}", error);
+
+    let (status, _) = request(addr, "POST", "/api/ffc", "");
+    assert_eq!(status, 200);
+    let (_, state) = request(addr, "GET", "/api/state", "");
+    assert!(
+        state["nuc"]["calibrating"].as_bool().unwrap()
+            || state["nuc"]["calibrated"].as_bool().unwrap()
+    );
+    assert_eq!(state["palette"], "BlackHot");
+
+    // The whole frame, so the reading covers the synthetic 20-24 °C
+    // background and the warm spot.
+    let roi =
+        r#"{"name": "All", "shape": {"type": "rect", "x0": 0, "y0": 0, "x1": 256, "y1": 192}}"#;
+    let (status, added) = request(addr, "POST", "/api/rois", roi);
+    assert_eq!(status, 201);
+    let id = added["id"].as_u64().unwrap();
+    let (status, reading) = request(addr, "GET", &format!("/api/rois/{}", id), "");
+    assert_eq!(status, 200);
+    assert_eq!(reading["name"], "All");
+    assert_eq!(reading["stats"]["count"], 256 * 192);
+    assert!(
+        reading["stats"]["max"].as_f64().unwrap() > 30.0,
+        "{}",
+        reading
+    );
+    assert_eq!(engine.rois().lock().unwrap().len(), 1);
+
+    let (status, _) = request(addr, "DELETE", &format!("/api/rois/{}", id), "");
+    assert_eq!(status, 200);
+    let (status, _) = request(addr, "DELETE", &format!("/api/rois/{}", id), "");
+    assert_eq!(status, 404);
+    let (status, _) = request(addr, "PATCH", "/api/state", "");
+    assert_eq!(status, 405);
+
+    let (status, openapi) = request(addr, "GET", "/api/openapi.json", "");
+    assert_eq!(status, 200);
+    assert!(openapi["paths"]["/api/rois/{id}"]["get"].is_object());
+
+    server.stop();
+    drop(rx);
+}
+
+#[test]
+fn control_is_refused_without_a_controller() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let server = StreamServer::start("127.0.0.1:0", engine.bus(), None).unwrap();
+    let (status, _) = request(
+        server.addr(),
+        "PUT",
+        "/api/palette",
+        r#"{"palette": "WhiteHot"}"#,
+    );
+    assert_eq!(status, 403);
+    assert_eq!(engine.display().lock().unwrap().palette, Palette::Ironbow);
+    let (status, _) = request(server.addr(), "GET", "/api/openapi.json", "");
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn actions_are_tagged_json() {
+    let action: Action = serde_json::from_str(
+        r#"{"action": "add_roi", "shape": {"type": "line", "points": [[0, 0], [10, 5]]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        action,
+        Action::AddRoi {
+            name: None,
+            shape: Shape::Line {
+                points: vec![(0.0, 0.0), (10.0, 5.0)]
+            }
+        }
+    );
+
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let control = engine.controller("test");
+    let short = Action::AddRoi {
+        name: None,
+        shape: Shape::Line {
+            points: vec![(1.0, 1.0)],
+        },
+    };
+    assert!(control.execute(short).is_err());
+    assert_eq!(control.execute(action).unwrap(), Outcome::Added { id: 1 });
+    assert_eq!(control.state().rois[0].name, "Line1");
+
+    let disable: Action =
+        serde_json::from_str(r#"{"action": "enable_stage", "stage": "denoise", "enabled": false}"#)
+            .unwrap();
+    control.execute(disable).unwrap();
+    control
+        .execute(Action::MoveStage { from: 0, to: 1 })
+        .unwrap();
+    let denoise: Action = serde_json::from_str(
+        r#"{"action": "set_denoise", "filters": [{"filter": "temporal", "alpha": 0.5, "motion_threshold": 20}], "use_for_measurement": true}"#,
+    )
+    .unwrap();
+    control.execute(denoise).unwrap();
+    let state = control.state().denoise;
+    assert!(state.use_for_measurement);
+    assert_eq!(state.filters[0].name(), "temporal");
+    let huge = Action::SetDenoise {
+        filters: Some(vec![FilterConfig::Guided {
+            radius: 100,
+            eps: 1.0,
+        }]),
+        use_for_measurement: None,
+    };
+    assert!(control.execute(huge).is_err());
+
+    let stages = control.state().stages;
+    assert_eq!(stages[1].name, "bad_pixel");
+    assert!(stages.iter().any(|s| s.name == "denoise" && !s.enabled));
+    let missing = Action::EnableStage {
+        stage: "missing".to_string(),
+        enabled: true,
+    };
+    assert!(control.execute(missing).is_err());
+    assert!(
+        control
+            .execute(Action::MoveStage { from: 0, to: 99 })
+            .is_err()
+    );
+}
+
+#[test]
+fn rois_far_outside_the_frame_are_refused() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    engine.start_synthetic();
+    let server =
+        StreamServer::start("127.0.0.1:0", engine.bus(), Some(engine.controller("test"))).unwrap();
+    let addr = server.addr();
+    // A frame has been published, so ROIs are checked against 256x192.
+    let (status, _) = request(addr, "GET", "/api/rois", "");
+    assert_eq!(status, 200);
+
+    let huge = [
+        r#"{"shape": {"type": "ellipse", "cx": 10, "cy": 10, "rx": 1e7, "ry": 1e7}}"#.to_string(),
+        r#"{"shape": {"type": "line", "points": [[-1e9, 5], [1e9, 5]]}}"#.to_string(),
+        r#"{"shape": {"type": "rect", "x0": 0, "y0": 0, "x1": 1000, "y1": 10}}"#.to_string(),
+        format!(
+            r#"{{"shape": {{"type": "line", "points": {}}}}}"#,
+            serde_json::to_string(&vec![[10.0, 10.0]; 1000]).unwrap()
+        ),
+    ];
+    for body in &huge {
+        let (status, error) = request(addr, "POST", "/api/rois", body);
+        assert_eq!(status, 400, "{}: {}", body, error);
+    }
+    assert!(engine.rois().lock().unwrap().is_empty());
+
+    // Partly outside is fine.
+    let edge = r#"{"shape": {"type": "rect", "x0": -20, "y0": -20, "x1": 20, "y1": 20}}"#;
+    let (status, added) = request(addr, "POST", "/api/rois", edge);
+    assert_eq!(status, 201);
+    let (_, reading) = request(addr, "GET", &format!("/api/rois/{}", added["id"]), "");
+    assert_eq!(reading["stats"]["count"], 20 * 20);
+    server.stop();
+}
+
+#[test]
+fn recordings_stay_in_the_recordings_directory() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let control = engine.controller("test");
+
+    for name in ["..", "/", " . "] {
+        let start = Action::StartRecording {
+            kind: RecordingKind::Tseq,
+            name: Some(name.to_string()),
+            fps: None,
+            burn_overlays: None,
+        };
+        assert!(control.execute(start).is_err(), "{:?}", name);
+    }
+
+    let start: Action = serde_json::from_str(
+        r#"{"action": "start_recording", "name": "../../api-test\\escape.tseq"}"#,
+    )
+    .unwrap();
+    let Outcome::Started { path } = control.execute(start).unwrap() else {
+        panic!("not started");
+    };
+    assert_eq!(path, Path::new("recordings/....api-testescape.tseq"));
+    control
+        .execute(Action::StopRecording { kind: None })
+        .unwrap();
+    control.wait().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn alarms_over_http() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let server =
+        StreamServer::start("127.0.0.1:0", engine.bus(), Some(engine.controller("test"))).unwrap();
+    let addr = server.addr();
+
+    let rule = r#"{"name": "Hot", "condition": {"Above": 50}, "actions": ["Flash", {"EventLog": "/etc/alarms.csv"}]}"#;
+    let (status, added) = request(addr, "POST", "/api/alarms", rule);
+    assert_eq!(status, 201, "{}", added);
+    let (status, list) = request(addr, "GET", "/api/alarms", "");
+    assert_eq!(status, 200);
+    assert_eq!(list[0]["name"], "Hot");
+    assert_eq!(list[0]["active"], false);
+    assert_eq!(
+        list[0]["actions"][1]["EventLog"],
+        "recordings/etcalarms.csv"
+    );
+
+    let command =
+        r#"{"name": "Shell", "condition": {"Above": 50}, "actions": [{"RunCommand": "rm -rf /"}]}"#;
+    let (status, _) = request(addr, "POST", "/api/alarms", command);
+    assert_eq!(status, 400);
+    let missing = r#"{"source": {"Region": 7}, "condition": {"Below": 0}}"#;
+    let (status, _) = request(addr, "POST", "/api/alarms", missing);
+    assert_eq!(status, 404);
+    assert_eq!(engine.alarms().lock().unwrap().rules.len(), 1);
+
+    let path = format!("/api/alarms/{}", added["id"]);
+    let (status, _) = request(addr, "DELETE", &path, "");
+    assert_eq!(status, 200);
+    let (status, _) = request(addr, "DELETE", &path, "");
+    assert_eq!(status, 404);
+    assert!(engine.alarms().lock().unwrap().rules.is_empty());
+    server.stop();
+}
+
+#[test]
+fn rois_stay_on_the_scene_when_rotating() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    engine.start_synthetic();
+    let server =
+        StreamServer::start("127.0.0.1:0", engine.bus(), Some(engine.controller("test"))).unwrap();
+    let addr = server.addr();
+
+    // The synthetic background rises from 20 °C at the top to 24 °C at the
+    // bottom; this corner is far from the moving spot.
+    let roi = r#"{"shape": {"type": "rect", "x0": 0, "y0": 0, "x1": 20, "y1": 10}}"#;
+    let (_, added) = request(addr, "POST", "/api/rois", roi);
+    let path = format!("/api/rois/{}", added["id"]);
+    let (_, before) = request(addr, "GET", &path, "");
+
+    let rotate = r#"{"action": "set_orientation", "orientation": {"mirror": false, "flip": false, "rotation": "Cw90"}}"#;
+    let (status, _) = request(addr, "POST", "/api/actions", rotate);
+    assert_eq!(status, 200);
+    // A frame may still have been in flight.
+    request(addr, "GET", &path, "");
+    let (_, after) = request(addr, "GET", &path, "");
+
+    assert_eq!(after["stats"]["count"], before["stats"]["count"]);
+    let mean = |reading: &serde_json::Value| reading["stats"]["mean"].as_f64().unwrap();
+    assert!(
+        (mean(&after) - mean(&before)).abs() < 0.3,
+        "{} vs {}",
+        before,
+        after
+    );
+    // The corner is now on the right-hand side.
+    assert_eq!(
+        after["shape"],
+        serde_json::json!({"type": "rect", "x0": 192.0, "y0": 0.0, "x1": 182.0, "y1": 20.0})
+    );
+    server.stop();
+}
+
+/// Status line and headers of a raw request.
+fn head(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    // The server may close before it has read everything.
+    let _ = stream.write_all(request);
+    let mut data = String::new();
+    let _ = stream.read_to_string(&mut data);
+    data.split("\r\n\r\n").next().unwrap().to_string()
+}
+
+#[test]
+fn control_is_same_origin_json_only() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let server =
+        StreamServer::start("127.0.0.1:0", engine.bus(), Some(engine.controller("test"))).unwrap();
+    let addr = server.addr();
+
+    // What a cross-site form can send.
+    let form = head(
+        addr,
+        b"POST /api/ffc HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 0\r\n\r\n",
+    );
+    assert!(form.starts_with("HTTP/1.1 415"), "{}", form);
+    let bare = head(
+        addr,
+        b"PUT /api/palette HTTP/1.1\r\nContent-Length: 23\r\n\r\n{\"palette\": \"BlackHot\"}",
+    );
+    assert!(bare.starts_with("HTTP/1.1 415"), "{}", bare);
+    assert_eq!(engine.display().lock().unwrap().palette, Palette::Ironbow);
+
+    let json = head(
+        addr,
+        b"PUT /api/palette HTTP/1.1\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 23\r\n\r\n{\"palette\": \"BlackHot\"}",
+    );
+    assert!(json.starts_with("HTTP/1.1 200"), "{}", json);
+    assert!(!json.contains("Access-Control"), "{}", json);
+    let preflight = head(addr, b"OPTIONS /api/palette HTTP/1.1\r\n\r\n");
+    assert!(!preflight.contains("Access-Control"), "{}", preflight);
+
+    // An endless request line is cut off rather than buffered.
+    let mut long = b"GET /".to_vec();
+    long.resize(1 << 20, b'a');
+    assert_eq!(head(addr, &long), "");
+    server.stop();
+}
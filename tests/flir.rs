@@ -0,0 +1,131 @@
+use eframe::egui;
+use std::path::PathBuf;
+use thermoscope_app::calibration::Calibration;
+use thermoscope_app::flir;
+use thermoscope_app::frame::ThermalFrame;
+use thermoscope_app::palette::Palette;
+use thermoscope_app::snapshot::{Snapshot, SnapshotFormat, SnapshotMetadata};
+
+/// Horizontal gradient from `min_c` to `max_c` (°C) as Kelvin * 64.
+fn gradient(width: usize, height: usize, min_c: f32, max_c: f32) -> Snapshot {
+    let calibration = Calibration {
+        emissivity: 0.95,
+        reflected_temp: 25.0,
+        ..Calibration::default()
+    };
+    let raw = (0..width * height)
+        .map(|i| {
+            let t = min_c + (max_c - min_c) * (i % width) as f32 / (width - 1) as f32;
+            ((t + 273.15) * 64.0).round() as u16
+        })
+        .collect();
+    Snapshot {
+        frame: ThermalFrame {
+            width,
+            height,
+            raw,
+            ..Default::default()
+        },
+        metadata: SnapshotMetadata {
+            timestamp: 1_700_000_000.25,
+            device: "0bda:5830".to_string(),
+            sequence: 1,
+            width,
+            height,
+            palette: Palette::default(),
+            unit: Default::default(),
+            range: [min_c, max_c],
+            range_locked: false,
+            calibration,
+        },
+    }
+}
+
+fn image(width: usize, height: usize) -> egui::ColorImage {
+    egui::ColorImage::new([width, height], egui::Color32::from_rgb(200, 80, 20))
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("thermoscope_flir_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn typical_scene_round_trips_exactly() {
+    let snapshot = gradient(160, 120, 15.0, 90.0);
+    let path = temp_path("typical.jpg");
+    flir::write(&path, &snapshot, &image(160, 120)).unwrap();
+
+    let back = flir::read(&path).unwrap();
+    assert_eq!((back.frame.width, back.frame.height), (160, 120));
+    assert_eq!(back.frame.raw, snapshot.frame.raw);
+    let calibration = back.metadata.calibration;
+    assert!((calibration.emissivity - 0.95).abs() < 1e-6);
+    assert!((calibration.reflected_temp - 25.0).abs() < 1e-3);
+    assert!(back.metadata.device.contains("0bda:5830"));
+    assert!((back.metadata.timestamp - 1_700_000_000.25).abs() < 1e-3);
+}
+
+#[test]
+fn wide_scene_stays_within_a_tenth_of_a_kelvin() {
+    let snapshot = gradient(256, 192, -20.0, 600.0);
+    let path = temp_path("wide.jpg");
+    flir::write(&path, &snapshot, &image(256, 192)).unwrap();
+
+    let back = flir::read(&path).unwrap();
+    let calibration = Calibration::default();
+    for (&a, &b) in snapshot.frame.raw.iter().zip(&back.frame.raw) {
+        let error = calibration.apparent_kelvin(a) - calibration.apparent_kelvin(b);
+        assert!(error.abs() < 0.1, "{} vs {}", a, b);
+    }
+}
+
+#[test]
+fn large_frames_span_several_app1_segments() {
+    let snapshot = gradient(640, 512, 20.0, 40.0);
+    let path = temp_path("large.jpg");
+    flir::write(&path, &snapshot, &image(640, 512)).unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    let segments = data.windows(6).filter(|w| w == b"FLIR\0\x01").count();
+    assert!(segments > 1);
+    assert_eq!(flir::read(&path).unwrap().frame.raw, snapshot.frame.raw);
+}
+
+#[test]
+fn visible_image_still_decodes() {
+    let snapshot = gradient(160, 120, 15.0, 90.0);
+    let path = temp_path("visible.jpg");
+    flir::write(&path, &snapshot, &image(160, 120)).unwrap();
+
+    let decoded = image::open(&path).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (160, 120));
+}
+
+#[test]
+fn snapshot_load_opens_radiometric_jpegs() {
+    let snapshot = gradient(160, 120, 15.0, 90.0);
+    let dir = temp_path("snapshots");
+    let path = snapshot
+        .save(&image(160, 120), &dir, SnapshotFormat::Radiometric)
+        .unwrap();
+    let raw = path.with_file_name(format!(
+        "{}_raw.png",
+        path.file_stem().unwrap().to_str().unwrap()
+    ));
+    std::fs::remove_file(raw).unwrap();
+
+    assert_eq!(Snapshot::load(&path).unwrap().frame.raw, snapshot.frame.raw);
+}
+
+#[test]
+fn plain_jpegs_are_rejected() {
+    let snapshot = gradient(32, 24, 15.0, 90.0);
+    let dir = temp_path("plain");
+    let path = snapshot
+        .save(&image(32, 24), &dir, SnapshotFormat::Jpeg)
+        .unwrap();
+
+    assert!(flir::read(&path).is_err());
+}
@@ -0,0 +1,234 @@
+use thermoscope_app::ThermalEngine;
+use thermoscope_app::alarm::{AlarmSource, Condition};
+use thermoscope_app::calibration::Calibration;
+use thermoscope_app::config::{Config, Profile};
+use thermoscope_app::filters::{FilterChain, TemporalFilter};
+use thermoscope_app::frame::ThermalFrame;
+use thermoscope_app::palette::Palette;
+use thermoscope_app::pipeline::{
+    FrameContext, Pipeline, PipelineConfig, SharedSettings, StageRegistry,
+};
+use thermoscope_app::queue::{self, QueueConfig};
+
+/// A uniform `celsius` field with one pixel 10 °C warmer.
+fn frame(width: usize, height: usize, celsius: f32, sequence: u64) -> ThermalFrame {
+    let base = ((celsius + 273.15) * 64.0) as u16;
+    let mut raw = vec![base; width * height];
+    raw[width + 1] = base + 640;
+    ThermalFrame {
+        width,
+        height,
+        sequence,
+        raw,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn disabled_denoise_leaves_no_stale_field() {
+    let settings = SharedSettings::default();
+    {
+        let mut chain = settings.filters.lock().unwrap();
+        *chain = FilterChain::new().with(TemporalFilter::default());
+        chain.use_for_measurement = true;
+    }
+    let mut pipeline = Pipeline::standard(&settings);
+
+    let mut ctx = FrameContext {
+        frame: frame(16, 12, 20.0, 0),
+        ..FrameContext::default()
+    };
+    pipeline.process(&mut ctx);
+    assert!(ctx.frame.denoised.is_some());
+
+    pipeline.set_enabled("denoise", false);
+    for (n, (width, height, celsius)) in [(8, 6, 40.0), (10, 4, 30.0)].into_iter().enumerate() {
+        // The engine hands each context the buffers of an earlier frame.
+        let mut next = FrameContext::recycle(ctx.into_processed());
+        next.frame = ThermalFrame {
+            denoised: next.frame.denoised.take(),
+            ..frame(width, height, celsius, n as u64 + 1)
+        };
+        ctx = next;
+        pipeline.process(&mut ctx);
+
+        assert!(ctx.frame.denoised.is_none());
+        assert_eq!(ctx.range, ThermalFrame::min_max(&ctx.frame.raw));
+        assert_eq!(ctx.temperature.len(), width * height);
+        assert_eq!(ctx.image.size, [width, height]);
+        let coldest = ctx
+            .temperature
+            .iter()
+            .copied()
+            .fold(f32::INFINITY, f32::min);
+        assert!(
+            (coldest - celsius).abs() < 2.0,
+            "{} vs {}",
+            coldest,
+            celsius
+        );
+    }
+}
+
+#[test]
+fn failed_profile_leaves_the_settings_alone() {
+    let settings = SharedSettings::default();
+    let calibration = Calibration {
+        emissivity: 0.8,
+        ..Calibration::default()
+    };
+    *settings.calibration.lock().unwrap() = calibration;
+    settings.display.lock().unwrap().palette = Palette::BlackHot;
+
+    // The calibration stage would apply before the unknown one fails.
+    let pipeline = PipelineConfig::from_toml(
+        r#"
+        [[stages]]
+        stage = "calibration"
+        emissivity = 0.5
+        reflected_temp = 40.0
+
+        [[stages]]
+        stage = "custom"
+        name = "missing"
+        "#,
+    )
+    .unwrap();
+    let profile = Profile {
+        palette: Some(Palette::WhiteHot),
+        pipeline: Some(pipeline),
+        ..Profile::default()
+    };
+    assert!(profile.build(&settings, &StageRegistry::new()).is_err());
+    assert_eq!(*settings.calibration.lock().unwrap(), calibration);
+    assert_eq!(settings.display.lock().unwrap().palette, Palette::BlackHot);
+}
+
+#[test]
+fn failed_pipeline_config_changes_nothing() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let settings = engine.settings();
+    let stages = engine.pipeline().lock().unwrap().stages();
+
+    // The calibration stage comes first, but is not applied either.
+    let config = PipelineConfig::from_toml(
+        r#"
+        [[stages]]
+        stage = "calibration"
+        emissivity = 0.5
+        reflected_temp = 40.0
+
+        [[stages]]
+        stage = "custom"
+        name = "missing"
+        "#,
+    )
+    .unwrap();
+    assert!(
+        engine
+            .load_pipeline(&config, &StageRegistry::new())
+            .is_err()
+    );
+    assert!(Pipeline::from_config(&config, &settings, &StageRegistry::new()).is_err());
+    assert_eq!(
+        *settings.calibration.lock().unwrap(),
+        Calibration::default()
+    );
+    assert_eq!(engine.pipeline().lock().unwrap().stages(), stages);
+
+    // Without the failing stage it applies.
+    let config = PipelineConfig {
+        stages: config.stages[..1].to_vec(),
+    };
+    engine
+        .load_pipeline(&config, &StageRegistry::new())
+        .unwrap();
+    assert_eq!(settings.calibration.lock().unwrap().emissivity, 0.5);
+}
+
+#[test]
+fn filters_that_would_divide_by_zero_are_refused() {
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let config = PipelineConfig::from_toml(
+        r#"
+        [[stages]]
+        stage = "denoise"
+        use_for_measurement = true
+
+        [[stages.filters]]
+        filter = "bilateral"
+        radius = 2
+        sigma_spatial = 1.5
+        sigma_range = 0.0
+        "#,
+    )
+    .unwrap();
+    let err = engine
+        .load_pipeline(&config, &StageRegistry::new())
+        .unwrap_err();
+    assert!(err.to_string().contains("bilateral"), "{err}");
+    assert!(
+        !engine
+            .settings()
+            .filters
+            .lock()
+            .unwrap()
+            .use_for_measurement
+    );
+}
+
+#[test]
+fn profiles_carry_alarm_rules() {
+    let mut config = Config::from_toml(
+        r#"
+        profile = "panel"
+
+        [[defaults.alarms]]
+        name = "Too hot"
+        condition = { Above = 80.0 }
+
+        [[profiles.panel.alarms]]
+        name = "Breaker"
+        source = { Region = 2 }
+        statistic = "Mean"
+        condition = { Above = 45.0 }
+        "#,
+    )
+    .unwrap();
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let registry = StageRegistry::new();
+
+    engine
+        .apply_profile(&config.active().unwrap(), &registry)
+        .unwrap();
+    {
+        let alarms = engine.alarms();
+        let alarms = alarms.lock().unwrap();
+        assert_eq!(alarms.rules.len(), 1);
+        let rule = &alarms.rules[0];
+        assert_eq!(rule.name, "Breaker");
+        assert_eq!(rule.source, AlarmSource::Region(2));
+        assert_eq!(rule.condition, Condition::Above(45.0));
+        // Unset fields take the defaults of a new rule.
+        assert!(rule.enabled);
+        assert_eq!(rule.hysteresis, 1.0);
+    }
+
+    // `[defaults]` applies under a profile without rules of its own.
+    config.profile = None;
+    engine
+        .apply_profile(&config.active().unwrap(), &registry)
+        .unwrap();
+    assert_eq!(engine.alarms().lock().unwrap().rules[0].name, "Too hot");
+
+    // And survives a save.
+    let saved = Config::from_toml(&config.to_toml().unwrap()).unwrap();
+    assert_eq!(saved.defaults.alarms, config.defaults.alarms);
+    assert_eq!(
+        saved.profiles["panel"].alarms,
+        config.profiles["panel"].alarms
+    );
+}
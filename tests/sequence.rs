@@ -0,0 +1,185 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use thermoscope_app::ThermalEngine;
+use thermoscope_app::calibration::Calibration;
+use thermoscope_app::frame::ThermalFrame;
+use thermoscope_app::queue::{self, QueueConfig};
+use thermoscope_app::sequence::{SequenceInfo, SequenceReader, SequenceWriter};
+
+/// A warm blob drifting across a 25 °C background with ±4 counts of noise.
+fn scene(width: usize, height: usize, n: usize, seed: &mut u32) -> ThermalFrame {
+    let raw = (0..width * height)
+        .map(|i| {
+            let (x, y) = ((i % width) as f32, (i / width) as f32);
+            let (cx, cy) = (20.0 + n as f32 * 2.0, height as f32 / 2.0);
+            let blob = 40.0 * (-((x - cx).powi(2) + (y - cy).powi(2)) / 200.0).exp();
+            *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (*seed >> 29) as f32 - 4.0;
+            ((25.0 + 273.15 + blob) * 64.0 + noise) as u16
+        })
+        .collect();
+    ThermalFrame {
+        width,
+        height,
+        sequence: n as u64,
+        raw,
+        ..Default::default()
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("thermoscope_tseq_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn record(name: &str, frames: &[ThermalFrame], calibrations: &[Calibration]) -> SequenceWriter {
+    let mut writer = SequenceWriter::create(&temp_path(name), SequenceInfo::new("test")).unwrap();
+    for (n, frame) in frames.iter().enumerate() {
+        let calibration = calibrations[n * calibrations.len() / frames.len()];
+        writer
+            .write(frame, 1_700_000_000.0 + n as f64 * 0.04, &calibration)
+            .unwrap();
+    }
+    writer
+}
+
+#[test]
+fn frames_round_trip_losslessly_in_any_order() {
+    let mut seed = 1;
+    let frames: Vec<_> = (0..60).map(|n| scene(256, 192, n, &mut seed)).collect();
+    let writer = record("order.tseq", &frames, &[Calibration::default()]);
+    let raw_size = writer.raw_size();
+    let size = writer.finish().unwrap();
+    assert!(raw_size > size * 2, "{} -> {} bytes", raw_size, size);
+
+    let mut reader = SequenceReader::open(&temp_path("order.tseq")).unwrap();
+    assert!(reader.is_indexed());
+    assert_eq!(reader.len(), 60);
+    for n in [59, 0, 1, 2, 33, 24, 25, 26, 12] {
+        let frame = reader.frame(n).unwrap();
+        assert_eq!(frame.sequence, n as u64);
+        assert_eq!(frame.raw, frames[n].raw, "frame {}", n);
+    }
+    assert!(reader.frame(60).is_err());
+}
+
+#[test]
+fn seeking_by_time_finds_the_frame_at_or_before() {
+    let mut seed = 2;
+    let frames: Vec<_> = (0..30).map(|n| scene(32, 24, n, &mut seed)).collect();
+    record("seek.tseq", &frames, &[Calibration::default()])
+        .finish()
+        .unwrap();
+
+    let reader = SequenceReader::open(&temp_path("seek.tseq")).unwrap();
+    assert_eq!(reader.position(Duration::ZERO), 0);
+    assert_eq!(reader.position(Duration::from_millis(500)), 12);
+    assert_eq!(reader.position(Duration::from_secs(60)), 29);
+    assert!((reader.duration().as_secs_f64() - 29.0 * 0.04).abs() < 1e-6);
+}
+
+#[test]
+fn calibration_changes_apply_from_their_frame_on() {
+    let mut seed = 3;
+    let frames: Vec<_> = (0..20).map(|n| scene(32, 24, n, &mut seed)).collect();
+    let other = Calibration {
+        emissivity: 0.9,
+        reflected_temp: 30.0,
+        ..Calibration::default()
+    };
+    record(
+        "calibration.tseq",
+        &frames,
+        &[Calibration::default(), other],
+    )
+    .finish()
+    .unwrap();
+
+    let reader = SequenceReader::open(&temp_path("calibration.tseq")).unwrap();
+    assert_eq!(reader.calibration(9), Some(Calibration::default()));
+    assert_eq!(reader.calibration(10), Some(other));
+    assert_eq!(reader.calibration(19), Some(other));
+}
+
+#[test]
+fn interrupted_recordings_are_rebuilt_by_scanning() {
+    let mut seed = 4;
+    let frames: Vec<_> = (0..40).map(|n| scene(64, 48, n, &mut seed)).collect();
+    // Dropped without `finish`: no index, like a crash mid-recording.
+    drop(record(
+        "interrupted.tseq",
+        &frames,
+        &[Calibration::default()],
+    ));
+    let path = temp_path("interrupted.tseq");
+    let size = std::fs::metadata(&path).unwrap().len();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(size - 10).unwrap();
+
+    let mut reader = SequenceReader::open(&path).unwrap();
+    assert!(!reader.is_indexed());
+    assert_eq!(reader.len(), 39);
+    assert_eq!(reader.frame(38).unwrap().raw, frames[38].raw);
+}
+
+#[test]
+fn size_changes_start_a_new_key_frame() {
+    let mut seed = 5;
+    let frames = vec![
+        scene(32, 24, 0, &mut seed),
+        scene(32, 24, 1, &mut seed),
+        scene(24, 32, 2, &mut seed),
+        scene(24, 32, 3, &mut seed),
+    ];
+    record("rotate.tseq", &frames, &[Calibration::default()])
+        .finish()
+        .unwrap();
+
+    let mut reader = SequenceReader::open(&temp_path("rotate.tseq")).unwrap();
+    for (n, expected) in frames.iter().enumerate() {
+        let frame = reader.frame(n).unwrap();
+        assert_eq!(
+            (frame.width, frame.height),
+            (expected.width, expected.height)
+        );
+        assert_eq!(frame.raw, expected.raw);
+    }
+}
+
+#[test]
+fn replacing_a_playback_restores_the_live_calibration() {
+    let mut seed = 6;
+    let frames: Vec<_> = (0..10).map(|n| scene(32, 24, n, &mut seed)).collect();
+    let recorded = Calibration {
+        emissivity: 0.7,
+        ..Calibration::default()
+    };
+    record("replaced.tseq", &frames, &[recorded])
+        .finish()
+        .unwrap();
+
+    let (tx, _rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    let live = Calibration {
+        emissivity: 0.85,
+        ..Calibration::default()
+    };
+    *engine.calibration().lock().unwrap() = live;
+    let wait_for = |expected: Calibration| {
+        let start = Instant::now();
+        while *engine.calibration().lock().unwrap() != expected {
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", expected);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    let player = engine.player();
+    player.open(&temp_path("replaced.tseq")).unwrap();
+    wait_for(recorded);
+    // Opened while the first still plays with the recorded calibration.
+    player.open(&temp_path("replaced.tseq")).unwrap();
+    wait_for(recorded);
+    player.close();
+    wait_for(live);
+}
@@ -0,0 +1,75 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use thermoscope_app::ThermalEngine;
+use thermoscope_app::queue::{self, QueueConfig};
+use thermoscope_app::server::StreamServer;
+
+/// Sends a GET and reads until the server closes or `limit` bytes arrived.
+fn get(addr: SocketAddr, path: &str, limit: usize) -> (String, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).unwrap();
+    let mut data = Vec::new();
+    let mut buf = [0; 16 * 1024];
+    while data.len() < limit {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+        }
+    }
+    let end = data.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&data[..end]).into_owned();
+    (head, data[end..].to_vec())
+}
+
+fn header(head: &str, name: &str) -> String {
+    head.lines()
+        .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[test]
+fn serves_the_synthetic_stream() {
+    let (tx, rx) = queue::bounded(QueueConfig::default());
+    let engine = ThermalEngine::new(tx);
+    engine.start_synthetic();
+    let server = StreamServer::start("127.0.0.1:0", engine.bus(), None).unwrap();
+    let addr = server.addr();
+
+    let (head, body) = get(addr, "/stats.json", usize::MAX);
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(header(&head, "Content-Type"), "application/json");
+    let json = String::from_utf8(body).unwrap();
+    assert!(json.contains("\"width\":256,\"height\":192"), "{}", json);
+    assert!(json.contains("\"alarms\":[]"), "{}", json);
+
+    let (head, body) = get(addr, "/raw.y16", usize::MAX);
+    assert_eq!(header(&head, "X-Frame-Width"), "256");
+    assert_eq!(body.len(), 256 * 192 * 2);
+    // The synthetic background is 20-24 °C, the spot up to ~45 °C.
+    let counts: Vec<u16> = body
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let kelvin = |c: u16| c as f32 / 64.0 - 273.15;
+    assert!(counts.iter().all(|&c| (19.0..50.0).contains(&kelvin(c))));
+
+    let (head, body) = get(addr, "/stream.mjpg", 60 * 1024);
+    assert_eq!(
+        header(&head, "Content-Type"),
+        "multipart/x-mixed-replace; boundary=frame"
+    );
+    assert!(body.starts_with(b"--frame\r\nContent-Type: image/jpeg\r\n"));
+    let part = body.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert_eq!(&body[part..part + 2], &[0xff, 0xd8]);
+
+    let (head, _) = get(addr, "/missing", usize::MAX);
+    assert!(head.starts_with("HTTP/1.1 404"), "{}", head);
+
+    server.stop();
+    drop(rx);
+}